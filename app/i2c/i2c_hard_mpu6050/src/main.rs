@@ -20,7 +20,7 @@ fn main() -> anyhow::Result<()> {
     let sda = peripherals.pins.gpio5;
     let scl = peripherals.pins.gpio6;
 
-    let mut mpu = Mpu6050::new(i2c, sda, scl)?;
+    let mut mpu = Mpu6050::from_pins(i2c, sda, scl)?;
     mpu.wake_up()?;
     let id = mpu.get_id()?;
     log::info!("MPU6050 ID {id}");
@@ -35,6 +35,10 @@ fn main() -> anyhow::Result<()> {
     let address = mpu.read_byte(WHOAMI).unwrap();
     log::info!("MPU6050 address {address}");
 
+    // 静止放置时校准零偏, 可以保存 calibration.to_bytes(), 下次启动时通过 set_calibration 恢复
+    let calibration = mpu.calibrate(500)?;
+    log::info!("MPU6050 calibration {:?}", calibration);
+
     log::info!("loop");
     loop {
         // 获取温度数据，单位为摄氏度
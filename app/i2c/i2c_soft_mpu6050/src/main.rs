@@ -18,7 +18,8 @@ fn main() -> anyhow::Result<()> {
 
     let sda = peripherals.pins.gpio5;
     let scl = peripherals.pins.gpio6;
-    let mut mpu = Mpu6050::new(scl, sda)?;
+    let mut mpu = Mpu6050::from_soft_pins(scl.into(), sda.into())?;
+    mpu.wake_up()?;
 
     let id = mpu.get_id()?;
     log::info!("MPU6050 ID {id}");
@@ -22,7 +22,7 @@ fn main() -> anyhow::Result<()> {
     let sck = peripherals.pins.gpio5;
     let mosi = peripherals.pins.gpio6;
     let miso = peripherals.pins.gpio7;
-    let mut w25q = W25Q64::new(spi2, cs.into(), sck.into(), mosi.into(), miso.into())?;
+    let mut w25q = W25Q64::from_pins(spi2, cs.into(), sck.into(), mosi.into(), miso.into())?;
 
     // 读取芯片的JEDEC设备ID
     let (manufacturer_id, memory_type, capacity) = w25q.read_jedec_device_id()?;
@@ -1,17 +1,18 @@
 use esp32s3_nrf24l01::{
-    self, Configuration, CrcMode, DataRate, Payload, RxMode, StandbyMode, TxMode, NRF24L01,
+    self, setup, Configuration, CrcMode, DataRate, Payload, RxMode, StandbyMode, TxMode, NRF24L01,
 };
 
 use esp_idf_svc::{
     hal::{
         delay::FreeRtos,
-        gpio::{AnyIOPin, InputPin, OutputPin},
+        gpio::{AnyIOPin, InputPin, Output, OutputPin, PinDriver},
         peripheral::Peripheral,
         peripherals::Peripherals,
-        spi::SPI2,
+        prelude::FromValueType,
+        spi::{SpiConfig, SpiDeviceDriver, SpiDriver, SpiDriverConfig, SPI2},
     },
     log::EspLogger,
-    sys::{link_patches, EspError},
+    sys::link_patches,
 };
 
 /// RF24L01 发送协议地址
@@ -26,7 +27,7 @@ pub const NRF24L01_RX_ADDR_P3: usize = 0x03;
 pub const NRF24L01_RX_ADDR_P4: usize = 0x04;
 pub const NRF24L01_RX_ADDR_P5: usize = 0x05;
 
-type Device<'d> = NRF24L01<'d, EspError>;
+type Device<'d> = NRF24L01<SpiDeviceDriver<'d, SpiDriver<'d>>, PinDriver<'d, AnyIOPin, Output>>;
 
 pub type RxTY<'d> = RxMode<Device<'d>>;
 
@@ -43,23 +44,32 @@ pub struct Nrf24L01<'d> {
 
 impl<'d> Nrf24L01<'d> {
     /// 初始化 NRF24L01 SPI 2.4 GHz 无线通信
+    /// CSN 作为 SPI 设备的片选引脚, 由 SPI 驱动控制
     pub fn new(
         spi2: impl Peripheral<P = SPI2> + 'd,
         sclk: impl Peripheral<P = impl OutputPin> + 'd,
-        miso: Option<impl Peripheral<P = impl InputPin + OutputPin> + 'd>,
+        miso: impl Peripheral<P = impl InputPin + OutputPin> + 'd,
         mosi: impl Peripheral<P = impl OutputPin> + 'd,
-        cs: Option<impl Peripheral<P = impl OutputPin> + 'd>,
         ce: AnyIOPin,
         csn: AnyIOPin,
-    ) -> Self {
-        let nrf24 = NRF24L01::new(spi2, sclk, miso, mosi, cs, ce, csn).unwrap();
+    ) -> anyhow::Result<Self> {
+        let driver = SpiDriver::new(spi2, sclk, mosi, Some(miso), &SpiDriverConfig::new())?;
+        let config = SpiConfig::new()
+            .baudrate(setup::clock_mhz().MHz().into())
+            .data_mode(setup::spi_mode());
+        let spi = SpiDeviceDriver::new(driver, Some(csn), &config)?;
+        let ce = PinDriver::output(ce)?;
+
+        // 未连接模块时返回 Error::NotConnected
+        let nrf24 = NRF24L01::new(ce, spi)
+            .map_err(|err| anyhow::anyhow!("nrf24l01 init failed: {err:?}"))?;
 
         let mut nrf24l01 = Nrf24L01 { nrf24 };
 
         // 配置设备
         nrf24l01.init_config();
 
-        nrf24l01
+        Ok(nrf24l01)
     }
 
     /// 配置设备
@@ -123,7 +133,7 @@ impl<'d> Nrf24L01<'d> {
             Err(_err) => return None,
         };
         // 接收数据包
-        let payload = rx.read().unwrap();
+        let payload = rx.read().unwrap()?;
         // let data: &[u8]  = payload.as_ref();
         // 处理接收到的数据包
         // println!("Received {} bytes on pipe {}", payload.len(), pipe);
@@ -163,18 +173,9 @@ fn main() -> anyhow::Result<()> {
     let mosi = peripherals.pins.gpio6;
     let ce = peripherals.pins.gpio7;
     let csn = peripherals.pins.gpio8;
-    let cs = peripherals.pins.gpio9;
 
     // 初始化 NRF24L01 2.4 GHz 无线通信
-    let nrf24l01 = Nrf24L01::new(
-        spi2,
-        sclk,
-        Some(miso),
-        mosi,
-        Some(cs),
-        ce.into(),
-        csn.into(),
-    );
+    let nrf24l01 = Nrf24L01::new(spi2, sclk, miso, mosi, ce.into(), csn.into())?;
     // let mut tx = nrf24l01.nrf24.tx().unwrap();
     // println!("init nrf24l01 tx ...");
 
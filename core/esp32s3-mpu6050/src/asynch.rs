@@ -0,0 +1,516 @@
+//! Async driver
+//!
+//! Same register handling as `Mpu6050`, but generic over the `embedded-hal-async` I2C bus and
+//! delay, so it runs on an async executor (e.g. embassy). The wake up and calibration delays
+//! `.await` the timer, and `wait_data_ready` `.await`s the INT pin instead of blocking a task.
+
+use embedded_hal::digital::{Error as _, ErrorKind};
+use embedded_hal_async::delay::DelayUs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::I2c;
+use nalgebra::Vector3;
+
+use crate::bits;
+use crate::calibration::Calibration;
+use crate::codec;
+use crate::device::*;
+use crate::fifo::{FifoConfig, FifoSample};
+use crate::fusion::{Fusion, Quaternion};
+use crate::motion::{MotionConfig, MotionEngine, MotionEvent};
+use crate::{DlpfBandwidth, CALIBRATION_SAMPLE_DELAY_MS};
+use mpu6050::sample_rate::{output_data_rate, sample_rate_divider};
+
+/// All possible errors of the async driver
+#[derive(Debug, PartialEq)]
+pub enum Mpu6050Error<E> {
+    /// I2C bus error
+    I2c(E),
+
+    /// Error of the INT pin
+    Pin(ErrorKind),
+
+    /// Invalid chip ID was read
+    InvalidChipId(u8),
+
+    /// FIFO buffer overflowed and was reset, samples were lost
+    FifoOverflow,
+}
+
+impl<E> From<E> for Mpu6050Error<E> {
+    fn from(e: E) -> Self {
+        Mpu6050Error::I2c(e)
+    }
+}
+impl<E: core::fmt::Debug> std::fmt::Display for Mpu6050Error<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Mpu6050Error::I2c(e) => write!(f, "I2c Error: {:?}", e),
+            Mpu6050Error::Pin(e) => write!(f, "INT pin Error: {:?}", e),
+            Mpu6050Error::InvalidChipId(e) => write!(f, "Invalid chip ID was read error: {}", e),
+            Mpu6050Error::FifoOverflow => write!(f, "FIFO overflow, samples were lost"),
+        }
+    }
+}
+impl<E: core::fmt::Debug> std::error::Error for Mpu6050Error<E> {}
+
+/// Handles all operations on/with Mpu6050, over an async I2C bus
+pub struct Mpu6050<I2C, D> {
+    i2c: I2C,
+    delay: D,
+    slave_addr: u8,
+    acc_sensitivity: f32,
+    gyro_sensitivity: f32,
+    calibration: Calibration,
+}
+
+impl<I2C, D, E> Mpu6050<I2C, D>
+where
+    I2C: I2c<Error = E>,
+    D: DelayUs,
+{
+    /// Side effect free constructor with default sensitivies, no calibration (see `calibrate`)
+    pub fn new(i2c: I2C, delay: D) -> Self {
+        Self::new_with_addr(i2c, delay, DEFAULT_SLAVE_ADDR)
+    }
+
+    /// Same as `new`, but the chip address can be specified (e.g. 0x69, if the A0 pin is pulled up)
+    pub fn new_with_addr(i2c: I2C, delay: D, slave_addr: u8) -> Self {
+        Mpu6050 {
+            i2c,
+            delay,
+            slave_addr,
+            acc_sensitivity: ACCEL_SENS.0,
+            gyro_sensitivity: GYRO_SENS.0,
+            calibration: Calibration::default(),
+        }
+    }
+
+    /// Releases the I2C bus and the delay
+    pub fn release(self) -> (I2C, D) {
+        (self.i2c, self.delay)
+    }
+
+    /// Init wakes MPU6050 and verifies register addr, e.g. in i2c
+    pub async fn init(&mut self) -> Result<(), Mpu6050Error<E>> {
+        // MPU6050 has sleep enabled by default -> set bit 0 to wake
+        // Set clock source to be PLL with x-axis gyroscope reference, bits 2:0 = 001
+        self.write_byte(PWR_MGMT_1::ADDR, 0x01).await?;
+        self.delay.delay_ms(100).await;
+
+        let address = self.read_byte(WHOAMI).await?;
+        if address != DEFAULT_SLAVE_ADDR {
+            return Err(Mpu6050Error::InvalidChipId(address));
+        }
+
+        self.set_accel_range(AccelRange::G2).await?;
+        self.set_gyro_range(GyroRange::D250).await?;
+        self.set_accel_hpf(ACCEL_HPF::_RESET).await
+    }
+
+    /// Configures the motion, free fall and zero motion detectors and enables their interrupts,
+    /// see `crate::Mpu6050::set_motion_config`
+    pub async fn set_motion_config(
+        &mut self,
+        config: &MotionConfig,
+    ) -> Result<(), Mpu6050Error<E>> {
+        self.set_accel_hpf(ACCEL_HPF::_5).await?;
+
+        for (reg, value) in codec::motion_registers(config) {
+            self.write_byte(reg, value).await?;
+        }
+
+        let enable = self.read_byte(INT_ENABLE::ADDR).await?;
+        self.write_byte(INT_ENABLE::ADDR, codec::motion_int_enable(enable, config))
+            .await
+    }
+
+    /// Reads the motion interrupts and one accelerometer sample and passes them to `engine`,
+    /// see `crate::Mpu6050::poll_motion_events`
+    pub async fn poll_motion_events(
+        &mut self,
+        engine: &mut MotionEngine,
+        mut on_event: impl FnMut(MotionEvent),
+    ) -> Result<(), Mpu6050Error<E>> {
+        let int_status = self.read_byte(INT_STATUS::ADDR).await?;
+        let mot_detect_status = self.read_byte(MOT_DETECT_STATUS::ADDR).await?;
+        engine.interrupts(int_status, mot_detect_status, &mut on_event);
+
+        let acc = self.get_acc().await?;
+        engine.update(acc, &mut on_event);
+        Ok(())
+    }
+
+    /// Low power accel only mode waking up at `rate`, `None` returns to normal operation,
+    /// see `crate::Mpu6050::set_low_power_wake`
+    pub async fn set_low_power_wake(
+        &mut self,
+        rate: Option<LP_WAKE_CTRL>,
+    ) -> Result<(), Mpu6050Error<E>> {
+        let pwr_mgmt_1 = self.read_byte(PWR_MGMT_1::ADDR).await?;
+        let pwr_mgmt_2 = self.read_byte(PWR_MGMT_2::ADDR).await?;
+        let (pwr_mgmt_1, pwr_mgmt_2) = codec::low_power_wake(pwr_mgmt_1, pwr_mgmt_2, rate);
+        self.write_byte(PWR_MGMT_2::ADDR, pwr_mgmt_2).await?;
+        self.write_byte(PWR_MGMT_1::ADDR, pwr_mgmt_1).await
+    }
+
+    /// get the wake up rate of the low power mode, `None` if the chip is not cycling
+    pub async fn get_low_power_wake(&mut self) -> Result<Option<LP_WAKE_CTRL>, Mpu6050Error<E>> {
+        let pwr_mgmt_1 = self.read_byte(PWR_MGMT_1::ADDR).await?;
+        let pwr_mgmt_2 = self.read_byte(PWR_MGMT_2::ADDR).await?;
+        Ok(codec::low_power_wake_rate(pwr_mgmt_1, pwr_mgmt_2))
+    }
+
+    /// Configures the INT pin as active high, push-pull, with a 50us pulse per event, and
+    /// enables or disables the data-ready interrupt
+    pub async fn enable_data_ready_interrupt(
+        &mut self,
+        enable: bool,
+    ) -> Result<(), Mpu6050Error<E>> {
+        let int_pin_cfg = self.read_byte(INT_PIN_CFG::ADDR).await?;
+        self.write_byte(
+            INT_PIN_CFG::ADDR,
+            codec::data_ready_int_pin_cfg(int_pin_cfg),
+        )
+        .await?;
+        self.write_bit(INT_ENABLE::ADDR, INT_ENABLE::DATA_RDY_EN, enable)
+            .await
+    }
+
+    /// Waits for a rising edge of the INT pin, configured with `enable_data_ready_interrupt`,
+    /// then reads INT_STATUS to clear the interrupt. Returns whether new data is ready, false if
+    /// the edge came from another interrupt source
+    pub async fn wait_data_ready<P: Wait>(&mut self, int: &mut P) -> Result<bool, Mpu6050Error<E>> {
+        int.wait_for_rising_edge()
+            .await
+            .map_err(|e| Mpu6050Error::Pin(e.kind()))?;
+        let status = self.read_byte(INT_STATUS::ADDR).await?;
+        Ok(bits::get_bit(status, INT_STATUS::DATA_RDY_INT) != 0)
+    }
+
+    /// Selects the sensors written into the FIFO on every sample, see `crate::Mpu6050::set_fifo_config`
+    pub async fn set_fifo_config(&mut self, config: FifoConfig) -> Result<(), Mpu6050Error<E>> {
+        let user_ctrl = self.read_byte(USER_CTRL::ADDR).await?;
+        let [disabled, reset, enabled] = codec::fifo_reset(user_ctrl, !config.is_empty());
+        self.write_byte(USER_CTRL::ADDR, disabled).await?;
+        self.write_byte(FIFO_EN::ADDR, config.to_register()).await?;
+        self.write_byte(USER_CTRL::ADDR, reset).await?;
+        self.write_byte(USER_CTRL::ADDR, enabled).await
+    }
+
+    /// get sensors written into the FIFO
+    pub async fn get_fifo_config(&mut self) -> Result<FifoConfig, Mpu6050Error<E>> {
+        Ok(FifoConfig::from_register(
+            self.read_byte(FIFO_EN::ADDR).await?,
+        ))
+    }
+
+    /// Discards the FIFO content, keeps the configuration
+    pub async fn reset_fifo(&mut self) -> Result<(), Mpu6050Error<E>> {
+        let user_ctrl = self.read_byte(USER_CTRL::ADDR).await?;
+        let enabled = bits::get_bit(user_ctrl, USER_CTRL::FIFO_EN) != 0;
+        for value in codec::fifo_reset(user_ctrl, enabled) {
+            self.write_byte(USER_CTRL::ADDR, value).await?;
+        }
+        Ok(())
+    }
+
+    /// Number of bytes in the FIFO
+    pub async fn fifo_count(&mut self) -> Result<u16, Mpu6050Error<E>> {
+        let mut buf: [u8; 2] = [0; 2];
+        self.read_bytes(FIFO_COUNT_H, &mut buf).await?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    /// Reads up to `max_samples` complete frames from the FIFO in one burst,
+    /// see `crate::Mpu6050::read_fifo`
+    pub async fn read_fifo(
+        &mut self,
+        max_samples: usize,
+    ) -> Result<Vec<FifoSample>, Mpu6050Error<E>> {
+        let config = self.get_fifo_config().await?;
+        let frame_size = config.frame_size();
+        if frame_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let count = self.fifo_count().await?;
+        let frames = match codec::fifo_frames(count, frame_size, max_samples) {
+            Some(frames) => frames,
+            None => {
+                self.reset_fifo().await?;
+                return Err(Mpu6050Error::FifoOverflow);
+            }
+        };
+        if frames == 0 {
+            return Ok(Vec::new());
+        }
+        let mut buf = vec![0; frames * frame_size];
+        self.read_bytes(FIFO_R_W, &mut buf).await?;
+
+        Ok(codec::fifo_samples(
+            &config,
+            &buf,
+            self.acc_sensitivity,
+            self.gyro_sensitivity,
+            &self.calibration,
+        ))
+    }
+
+    /// Set the digital low pass filter. Changes the gyro output rate between 8kHz (`Hz260`) and
+    /// 1kHz, so set the sample rate again afterwards
+    pub async fn set_dlpf(&mut self, dlpf: DlpfBandwidth) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(
+            CONFIG::ADDR,
+            CONFIG::DLPF_CFG.bit,
+            CONFIG::DLPF_CFG.length,
+            dlpf.to_register(),
+        )
+        .await
+    }
+
+    /// get digital low pass filter
+    pub async fn get_dlpf(&mut self) -> Result<DlpfBandwidth, Mpu6050Error<E>> {
+        let byte = self
+            .read_bits(CONFIG::ADDR, CONFIG::DLPF_CFG.bit, CONFIG::DLPF_CFG.length)
+            .await?;
+        Ok(DlpfBandwidth::from_register(byte))
+    }
+
+    /// Set the sample rate in Hz, rounded to the nearest achievable rate
+    pub async fn set_sample_rate(&mut self, hz: u32) -> Result<(), Mpu6050Error<E>> {
+        let dlpf = self.get_dlpf().await?;
+        self.write_byte(SMPLRT_DIV, sample_rate_divider(dlpf, hz))
+            .await
+    }
+
+    /// Effective output data rate in Hz
+    pub async fn get_sample_rate(&mut self) -> Result<f32, Mpu6050Error<E>> {
+        let dlpf = self.get_dlpf().await?;
+        let divider = self.read_byte(SMPLRT_DIV).await?;
+        Ok(output_data_rate(dlpf, divider))
+    }
+
+    /// set accel high pass filter mode
+    pub async fn set_accel_hpf(&mut self, mode: ACCEL_HPF) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(
+            ACCEL_CONFIG::ADDR,
+            ACCEL_CONFIG::ACCEL_HPF.bit,
+            ACCEL_CONFIG::ACCEL_HPF.length,
+            mode as u8,
+        )
+        .await
+    }
+
+    /// Set gyro range, and update sensitivity accordingly
+    pub async fn set_gyro_range(&mut self, range: GyroRange) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(
+            GYRO_CONFIG::ADDR,
+            GYRO_CONFIG::FS_SEL.bit,
+            GYRO_CONFIG::FS_SEL.length,
+            range as u8,
+        )
+        .await?;
+
+        self.gyro_sensitivity = range.sensitivity();
+        Ok(())
+    }
+
+    /// get current gyro range
+    pub async fn get_gyro_range(&mut self) -> Result<GyroRange, Mpu6050Error<E>> {
+        let byte = self
+            .read_bits(
+                GYRO_CONFIG::ADDR,
+                GYRO_CONFIG::FS_SEL.bit,
+                GYRO_CONFIG::FS_SEL.length,
+            )
+            .await?;
+
+        Ok(GyroRange::from(byte))
+    }
+
+    /// set accel range, and update sensitivy accordingly
+    pub async fn set_accel_range(&mut self, range: AccelRange) -> Result<(), Mpu6050Error<E>> {
+        self.write_bits(
+            ACCEL_CONFIG::ADDR,
+            ACCEL_CONFIG::FS_SEL.bit,
+            ACCEL_CONFIG::FS_SEL.length,
+            range as u8,
+        )
+        .await?;
+
+        self.acc_sensitivity = range.sensitivity();
+        Ok(())
+    }
+
+    /// get current accel_range
+    pub async fn get_accel_range(&mut self) -> Result<AccelRange, Mpu6050Error<E>> {
+        let byte = self
+            .read_bits(
+                ACCEL_CONFIG::ADDR,
+                ACCEL_CONFIG::FS_SEL.bit,
+                ACCEL_CONFIG::FS_SEL.length,
+            )
+            .await?;
+
+        Ok(AccelRange::from(byte))
+    }
+
+    /// Uncalibrated accelerometer readings in g
+    async fn read_acc(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mut buf: [u8; 6] = [0; 6];
+        self.read_bytes(ACC_REGX_H, &mut buf).await?;
+        Ok(codec::acc(&buf, self.acc_sensitivity))
+    }
+
+    /// Uncalibrated gyro readings in rad/s
+    async fn read_gyro(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let mut buf: [u8; 6] = [0; 6];
+        self.read_bytes(GYRO_REGX_H, &mut buf).await?;
+        Ok(codec::gyro(&buf, self.gyro_sensitivity))
+    }
+
+    /// Accelerometer readings in g, with the calibration offset removed
+    pub async fn get_acc(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let acc = self.read_acc().await?;
+        Ok(self.calibration.apply_acc(acc))
+    }
+
+    /// Gyro readings in rad/s, with the calibration offset removed
+    pub async fn get_gyro(&mut self) -> Result<Vector3<f32>, Mpu6050Error<E>> {
+        let gyro = self.read_gyro().await?;
+        Ok(self.calibration.apply_gyro(gyro))
+    }
+
+    /// Sensor Temp in degrees celcius
+    pub async fn get_temp(&mut self) -> Result<f32, Mpu6050Error<E>> {
+        let mut buf: [u8; 2] = [0; 2];
+        self.read_bytes(TEMP_OUT_H, &mut buf).await?;
+        Ok(codec::temp(buf))
+    }
+
+    /// Reads accel and gyro and feeds them into the orientation estimator.
+    /// Call once per sample period of `fusion`
+    pub async fn update_fusion(
+        &mut self,
+        fusion: &mut Fusion,
+    ) -> Result<Quaternion, Mpu6050Error<E>> {
+        let acc = self.get_acc().await?;
+        let gyro = self.get_gyro().await?;
+        Ok(fusion.update(acc.into(), gyro.into()))
+    }
+
+    /// Calibration applied in `get_acc` and `get_gyro`
+    pub fn get_calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    /// Set the calibration applied in `get_acc` and `get_gyro`, e.g. one loaded from flash
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Measures the accel and gyro offsets by averaging `samples` readings, see
+    /// `crate::Mpu6050::calibrate`. The delay between two samples is awaited
+    pub async fn calibrate(&mut self, samples: u16) -> Result<Calibration, Mpu6050Error<E>> {
+        let samples = samples.max(1);
+        let mut acc_sum = Vector3::<f32>::zeros();
+        let mut gyro_sum = Vector3::<f32>::zeros();
+
+        for _ in 0..samples {
+            acc_sum += self.read_acc().await?;
+            gyro_sum += self.read_gyro().await?;
+            self.delay.delay_ms(CALIBRATION_SAMPLE_DELAY_MS).await;
+        }
+
+        let calibration =
+            Calibration::from_stationary(acc_sum / samples as f32, gyro_sum / samples as f32);
+        self.calibration = calibration;
+        Ok(calibration)
+    }
+
+    /// Moves the current calibration into the chip's offset registers, then clears the
+    /// software calibration, see `crate::Mpu6050::write_offset_registers`
+    pub async fn write_offset_registers(&mut self) -> Result<(), Mpu6050Error<E>> {
+        let mut current = [0; 6];
+        for (value, reg) in current.iter_mut().zip(codec::OFFSET_REGISTERS) {
+            *value = self.read_offset(reg).await?;
+        }
+
+        let values = codec::offset_registers(&self.calibration, current);
+        for (&value, reg) in values.iter().zip(codec::OFFSET_REGISTERS) {
+            self.write_offset(reg, value).await?;
+        }
+
+        self.calibration = Calibration::default();
+        Ok(())
+    }
+
+    /// Reads a 16 bit offset register
+    async fn read_offset(&mut self, reg: u8) -> Result<i16, Mpu6050Error<E>> {
+        let mut buf: [u8; 2] = [0; 2];
+        self.read_bytes(reg, &mut buf).await?;
+        Ok(i16::from_be_bytes(buf))
+    }
+
+    /// Writes a 16 bit offset register
+    async fn write_offset(&mut self, reg: u8, value: i16) -> Result<(), Mpu6050Error<E>> {
+        let [high, low] = value.to_be_bytes();
+        self.write_byte(reg, high).await?;
+        self.write_byte(reg + 1, low).await
+    }
+
+    /// Writes byte to register
+    pub async fn write_byte(&mut self, reg: u8, byte: u8) -> Result<(), Mpu6050Error<E>> {
+        self.i2c.write(self.slave_addr, &[reg, byte]).await?;
+        Ok(())
+    }
+
+    /// Enables bit n at register address reg
+    pub async fn write_bit(
+        &mut self,
+        reg: u8,
+        bit_n: u8,
+        enable: bool,
+    ) -> Result<(), Mpu6050Error<E>> {
+        let mut byte = self.read_byte(reg).await?;
+        bits::set_bit(&mut byte, bit_n, enable);
+        self.write_byte(reg, byte).await
+    }
+
+    /// Write bits data at reg from start_bit to start_bit+length
+    pub async fn write_bits(
+        &mut self,
+        reg: u8,
+        start_bit: u8,
+        length: u8,
+        data: u8,
+    ) -> Result<(), Mpu6050Error<E>> {
+        let mut byte = self.read_byte(reg).await?;
+        bits::set_bits(&mut byte, start_bit, length, data);
+        self.write_byte(reg, byte).await
+    }
+
+    /// Read bits at register reg, starting with bit start_bit, until start_bit+length
+    pub async fn read_bits(
+        &mut self,
+        reg: u8,
+        start_bit: u8,
+        length: u8,
+    ) -> Result<u8, Mpu6050Error<E>> {
+        let byte = self.read_byte(reg).await?;
+        Ok(bits::get_bits(byte, start_bit, length))
+    }
+
+    /// Reads byte from register
+    pub async fn read_byte(&mut self, reg: u8) -> Result<u8, Mpu6050Error<E>> {
+        let mut byte: [u8; 1] = [0; 1];
+        self.read_bytes(reg, &mut byte).await?;
+        Ok(byte[0])
+    }
+
+    /// Reads series of bytes into buf from specified reg
+    pub async fn read_bytes(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Mpu6050Error<E>> {
+        self.i2c.write_read(self.slave_addr, &[reg], buf).await?;
+        Ok(())
+    }
+}
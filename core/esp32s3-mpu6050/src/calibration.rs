@@ -0,0 +1,126 @@
+//! Accelerometer and gyro offset calibration
+//!
+//! Offsets are stored in physical units (g and rad/s), so a saved calibration stays valid when
+//! the accelerometer or gyro range is changed afterwards.
+
+use nalgebra::Vector3;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Size of a calibration encoded with `Calibration::to_bytes`
+pub const CALIBRATION_SIZE: usize = 24;
+
+/// Per-axis sensor offsets, subtracted from the readings of `get_acc` and `get_gyro`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Calibration {
+    /// Accelerometer offset in g
+    pub acc_offset: Vector3<f32>,
+    /// Gyro offset in rad/s
+    pub gyro_offset: Vector3<f32>,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            acc_offset: Vector3::zeros(),
+            gyro_offset: Vector3::zeros(),
+        }
+    }
+}
+
+impl Calibration {
+    /// Computes offsets from mean readings taken while the sensor is stationary.
+    ///
+    /// The gyro should read zero, so its mean is the offset. The accelerometer should read 1g
+    /// on the axis pointing up or down (the one with the largest mean) and zero on the others.
+    pub fn from_stationary(acc_mean: Vector3<f32>, gyro_mean: Vector3<f32>) -> Self {
+        let axis = acc_mean.iamax();
+        let mut gravity = Vector3::zeros();
+        gravity[axis] = if acc_mean[axis] < 0. { -1. } else { 1. };
+
+        Calibration {
+            acc_offset: acc_mean - gravity,
+            gyro_offset: gyro_mean,
+        }
+    }
+
+    /// Removes the accelerometer offset from a reading in g
+    pub fn apply_acc(&self, acc: Vector3<f32>) -> Vector3<f32> {
+        acc - self.acc_offset
+    }
+
+    /// Removes the gyro offset from a reading in rad/s
+    pub fn apply_gyro(&self, gyro: Vector3<f32>) -> Vector3<f32> {
+        gyro - self.gyro_offset
+    }
+
+    /// Encodes the calibration as little endian f32s: acc x/y/z, then gyro x/y/z
+    pub fn to_bytes(&self) -> [u8; CALIBRATION_SIZE] {
+        let mut bytes = [0; CALIBRATION_SIZE];
+        let values = self.acc_offset.iter().chain(self.gyro_offset.iter());
+        for (chunk, value) in bytes.chunks_exact_mut(4).zip(values) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a calibration saved with `to_bytes`
+    pub fn from_bytes(bytes: &[u8; CALIBRATION_SIZE]) -> Self {
+        let mut values = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        let mut value = || values.next().unwrap_or_default();
+        Calibration {
+            acc_offset: Vector3::new(value(), value(), value()),
+            gyro_offset: Vector3::new(value(), value(), value()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).amax() < 1e-6
+    }
+
+    #[test]
+    fn from_stationary_test() {
+        // flat, z axis up
+        let cal = Calibration::from_stationary(
+            Vector3::new(0.02, -0.01, 1.05),
+            Vector3::new(0.01, -0.02, 0.03),
+        );
+        assert!(close(cal.acc_offset, Vector3::new(0.02, -0.01, 0.05)));
+        assert_eq!(cal.gyro_offset, Vector3::new(0.01, -0.02, 0.03));
+        assert!(close(
+            cal.apply_acc(Vector3::new(0.02, -0.01, 1.05)),
+            Vector3::z()
+        ));
+        assert_eq!(
+            cal.apply_gyro(Vector3::new(0.01, -0.02, 0.03)),
+            Vector3::zeros()
+        );
+
+        // upside down, y axis
+        let cal = Calibration::from_stationary(Vector3::new(0.1, -0.98, 0.), Vector3::zeros());
+        assert!(close(cal.acc_offset, Vector3::new(0.1, 0.02, 0.)));
+    }
+
+    #[test]
+    fn bytes_test() {
+        let cal = Calibration {
+            acc_offset: Vector3::new(0.5, -0.25, 0.125),
+            gyro_offset: Vector3::new(-1., 2., 1e-3),
+        };
+        let bytes = cal.to_bytes();
+        assert_eq!(bytes[0..4], 0.5f32.to_le_bytes());
+        assert_eq!(Calibration::from_bytes(&bytes), cal);
+        assert_eq!(
+            Calibration::from_bytes(&[0; CALIBRATION_SIZE]),
+            Calibration::default()
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Register encoding and decoding shared by `Mpu6050` and `asynch::Mpu6050`
+//!
+//! The drivers only do the bus I/O: they read the registers, pass the bytes through these
+//! functions and write back the results, so both drivers produce the same register values.
+
+use nalgebra::Vector3;
+
+use crate::bits;
+use crate::calibration::Calibration;
+use crate::device::*;
+use crate::fifo::{FifoConfig, FifoSample};
+use crate::motion::MotionConfig;
+use crate::PI_180;
+
+/// Accel offset registers x, y, z followed by the gyro offset registers x, y, z
+pub(crate) const OFFSET_REGISTERS: [u8; 6] = [
+    XA_OFFS_H,
+    XA_OFFS_H + 2,
+    XA_OFFS_H + 4,
+    XG_OFFS_USRH,
+    XG_OFFS_USRH + 2,
+    XG_OFFS_USRH + 4,
+];
+
+/// Decodes three big endian words (x, y, z) into raw LSB values
+fn vector(buf: &[u8; 6]) -> Vector3<f32> {
+    Vector3::new(
+        i16::from_be_bytes([buf[0], buf[1]]) as f32,
+        i16::from_be_bytes([buf[2], buf[3]]) as f32,
+        i16::from_be_bytes([buf[4], buf[5]]) as f32,
+    )
+}
+
+/// Uncalibrated accelerometer reading in g from ACCEL_XOUT_H..ACCEL_ZOUT_L
+pub(crate) fn acc(buf: &[u8; 6], sensitivity: f32) -> Vector3<f32> {
+    vector(buf) / sensitivity
+}
+
+/// Uncalibrated gyro reading in rad/s from GYRO_XOUT_H..GYRO_ZOUT_L
+pub(crate) fn gyro(buf: &[u8; 6], sensitivity: f32) -> Vector3<f32> {
+    vector(buf) * (PI_180 / sensitivity)
+}
+
+/// Temperature in degrees celcius from TEMP_OUT_H..TEMP_OUT_L, according to revision 4.2
+pub(crate) fn temp(buf: [u8; 2]) -> f32 {
+    i16::from_be_bytes(buf) as f32 / TEMP_SENSITIVITY + TEMP_OFFSET
+}
+
+/// Detector registers written by `set_motion_config`, as (register, value) in write order.
+/// Disabled detectors keep their thresholds
+pub(crate) fn motion_registers(config: &MotionConfig) -> Vec<(u8, u8)> {
+    let mut registers = Vec::with_capacity(7);
+    if let Some(motion) = config.motion {
+        registers.push((MOT_THR, motion.threshold_register()));
+        registers.push((MOT_DUR, motion.duration_register()));
+    }
+    if let Some(free_fall) = config.free_fall {
+        registers.push((FF_THR, free_fall.threshold_register()));
+        registers.push((FF_DUR, free_fall.duration_register()));
+    }
+    if let Some(zero_motion) = config.zero_motion {
+        registers.push((ZRMOT_THR, zero_motion.threshold_register()));
+        registers.push((ZRMOT_DUR, zero_motion.zero_motion_duration_register()));
+    }
+    registers.push((MOT_DETECT_CONTROL::ADDR, config.detect_control_register()));
+    registers
+}
+
+/// INT_ENABLE with the detector interrupts of `config` enabled, other interrupts unchanged
+pub(crate) fn motion_int_enable(mut int_enable: u8, config: &MotionConfig) -> u8 {
+    bits::set_bit(&mut int_enable, INT_ENABLE::MOT_EN, config.motion.is_some());
+    bits::set_bit(
+        &mut int_enable,
+        INT_ENABLE::FF_EN,
+        config.free_fall.is_some(),
+    );
+    bits::set_bit(
+        &mut int_enable,
+        INT_ENABLE::ZMOT_EN,
+        config.zero_motion.is_some(),
+    );
+    int_enable
+}
+
+/// INT_PIN_CFG for an active high, push-pull INT pin with a 50us pulse per event
+pub(crate) fn data_ready_int_pin_cfg(mut int_pin_cfg: u8) -> u8 {
+    bits::set_bit(&mut int_pin_cfg, INT_PIN_CFG::INT_LEVEL, false);
+    bits::set_bit(&mut int_pin_cfg, INT_PIN_CFG::INT_OPEN, false);
+    bits::set_bit(&mut int_pin_cfg, INT_PIN_CFG::LATCH_INT_EN, false);
+    int_pin_cfg
+}
+
+/// USER_CTRL values written in order to reset the FIFO, FIFO_RESET only works while
+/// FIFO_EN is 0. The FIFO is enabled again afterwards if `enable` is set
+pub(crate) fn fifo_reset(mut user_ctrl: u8, enable: bool) -> [u8; 3] {
+    bits::set_bit(&mut user_ctrl, USER_CTRL::FIFO_RESET, false);
+    bits::set_bit(&mut user_ctrl, USER_CTRL::FIFO_EN, false);
+    let disabled = user_ctrl;
+    bits::set_bit(&mut user_ctrl, USER_CTRL::FIFO_RESET, true);
+    let reset = user_ctrl;
+    bits::set_bit(&mut user_ctrl, USER_CTRL::FIFO_RESET, false);
+    bits::set_bit(&mut user_ctrl, USER_CTRL::FIFO_EN, enable);
+    [disabled, reset, user_ctrl]
+}
+
+/// Number of complete frames to read from `count` bytes in the FIFO, at most `max_samples`.
+/// `None` if the FIFO is full: it has overwritten old data and lost the frame boundary
+pub(crate) fn fifo_frames(count: u16, frame_size: usize, max_samples: usize) -> Option<usize> {
+    if count >= FIFO_SIZE {
+        return None;
+    }
+    Some((count as usize / frame_size).min(max_samples))
+}
+
+/// Decodes a burst of complete FIFO frames and applies the calibration
+pub(crate) fn fifo_samples(
+    config: &FifoConfig,
+    buf: &[u8],
+    acc_sensitivity: f32,
+    gyro_sensitivity: f32,
+    calibration: &Calibration,
+) -> Vec<FifoSample> {
+    buf.chunks_exact(config.frame_size())
+        .map(|frame| {
+            let mut sample = config.decode(frame, acc_sensitivity, gyro_sensitivity);
+            sample.acc = sample.acc.map(|acc| calibration.apply_acc(acc));
+            sample.gyro = sample.gyro.map(|gyro| calibration.apply_gyro(gyro));
+            sample
+        })
+        .collect()
+}
+
+/// PWR_MGMT_1 and PWR_MGMT_2 for the low power accel only mode waking up at `rate`:
+/// gyros in standby, temperature sensor off, sleep off and CYCLE set.
+/// `None` returns to normal operation with all sensors enabled
+pub(crate) fn low_power_wake(
+    mut pwr_mgmt_1: u8,
+    mut pwr_mgmt_2: u8,
+    rate: Option<LP_WAKE_CTRL>,
+) -> (u8, u8) {
+    let cycle = rate.is_some();
+    if let Some(rate) = rate {
+        bits::set_bits(
+            &mut pwr_mgmt_2,
+            PWR_MGMT_2::LP_WAKE_CTRL.bit,
+            PWR_MGMT_2::LP_WAKE_CTRL.length,
+            rate as u8,
+        );
+    }
+    bits::set_bit(&mut pwr_mgmt_2, PWR_MGMT_2::STBY_XG, cycle);
+    bits::set_bit(&mut pwr_mgmt_2, PWR_MGMT_2::STBY_YG, cycle);
+    bits::set_bit(&mut pwr_mgmt_2, PWR_MGMT_2::STBY_ZG, cycle);
+
+    // TEMP_DIS saves the "disabled status"
+    bits::set_bit(&mut pwr_mgmt_1, PWR_MGMT_1::TEMP_DIS, cycle);
+    bits::set_bit(&mut pwr_mgmt_1, PWR_MGMT_1::SLEEP, false);
+    bits::set_bit(&mut pwr_mgmt_1, PWR_MGMT_1::CYCLE, cycle);
+    (pwr_mgmt_1, pwr_mgmt_2)
+}
+
+/// Wake up rate of the low power mode, `None` if CYCLE is not set
+pub(crate) fn low_power_wake_rate(pwr_mgmt_1: u8, pwr_mgmt_2: u8) -> Option<LP_WAKE_CTRL> {
+    if bits::get_bit(pwr_mgmt_1, PWR_MGMT_1::CYCLE) == 0 {
+        return None;
+    }
+    let rate = bits::get_bits(
+        pwr_mgmt_2,
+        PWR_MGMT_2::LP_WAKE_CTRL.bit,
+        PWR_MGMT_2::LP_WAKE_CTRL.length,
+    );
+    Some(LP_WAKE_CTRL::from(rate))
+}
+
+/// New values of `OFFSET_REGISTERS` that move `calibration` into the chip, adjusting the
+/// `current` register values
+pub(crate) fn offset_registers(calibration: &Calibration, current: [i16; 6]) -> [i16; 6] {
+    let mut values = current;
+    for axis in 0..3 {
+        let current = current[axis];
+        let delta = (calibration.acc_offset[axis] * ACCEL_OFFSET_SENS).round() as i32;
+        // bit 0 of the accel offset registers is reserved, keep it
+        values[axis] = (clamp_i16(current as i32 - delta) & !1) | (current & 1);
+    }
+    for axis in 0..3 {
+        let current = current[3 + axis];
+        let delta = (calibration.gyro_offset[axis] / PI_180 * GYRO_OFFSET_SENS).round() as i32;
+        values[3 + axis] = clamp_i16(current as i32 - delta);
+    }
+    values
+}
+
+/// Saturates to the range of an offset register
+fn clamp_i16(value: i32) -> i16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_test() {
+        let buf = [0x40, 0x00, 0xC0, 0x00, 0x00, 0x00];
+        assert_eq!(acc(&buf, 16384.), Vector3::new(1., -1., 0.));
+        let gyro = gyro(&[0x00, 0x83, 0xFF, 0x7D, 0x00, 0x00], 131.);
+        assert!((gyro.x - PI_180).abs() < 1e-6);
+        assert!((gyro.y + PI_180).abs() < 1e-6);
+        assert_eq!(temp([0, 0]), TEMP_OFFSET);
+    }
+
+    #[test]
+    fn motion_registers_test() {
+        let config = MotionConfig {
+            free_fall: None,
+            ..Default::default()
+        };
+        let registers = motion_registers(&config);
+        assert!(registers
+            .iter()
+            .all(|&(reg, _)| reg != FF_THR && reg != FF_DUR));
+        assert_eq!(registers.last().unwrap().0, MOT_DETECT_CONTROL::ADDR);
+
+        let enable = motion_int_enable(1 << INT_ENABLE::FF_EN | 1, &config);
+        assert_eq!(bits::get_bit(enable, INT_ENABLE::FF_EN), 0);
+        assert_eq!(bits::get_bit(enable, INT_ENABLE::MOT_EN), 1);
+        // data ready stays enabled
+        assert_eq!(enable & 1, 1);
+    }
+
+    #[test]
+    fn fifo_reset_test() {
+        let fifo_en = 1 << USER_CTRL::FIFO_EN;
+        let reset = 1 << USER_CTRL::FIFO_RESET;
+        assert_eq!(fifo_reset(fifo_en | 1, true), [1, reset | 1, fifo_en | 1]);
+        assert_eq!(fifo_reset(fifo_en, false), [0, reset, 0]);
+    }
+
+    #[test]
+    fn fifo_frames_test() {
+        assert_eq!(fifo_frames(30, 12, 10), Some(2));
+        assert_eq!(fifo_frames(30, 12, 1), Some(1));
+        assert_eq!(fifo_frames(11, 12, 10), Some(0));
+        assert_eq!(fifo_frames(FIFO_SIZE, 12, 10), None);
+    }
+
+    #[test]
+    fn low_power_wake_test() {
+        let sleeping = 1 << PWR_MGMT_1::SLEEP | 0x01;
+        let (pwr_mgmt_1, pwr_mgmt_2) = low_power_wake(sleeping, 0, Some(LP_WAKE_CTRL::_5));
+        assert_eq!(
+            pwr_mgmt_1,
+            1 << PWR_MGMT_1::CYCLE | 1 << PWR_MGMT_1::TEMP_DIS | 0x01
+        );
+        assert_eq!(pwr_mgmt_2, 0b1000_0111);
+        assert_eq!(
+            low_power_wake_rate(pwr_mgmt_1, pwr_mgmt_2),
+            Some(LP_WAKE_CTRL::_5)
+        );
+
+        let (pwr_mgmt_1, pwr_mgmt_2) = low_power_wake(pwr_mgmt_1, pwr_mgmt_2, None);
+        assert_eq!(pwr_mgmt_1, 0x01);
+        // the wake up rate is kept, it has no effect without CYCLE
+        assert_eq!(pwr_mgmt_2, 0b1000_0000);
+        assert_eq!(low_power_wake_rate(pwr_mgmt_1, pwr_mgmt_2), None);
+    }
+
+    #[test]
+    fn offset_registers_test() {
+        let calibration = Calibration {
+            acc_offset: Vector3::new(0.5, 0., 0.),
+            gyro_offset: Vector3::new(0., PI_180, 0.),
+        };
+        let values = offset_registers(&calibration, [1001, -3, i16::MIN + 1, 0, 10, 0]);
+        // 0.5g = 1024 LSB, bit 0 is kept
+        assert_eq!(values[0], 1001 - 1024);
+        assert_eq!(values[1], -3);
+        assert_eq!(values[2], i16::MIN + 1);
+        assert_eq!(values[3], 0);
+        // 1 deg/s = 32.8 LSB
+        assert_eq!(values[4], 10 - 33);
+
+        let saturated = Calibration {
+            acc_offset: Vector3::new(-100., 0., 0.),
+            ..Default::default()
+        };
+        assert_eq!(offset_registers(&saturated, [0; 6])[0], i16::MAX & !1);
+    }
+}
@@ -38,10 +38,20 @@ pub const TEMP_OFFSET: f32 = 36.53;
 /// Temperature Sensitivity
 pub const TEMP_SENSITIVITY: f32 = 340.;
 
+/// Sample Rate Divider Register, sample rate = gyro output rate / (1 + SMPLRT_DIV)
+pub const SMPLRT_DIV: u8 = 0x19;
+/// Free Fall Threshold Register, 1 LSB = 2mg
+pub const FF_THR: u8 = 0x1D;
+/// Free Fall Duration Register, 1 LSB = 1ms
+pub const FF_DUR: u8 = 0x1E;
 /// Motion Threshold Register
 pub const MOT_THR: u8 = 0x1F;
 /// Motion Duration Detection Register
 pub const MOT_DUR: u8 = 0x20;
+/// Zero Motion Threshold Register, 1 LSB = 2mg
+pub const ZRMOT_THR: u8 = 0x21;
+/// Zero Motion Duration Register, 1 LSB = 64ms
+pub const ZRMOT_DUR: u8 = 0x22;
 /// High Byte Register Gyro x orientation
 pub const GYRO_REGX_H: u8 = 0x43;
 /// High Byte Register Gyro y orientation
@@ -60,6 +70,20 @@ pub const TEMP_OUT_H : u8= 0x41;
 pub const DEFAULT_SLAVE_ADDR: u8 = 0x68;
 /// Internal register to check slave addr
 pub const WHOAMI: u8 = 0x75;
+/// High Byte Register FIFO count
+pub const FIFO_COUNT_H: u8 = 0x72;
+/// FIFO read/write register
+pub const FIFO_R_W: u8 = 0x74;
+/// FIFO buffer size in bytes
+pub const FIFO_SIZE: u16 = 1024;
+/// High Byte Register accel x offset, y and z follow at +2 and +4 (not in the register map)
+pub const XA_OFFS_H: u8 = 0x06;
+/// High Byte Register gyro x offset, y and z follow at +2 and +4 (not in the register map)
+pub const XG_OFFS_USRH: u8 = 0x13;
+/// Accel offset register sensitivity, LSB/g (+-16g scale)
+pub const ACCEL_OFFSET_SENS: f32 = 2048.;
+/// Gyro offset register sensitivity, LSB/(deg/s) (+-1000 deg/s scale)
+pub const GYRO_OFFSET_SENS: f32 = 32.8;
 
 /// Describes a bit block from bit number 'bit' to 'bit'+'length'
 pub struct BitBlock {
@@ -118,6 +142,32 @@ impl ACCEL_CONFIG {
     pub const ACCEL_HPF: BitBlock = BitBlock { bit: 2, length: 3};
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 35: FIFO Enable, selects which sensor registers are written into the FIFO
+pub struct FIFO_EN;
+
+impl FIFO_EN {
+    /// Base Address
+    pub const ADDR: u8 = 0x23;
+    /// TEMP_OUT registers
+    pub const TEMP_FIFO_EN: u8 = 7;
+    /// GYRO_XOUT registers
+    pub const XG_FIFO_EN: u8 = 6;
+    /// GYRO_YOUT registers
+    pub const YG_FIFO_EN: u8 = 5;
+    /// GYRO_ZOUT registers
+    pub const ZG_FIFO_EN: u8 = 4;
+    /// ACCEL_XOUT, ACCEL_YOUT and ACCEL_ZOUT registers
+    pub const ACCEL_FIFO_EN: u8 = 3;
+    /// EXT_SENS_DATA registers of slave 2
+    pub const SLV2_FIFO_EN: u8 = 2;
+    /// EXT_SENS_DATA registers of slave 1
+    pub const SLV1_FIFO_EN: u8 = 1;
+    /// EXT_SENS_DATA registers of slave 0
+    pub const SLV0_FIFO_EN: u8 = 0;
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 /// Register 55: INT Pin / Bypass Enable Configuration
@@ -228,6 +278,28 @@ impl MOT_DETECT_CONTROL {
     pub const MOT_COUNT: BitBlock = BitBlock { bit: 1, length: 2};
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+/// Register 106: User Control
+pub struct USER_CTRL;
+
+impl USER_CTRL {
+    /// Base Address
+    pub const ADDR: u8 = 0x6a;
+    /// enable FIFO operations
+    pub const FIFO_EN: u8 = 6;
+    /// enable I2C Master Mode
+    pub const I2C_MST_EN: u8 = 5;
+    /// disable primary I2C interface (MPU-6000 only)
+    pub const I2C_IF_DIS: u8 = 4;
+    /// reset FIFO buffer while FIFO_EN is 0, clears automatically
+    pub const FIFO_RESET: u8 = 2;
+    /// reset I2C Master while I2C_MST_EN is 0, clears automatically
+    pub const I2C_MST_RESET: u8 = 1;
+    /// reset signal paths and sensor registers
+    pub const SIG_COND_RESET: u8 = 0;
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 /// Register 107: Power Management 1
@@ -286,6 +358,19 @@ pub enum LP_WAKE_CTRL {
     _10,
 }
 
+impl From<u8> for LP_WAKE_CTRL {
+    fn from(rate: u8) -> Self
+    {
+        match rate {
+            0 => LP_WAKE_CTRL::_1P25,
+            1 => LP_WAKE_CTRL::_2P5,
+            2 => LP_WAKE_CTRL::_5,
+            3 => LP_WAKE_CTRL::_10,
+            _ => LP_WAKE_CTRL::_1P25
+        }
+    }
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 /// Accelerometer High Pass Filter Values
@@ -412,6 +497,7 @@ impl From<u8> for AccelRange {
 
 impl AccelRange {
     // Converts accelerometer range to correction/scaling factor, see register sheet
+    #[cfg(any(target_os = "espidf", feature = "async"))]
     pub(crate) fn sensitivity(&self) -> f32 {
         match &self {
             AccelRange::G2 => ACCEL_SENS.0,
@@ -424,6 +510,7 @@ impl AccelRange {
 
 impl GyroRange {
     // Converts gyro range to correction/scaling factor, see register sheet
+    #[cfg(any(target_os = "espidf", feature = "async"))]
     pub(crate) fn sensitivity(&self) -> f32 {
         match &self {
             GyroRange::D250 => GYRO_SENS.0,
@@ -0,0 +1,164 @@
+//! FIFO configuration and frame decoding
+//!
+//! The MPU6050 writes one frame per sample into its 1024 byte FIFO. A frame holds the enabled
+//! sensor registers in register order: accel x/y/z, temperature, gyro x/y/z, 2 bytes each,
+//! big endian.
+
+use crate::device::FIFO_EN;
+use nalgebra::Vector3;
+
+/// Sensors written into the FIFO on every sample
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FifoConfig {
+    /// accel x, y and z
+    pub accel: bool,
+    /// temperature
+    pub temp: bool,
+    /// gyro x, y and z
+    pub gyro: bool,
+}
+
+impl FifoConfig {
+    /// Accel and gyro, 12 bytes per frame
+    pub const fn accel_gyro() -> Self {
+        FifoConfig {
+            accel: true,
+            temp: false,
+            gyro: true,
+        }
+    }
+
+    /// No sensor enabled, the FIFO is disabled
+    pub fn is_empty(&self) -> bool {
+        !(self.accel || self.temp || self.gyro)
+    }
+
+    /// Bytes per frame
+    pub fn frame_size(&self) -> usize {
+        self.accel as usize * 6 + self.temp as usize * 2 + self.gyro as usize * 6
+    }
+
+    /// Value of the FIFO_EN register
+    pub fn to_register(&self) -> u8 {
+        let gyro =
+            (1 << FIFO_EN::XG_FIFO_EN) | (1 << FIFO_EN::YG_FIFO_EN) | (1 << FIFO_EN::ZG_FIFO_EN);
+        (self.temp as u8) << FIFO_EN::TEMP_FIFO_EN
+            | (self.accel as u8) << FIFO_EN::ACCEL_FIFO_EN
+            | if self.gyro { gyro } else { 0 }
+    }
+
+    /// Reads back the FIFO_EN register, gyro counts as enabled if all three axes are
+    pub fn from_register(byte: u8) -> Self {
+        let bit = |n: u8| byte & (1 << n) != 0;
+        FifoConfig {
+            accel: bit(FIFO_EN::ACCEL_FIFO_EN),
+            temp: bit(FIFO_EN::TEMP_FIFO_EN),
+            gyro: bit(FIFO_EN::XG_FIFO_EN) && bit(FIFO_EN::YG_FIFO_EN) && bit(FIFO_EN::ZG_FIFO_EN),
+        }
+    }
+
+    /// Decodes one frame of `frame_size` bytes, without calibration
+    #[cfg(any(target_os = "espidf", feature = "async", test))]
+    pub(crate) fn decode(
+        &self,
+        frame: &[u8],
+        acc_sensitivity: f32,
+        gyro_sensitivity: f32,
+    ) -> FifoSample {
+        use crate::device::{TEMP_OFFSET, TEMP_SENSITIVITY};
+        use crate::PI_180;
+
+        fn vector(words: &mut impl Iterator<Item = f32>) -> Vector3<f32> {
+            let mut next = || words.next().unwrap_or_default();
+            Vector3::new(next(), next(), next())
+        }
+
+        let mut words = frame
+            .chunks_exact(2)
+            .map(|word| i16::from_be_bytes([word[0], word[1]]) as f32);
+
+        let acc = self.accel.then(|| vector(&mut words) / acc_sensitivity);
+        let temp = self
+            .temp
+            .then(|| words.next().unwrap_or_default() / TEMP_SENSITIVITY + TEMP_OFFSET);
+        let gyro = self
+            .gyro
+            .then(|| vector(&mut words) * (PI_180 / gyro_sensitivity));
+
+        FifoSample { acc, temp, gyro }
+    }
+}
+
+/// One decoded FIFO frame, sensors not enabled in `FifoConfig` are `None`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FifoSample {
+    /// Accelerometer reading in g
+    pub acc: Option<Vector3<f32>>,
+    /// Temperature in degrees celcius
+    pub temp: Option<f32>,
+    /// Gyro reading in rad/s
+    pub gyro: Option<Vector3<f32>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::TEMP_OFFSET;
+    use crate::PI_180;
+
+    #[test]
+    fn frame_size_test() {
+        assert_eq!(FifoConfig::default().frame_size(), 0);
+        assert!(FifoConfig::default().is_empty());
+        assert_eq!(FifoConfig::accel_gyro().frame_size(), 12);
+        let all = FifoConfig {
+            accel: true,
+            temp: true,
+            gyro: true,
+        };
+        assert_eq!(all.frame_size(), 14);
+    }
+
+    #[test]
+    fn register_test() {
+        assert_eq!(FifoConfig::accel_gyro().to_register(), 0b0111_1000);
+        let temp = FifoConfig {
+            temp: true,
+            ..Default::default()
+        };
+        assert_eq!(temp.to_register(), 0b1000_0000);
+        assert_eq!(FifoConfig::from_register(0b1111_1000).frame_size(), 14);
+        // a single gyro axis is not decoded
+        assert_eq!(
+            FifoConfig::from_register(0b0100_0000),
+            FifoConfig::default()
+        );
+    }
+
+    #[test]
+    fn decode_test() {
+        let all = FifoConfig {
+            accel: true,
+            temp: true,
+            gyro: true,
+        };
+        let frame = [
+            0x40, 0x00, 0xC0, 0x00, 0x00, 0x00, // accel 1g, -1g, 0g
+            0x00, 0x00, // temp
+            0x00, 0x83, 0xFF, 0x7D, 0x00, 0x00, // gyro 1, -1, 0 deg/s
+        ];
+        let sample = all.decode(&frame, 16384., 131.);
+        assert_eq!(sample.acc, Some(Vector3::new(1., -1., 0.)));
+        assert_eq!(sample.temp, Some(TEMP_OFFSET));
+        let gyro = sample.gyro.unwrap();
+        assert!((gyro.x - PI_180).abs() < 1e-6);
+        assert!((gyro.y + PI_180).abs() < 1e-6);
+
+        // without temperature the gyro follows the accel
+        let sample = FifoConfig::accel_gyro().decode(&frame[..12], 16384., 131.);
+        assert_eq!(sample.temp, None);
+        let gyro = sample.gyro.unwrap();
+        assert_eq!(gyro.x, 0.);
+        assert!((gyro.y - PI_180).abs() < 1e-6);
+    }
+}
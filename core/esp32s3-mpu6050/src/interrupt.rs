@@ -0,0 +1,52 @@
+//! Data-ready interrupt
+//!
+//! The MPU6050 INT pin raises a GPIO interrupt on the ESP32, the ISR notifies the task that
+//! created `DataReady`, so it can block until a new sample (or FIFO batch) is available
+//! instead of polling the sensor registers.
+
+use std::num::NonZeroU32;
+
+use esp_idf_hal::{
+    gpio::{AnyInputPin, Input, InterruptType, PinDriver},
+    sys::{EspError, TickType_t},
+    task::notification::Notification,
+};
+
+/// Waits for the MPU6050 INT pin, configured with `Mpu6050::enable_data_ready_interrupt`
+pub struct DataReady<'d> {
+    // dropped first, unsubscribes the ISR before the notification is freed
+    pin: PinDriver<'d, AnyInputPin, Input>,
+    notification: Notification,
+}
+
+impl<'d> DataReady<'d> {
+    /// Subscribes to rising edges of the INT pin (active high, push-pull).
+    /// Must be created on the consumer task, which is the task notified by the ISR
+    pub fn new(pin: AnyInputPin) -> Result<Self, EspError> {
+        let mut pin = PinDriver::input(pin)?;
+        pin.set_interrupt_type(InterruptType::PosEdge)?;
+
+        let notification = Notification::new();
+        let notifier = notification.notifier();
+        // Safety: the `Notification` object is dropped after the pin, which ends the subscription
+        unsafe {
+            pin.subscribe(move || {
+                notifier.notify_and_yield(NonZeroU32::new(1).unwrap());
+            })?;
+        }
+        pin.enable_interrupt()?;
+
+        Ok(DataReady { pin, notification })
+    }
+
+    /// Blocks the current task until the data-ready interrupt fired or `timeout` ticks passed,
+    /// e.g. `esp_idf_hal::delay::BLOCK`. Returns false on timeout
+    pub fn wait(&mut self, timeout: TickType_t) -> Result<bool, EspError> {
+        if self.notification.wait(timeout).is_none() {
+            return Ok(false);
+        }
+        // the GPIO driver disables the interrupt after it fired
+        self.pin.enable_interrupt()?;
+        Ok(true)
+    }
+}
@@ -1,15 +1,41 @@
 //! # Mpu6050 sensor driver.
 
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(any(target_os = "espidf", feature = "async"))]
 mod bits;
+pub mod calibration;
+#[cfg(any(target_os = "espidf", feature = "async"))]
+mod codec;
 pub mod device;
-
+pub mod fifo;
+#[cfg(target_os = "espidf")]
+pub mod interrupt;
+pub mod motion;
+
+pub use crate::calibration::Calibration;
+pub use crate::fifo::{FifoConfig, FifoSample};
+pub use crate::motion::{MotionConfig, MotionEngine, MotionEvent};
+/// Orientation estimation, shared with the `mpu6050` crate
+pub use mpu6050::fusion;
+/// Digital low pass filter bandwidth, shared with the `mpu6050` crate
+pub use mpu6050::sample_rate::DlpfBandwidth;
+
+#[cfg(target_os = "espidf")]
 use crate::device::*;
+#[cfg(target_os = "espidf")]
+use crate::fusion::{Fusion, Quaternion};
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::{
     delay::{FreeRtos, BLOCK},
     i2c::I2cDriver,
     sys::EspError,
 };
+#[cfg(target_os = "espidf")]
 use libm::{atan2f, powf, sqrtf};
+#[cfg(target_os = "espidf")]
+use mpu6050::sample_rate::{output_data_rate, sample_rate_divider};
+#[cfg(target_os = "espidf")]
 use nalgebra::{Vector2, Vector3};
 
 /// PI, f32
@@ -18,7 +44,12 @@ pub const PI: f32 = core::f32::consts::PI;
 /// PI / 180, for conversion to radians
 pub const PI_180: f32 = PI / 180.0;
 
+/// Delay between two samples in `Mpu6050::calibrate`
+#[cfg(any(target_os = "espidf", feature = "async"))]
+const CALIBRATION_SAMPLE_DELAY_MS: u32 = 2;
+
 /// All possible errors in this crate
+#[cfg(target_os = "espidf")]
 #[derive(Debug)]
 pub enum Mpu6050Error {
     /// I2C bus error
@@ -26,39 +57,50 @@ pub enum Mpu6050Error {
 
     /// Invalid chip ID was read
     InvalidChipId(u8),
+
+    /// FIFO buffer overflowed and was reset, samples were lost
+    FifoOverflow,
 }
 
+#[cfg(target_os = "espidf")]
 impl From<EspError> for Mpu6050Error {
     fn from(e: EspError) -> Self {
         Mpu6050Error::I2c(e)
     }
 }
+#[cfg(target_os = "espidf")]
 impl std::fmt::Display for Mpu6050Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Mpu6050Error::I2c(e) => write!(f, "Esp Error: {}", e),
             Mpu6050Error::InvalidChipId(e) => write!(f, "Invalid chip ID was read error: {}", e),
+            Mpu6050Error::FifoOverflow => write!(f, "FIFO overflow, samples were lost"),
         }
     }
 }
+#[cfg(target_os = "espidf")]
 impl std::error::Error for Mpu6050Error {}
 
 /// Handles all operations on/with Mpu6050
+#[cfg(target_os = "espidf")]
 pub struct Mpu6050<'d> {
     i2c: I2cDriver<'d>,
     slave_addr: u8,
     acc_sensitivity: f32,
     gyro_sensitivity: f32,
+    calibration: Calibration,
 }
 
+#[cfg(target_os = "espidf")]
 impl<'d> Mpu6050<'d> {
-    /// Side effect free constructor with default sensitivies, no calibration
+    /// Side effect free constructor with default sensitivies, no calibration (see `calibrate`)
     pub fn new(i2c: I2cDriver<'d>) -> Self {
         Mpu6050 {
             i2c,
             slave_addr: DEFAULT_SLAVE_ADDR,
             acc_sensitivity: ACCEL_SENS.0,
             gyro_sensitivity: GYRO_SENS.0,
+            calibration: Calibration::default(),
         }
     }
 
@@ -69,6 +111,7 @@ impl<'d> Mpu6050<'d> {
             slave_addr: DEFAULT_SLAVE_ADDR,
             acc_sensitivity: arange.sensitivity(),
             gyro_sensitivity: grange.sensitivity(),
+            calibration: Calibration::default(),
         }
     }
 
@@ -79,6 +122,7 @@ impl<'d> Mpu6050<'d> {
             slave_addr,
             acc_sensitivity: ACCEL_SENS.0,
             gyro_sensitivity: GYRO_SENS.0,
+            calibration: Calibration::default(),
         }
     }
 
@@ -94,6 +138,7 @@ impl<'d> Mpu6050<'d> {
             slave_addr,
             acc_sensitivity: arange.sensitivity(),
             gyro_sensitivity: grange.sensitivity(),
+            calibration: Calibration::default(),
         }
     }
 
@@ -161,19 +206,184 @@ impl<'d> Mpu6050<'d> {
         self.write_byte(0x6B, 0x00)?;
         // optional? self.write_byte(0x68, 0x07)?; // Reset all internal signal paths in the MPU-6050 by writing 0x07 to register 0x68;
         self.write_byte(INT_PIN_CFG::ADDR, 0x20)?; //write register 0x37 to select how to use the interrupt pin. For an active high, push-pull signal that stays until register (decimal) 58 is read, write 0x20.
-        self.write_byte(ACCEL_CONFIG::ADDR, 0x01)?; //Write register 28 (==0x1C) to set the Digital High Pass Filter, bits 3:0. For example set it to 0x01 for 5Hz. (These 3 bits are grey in the data sheet, but they are used! Leaving them 0 means the filter always outputs 0.)
-        self.write_byte(MOT_THR, 10)?; //Write the desired Motion threshold to register 0x1F (For example, write decimal 20).
-        self.write_byte(MOT_DUR, 40)?; //Set motion detect duration to 1  ms; LSB is 1 ms @ 1 kHz rate
-        self.write_byte(0x69, 0x15)?; //to register 0x69, write the motion detection decrement and a few other settings (for example write 0x15 to set both free-fall and motion decrements to 1 and accelerometer start-up delay to 5ms total by adding 1ms. )
-        self.write_byte(INT_ENABLE::ADDR, 0x40)?; //write register 0x38, bit 6 (0x40), to enable motion detection interrupt.
+        self.set_motion_config(&MotionConfig::default())
+    }
+
+    /// Configures the motion, free fall and zero motion detectors and enables their interrupts.
+    /// Sets the accel high pass filter to 5Hz, the detectors compare its output (leaving it
+    /// in reset means the filter always outputs 0)
+    pub fn set_motion_config(&mut self, config: &MotionConfig) -> Result<(), Mpu6050Error> {
+        self.set_accel_hpf(ACCEL_HPF::_5)?;
+
+        for (reg, value) in codec::motion_registers(config) {
+            self.write_byte(reg, value)?;
+        }
+
+        let enable = self.read_byte(INT_ENABLE::ADDR)?;
+        self.write_byte(INT_ENABLE::ADDR, codec::motion_int_enable(enable, config))
+    }
+
+    /// Reads the motion interrupts and one accelerometer sample and passes them to `engine`,
+    /// which reports the resulting events to `on_event`. Call once per sample period of `engine`.
+    /// NOTE: reading INT_STATUS clears all interrupt status bits
+    pub fn poll_motion_events(
+        &mut self,
+        engine: &mut MotionEngine,
+        mut on_event: impl FnMut(MotionEvent),
+    ) -> Result<(), Mpu6050Error> {
+        let int_status = self.read_byte(INT_STATUS::ADDR)?;
+        let mot_detect_status = self.read_byte(MOT_DETECT_STATUS::ADDR)?;
+        engine.interrupts(int_status, mot_detect_status, &mut on_event);
+
+        let acc = self.get_acc()?;
+        engine.update(acc, &mut on_event);
         Ok(())
     }
 
+    /// Low power accel only mode: the chip sleeps and wakes up at `rate` to take one accel
+    /// sample, e.g. to wait for a motion interrupt. The gyros and the temperature sensor are
+    /// switched off. `None` returns to normal operation with all sensors enabled
+    pub fn set_low_power_wake(&mut self, rate: Option<LP_WAKE_CTRL>) -> Result<(), Mpu6050Error> {
+        let pwr_mgmt_1 = self.read_byte(PWR_MGMT_1::ADDR)?;
+        let pwr_mgmt_2 = self.read_byte(PWR_MGMT_2::ADDR)?;
+        let (pwr_mgmt_1, pwr_mgmt_2) = codec::low_power_wake(pwr_mgmt_1, pwr_mgmt_2, rate);
+        self.write_byte(PWR_MGMT_2::ADDR, pwr_mgmt_2)?;
+        self.write_byte(PWR_MGMT_1::ADDR, pwr_mgmt_1)
+    }
+
+    /// get the wake up rate of the low power mode, `None` if the chip is not cycling
+    pub fn get_low_power_wake(&mut self) -> Result<Option<LP_WAKE_CTRL>, Mpu6050Error> {
+        let pwr_mgmt_1 = self.read_byte(PWR_MGMT_1::ADDR)?;
+        let pwr_mgmt_2 = self.read_byte(PWR_MGMT_2::ADDR)?;
+        Ok(codec::low_power_wake_rate(pwr_mgmt_1, pwr_mgmt_2))
+    }
+
     /// get whether or not motion has been detected (INT_STATUS, MOT_INT)
     pub fn get_motion_detected(&mut self) -> Result<bool, Mpu6050Error> {
         Ok(self.read_bit(INT_STATUS::ADDR, INT_STATUS::MOT_INT)? != 0)
     }
 
+    /// Configures the INT pin as active high, push-pull, with a 50us pulse per event, and
+    /// enables or disables the data-ready interrupt. See `interrupt::DataReady`
+    pub fn enable_data_ready_interrupt(&mut self, enable: bool) -> Result<(), Mpu6050Error> {
+        let int_pin_cfg = self.read_byte(INT_PIN_CFG::ADDR)?;
+        self.write_byte(
+            INT_PIN_CFG::ADDR,
+            codec::data_ready_int_pin_cfg(int_pin_cfg),
+        )?;
+        self.write_bit(INT_ENABLE::ADDR, INT_ENABLE::DATA_RDY_EN, enable)
+    }
+
+    /// get whether new sensor data is ready (INT_STATUS, DATA_RDY_INT)
+    /// NOTE: reading INT_STATUS clears all interrupt status bits
+    pub fn get_data_ready(&mut self) -> Result<bool, Mpu6050Error> {
+        Ok(self.read_bit(INT_STATUS::ADDR, INT_STATUS::DATA_RDY_INT)? != 0)
+    }
+
+    /// Selects the sensors written into the FIFO on every sample. Enables the FIFO if any
+    /// sensor is selected and resets it, so the buffer starts on a frame boundary
+    pub fn set_fifo_config(&mut self, config: FifoConfig) -> Result<(), Mpu6050Error> {
+        let user_ctrl = self.read_byte(USER_CTRL::ADDR)?;
+        let [disabled, reset, enabled] = codec::fifo_reset(user_ctrl, !config.is_empty());
+        self.write_byte(USER_CTRL::ADDR, disabled)?;
+        self.write_byte(FIFO_EN::ADDR, config.to_register())?;
+        self.write_byte(USER_CTRL::ADDR, reset)?;
+        self.write_byte(USER_CTRL::ADDR, enabled)
+    }
+
+    /// get sensors written into the FIFO
+    pub fn get_fifo_config(&mut self) -> Result<FifoConfig, Mpu6050Error> {
+        Ok(FifoConfig::from_register(self.read_byte(FIFO_EN::ADDR)?))
+    }
+
+    /// Discards the FIFO content, keeps the configuration
+    pub fn reset_fifo(&mut self) -> Result<(), Mpu6050Error> {
+        let user_ctrl = self.read_byte(USER_CTRL::ADDR)?;
+        let enabled = bits::get_bit(user_ctrl, USER_CTRL::FIFO_EN) != 0;
+        for value in codec::fifo_reset(user_ctrl, enabled) {
+            self.write_byte(USER_CTRL::ADDR, value)?;
+        }
+        Ok(())
+    }
+
+    /// Number of bytes in the FIFO
+    pub fn fifo_count(&mut self) -> Result<u16, Mpu6050Error> {
+        let mut buf: [u8; 2] = [0; 2];
+        self.read_bytes(FIFO_COUNT_H, &mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    /// get whether the FIFO overflowed (INT_STATUS, FIFO_OFLOW_INT)
+    /// NOTE: reading INT_STATUS clears all interrupt status bits
+    pub fn get_fifo_overflow(&mut self) -> Result<bool, Mpu6050Error> {
+        Ok(self.read_bit(INT_STATUS::ADDR, INT_STATUS::FIFO_OFLOW_INT)? != 0)
+    }
+
+    /// Reads up to `max_samples` complete frames from the FIFO in one burst, decoded with the
+    /// current sensitivities and calibration. Incomplete frames stay in the FIFO.
+    /// A full FIFO has overwritten old data and lost the frame boundary, it is reset and
+    /// `Mpu6050Error::FifoOverflow` is returned
+    pub fn read_fifo(&mut self, max_samples: usize) -> Result<Vec<FifoSample>, Mpu6050Error> {
+        let config = self.get_fifo_config()?;
+        let frame_size = config.frame_size();
+        if frame_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let count = self.fifo_count()?;
+        let frames = match codec::fifo_frames(count, frame_size, max_samples) {
+            Some(frames) => frames,
+            None => {
+                self.reset_fifo()?;
+                return Err(Mpu6050Error::FifoOverflow);
+            }
+        };
+        if frames == 0 {
+            return Ok(Vec::new());
+        }
+        let mut buf = vec![0; frames * frame_size];
+        self.read_bytes(FIFO_R_W, &mut buf)?;
+
+        Ok(codec::fifo_samples(
+            &config,
+            &buf,
+            self.acc_sensitivity,
+            self.gyro_sensitivity,
+            &self.calibration,
+        ))
+    }
+
+    /// Set the digital low pass filter. Changes the gyro output rate between 8kHz (`Hz260`) and
+    /// 1kHz, so set the sample rate again afterwards
+    pub fn set_dlpf(&mut self, dlpf: DlpfBandwidth) -> Result<(), Mpu6050Error> {
+        self.write_bits(
+            CONFIG::ADDR,
+            CONFIG::DLPF_CFG.bit,
+            CONFIG::DLPF_CFG.length,
+            dlpf.to_register(),
+        )
+    }
+
+    /// get digital low pass filter
+    pub fn get_dlpf(&mut self) -> Result<DlpfBandwidth, Mpu6050Error> {
+        let byte = self.read_bits(CONFIG::ADDR, CONFIG::DLPF_CFG.bit, CONFIG::DLPF_CFG.length)?;
+        Ok(DlpfBandwidth::from_register(byte))
+    }
+
+    /// Set the sample rate in Hz, the divider is computed from the gyro output rate of the
+    /// current DLPF setting and rounded to the nearest achievable rate
+    pub fn set_sample_rate(&mut self, hz: u32) -> Result<(), Mpu6050Error> {
+        let dlpf = self.get_dlpf()?;
+        self.write_byte(SMPLRT_DIV, sample_rate_divider(dlpf, hz))
+    }
+
+    /// Effective output data rate in Hz, also the rate of FIFO frames and data-ready interrupts
+    pub fn get_sample_rate(&mut self) -> Result<f32, Mpu6050Error> {
+        let dlpf = self.get_dlpf()?;
+        let divider = self.read_byte(SMPLRT_DIV)?;
+        Ok(output_data_rate(dlpf, divider))
+    }
+
     /// set accel high pass filter mode
     pub fn set_accel_hpf(&mut self, mode: ACCEL_HPF) -> Result<(), Mpu6050Error> {
         self.write_bits(
@@ -307,6 +517,7 @@ impl<'d> Mpu6050<'d> {
 
     /// Roll and pitch estimation from raw accelerometer readings
     /// NOTE: no yaw! no magnetometer present on MPU6050
+    /// Only valid while the sensor is not accelerating, see `update_fusion`
     /// https://www.nxp.com/docs/en/application-note/AN3461.pdf equation 28, 29
     pub fn get_acc_angles(&mut self) -> Result<Vector2<f32>, Mpu6050Error> {
         let acc = self.get_acc()?;
@@ -317,57 +528,108 @@ impl<'d> Mpu6050<'d> {
         ))
     }
 
-    /// Converts 2 bytes number in 2 compliment
-    /// TODO i16?! whats 0x8000?!
-    fn read_word_2c(&self, byte: &[u8]) -> i32 {
-        let high: i32 = byte[0] as i32;
-        let low: i32 = byte[1] as i32;
-        let mut word: i32 = (high << 8) + low;
+    /// Uncalibrated accelerometer readings in g
+    fn read_acc(&mut self) -> Result<Vector3<f32>, Mpu6050Error> {
+        let mut buf: [u8; 6] = [0; 6];
+        self.read_bytes(ACC_REGX_H, &mut buf)?;
+        Ok(codec::acc(&buf, self.acc_sensitivity))
+    }
 
-        if word >= 0x8000 {
-            word = -((65535 - word) + 1);
-        }
+    /// Uncalibrated gyro readings in rad/s
+    fn read_gyro(&mut self) -> Result<Vector3<f32>, Mpu6050Error> {
+        let mut buf: [u8; 6] = [0; 6];
+        self.read_bytes(GYRO_REGX_H, &mut buf)?;
+        Ok(codec::gyro(&buf, self.gyro_sensitivity))
+    }
 
-        word
+    /// Accelerometer readings in g, with the calibration offset removed
+    pub fn get_acc(&mut self) -> Result<Vector3<f32>, Mpu6050Error> {
+        let acc = self.read_acc()?;
+        Ok(self.calibration.apply_acc(acc))
     }
 
-    /// Reads rotation (gyro/acc) from specified register
-    fn read_rot(&mut self, reg: u8) -> Result<Vector3<f32>, Mpu6050Error> {
-        let mut buf: [u8; 6] = [0; 6];
-        self.read_bytes(reg, &mut buf)?;
+    /// Gyro readings in rad/s, with the calibration offset removed
+    pub fn get_gyro(&mut self) -> Result<Vector3<f32>, Mpu6050Error> {
+        let gyro = self.read_gyro()?;
+        Ok(self.calibration.apply_gyro(gyro))
+    }
 
-        Ok(Vector3::<f32>::new(
-            self.read_word_2c(&buf[0..2]) as f32,
-            self.read_word_2c(&buf[2..4]) as f32,
-            self.read_word_2c(&buf[4..6]) as f32,
-        ))
+    /// Reads accel and gyro and feeds them into the orientation estimator.
+    /// Call once per sample period of `fusion`
+    pub fn update_fusion(&mut self, fusion: &mut Fusion) -> Result<Quaternion, Mpu6050Error> {
+        let acc = self.get_acc()?;
+        let gyro = self.get_gyro()?;
+        Ok(fusion.update(acc.into(), gyro.into()))
     }
 
-    /// Accelerometer readings in g
-    pub fn get_acc(&mut self) -> Result<Vector3<f32>, Mpu6050Error> {
-        let mut acc = self.read_rot(ACC_REGX_H)?;
-        acc /= self.acc_sensitivity;
+    /// Calibration applied in `get_acc` and `get_gyro`
+    pub fn get_calibration(&self) -> Calibration {
+        self.calibration
+    }
 
-        Ok(acc)
+    /// Set the calibration applied in `get_acc` and `get_gyro`, e.g. one loaded from flash
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
     }
 
-    /// Gyro readings in rad/s
-    pub fn get_gyro(&mut self) -> Result<Vector3<f32>, Mpu6050Error> {
-        let mut gyro = self.read_rot(GYRO_REGX_H)?;
+    /// Measures the accel and gyro offsets by averaging `samples` readings.
+    /// The sensor must lie still with one axis pointing up or down while this runs.
+    /// The result replaces the current calibration and is returned so it can be saved.
+    pub fn calibrate(&mut self, samples: u16) -> Result<Calibration, Mpu6050Error> {
+        let samples = samples.max(1);
+        let mut acc_sum = Vector3::<f32>::zeros();
+        let mut gyro_sum = Vector3::<f32>::zeros();
+
+        for _ in 0..samples {
+            acc_sum += self.read_acc()?;
+            gyro_sum += self.read_gyro()?;
+            FreeRtos::delay_ms(CALIBRATION_SAMPLE_DELAY_MS);
+        }
+
+        let calibration =
+            Calibration::from_stationary(acc_sum / samples as f32, gyro_sum / samples as f32);
+        self.calibration = calibration;
+        Ok(calibration)
+    }
+
+    /// Moves the current calibration into the chip's offset registers, so the raw readings
+    /// (and the FIFO) are corrected as well, then clears the software calibration.
+    /// The registers are adjusted relative to their current values and lost on power off,
+    /// keep the `Calibration` returned by `calibrate` to restore them.
+    pub fn write_offset_registers(&mut self) -> Result<(), Mpu6050Error> {
+        let mut current = [0; 6];
+        for (value, reg) in current.iter_mut().zip(codec::OFFSET_REGISTERS) {
+            *value = self.read_offset(reg)?;
+        }
+
+        let values = codec::offset_registers(&self.calibration, current);
+        for (&value, reg) in values.iter().zip(codec::OFFSET_REGISTERS) {
+            self.write_offset(reg, value)?;
+        }
+
+        self.calibration = Calibration::default();
+        Ok(())
+    }
 
-        gyro *= PI_180 / self.gyro_sensitivity;
+    /// Reads a 16 bit offset register
+    fn read_offset(&mut self, reg: u8) -> Result<i16, Mpu6050Error> {
+        let mut buf: [u8; 2] = [0; 2];
+        self.read_bytes(reg, &mut buf)?;
+        Ok(i16::from_be_bytes(buf))
+    }
 
-        Ok(gyro)
+    /// Writes a 16 bit offset register
+    fn write_offset(&mut self, reg: u8, value: i16) -> Result<(), Mpu6050Error> {
+        let [high, low] = value.to_be_bytes();
+        self.write_byte(reg, high)?;
+        self.write_byte(reg + 1, low)
     }
 
     /// Sensor Temp in degrees celcius
     pub fn get_temp(&mut self) -> Result<f32, Mpu6050Error> {
         let mut buf: [u8; 2] = [0; 2];
         self.read_bytes(TEMP_OUT_H, &mut buf)?;
-        let raw_temp = self.read_word_2c(&buf[0..2]) as f32;
-
-        // According to revision 4.2
-        Ok((raw_temp / TEMP_SENSITIVITY) + TEMP_OFFSET)
+        Ok(codec::temp(buf))
     }
 
     /// Writes byte to register
@@ -0,0 +1,677 @@
+//! Motion events
+//!
+//! Motion, free fall and zero motion are detected by the MPU6050 itself and reported through
+//! INT_STATUS and MOT_DETECT_STATUS (register map rev 3.2, the registers are undocumented in later
+//! revisions). Taps, double taps and orientation changes are detected in software from the
+//! accelerometer samples fed into `MotionEngine::update`.
+//!
+//! Events are delivered to a callback, use `channel_sink` to forward them into a channel.
+
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use crate::device::{INT_STATUS, MOT_DETECT_STATUS};
+use nalgebra::Vector3;
+
+/// Threshold register LSB of the motion, free fall and zero motion detectors, in mg
+#[cfg(any(target_os = "espidf", feature = "async", test))]
+const THRESHOLD_LSB_MG: u16 = 2;
+/// Duration register LSB of the zero motion detector, in ms
+#[cfg(any(target_os = "espidf", feature = "async", test))]
+const ZERO_MOTION_DURATION_LSB_MS: u16 = 64;
+
+/// Threshold and duration of one hardware detector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threshold {
+    /// Acceleration threshold in mg, 2mg resolution
+    pub threshold_mg: u16,
+    /// Time the condition has to hold in ms, 1ms resolution (64ms for zero motion)
+    pub duration_ms: u16,
+}
+
+impl Threshold {
+    /// Value of the threshold register
+    #[cfg(any(target_os = "espidf", feature = "async", test))]
+    pub(crate) fn threshold_register(&self) -> u8 {
+        (self.threshold_mg / THRESHOLD_LSB_MG).min(u8::MAX as u16) as u8
+    }
+
+    /// Value of the motion and free fall duration registers
+    #[cfg(any(target_os = "espidf", feature = "async", test))]
+    pub(crate) fn duration_register(&self) -> u8 {
+        self.duration_ms.min(u8::MAX as u16) as u8
+    }
+
+    /// Value of the zero motion duration register
+    #[cfg(any(target_os = "espidf", feature = "async", test))]
+    pub(crate) fn zero_motion_duration_register(&self) -> u8 {
+        (self.duration_ms / ZERO_MOTION_DURATION_LSB_MS).min(u8::MAX as u16) as u8
+    }
+}
+
+/// How fast the motion and free fall counters decrement while the condition is not met
+/// (MOT_DETECT_CONTROL, FF_COUNT and MOT_COUNT)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CounterDecrement {
+    /// Reset the counter
+    Reset = 0,
+    /// Decrement by 1
+    #[default]
+    One = 1,
+    /// Decrement by 2
+    Two = 2,
+    /// Decrement by 4
+    Four = 3,
+}
+
+/// Configuration of the hardware detectors, `None` disables the detector and its interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionConfig {
+    /// Acceleration above the threshold on any axis
+    pub motion: Option<Threshold>,
+    /// Acceleration below the threshold on all axes
+    pub free_fall: Option<Threshold>,
+    /// Acceleration below the threshold on all axes, reported when it starts and when it ends
+    pub zero_motion: Option<Threshold>,
+    /// Counter decrement of the motion and free fall detectors
+    pub decrement: CounterDecrement,
+}
+
+impl Default for MotionConfig {
+    /// Motion detection only, 20mg for 40ms
+    fn default() -> Self {
+        MotionConfig {
+            motion: Some(Threshold {
+                threshold_mg: 20,
+                duration_ms: 40,
+            }),
+            free_fall: None,
+            zero_motion: None,
+            decrement: CounterDecrement::One,
+        }
+    }
+}
+
+impl MotionConfig {
+    /// Value of the MOT_DETECT_CONTROL register, with 1ms extra accelerometer power on delay
+    #[cfg(any(target_os = "espidf", feature = "async", test))]
+    pub(crate) fn detect_control_register(&self) -> u8 {
+        let decrement = self.decrement as u8;
+        (1 << 4) | (decrement << 2) | decrement
+    }
+}
+
+/// Axes and directions that triggered a motion interrupt (MOT_DETECT_STATUS)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MotionAxes {
+    /// positive x
+    pub x_pos: bool,
+    /// negative x
+    pub x_neg: bool,
+    /// positive y
+    pub y_pos: bool,
+    /// negative y
+    pub y_neg: bool,
+    /// positive z
+    pub z_pos: bool,
+    /// negative z
+    pub z_neg: bool,
+}
+
+impl MotionAxes {
+    /// Decodes the MOT_DETECT_STATUS register
+    pub fn from_register(byte: u8) -> Self {
+        let bit = |n: u8| byte & (1 << n) != 0;
+        MotionAxes {
+            x_pos: bit(MOT_DETECT_STATUS::MOT_XPOS),
+            x_neg: bit(MOT_DETECT_STATUS::MOT_XNEG),
+            y_pos: bit(MOT_DETECT_STATUS::MOT_YPOS),
+            y_neg: bit(MOT_DETECT_STATUS::MOT_YNEG),
+            z_pos: bit(MOT_DETECT_STATUS::MOT_ZPOS),
+            z_neg: bit(MOT_DETECT_STATUS::MOT_ZNEG),
+        }
+    }
+}
+
+/// Sensor axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// x axis
+    X,
+    /// y axis
+    Y,
+    /// z axis
+    Z,
+}
+
+/// Which side of the sensor points up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// +y up
+    PortraitUp,
+    /// -y up
+    PortraitDown,
+    /// +x up
+    LandscapeLeft,
+    /// -x up
+    LandscapeRight,
+    /// +z up
+    FaceUp,
+    /// -z up
+    FaceDown,
+}
+
+/// Events reported by `MotionEngine`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionEvent {
+    /// Hardware motion interrupt
+    Motion(MotionAxes),
+    /// Hardware free fall interrupt
+    FreeFall,
+    /// Hardware zero motion interrupt, the sensor came to rest
+    ZeroMotion,
+    /// Hardware zero motion interrupt, the sensor moves again
+    MotionResumed,
+    /// Single tap, reported once the double tap window passed
+    Tap {
+        /// axis with the largest acceleration spike
+        axis: Axis,
+        /// direction of the spike
+        positive: bool,
+    },
+    /// Two taps within the double tap window
+    DoubleTap {
+        /// axis with the largest acceleration spike of the second tap
+        axis: Axis,
+        /// direction of the spike
+        positive: bool,
+    },
+    /// The orientation changed and was stable for `OrientationConfig::hold`
+    Orientation(Orientation),
+}
+
+/// Forwards events into a channel, e.g. to a consumer thread.
+/// Events are dropped once the receiver is gone
+pub fn channel_sink(sender: Sender<MotionEvent>) -> impl FnMut(MotionEvent) {
+    move |event| {
+        let _ = sender.send(event);
+    }
+}
+
+/// Software tap detection, a short spike of the acceleration against its slow moving average
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TapConfig {
+    /// Minimum spike in g
+    pub threshold: f32,
+    /// Maximum spike length, longer spikes are movements
+    pub max_duration: Duration,
+    /// Time after a tap in which no second tap is accepted, the sensor still rings
+    pub latency: Duration,
+    /// Time after a tap in which a second tap makes a double tap
+    pub window: Duration,
+}
+
+impl Default for TapConfig {
+    fn default() -> Self {
+        TapConfig {
+            threshold: 0.5,
+            max_duration: Duration::from_millis(60),
+            latency: Duration::from_millis(80),
+            window: Duration::from_millis(400),
+        }
+    }
+}
+
+/// Software orientation classification from the direction of gravity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrientationConfig {
+    /// Minimum share of gravity on the up axis, 0.8 is about 37 degrees of tilt
+    pub threshold: f32,
+    /// Time a new orientation has to be stable before it is reported
+    pub hold: Duration,
+}
+
+impl Default for OrientationConfig {
+    fn default() -> Self {
+        OrientationConfig {
+            threshold: 0.8,
+            hold: Duration::from_millis(300),
+        }
+    }
+}
+
+/// Time constant of the acceleration average the tap spikes are measured against, in samples
+const TAP_BASELINE_SAMPLES: f32 = 16.;
+/// Accelerations outside of 1g +- this are not used to classify the orientation
+const ORIENTATION_GRAVITY_TOLERANCE: f32 = 0.25;
+
+/// Spike currently above the tap threshold
+#[derive(Debug, Clone, Copy)]
+struct Spike {
+    start: u32,
+    axis: Axis,
+    positive: bool,
+    peak: f32,
+}
+
+/// Tap reported as `Tap` unless a second one follows
+#[derive(Debug, Clone, Copy)]
+struct PendingTap {
+    end: u32,
+    axis: Axis,
+    positive: bool,
+}
+
+/// Turns the hardware interrupts and accelerometer samples into `MotionEvent`s
+#[derive(Debug, Clone)]
+pub struct MotionEngine {
+    sample_period: Duration,
+    tap: Option<TapConfig>,
+    orientation: Option<OrientationConfig>,
+    /// samples since creation
+    now: u32,
+    baseline: Option<Vector3<f32>>,
+    spike: Option<Spike>,
+    pending_tap: Option<PendingTap>,
+    current_orientation: Option<Orientation>,
+    candidate: Option<(Orientation, u32)>,
+}
+
+impl MotionEngine {
+    /// Engine for accelerometer samples taken every `sample_period`,
+    /// with the default tap and orientation detection
+    pub fn new(sample_period: Duration) -> Self {
+        MotionEngine {
+            sample_period,
+            tap: Some(TapConfig::default()),
+            orientation: Some(OrientationConfig::default()),
+            now: 0,
+            baseline: None,
+            spike: None,
+            pending_tap: None,
+            current_orientation: None,
+            candidate: None,
+        }
+    }
+
+    /// Configures or disables (`None`) tap detection
+    pub fn with_tap(mut self, tap: Option<TapConfig>) -> Self {
+        self.tap = tap;
+        self.spike = None;
+        self.pending_tap = None;
+        self
+    }
+
+    /// Configures or disables (`None`) orientation detection
+    pub fn with_orientation(mut self, orientation: Option<OrientationConfig>) -> Self {
+        self.orientation = orientation;
+        self.candidate = None;
+        self
+    }
+
+    /// Last reported orientation
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.current_orientation
+    }
+
+    /// Decodes the hardware interrupts from INT_STATUS and MOT_DETECT_STATUS
+    pub fn interrupts(
+        &mut self,
+        int_status: u8,
+        mot_detect_status: u8,
+        on_event: &mut impl FnMut(MotionEvent),
+    ) {
+        let bit = |n: u8| int_status & (1 << n) != 0;
+        if bit(INT_STATUS::FF_INT) {
+            on_event(MotionEvent::FreeFall);
+        }
+        if bit(INT_STATUS::MOT_INT) {
+            on_event(MotionEvent::Motion(MotionAxes::from_register(
+                mot_detect_status,
+            )));
+        }
+        if bit(INT_STATUS::ZMOT_INT) {
+            if mot_detect_status & (1 << MOT_DETECT_STATUS::MOT_ZRMOT) != 0 {
+                on_event(MotionEvent::ZeroMotion);
+            } else {
+                on_event(MotionEvent::MotionResumed);
+            }
+        }
+    }
+
+    /// Feeds one accelerometer sample in g, call once per sample period
+    pub fn update(&mut self, acc: Vector3<f32>, on_event: &mut impl FnMut(MotionEvent)) {
+        if let Some(tap) = self.tap {
+            self.update_tap(&tap, acc, on_event);
+        }
+        if let Some(orientation) = self.orientation {
+            self.update_orientation(&orientation, acc, on_event);
+        }
+        self.now = self.now.wrapping_add(1);
+    }
+
+    /// Duration in samples, at least one
+    fn samples(&self, duration: Duration) -> u32 {
+        let period = self.sample_period.as_secs_f32().max(f32::EPSILON);
+        ((duration.as_secs_f32() / period).round() as u32).max(1)
+    }
+
+    fn update_tap(
+        &mut self,
+        config: &TapConfig,
+        acc: Vector3<f32>,
+        on_event: &mut impl FnMut(MotionEvent),
+    ) {
+        // a single tap is reported once no second tap can follow
+        if let Some(pending) = self.pending_tap {
+            if self.now.wrapping_sub(pending.end) > self.samples(config.window) {
+                self.pending_tap = None;
+                on_event(MotionEvent::Tap {
+                    axis: pending.axis,
+                    positive: pending.positive,
+                });
+            }
+        }
+
+        let baseline = *self.baseline.get_or_insert(acc);
+        let delta = acc - baseline;
+        let peak = delta.amax();
+
+        match self.spike {
+            None if peak >= config.threshold => {
+                let (axis, positive) = dominant_axis(&delta);
+                self.spike = Some(Spike {
+                    start: self.now,
+                    axis,
+                    positive,
+                    peak,
+                });
+            }
+            None => {
+                // the baseline only follows the slow movements outside of spikes
+                self.baseline = Some(baseline + (acc - baseline) / TAP_BASELINE_SAMPLES);
+            }
+            Some(spike)
+                if self.now.wrapping_sub(spike.start) > self.samples(config.max_duration) =>
+            {
+                // too long for a tap: a movement, or the board was turned and gravity moved to
+                // another axis. Start over from the new position
+                self.spike = None;
+                self.baseline = Some(acc);
+            }
+            Some(mut spike) if peak >= config.threshold / 2. => {
+                if peak > spike.peak {
+                    (spike.axis, spike.positive) = dominant_axis(&delta);
+                    spike.peak = peak;
+                    self.spike = Some(spike);
+                }
+            }
+            Some(spike) => {
+                self.spike = None;
+                let length = self.now.wrapping_sub(spike.start);
+                if length <= self.samples(config.max_duration) {
+                    self.tap_detected(config, spike, on_event);
+                }
+            }
+        }
+    }
+
+    fn tap_detected(
+        &mut self,
+        config: &TapConfig,
+        spike: Spike,
+        on_event: &mut impl FnMut(MotionEvent),
+    ) {
+        match self.pending_tap {
+            Some(pending) if self.now.wrapping_sub(pending.end) < self.samples(config.latency) => {
+                // ringing of the first tap
+            }
+            Some(_) => {
+                self.pending_tap = None;
+                on_event(MotionEvent::DoubleTap {
+                    axis: spike.axis,
+                    positive: spike.positive,
+                });
+            }
+            None => {
+                self.pending_tap = Some(PendingTap {
+                    end: self.now,
+                    axis: spike.axis,
+                    positive: spike.positive,
+                });
+            }
+        }
+    }
+
+    fn update_orientation(
+        &mut self,
+        config: &OrientationConfig,
+        acc: Vector3<f32>,
+        on_event: &mut impl FnMut(MotionEvent),
+    ) {
+        let Some(orientation) = classify_orientation(&acc, config.threshold) else {
+            self.candidate = None;
+            return;
+        };
+
+        if Some(orientation) == self.current_orientation {
+            self.candidate = None;
+            return;
+        }
+
+        match self.candidate {
+            Some((candidate, since)) if candidate == orientation => {
+                if self.now.wrapping_sub(since) + 1 >= self.samples(config.hold) {
+                    self.candidate = None;
+                    self.current_orientation = Some(orientation);
+                    on_event(MotionEvent::Orientation(orientation));
+                }
+            }
+            _ => self.candidate = Some((orientation, self.now)),
+        }
+    }
+}
+
+/// Axis and direction of the largest component
+fn dominant_axis(v: &Vector3<f32>) -> (Axis, bool) {
+    let index = v.iamax();
+    let axis = match index {
+        0 => Axis::X,
+        1 => Axis::Y,
+        _ => Axis::Z,
+    };
+    (axis, v[index] >= 0.)
+}
+
+/// Orientation of a sensor at rest, `None` while accelerating or between two orientations
+fn classify_orientation(acc: &Vector3<f32>, threshold: f32) -> Option<Orientation> {
+    let magnitude = acc.dot(acc).sqrt();
+    if (magnitude - 1.).abs() > ORIENTATION_GRAVITY_TOLERANCE {
+        return None;
+    }
+
+    let (axis, positive) = dominant_axis(acc);
+    let share = acc.amax() / magnitude;
+    if share < threshold {
+        return None;
+    }
+
+    let orientation = match (axis, positive) {
+        (Axis::X, true) => Orientation::LandscapeLeft,
+        (Axis::X, false) => Orientation::LandscapeRight,
+        (Axis::Y, true) => Orientation::PortraitUp,
+        (Axis::Y, false) => Orientation::PortraitDown,
+        (Axis::Z, true) => Orientation::FaceUp,
+        (Axis::Z, false) => Orientation::FaceDown,
+    };
+    Some(orientation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    fn run(engine: &mut MotionEngine, samples: &[Vector3<f32>]) -> Vec<MotionEvent> {
+        let mut events = Vec::new();
+        for acc in samples {
+            engine.update(*acc, &mut |event| events.push(event));
+        }
+        events
+    }
+
+    fn repeat(acc: Vector3<f32>, count: usize) -> Vec<Vector3<f32>> {
+        vec![acc; count]
+    }
+
+    fn flat() -> Vector3<f32> {
+        Vector3::new(0., 0., 1.)
+    }
+
+    /// a 20ms spike on z
+    fn tap(z: f32) -> Vec<Vector3<f32>> {
+        repeat(Vector3::new(0., 0., 1. + z), 2)
+    }
+
+    #[test]
+    fn register_test() {
+        let threshold = Threshold {
+            threshold_mg: 41,
+            duration_ms: 300,
+        };
+        assert_eq!(threshold.threshold_register(), 20);
+        assert_eq!(threshold.duration_register(), 255);
+        assert_eq!(threshold.zero_motion_duration_register(), 4);
+        assert_eq!(MotionConfig::default().detect_control_register(), 0x15);
+
+        let axes = MotionAxes::from_register(0b1000_0100);
+        assert!(axes.x_neg && axes.z_pos);
+        assert!(!axes.x_pos && !axes.y_pos && !axes.y_neg && !axes.z_neg);
+    }
+
+    #[test]
+    fn interrupts_test() {
+        let mut engine = MotionEngine::new(PERIOD);
+        let mut events = Vec::new();
+        let mut sink = |event| events.push(event);
+        engine.interrupts(1 << INT_STATUS::FF_INT, 0, &mut sink);
+        engine.interrupts(
+            1 << INT_STATUS::MOT_INT,
+            1 << MOT_DETECT_STATUS::MOT_YPOS,
+            &mut sink,
+        );
+        engine.interrupts(1 << INT_STATUS::ZMOT_INT, 1, &mut sink);
+        engine.interrupts(1 << INT_STATUS::ZMOT_INT, 0, &mut sink);
+        // data ready only
+        engine.interrupts(1, 0, &mut sink);
+
+        let motion = MotionAxes {
+            y_pos: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            events,
+            vec![
+                MotionEvent::FreeFall,
+                MotionEvent::Motion(motion),
+                MotionEvent::ZeroMotion,
+                MotionEvent::MotionResumed,
+            ]
+        );
+    }
+
+    #[test]
+    fn tap_test() {
+        let mut engine = MotionEngine::new(PERIOD).with_orientation(None);
+        let mut samples = repeat(flat(), 20);
+        samples.extend(tap(-1.5));
+        samples.extend(repeat(flat(), 60));
+        assert_eq!(
+            run(&mut engine, &samples),
+            vec![MotionEvent::Tap {
+                axis: Axis::Z,
+                positive: false
+            }]
+        );
+    }
+
+    #[test]
+    fn double_tap_test() {
+        let mut engine = MotionEngine::new(PERIOD).with_orientation(None);
+        let mut samples = repeat(flat(), 20);
+        samples.extend(tap(1.5));
+        samples.extend(repeat(flat(), 3));
+        // ringing inside the latency is ignored
+        samples.extend(tap(0.8));
+        samples.extend(repeat(flat(), 15));
+        samples.extend(tap(1.2));
+        samples.extend(repeat(flat(), 60));
+        assert_eq!(
+            run(&mut engine, &samples),
+            vec![MotionEvent::DoubleTap {
+                axis: Axis::Z,
+                positive: true
+            }]
+        );
+    }
+
+    #[test]
+    fn long_spike_is_not_a_tap() {
+        let mut engine = MotionEngine::new(PERIOD).with_orientation(None);
+        let mut samples = repeat(flat(), 20);
+        samples.extend(repeat(Vector3::new(1., 0., 1.), 20));
+        samples.extend(repeat(flat(), 60));
+        assert!(run(&mut engine, &samples).is_empty());
+    }
+
+    #[test]
+    fn tap_after_rotation() {
+        let mut engine = MotionEngine::new(PERIOD).with_orientation(None);
+        let mut samples = repeat(flat(), 20);
+        // turned onto its side, gravity stays on x
+        let side = Vector3::new(1., 0., 0.);
+        samples.extend(repeat(side, 100));
+        samples.extend(repeat(Vector3::new(-0.5, 0., 0.), 2));
+        samples.extend(repeat(side, 60));
+        assert_eq!(
+            run(&mut engine, &samples),
+            vec![MotionEvent::Tap {
+                axis: Axis::X,
+                positive: false
+            }]
+        );
+    }
+
+    #[test]
+    fn orientation_test() {
+        let mut engine = MotionEngine::new(PERIOD).with_tap(None);
+        let mut samples = repeat(flat(), 40);
+        // too short to be reported
+        samples.extend(repeat(Vector3::new(0., 1., 0.), 10));
+        // between two orientations
+        samples.extend(repeat(Vector3::new(0.7, 0., 0.7), 40));
+        samples.extend(repeat(Vector3::new(-0.95, 0., 0.1), 40));
+        // shaking
+        samples.extend(repeat(Vector3::new(0., 0., 2.), 40));
+        assert_eq!(
+            run(&mut engine, &samples),
+            vec![
+                MotionEvent::Orientation(Orientation::FaceUp),
+                MotionEvent::Orientation(Orientation::LandscapeRight),
+            ]
+        );
+        assert_eq!(engine.orientation(), Some(Orientation::LandscapeRight));
+    }
+
+    #[test]
+    fn channel_sink_test() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut engine = MotionEngine::new(PERIOD);
+        engine.interrupts(1 << INT_STATUS::FF_INT, 0, &mut channel_sink(sender));
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![MotionEvent::FreeFall]
+        );
+    }
+}
@@ -0,0 +1,416 @@
+//! Host tests of the async driver against a simulated register file
+#![cfg(feature = "async")]
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+
+use embedded_hal::digital::ErrorType as PinErrorType;
+use embedded_hal_async::delay::DelayUs;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::{ErrorType, I2c, Operation};
+use esp32s3_mpu6050::asynch::{Mpu6050, Mpu6050Error};
+use esp32s3_mpu6050::device::*;
+use esp32s3_mpu6050::{DlpfBandwidth, FifoConfig};
+use futures::executor::block_on;
+
+/// MPU6050 registers with auto increment, reading FIFO_R_W pops from `fifo`
+struct MockBus {
+    regs: [u8; 128],
+    fifo: VecDeque<u8>,
+    /// number of FIFO resets
+    fifo_resets: u32,
+}
+
+impl MockBus {
+    fn new() -> Self {
+        let mut regs = [0; 128];
+        regs[WHOAMI as usize] = DEFAULT_SLAVE_ADDR;
+        regs[PWR_MGMT_1::ADDR as usize] = 0x40;
+        MockBus {
+            regs,
+            fifo: VecDeque::new(),
+            fifo_resets: 0,
+        }
+    }
+
+    fn set_word(&mut self, reg: u8, value: i16) {
+        let [high, low] = value.to_be_bytes();
+        self.regs[reg as usize] = high;
+        self.regs[reg as usize + 1] = low;
+    }
+
+    fn write(&mut self, reg: u8, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.regs[reg as usize + i] = *byte;
+        }
+        // FIFO_RESET only works while FIFO_EN is 0 and clears automatically
+        let user_ctrl = &mut self.regs[USER_CTRL::ADDR as usize];
+        if *user_ctrl & 1 << USER_CTRL::FIFO_RESET != 0 {
+            assert_eq!(*user_ctrl & 1 << USER_CTRL::FIFO_EN, 0);
+            *user_ctrl &= !(1 << USER_CTRL::FIFO_RESET);
+            self.fifo.clear();
+            self.fifo_resets += 1;
+        }
+    }
+
+    fn read(&mut self, reg: u8, buf: &mut [u8]) {
+        // burst reads of FIFO_R_W stay on the register
+        if reg == FIFO_R_W {
+            buf.fill_with(|| self.fifo.pop_front().unwrap_or_default());
+            return;
+        }
+        let count = (self.fifo.len() as u16).to_be_bytes();
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let reg = reg as usize + i;
+            *byte = match reg as u8 {
+                FIFO_COUNT_H => count[0],
+                r if r == FIFO_COUNT_H + 1 => count[1],
+                _ => self.regs[reg],
+            };
+        }
+        // reading INT_STATUS clears it
+        if (reg..reg + buf.len() as u8).contains(&INT_STATUS::ADDR) {
+            self.regs[INT_STATUS::ADDR as usize] = 0;
+        }
+    }
+}
+
+impl ErrorType for MockBus {
+    type Error = Infallible;
+}
+
+impl I2c for MockBus {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        assert_eq!(address, DEFAULT_SLAVE_ADDR);
+        let mut reg = None;
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    reg = Some(bytes[0]);
+                    self.write(bytes[0], &bytes[1..]);
+                }
+                Operation::Read(buf) => self.read(reg.expect("register address"), buf),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sums up the awaited delays
+#[derive(Default)]
+struct MockDelay {
+    waited_ms: u32,
+}
+
+impl DelayUs for MockDelay {
+    async fn delay_us(&mut self, us: u32) {
+        self.waited_ms += us / 1000;
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.waited_ms += ms;
+    }
+}
+
+/// INT pin whose edges arrive immediately
+#[derive(Default)]
+struct MockPin {
+    edges: u32,
+}
+
+impl PinErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl Wait for MockPin {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.edges += 1;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+fn new_mpu(bus: MockBus) -> Mpu6050<MockBus, MockDelay> {
+    Mpu6050::new(bus, MockDelay::default())
+}
+
+#[test]
+fn init_test() {
+    let mut mpu = new_mpu(MockBus::new());
+    block_on(mpu.init()).unwrap();
+
+    let (bus, delay) = mpu.release();
+    assert_eq!(bus.regs[PWR_MGMT_1::ADDR as usize], 0x01);
+    assert_eq!(delay.waited_ms, 100);
+
+    let mut bus = MockBus::new();
+    bus.regs[WHOAMI as usize] = 0x70;
+    let mut mpu = new_mpu(bus);
+    assert_eq!(block_on(mpu.init()), Err(Mpu6050Error::InvalidChipId(0x70)));
+}
+
+#[test]
+fn read_sensors_test() {
+    let mut bus = MockBus::new();
+    bus.set_word(ACC_REGX_H, 8192);
+    bus.set_word(ACC_REGX_H + 2, -16384);
+    bus.set_word(ACC_REGX_H + 4, 0);
+    bus.set_word(GYRO_REGX_H, 131);
+    bus.set_word(TEMP_OUT_H, 340);
+    let mut mpu = new_mpu(bus);
+
+    block_on(async {
+        mpu.init().await.unwrap();
+        let acc = mpu.get_acc().await.unwrap();
+        assert_eq!((acc.x, acc.y, acc.z), (0.5, -1.0, 0.0));
+        let gyro = mpu.get_gyro().await.unwrap();
+        assert!((gyro.x - 1.0f32.to_radians()).abs() < 1e-6);
+        let temp = mpu.get_temp().await.unwrap();
+        assert!((temp - (1.0 + TEMP_OFFSET)).abs() < 1e-3);
+
+        mpu.set_accel_range(AccelRange::G4).await.unwrap();
+        assert_eq!(mpu.get_accel_range().await.unwrap(), AccelRange::G4);
+        assert_eq!(mpu.get_acc().await.unwrap().x, 1.0);
+    });
+}
+
+#[test]
+fn calibrate_test() {
+    let mut bus = MockBus::new();
+    bus.set_word(ACC_REGX_H, 164);
+    bus.set_word(ACC_REGX_H + 4, 16384);
+    bus.set_word(GYRO_REGX_H + 2, -131);
+    let mut mpu = new_mpu(bus);
+
+    let calibration = block_on(async {
+        mpu.init().await.unwrap();
+        mpu.calibrate(10).await.unwrap()
+    });
+    assert!((calibration.acc_offset.x - 0.01).abs() < 1e-3);
+    assert!((calibration.gyro_offset.y + 1.0f32.to_radians()).abs() < 1e-6);
+
+    let acc = block_on(mpu.get_acc()).unwrap();
+    assert!(acc.x.abs() < 1e-6);
+    assert!((acc.z - 1.0).abs() < 1e-6);
+
+    let (_, delay) = mpu.release();
+    // 100ms wake up and 2ms between the samples
+    assert_eq!(delay.waited_ms, 100 + 10 * 2);
+}
+
+#[test]
+fn write_offset_registers_test() {
+    let mut bus = MockBus::new();
+    bus.set_word(ACC_REGX_H, 160);
+    bus.set_word(ACC_REGX_H + 4, 16384);
+    bus.set_word(XA_OFFS_H, 1001);
+    bus.set_word(GYRO_REGX_H + 2, -131);
+    bus.set_word(XG_OFFS_USRH + 2, 10);
+    let mut mpu = new_mpu(bus);
+
+    block_on(async {
+        mpu.calibrate(1).await.unwrap();
+        mpu.write_offset_registers().await.unwrap();
+    });
+    assert_eq!(mpu.get_calibration(), Default::default());
+
+    let (bus, _) = mpu.release();
+    let word = |reg: u8| i16::from_be_bytes([bus.regs[reg as usize], bus.regs[reg as usize + 1]]);
+    // 160 LSB at +-2g are 20 LSB of the offset register, the reserved bit 0 is kept
+    assert_eq!(word(XA_OFFS_H), 1001 - 20);
+    assert_eq!(word(XA_OFFS_H + 2), 0);
+    // -1 deg/s is -33 LSB of the offset register
+    assert_eq!(word(XG_OFFS_USRH + 2), 10 + 33);
+    assert_eq!(word(XG_OFFS_USRH), 0);
+}
+
+#[test]
+fn low_power_wake_test() {
+    let mut mpu = new_mpu(MockBus::new());
+
+    block_on(async {
+        mpu.init().await.unwrap();
+        assert_eq!(mpu.get_low_power_wake().await.unwrap(), None);
+        mpu.set_low_power_wake(Some(LP_WAKE_CTRL::_10))
+            .await
+            .unwrap();
+        assert_eq!(
+            mpu.get_low_power_wake().await.unwrap(),
+            Some(LP_WAKE_CTRL::_10)
+        );
+    });
+
+    let (bus, delay) = mpu.release();
+    assert_eq!(
+        bus.regs[PWR_MGMT_1::ADDR as usize],
+        1 << PWR_MGMT_1::CYCLE | 1 << PWR_MGMT_1::TEMP_DIS | 0x01
+    );
+    assert_eq!(bus.regs[PWR_MGMT_2::ADDR as usize], 0b1100_0111);
+
+    let mut mpu = Mpu6050::new(bus, delay);
+    block_on(mpu.set_low_power_wake(None)).unwrap();
+    let (bus, _) = mpu.release();
+    assert_eq!(bus.regs[PWR_MGMT_1::ADDR as usize], 0x01);
+    assert_eq!(bus.regs[PWR_MGMT_2::ADDR as usize] & 0b111, 0);
+}
+
+#[test]
+fn sample_rate_test() {
+    let mut mpu = new_mpu(MockBus::new());
+
+    block_on(async {
+        mpu.set_dlpf(DlpfBandwidth::Hz44).await.unwrap();
+        assert_eq!(mpu.get_dlpf().await.unwrap(), DlpfBandwidth::Hz44);
+        mpu.set_sample_rate(200).await.unwrap();
+        assert_eq!(mpu.get_sample_rate().await.unwrap(), 200.0);
+    });
+
+    let (bus, _) = mpu.release();
+    assert_eq!(bus.regs[SMPLRT_DIV as usize], 4);
+}
+
+/// Pushes one accel + gyro frame into the FIFO
+fn push_frame(bus: &mut MockBus, words: [i16; 6]) {
+    bus.fifo
+        .extend(words.iter().flat_map(|word| word.to_be_bytes()));
+}
+
+#[test]
+fn fifo_test() {
+    let mut mpu = new_mpu(MockBus::new());
+    block_on(mpu.set_fifo_config(FifoConfig::accel_gyro())).unwrap();
+
+    let (mut bus, delay) = mpu.release();
+    assert_eq!(bus.regs[FIFO_EN::ADDR as usize], 0b0111_1000);
+    assert_eq!(bus.regs[USER_CTRL::ADDR as usize], 1 << USER_CTRL::FIFO_EN);
+    assert_eq!(bus.fifo_resets, 1);
+
+    // two and a half frames
+    for frame in 0..2 {
+        push_frame(&mut bus, [16384 * frame, 0, 0, 0, 0, 131]);
+    }
+    bus.fifo.extend([0; 6]);
+    let mut mpu = Mpu6050::new(bus, delay);
+
+    let samples = block_on(mpu.read_fifo(8)).unwrap();
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[1].acc.unwrap().x, 1.0);
+    assert!((samples[0].gyro.unwrap().z - 1.0f32.to_radians()).abs() < 1e-6);
+    assert!(samples[0].temp.is_none());
+
+    // the partial frame stays in the FIFO until it is complete
+    assert!(block_on(mpu.read_fifo(8)).unwrap().is_empty());
+    let (mut bus, delay) = mpu.release();
+    assert_eq!(bus.fifo.len(), 6);
+    bus.fifo.extend([0, 0, 0, 0, 0, 131]);
+    let mut mpu = Mpu6050::new(bus, delay);
+    let samples = block_on(mpu.read_fifo(8)).unwrap();
+    assert_eq!(samples.len(), 1);
+    assert!((samples[0].gyro.unwrap().z - 1.0f32.to_radians()).abs() < 1e-6);
+}
+
+#[test]
+fn fifo_max_samples_test() {
+    let mut mpu = new_mpu(MockBus::new());
+    block_on(mpu.set_fifo_config(FifoConfig::accel_gyro())).unwrap();
+
+    let (mut bus, delay) = mpu.release();
+    for frame in 0..5 {
+        push_frame(&mut bus, [4096 * frame, 0, 0, 0, 0, 0]);
+    }
+    let mut mpu = Mpu6050::new(bus, delay);
+
+    block_on(async {
+        let samples = mpu.read_fifo(3).await.unwrap();
+        let acc: Vec<_> = samples.iter().map(|s| s.acc.unwrap().x).collect();
+        assert_eq!(acc, [0.0, 0.25, 0.5]);
+        assert_eq!(mpu.fifo_count().await.unwrap(), 2 * 12);
+
+        let samples = mpu.read_fifo(3).await.unwrap();
+        let acc: Vec<_> = samples.iter().map(|s| s.acc.unwrap().x).collect();
+        assert_eq!(acc, [0.75, 1.0]);
+        assert!(mpu.read_fifo(0).await.unwrap().is_empty());
+    });
+}
+
+#[test]
+fn fifo_overflow_test() {
+    let mut mpu = new_mpu(MockBus::new());
+    block_on(mpu.set_fifo_config(FifoConfig::accel_gyro())).unwrap();
+
+    let (mut bus, delay) = mpu.release();
+    bus.fifo.extend([0; FIFO_SIZE as usize]);
+    let mut mpu = Mpu6050::new(bus, delay);
+    assert_eq!(block_on(mpu.read_fifo(8)), Err(Mpu6050Error::FifoOverflow));
+
+    // the FIFO was reset and enabled again, the next frames are read from a frame boundary
+    let (mut bus, delay) = mpu.release();
+    assert!(bus.fifo.is_empty());
+    assert_eq!(bus.fifo_resets, 2);
+    assert_eq!(bus.regs[USER_CTRL::ADDR as usize], 1 << USER_CTRL::FIFO_EN);
+    push_frame(&mut bus, [16384, 0, 0, 0, 0, 0]);
+    let mut mpu = Mpu6050::new(bus, delay);
+    let samples = block_on(mpu.read_fifo(8)).unwrap();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].acc.unwrap().x, 1.0);
+}
+
+#[test]
+fn reset_fifo_test() {
+    let mut mpu = new_mpu(MockBus::new());
+
+    // a disabled FIFO stays disabled
+    block_on(mpu.reset_fifo()).unwrap();
+    let (mut bus, delay) = mpu.release();
+    assert_eq!(bus.regs[USER_CTRL::ADDR as usize], 0);
+    assert_eq!(bus.fifo_resets, 1);
+
+    bus.regs[USER_CTRL::ADDR as usize] = 1 << USER_CTRL::FIFO_EN;
+    bus.fifo.extend([0; 20]);
+    let mut mpu = Mpu6050::new(bus, delay);
+    block_on(mpu.reset_fifo()).unwrap();
+    let (bus, _) = mpu.release();
+    assert!(bus.fifo.is_empty());
+    assert_eq!(bus.regs[USER_CTRL::ADDR as usize], 1 << USER_CTRL::FIFO_EN);
+}
+
+#[test]
+fn wait_data_ready_test() {
+    let mut mpu = new_mpu(MockBus::new());
+    block_on(mpu.enable_data_ready_interrupt(true)).unwrap();
+
+    let (mut bus, delay) = mpu.release();
+    assert_eq!(
+        bus.regs[INT_ENABLE::ADDR as usize],
+        1 << INT_ENABLE::DATA_RDY_EN
+    );
+    bus.regs[INT_STATUS::ADDR as usize] = 1 << INT_STATUS::DATA_RDY_INT;
+    let mut mpu = Mpu6050::new(bus, delay);
+    let mut pin = MockPin::default();
+
+    block_on(async {
+        assert!(mpu.wait_data_ready(&mut pin).await.unwrap());
+        // the status was cleared by the first read
+        assert!(!mpu.wait_data_ready(&mut pin).await.unwrap());
+    });
+    assert_eq!(pin.edges, 2);
+}
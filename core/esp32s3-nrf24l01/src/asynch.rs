@@ -0,0 +1,169 @@
+//! Async operation modes
+//!
+//! [`TxMode`] and [`RxMode`] wrap the blocking [`crate::TxMode`] and
+//! [`crate::RxMode`] together with the IRQ pin of the nRF24L01. Instead
+//! of polling the status register they `.await` the (active low) IRQ pin
+//! through [`embedded_hal_async::digital::Wait`], so an executor can run
+//! other tasks while a packet is in the air.
+//!
+//! The interrupts must not be masked in the `CONFIG` register, which is
+//! the default after [`NRF24L01::new`](../struct.NRF24L01.html).
+
+use crate::command::Nop;
+use crate::config::Configuration;
+use crate::device::Device;
+use crate::payload::Payload;
+use crate::registers::Status;
+use crate::standby::StandbyMode;
+use core::fmt;
+use embedded_hal_async::digital::Wait;
+
+/// Error of the async modes
+#[derive(Debug)]
+pub enum Error<DE, IE> {
+    /// Error of the device
+    Device(DE),
+    /// Error of the IRQ pin
+    Irq(IE),
+}
+
+/// Async **TX Mode**, see [`crate::TxMode`]
+pub struct TxMode<D: Device, IRQ> {
+    tx: crate::TxMode<D>,
+    irq: IRQ,
+}
+
+impl<D: Device, IRQ> fmt::Debug for TxMode<D, IRQ> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "asynch::TxMode")
+    }
+}
+
+impl<D: Device, IRQ: Wait> TxMode<D, IRQ> {
+    /// Wrap a TX mode and the IRQ pin of the same device
+    pub fn new(tx: crate::TxMode<D>, irq: IRQ) -> Self {
+        TxMode { tx, irq }
+    }
+
+    /// Give back the blocking TX mode and the IRQ pin
+    pub fn release(self) -> (crate::TxMode<D>, IRQ) {
+        (self.tx, self.irq)
+    }
+
+    /// Wait until the TX FIFO is empty, then disable `CE` so that you
+    /// can switch into RX mode.
+    pub async fn standby(mut self) -> Result<(StandbyMode<D>, IRQ), Error<D::Error, IRQ::Error>> {
+        self.wait_empty().await?;
+        let standby = self.tx.standby().map_err(Error::Device)?;
+        Ok((standby, self.irq))
+    }
+
+    /// Send a packet and wait until the TX FIFO is empty
+    ///
+    /// Returns `false` if the maximum amount of retries was reached. The
+    /// TX FIFO is flushed then, together with any other queued packets.
+    pub async fn send(&mut self, packet: &[u8]) -> Result<bool, Error<D::Error, IRQ::Error>> {
+        self.tx.send(packet).map_err(Error::Device)?;
+        self.wait_sent().await
+    }
+
+    /// Wait until TX FIFO is empty, see [`crate::TxMode::wait_empty`]
+    pub async fn wait_empty(&mut self) -> Result<(), Error<D::Error, IRQ::Error>> {
+        self.wait_sent().await?;
+        Ok(())
+    }
+
+    async fn wait_sent(&mut self) -> Result<bool, Error<D::Error, IRQ::Error>> {
+        loop {
+            match self.tx.poll_send() {
+                Ok(sent) => return Ok(sent),
+                Err(nb::Error::Other(e)) => return Err(Error::Device(e)),
+                Err(nb::Error::WouldBlock) => {}
+            }
+
+            // Another packet of the FIFO has been sent. Clear TX_DS so that
+            // the IRQ pin goes high again and signals the next one.
+            let device = self.tx.device();
+            let (status, ()) = device.send_command(&Nop).map_err(Error::Device)?;
+            if status.tx_ds() {
+                let mut clear = Status(0);
+                clear.set_tx_ds(true);
+                device.write_register(clear).map_err(Error::Device)?;
+                continue;
+            }
+
+            self.irq.wait_for_low().await.map_err(Error::Irq)?;
+        }
+    }
+}
+
+impl<D: Device, IRQ> Configuration for TxMode<D, IRQ> {
+    type Inner = D;
+    fn device(&mut self) -> &mut Self::Inner {
+        self.tx.device()
+    }
+}
+
+/// Async **RX Mode**, see [`crate::RxMode`]
+pub struct RxMode<D: Device, IRQ> {
+    rx: crate::RxMode<D>,
+    irq: IRQ,
+}
+
+impl<D: Device, IRQ> fmt::Debug for RxMode<D, IRQ> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "asynch::RxMode")
+    }
+}
+
+impl<D: Device, IRQ: Wait> RxMode<D, IRQ> {
+    /// Wrap an RX mode and the IRQ pin of the same device
+    pub fn new(rx: crate::RxMode<D>, irq: IRQ) -> Self {
+        RxMode { rx, irq }
+    }
+
+    /// Give back the blocking RX mode and the IRQ pin
+    pub fn release(self) -> (crate::RxMode<D>, IRQ) {
+        (self.rx, self.irq)
+    }
+
+    /// Disable `CE` so that you can switch into TX mode.
+    pub fn standby(self) -> Result<(StandbyMode<D>, IRQ), (Self, D::Error)> {
+        match self.rx.standby() {
+            Ok(standby) => Ok((standby, self.irq)),
+            Err((rx, e)) => Err((RxMode { rx, irq: self.irq }, e)),
+        }
+    }
+
+    /// Wait until there is incoming data to read. Return the pipe number.
+    pub async fn wait_read(&mut self) -> Result<u8, Error<D::Error, IRQ::Error>> {
+        loop {
+            // `can_read()` acknowledges all interrupts before looking at the
+            // RX FIFO, a packet arriving afterwards pulls the IRQ pin low again.
+            if let Some(pipe) = self.rx.can_read().map_err(Error::Device)? {
+                return Ok(pipe);
+            }
+            self.irq.wait_for_low().await.map_err(Error::Irq)?;
+        }
+    }
+
+    /// Wait for and read the next received packet
+    ///
+    /// Use [`wait_read()`](#method.wait_read) first to learn the pipe number.
+    /// Packets with a corrupted width are flushed and skipped.
+    pub async fn read(&mut self) -> Result<Payload, Error<D::Error, IRQ::Error>> {
+        loop {
+            self.wait_read().await?;
+            if let Some(payload) = self.rx.read().map_err(Error::Device)? {
+                return Ok(payload);
+            }
+        }
+    }
+}
+
+impl<D: Device, IRQ> Configuration for RxMode<D, IRQ> {
+    type Inner = D;
+    fn device(&mut self) -> &mut Self::Inner {
+        self.rx.device()
+    }
+}
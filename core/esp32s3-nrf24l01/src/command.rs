@@ -110,6 +110,72 @@ impl<'a> Command for WriteTxPayload<'a> {
     fn decode_response(_: &[u8]) -> Self::Response {}
 }
 
+pub struct WriteAckPayload<'a> {
+    pipe: u8,
+    data: &'a [u8],
+}
+
+impl<'a> WriteAckPayload<'a> {
+    pub fn new(pipe: u8, data: &'a [u8]) -> Self {
+        WriteAckPayload { pipe, data }
+    }
+}
+
+impl<'a> Command for WriteAckPayload<'a> {
+    fn len(&self) -> usize {
+        1 + self.data.len()
+    }
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = 0b1010_1000 | self.pipe;
+        buf[1..].copy_from_slice(self.data);
+    }
+
+    type Response = ();
+    fn decode_response(_: &[u8]) -> Self::Response {}
+}
+
+pub struct WriteTxPayloadNoack<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> WriteTxPayloadNoack<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        WriteTxPayloadNoack { data }
+    }
+}
+
+impl<'a> Command for WriteTxPayloadNoack<'a> {
+    fn len(&self) -> usize {
+        1 + self.data.len()
+    }
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = 0b1011_0000;
+        buf[1..].copy_from_slice(self.data);
+    }
+
+    type Response = ();
+    fn decode_response(_: &[u8]) -> Self::Response {}
+}
+
+/// Unlocks `FEATURE` and the commands it enables on the original nRF24L01
+pub struct Activate;
+
+impl Command for Activate {
+    fn len(&self) -> usize {
+        2
+    }
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = 0b0101_0000;
+        buf[1] = 0x73;
+    }
+
+    type Response = ();
+    fn decode_response(_: &[u8]) -> Self::Response {}
+}
+
 pub struct ReadRxPayloadWidth;
 
 impl Command for ReadRxPayloadWidth {
@@ -1,4 +1,4 @@
-use crate::command::{FlushRx, FlushTx, Nop};
+use crate::command::{Activate, FlushRx, FlushTx, Nop};
 use crate::device::Device;
 use crate::registers::{
     Config, Dynpd, EnAa, EnRxaddr, Feature, RfCh, RfSetup, SetupAw, SetupRetr, Status, TxAddr,
@@ -287,4 +287,44 @@ pub trait Configuration {
 
         Ok(())
     }
+
+    /// Obtain the features as `(EN_DPL, EN_ACK_PAY, EN_DYN_ACK)`
+    fn get_features(
+        &mut self,
+    ) -> Result<(bool, bool, bool), <<Self as Configuration>::Inner as Device>::Error> {
+        let (_, feature) = self.device().read_register::<Feature>()?;
+        Ok((feature.en_dpl(), feature.en_ack_pay(), feature.en_dyn_ack()))
+    }
+
+    /// Enable features
+    ///
+    /// * `dynamic_payload`: Dynamic payload lengths, the pipes are
+    ///   selected with [`set_pipes_rx_lengths()`](#method.set_pipes_rx_lengths)
+    /// * `ack_payload`: Payloads with the acknowledgement, see
+    ///   [`RxMode::queue_ack_payload()`](struct.RxMode.html#method.queue_ack_payload)
+    /// * `dynamic_ack`: Packets without acknowledgement, see
+    ///   [`TxMode::send_no_ack()`](struct.TxMode.html#method.send_no_ack)
+    ///
+    /// ACK payloads need dynamic payload lengths on pipe 0 of the
+    /// transmitter and on the receiving pipe.
+    fn set_features(
+        &mut self,
+        dynamic_payload: bool,
+        ack_payload: bool,
+        dynamic_ack: bool,
+    ) -> Result<(), <<Self as Configuration>::Inner as Device>::Error> {
+        let mut feature = Feature(0);
+        feature.set_en_dpl(dynamic_payload);
+        feature.set_en_ack_pay(ack_payload);
+        feature.set_en_dyn_ack(dynamic_ack);
+        self.device().write_register(feature.clone())?;
+
+        // The original nRF24L01 ignores FEATURE until it is activated
+        let (_, written) = self.device().read_register::<Feature>()?;
+        if written != feature {
+            self.device().send_command(&Activate)?;
+            self.device().write_register(feature)?;
+        }
+        Ok(())
+    }
 }
@@ -8,19 +8,19 @@ pub trait Device {
     type Error;
 
     /// Set CE pin high
-    fn ce_enable(&mut self);
+    fn ce_enable(&mut self) -> Result<(), Self::Error>;
     /// Set CE pin low
-    fn ce_disable(&mut self);
+    fn ce_disable(&mut self) -> Result<(), Self::Error>;
     /// Helper; the receiving during RX and sending during TX require `CE`
     /// to be low.
-    fn with_ce_disabled<F, R>(&mut self, f: F) -> R
+    fn with_ce_disabled<F, R>(&mut self, f: F) -> Result<R, Self::Error>
     where
         F: FnOnce(&mut Self) -> R,
     {
-        self.ce_disable();
+        self.ce_disable()?;
         let r = f(self);
-        self.ce_enable();
-        r
+        self.ce_enable()?;
+        Ok(r)
     }
 
     /// Send a command via SPI
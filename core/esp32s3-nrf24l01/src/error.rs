@@ -1,12 +1,17 @@
 use core::fmt::Debug;
 
-/// Wraps an SPI error
-///
-/// TODO: eliminate this?
+use embedded_hal::digital::ErrorKind;
+
+/// Driver errors
 #[derive(Debug)]
 pub enum Error<SPIE: Debug> {
     /// Wrap an SPI error
     SpiError(SPIE),
+    /// Setting the CE pin failed
+    Ce(ErrorKind),
+    /// The radio did not answer with a valid `SETUP_AW` register, it is
+    /// absent or not powered
+    NotConnected,
 }
 
 impl<SPIE: Debug> From<SPIE> for Error<SPIE> {
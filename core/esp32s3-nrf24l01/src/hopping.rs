@@ -0,0 +1,488 @@
+//! Synchronized frequency hopping
+//!
+//! Both ends share a [`HopSequence`], a pseudo-random order of RF
+//! channels derived from a seed, e.g. the quiet channels of a
+//! [`scan`](../scan/index.html). Every packet of the [`HopTx`] goes out
+//! on the channel of its slot and starts with a header of [`HEADER_LEN`]
+//! bytes:
+//!
+//! | Byte | Content                      |
+//! |------|------------------------------|
+//! | 0    | `0` for data, `1` for a beacon |
+//! | 1-2  | Slot, little endian          |
+//! | 3    | Sequence number of the payload |
+//!
+//! The sender moves on to the next slot after every packet, acknowledged
+//! or not, and the [`HopRx`] follows the slot of the last packet it
+//! received. A payload whose acknowledgement got lost is sent again in
+//! the next slot with the same sequence number, which the receiver
+//! drops. When the sender loses [`HopConfig::resync_after`] packets
+//! in a row it assumes that the receiver lost track, and sends beacons
+//! with its slot on the sync channel until one is acknowledged. A
+//! receiver that heard nothing for [`HopConfig::resync_polls`] polls
+//! waits for the beacons on the sync channel.
+//!
+//! Both ends count [`LinkStats`] per channel. The sync channel should
+//! not be part of the sequence, and both ends need the same address
+//! setup as without hopping.
+use crate::config::Configuration;
+use crate::device::Device;
+use crate::payload::{Payload, PACKET_LEN};
+use crate::rx::RxMode;
+use crate::scan::{tune, CHANNELS};
+use crate::tx::TxMode;
+use crate::PIPES_COUNT;
+use core::fmt;
+use embedded_hal::delay::DelayUs;
+
+/// Length of the packet header
+pub const HEADER_LEN: usize = 4;
+/// Longest payload of a packet
+pub const MAX_PAYLOAD_LEN: usize = PACKET_LEN - HEADER_LEN;
+
+// Packet kinds
+const KIND_DATA: u8 = 0;
+const KIND_BEACON: u8 = 1;
+
+/// Pseudo-random order of RF channels
+#[derive(Clone, PartialEq, Eq)]
+pub struct HopSequence {
+    channels: [u8; CHANNELS],
+    len: usize,
+}
+
+impl fmt::Debug for HopSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.channels()).finish()
+    }
+}
+
+impl HopSequence {
+    /// Shuffle up to [`CHANNELS`] channels, the same `seed` and
+    /// `channels` give the same sequence on both ends
+    ///
+    /// Returns `None` if `channels` is empty or contains a channel above
+    /// 125, e.g. when a scan found no quiet channel.
+    pub fn new(seed: u32, channels: impl IntoIterator<Item = u8>) -> Option<Self> {
+        let mut sequence = HopSequence {
+            channels: [0; CHANNELS],
+            len: 0,
+        };
+        for channel in channels.into_iter().take(CHANNELS) {
+            if channel >= CHANNELS as u8 {
+                return None;
+            }
+            sequence.channels[sequence.len] = channel;
+            sequence.len += 1;
+        }
+        if sequence.len == 0 {
+            return None;
+        }
+
+        // Fisher-Yates with xorshift32, which never leaves 0
+        let mut state = if seed == 0 { 0x9E37_79B9 } else { seed };
+        for i in (1..sequence.len).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let j = (state % (i as u32 + 1)) as usize;
+            sequence.channels.swap(i, j);
+        }
+        Some(sequence)
+    }
+
+    /// Number of channels
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The channels in hopping order
+    pub fn channels(&self) -> &[u8] {
+        &self.channels[..self.len]
+    }
+
+    /// Channel of `slot`
+    pub fn channel(&self, slot: u16) -> u8 {
+        self.channels[slot as usize % self.len]
+    }
+}
+
+/// Settings shared by [`HopTx`] and [`HopRx`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopConfig {
+    /// Channel of the resync beacons
+    pub sync_channel: u8,
+    /// Packets lost in a row before the sender resyncs
+    pub resync_after: u8,
+    /// Polls of [`HopRx::receive`] without a packet before the receiver
+    /// waits on the sync channel
+    pub resync_polls: u32,
+    /// Slots tried for one payload
+    pub max_attempts: u8,
+    /// Beacons per resync
+    pub max_beacons: u16,
+    /// Wait after every beacon
+    pub beacon_interval_us: u32,
+}
+
+impl Default for HopConfig {
+    fn default() -> Self {
+        HopConfig {
+            sync_channel: 2,
+            resync_after: 2,
+            resync_polls: 64,
+            max_attempts: 8,
+            max_beacons: 100,
+            beacon_interval_us: 1000,
+        }
+    }
+}
+
+/// Counters of one channel
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Packets sent
+    pub sent: u32,
+    /// Packets lost after all auto retransmits (`MAX_RT`)
+    pub lost: u32,
+    /// Auto retransmits, from `ARC_CNT` of `OBSERVE_TX`
+    pub retransmits: u32,
+    /// Packets received
+    pub received: u32,
+}
+
+impl ChannelStats {
+    /// Share of the sent packets that were lost, `0.0` to `1.0`
+    pub fn loss_ratio(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        self.lost as f32 / self.sent as f32
+    }
+}
+
+/// Counters of a [`HopTx`] or a [`HopRx`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkStats {
+    channels: [ChannelStats; CHANNELS],
+    /// Resyncs started
+    pub resyncs: u32,
+    /// Beacons sent or received
+    pub beacons: u32,
+    /// Payloads received again after a lost acknowledgement
+    pub duplicates: u32,
+}
+
+impl Default for LinkStats {
+    fn default() -> Self {
+        LinkStats {
+            channels: [ChannelStats::default(); CHANNELS],
+            resyncs: 0,
+            beacons: 0,
+            duplicates: 0,
+        }
+    }
+}
+
+impl LinkStats {
+    /// Counters of `channel`, `None` if it is not below [`CHANNELS`]
+    pub fn channel(&self, channel: u8) -> Option<ChannelStats> {
+        self.channels.get(channel as usize).copied()
+    }
+
+    fn channel_mut(&mut self, channel: u8) -> &mut ChannelStats {
+        &mut self.channels[channel as usize]
+    }
+}
+
+/// Hopping errors
+#[derive(Debug)]
+pub enum HopError<E> {
+    /// Error of the radio
+    Device(E),
+    /// The payload is longer than [`MAX_PAYLOAD_LEN`]
+    TooLong,
+    /// The payload was lost in all of [`HopConfig::max_attempts`] slots
+    MaxAttempts,
+}
+
+impl<E> From<E> for HopError<E> {
+    fn from(e: E) -> Self {
+        HopError::Device(e)
+    }
+}
+
+/// Hopping sender, wraps a [`TxMode`]
+pub struct HopTx<D: Device, DL> {
+    tx: TxMode<D>,
+    delay: DL,
+    sequence: HopSequence,
+    config: HopConfig,
+    slot: u16,
+    seq: u8,
+    // Packets lost in a row
+    failures: u8,
+    stats: LinkStats,
+}
+
+impl<D: Device, DL> fmt::Debug for HopTx<D, DL> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HopTx")
+    }
+}
+
+impl<D: Device, DL: DelayUs> HopTx<D, DL> {
+    /// Enables dynamic payload lengths, starts at slot 0
+    ///
+    /// `delay` waits between beacons.
+    pub fn new(mut tx: TxMode<D>, delay: DL, sequence: HopSequence) -> Result<Self, D::Error> {
+        tx.set_pipes_rx_lengths(&[None; PIPES_COUNT])?;
+        Ok(HopTx {
+            tx,
+            delay,
+            sequence,
+            config: HopConfig::default(),
+            slot: 0,
+            seq: 0,
+            failures: 0,
+            stats: LinkStats::default(),
+        })
+    }
+
+    /// Replace the default settings
+    pub fn with_config(mut self, config: HopConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Give back the TX mode and the delay
+    pub fn release(self) -> (TxMode<D>, DL) {
+        (self.tx, self.delay)
+    }
+
+    /// Counters since construction
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    /// Slot of the next packet
+    pub fn slot(&self) -> u16 {
+        self.slot
+    }
+
+    /// Send `payload` in the next slot, and in the following ones until
+    /// it is acknowledged
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), HopError<D::Error>> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(HopError::TooLong);
+        }
+        let seq = self.seq;
+        self.seq = seq.wrapping_add(1);
+        for _ in 0..self.config.max_attempts {
+            let slot = self.slot;
+            self.slot = slot.wrapping_add(1);
+            let channel = self.sequence.channel(slot);
+            if self.transmit(channel, KIND_DATA, slot, seq, payload)? {
+                self.failures = 0;
+                return Ok(());
+            }
+
+            self.failures = self.failures.saturating_add(1);
+            if self.failures >= self.config.resync_after {
+                self.resync()?;
+            }
+        }
+        Err(HopError::MaxAttempts)
+    }
+
+    /// Send beacons with the next slot on the sync channel until one is
+    /// acknowledged, returns `false` if none was
+    pub fn resync(&mut self) -> Result<bool, D::Error> {
+        self.stats.resyncs += 1;
+        for _ in 0..self.config.max_beacons {
+            self.stats.beacons += 1;
+            let sent = self.transmit(self.config.sync_channel, KIND_BEACON, self.slot, 0, &[])?;
+            // On success the receiver needs a moment to tune to the slot
+            self.delay.delay_us(self.config.beacon_interval_us);
+            if sent {
+                self.failures = 0;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn transmit(
+        &mut self,
+        channel: u8,
+        kind: u8,
+        slot: u16,
+        seq: u8,
+        payload: &[u8],
+    ) -> Result<bool, D::Error> {
+        let mut packet = [0; PACKET_LEN];
+        let [slot_lo, slot_hi] = slot.to_le_bytes();
+        packet[..HEADER_LEN].copy_from_slice(&[kind, slot_lo, slot_hi, seq]);
+        packet[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+
+        self.tx.set_frequency(channel)?;
+        self.tx.send(&packet[..HEADER_LEN + payload.len()])?;
+        let sent = nb::block!(self.tx.poll_send())?;
+        let retransmits = self.tx.observe()?.arc_cnt();
+
+        let stats = self.stats.channel_mut(channel);
+        stats.sent += 1;
+        stats.retransmits += retransmits as u32;
+        if !sent {
+            stats.lost += 1;
+        }
+        Ok(sent)
+    }
+}
+
+/// Hopping receiver, wraps an [`RxMode`]
+pub struct HopRx<D: Device> {
+    rx: RxMode<D>,
+    sequence: HopSequence,
+    config: HopConfig,
+    // Slot of the next packet
+    slot: u16,
+    // Sequence number of the last payload
+    last_seq: Option<u8>,
+    // Polls without a packet
+    misses: u32,
+    // Waiting for a beacon on the sync channel
+    syncing: bool,
+    stats: LinkStats,
+}
+
+impl<D: Device> fmt::Debug for HopRx<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HopRx")
+    }
+}
+
+impl<D: Device> HopRx<D> {
+    /// Enables dynamic payload lengths, listens on the channel of slot 0
+    pub fn new(mut rx: RxMode<D>, sequence: HopSequence) -> Result<Self, D::Error> {
+        rx.set_pipes_rx_lengths(&[None; PIPES_COUNT])?;
+        tune(&mut rx, sequence.channel(0))?;
+        Ok(HopRx {
+            rx,
+            sequence,
+            config: HopConfig::default(),
+            slot: 0,
+            last_seq: None,
+            misses: 0,
+            syncing: false,
+            stats: LinkStats::default(),
+        })
+    }
+
+    /// Replace the default settings
+    pub fn with_config(mut self, config: HopConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Give back the RX mode
+    pub fn release(self) -> RxMode<D> {
+        self.rx
+    }
+
+    /// Counters since construction
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    /// Slot of the next packet
+    pub fn slot(&self) -> u16 {
+        self.slot
+    }
+
+    /// Is the receiver waiting for a beacon?
+    pub fn is_syncing(&self) -> bool {
+        self.syncing
+    }
+
+    /// Poll for a payload and its pipe, following the slots of the
+    /// sender
+    pub fn receive(&mut self) -> Result<Option<(u8, Payload)>, D::Error> {
+        while let Some(pipe) = self.rx.can_read()? {
+            let Some(packet) = self.rx.read()? else {
+                continue;
+            };
+            let [kind, slot_lo, slot_hi, seq, ..] = packet[..] else {
+                continue;
+            };
+            let slot = u16::from_le_bytes([slot_lo, slot_hi]);
+            self.misses = 0;
+            match kind {
+                KIND_DATA => {
+                    let channel = self.sequence.channel(slot);
+                    self.stats.channel_mut(channel).received += 1;
+                    self.follow(slot.wrapping_add(1))?;
+                    if self.last_seq.replace(seq) == Some(seq) {
+                        self.stats.duplicates += 1;
+                        continue;
+                    }
+                    return Ok(Some((pipe, Payload::new(&packet[HEADER_LEN..]))));
+                }
+                KIND_BEACON => {
+                    self.stats.beacons += 1;
+                    self.follow(slot)?;
+                }
+                _ => {}
+            }
+        }
+
+        self.misses = self.misses.saturating_add(1);
+        if self.misses >= self.config.resync_polls && !self.syncing {
+            self.syncing = true;
+            self.stats.resyncs += 1;
+            tune(&mut self.rx, self.config.sync_channel)?;
+        }
+        Ok(None)
+    }
+
+    fn follow(&mut self, slot: u16) -> Result<(), D::Error> {
+        self.slot = slot;
+        self.syncing = false;
+        tune(&mut self.rx, self.sequence.channel(slot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence_is_a_shared_permutation() {
+        let a = HopSequence::new(42, 10..30).unwrap();
+        let b = HopSequence::new(42, 10..30).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(
+            a.channels(),
+            HopSequence::new(43, 10..30).unwrap().channels()
+        );
+
+        let mut sorted = [0; 20];
+        sorted.copy_from_slice(a.channels());
+        sorted.sort_unstable();
+        assert!(sorted.iter().copied().eq(10..30));
+        assert_eq!(a.channel(20), a.channel(0));
+    }
+
+    #[test]
+    fn sequence_takes_at_most_all_channels() {
+        let sequence = HopSequence::new(0, (0..CHANNELS as u8).chain(0..10)).unwrap();
+        assert_eq!(sequence.len(), CHANNELS);
+    }
+
+    #[test]
+    fn sequence_needs_valid_channels() {
+        assert_eq!(HopSequence::new(42, []), None);
+        assert_eq!(HopSequence::new(42, [10, CHANNELS as u8]), None);
+    }
+}
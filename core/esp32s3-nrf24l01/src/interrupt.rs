@@ -0,0 +1,60 @@
+//! IRQ pin interrupt
+//!
+//! The nRF24L01 IRQ pin raises a GPIO interrupt on the ESP32, the ISR
+//! notifies the task that created [`IrqPin`], so the
+//! [`service::Radio`](../service/struct.Radio.html) worker can block until
+//! a packet arrived or was sent instead of polling the status register.
+
+use core::num::NonZeroU32;
+use core::time::Duration;
+
+use esp_idf_hal::{
+    delay::TickType,
+    gpio::{AnyInputPin, Input, InterruptType, PinDriver},
+    sys::EspError,
+    task::notification::Notification,
+};
+
+use crate::service::Irq;
+
+/// Waits for the IRQ pin of the nRF24L01
+pub struct IrqPin<'d> {
+    // dropped first, unsubscribes the ISR before the notification is freed
+    pin: PinDriver<'d, AnyInputPin, Input>,
+    notification: Notification,
+}
+
+impl<'d> IrqPin<'d> {
+    /// Subscribes to falling edges of the IRQ pin (active low).
+    /// Must be created on the worker task, which is the task notified by the ISR
+    pub fn new(pin: AnyInputPin) -> Result<Self, EspError> {
+        let mut pin = PinDriver::input(pin)?;
+        pin.set_interrupt_type(InterruptType::NegEdge)?;
+
+        let notification = Notification::new();
+        let notifier = notification.notifier();
+        // Safety: the `Notification` object is dropped after the pin, which ends the subscription
+        unsafe {
+            pin.subscribe(move || {
+                notifier.notify_and_yield(NonZeroU32::new(1).unwrap());
+            })?;
+        }
+        pin.enable_interrupt()?;
+
+        Ok(IrqPin { pin, notification })
+    }
+}
+
+impl Irq for IrqPin<'_> {
+    type Error = EspError;
+
+    fn wait(&mut self, timeout: Duration) -> Result<bool, EspError> {
+        let ticks = TickType::from(timeout).ticks();
+        if self.notification.wait(ticks).is_none() {
+            return Ok(false);
+        }
+        // the GPIO driver disables the interrupt after it fired
+        self.pin.enable_interrupt()?;
+        Ok(true)
+    }
+}
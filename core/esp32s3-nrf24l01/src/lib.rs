@@ -10,18 +10,15 @@
 #![no_std]
 #[macro_use]
 extern crate bitfield;
+#[cfg(feature = "std")]
+extern crate std;
 
 use core::fmt;
-use core::fmt::Debug;
 
-mod config;
-use esp_idf_hal::{
-    gpio::{AnyIOPin, InputPin, Output, OutputPin, PinDriver},
-    peripheral::Peripheral,
-    prelude::FromValueType,
-    spi::{self, SpiDeviceDriver, SpiDriver, SpiDriverConfig, SPI2},
-};
+use embedded_hal::digital::{Error as _, OutputPin};
+use embedded_hal::spi::SpiDevice;
 
+mod config;
 pub use crate::config::{Configuration, CrcMode, DataRate};
 pub mod setup;
 
@@ -30,7 +27,7 @@ use crate::registers::{Config, Register, SetupAw, Status};
 mod command;
 use crate::command::{Command, ReadRegister, WriteRegister};
 mod payload;
-pub use crate::payload::Payload;
+pub use crate::payload::{Payload, PACKET_LEN};
 mod error;
 pub use crate::error::Error;
 
@@ -42,6 +39,16 @@ mod rx;
 pub use crate::rx::RxMode;
 mod tx;
 pub use crate::tx::TxMode;
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod transport;
+pub mod scan;
+pub mod hopping;
+pub mod network;
+#[cfg(feature = "std")]
+pub mod service;
+#[cfg(all(feature = "std", target_os = "espidf"))]
+pub mod interrupt;
 
 /// Number of RX pipes with configurable addresses
 pub const PIPES_COUNT: usize = 6;
@@ -59,91 +66,65 @@ pub const MAX_ADDR_BYTES: usize = 5;
 /// * [`TxMode<D>`](struct.TxMode.html)
 ///
 /// where `D: `[`Device`](trait.Device.html)
-pub struct NRF24L01<'d, E>
-where
-    E: Debug,
-{
-    ce: PinDriver<'d, AnyIOPin, Output>,
-    csn: PinDriver<'d, AnyIOPin, Output>,
-    spi: SpiDeviceDriver<'d, SpiDriver<'d>>,
+pub struct NRF24L01<SPI, CE> {
+    ce: CE,
+    spi: SPI,
     config: Config,
-    _e: *const E,
 }
 
-impl<'d, E> fmt::Debug for NRF24L01<'d, E>
-where
-    E: Debug,
-{
+impl<SPI, CE> fmt::Debug for NRF24L01<SPI, CE> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "NRF24L01")
     }
 }
 
-impl<'d, E> NRF24L01<'d, E>
-where
-    E: Debug,
-    error::Error<E>: core::convert::From<esp_idf_hal::sys::EspError>,
-{
+impl<SPI: SpiDevice, CE: OutputPin> NRF24L01<SPI, CE> {
     /// Construct a new driver instance.
-    pub fn new(
-        spi2: impl Peripheral<P = SPI2> + 'd,
-        sclk: impl Peripheral<P = impl OutputPin> + 'd,
-        miso: Option<impl Peripheral<P = impl InputPin + OutputPin> + 'd>,
-        mosi: impl Peripheral<P = impl OutputPin> + 'd,
-        cs: Option<impl Peripheral<P = impl OutputPin> + 'd>,
-        ce: AnyIOPin,
-        csn: AnyIOPin,
-    ) -> Result<StandbyMode<Self>, Error<E>> {
-        let driver = SpiDriver::new::<SPI2>(spi2, sclk, mosi, miso, &SpiDriverConfig::new())?;
-
-        let config = spi::config::Config::new().baudrate(26.MHz().into());
-        let spi_device = SpiDeviceDriver::new(driver, cs, &config)?;
-
-        let mut ce_device = PinDriver::output(ce)?;
-        let mut csn_device = PinDriver::output(csn)?;
-
-        ce_device.set_low()?;
-        csn_device.set_high()?;
-
+    ///
+    /// `spi` drives the **CSN** pin as its chip select, see
+    /// [`setup`](setup/index.html) for the SPI parameters. `ce` is the
+    /// **CE** pin.
+    ///
+    /// Returns [`Error::NotConnected`](enum.Error.html) if the radio does
+    /// not answer, e.g. when it is absent or not powered.
+    pub fn new(ce: CE, spi: SPI) -> Result<StandbyMode<Self>, Error<SPI::Error>> {
         // Reset value
         let mut config = Config(0b0000_1000);
         config.set_mask_rx_dr(false);
         config.set_mask_tx_ds(false);
         config.set_mask_max_rt(false);
-        let mut device = NRF24L01 {
-            ce: ce_device,
-            csn: csn_device,
-            spi: spi_device,
-            config,
-            _e: core::ptr::null(),
-        };
-        assert!(device.is_connected()?);
-
-        // TODO: activate features?
-
-        Ok(StandbyMode::power_up(device).unwrap())
+        let mut device = NRF24L01 { ce, spi, config };
+        device.ce_disable()?;
+
+        if !device.is_connected()? {
+            return Err(Error::NotConnected);
+        }
+
+        StandbyMode::power_up(device).map_err(|(_, e)| e)
     }
 
     /// Reads and validates content of the `SETUP_AW` register.
-    pub fn is_connected(&mut self) -> Result<bool, Error<E>> {
+    pub fn is_connected(&mut self) -> Result<bool, Error<SPI::Error>> {
         let (_, setup_aw) = self.read_register::<SetupAw>()?;
         let valid = setup_aw.aw() >= 3 && setup_aw.aw() <= 5;
         Ok(valid)
     }
+
+    /// Give back the SPI device and the CE pin
+    pub fn release(self) -> (SPI, CE) {
+        (self.spi, self.ce)
+    }
 }
 
-impl<'d, E> Device for NRF24L01<'d, E>
-where
-    E: Debug,
-{
-    type Error = Error<E>;
+impl<SPI: SpiDevice, CE: OutputPin> Device for NRF24L01<SPI, CE> {
+    type Error = Error<SPI::Error>;
 
-    fn ce_enable(&mut self) {
-        self.ce.set_high().unwrap();
+    fn ce_enable(&mut self) -> Result<(), Self::Error> {
+        self.ce.set_high().map_err(|e| Error::Ce(e.kind()))
     }
 
-    fn ce_disable(&mut self) {
-        self.ce.set_low().unwrap();
+    fn ce_disable(&mut self) -> Result<(), Self::Error> {
+        self.ce.set_low().map_err(|e| Error::Ce(e.kind()))
     }
 
     fn send_command<C: Command>(
@@ -159,12 +140,8 @@ where
         // Serialize the command
         command.encode(write);
 
-        // SPI transaction
-        self.csn.set_low().unwrap();
-        let transfer_result = self.spi.transfer(read, write).map(|_| {});
-        self.csn.set_high().unwrap();
-        // Propagate Err only after csn.set_high():
-        transfer_result.unwrap();
+        // SPI transaction, the SPI device asserts CSN
+        self.spi.transfer(read, write)?;
 
         // Parse response
         let status = Status(read[0]);
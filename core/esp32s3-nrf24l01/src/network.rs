@@ -0,0 +1,651 @@
+//! Star network of one [`Hub`] and up to [`MAX_NODES`] [`Node`]s
+//!
+//! Every node owns one RX pipe of the hub. The addresses are derived
+//! from a 5 byte base address: node `n` uses the base address with
+//! `n` added to its first (least significant) byte, see
+//! [`node_address`]. Pipes 2 to 5 of the hub only compare the first
+//! byte, the other four are shared with pipe 1, which is why the
+//! addresses may only differ there.
+//!
+//! A node sends to its own address, and listens on it in between. The
+//! hub receives on the pipe of the node, so it knows the source of every
+//! packet, and sends to the same address to reach the node. Both ends
+//! get auto-acknowledgements and resend a packet lost after `MAX_RT`.
+//! Every packet starts with a header of [`HEADER_LEN`] bytes:
+//!
+//! | Byte | Content                                                  |
+//! |------|----------------------------------------------------------|
+//! | 0    | `0` for data, `1` for a heartbeat                        |
+//! | 1    | Destination (from a node) or origin (from the hub), `0xFF` for the hub |
+//! | 2    | Session of the sender                                    |
+//! | 3    | Sequence number of the sender, to drop resent packets    |
+//!
+//! A receiver drops a packet with the same session and sequence number
+//! as the last one. The session changes when the sender starts over, so
+//! the first packet after a reset is not mistaken for a resent one.
+//!
+//! Nodes can reach each other, the hub forwards their packets. The
+//! hub reports a node as joined with its first packet, and as left once
+//! it did not hear from it for [`NetworkConfig::timeout_ms`]. Nodes
+//! send heartbeats every [`NetworkConfig::heartbeat_ms`]. The time is
+//! passed into [`Hub::poll`] and [`Node::poll`] in milliseconds, from
+//! any monotonic clock.
+//!
+//! All radios need 5 byte addresses, the reset default, and the same
+//! channel and data rate.
+use crate::config::Configuration;
+use crate::device::Device;
+use crate::payload::Payload;
+use crate::rx::RxMode;
+use crate::tx::TxMode;
+use crate::{MAX_ADDR_BYTES, PACKET_LEN, PIPES_COUNT};
+use core::fmt;
+
+/// Maximum number of nodes, one per pipe of the hub
+pub const MAX_NODES: usize = PIPES_COUNT;
+/// Length of the packet header
+pub const HEADER_LEN: usize = 4;
+/// Longest payload of a packet
+pub const MAX_PAYLOAD_LEN: usize = PACKET_LEN - HEADER_LEN;
+
+// Packet kinds
+const KIND_DATA: u8 = 0;
+const KIND_HEARTBEAT: u8 = 1;
+// Peer byte of the hub
+const HUB: u8 = 0xFF;
+
+/// Number of a node, `0` to `MAX_NODES - 1`
+pub type NodeId = u8;
+
+/// Base address with `id` added to its first byte
+pub fn node_address(base: &[u8; MAX_ADDR_BYTES], id: NodeId) -> [u8; MAX_ADDR_BYTES] {
+    let mut address = *base;
+    address[0] = base[0].wrapping_add(id);
+    address
+}
+
+/// Sender or receiver of a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+    /// The hub
+    Hub,
+    /// A node, through the hub
+    Node(NodeId),
+}
+
+impl Peer {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            HUB => Some(Peer::Hub),
+            id if (id as usize) < MAX_NODES => Some(Peer::Node(id)),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Peer::Hub => HUB,
+            Peer::Node(id) => id,
+        }
+    }
+}
+
+/// Settings shared by the [`Hub`] and the [`Node`]s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkConfig {
+    /// Interval of the node heartbeats
+    pub heartbeat_ms: u32,
+    /// Silence after which the hub reports a node as left
+    pub timeout_ms: u32,
+    /// Resends of a packet after `MAX_RT`
+    pub resends: u8,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            heartbeat_ms: 1000,
+            timeout_ms: 3500,
+            resends: 2,
+        }
+    }
+}
+
+/// Counters of one node, kept by the hub, or of a node itself
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NodeStats {
+    /// Data packets received
+    pub received: u32,
+    /// Packets acknowledged, including heartbeats
+    pub sent: u32,
+    /// Packets lost after all resends
+    pub lost: u32,
+    /// Packets received again after a lost acknowledgement
+    pub duplicates: u32,
+    /// Packets forwarded to another node by the hub
+    pub forwarded: u32,
+}
+
+/// Network errors
+#[derive(Debug)]
+pub enum NetworkError<E> {
+    /// Error of the radio
+    Device(E),
+    /// The payload is longer than [`MAX_PAYLOAD_LEN`]
+    TooLong,
+    /// The node number is not below [`MAX_NODES`]
+    NoSuchNode,
+}
+
+impl<E> From<E> for NetworkError<E> {
+    fn from(e: E) -> Self {
+        NetworkError::Device(e)
+    }
+}
+
+/// What the hub noticed
+pub enum Event {
+    /// First packet of a node, or first one after it left
+    Joined(NodeId),
+    /// No packet of the node within [`NetworkConfig::timeout_ms`]
+    Left(NodeId),
+    /// Data sent by a node to the hub
+    Message {
+        /// The sending node
+        from: NodeId,
+        /// The content
+        payload: Payload,
+    },
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Joined(id) => f.debug_tuple("Joined").field(id).finish(),
+            Event::Left(id) => f.debug_tuple("Left").field(id).finish(),
+            Event::Message { from, payload } => f
+                .debug_struct("Message")
+                .field("from", from)
+                .field("payload", &payload.as_ref())
+                .finish(),
+        }
+    }
+}
+
+/// A packet off the air, split into its header and payload
+struct Packet {
+    kind: u8,
+    peer: u8,
+    session: u8,
+    seq: u8,
+    payload: Payload,
+}
+
+impl Packet {
+    fn parse(packet: &Payload) -> Option<Self> {
+        let [kind, peer, session, seq, ..] = packet[..] else {
+            return None;
+        };
+        Some(Packet {
+            kind,
+            peer,
+            session,
+            seq,
+            payload: Payload::new(&packet[HEADER_LEN..]),
+        })
+    }
+}
+
+/// Listens, and switches to TX mode for every outgoing packet
+struct HalfDuplex<D: Device> {
+    // only `None` while switching
+    rx: Option<RxMode<D>>,
+    // Address of pipe 0 in RX mode
+    pipe0: [u8; MAX_ADDR_BYTES],
+    // Last TX address
+    tx_addr: Option<[u8; MAX_ADDR_BYTES]>,
+}
+
+impl<D: Device> HalfDuplex<D> {
+    fn new(rx: RxMode<D>, pipe0: [u8; MAX_ADDR_BYTES]) -> Self {
+        HalfDuplex {
+            rx: Some(rx),
+            pipe0,
+            tx_addr: None,
+        }
+    }
+
+    fn rx(&mut self) -> &mut RxMode<D> {
+        self.rx.as_mut().expect("radio left in TX mode")
+    }
+
+    fn release(self) -> RxMode<D> {
+        self.rx.expect("radio left in TX mode")
+    }
+
+    /// Send a packet to `address`, resending it up to `resends` times,
+    /// and listen again. Returns whether it was acknowledged.
+    fn send(
+        &mut self,
+        address: [u8; MAX_ADDR_BYTES],
+        packet: &[u8],
+        resends: u8,
+    ) -> Result<bool, D::Error> {
+        let standby = match self.rx.take().expect("radio left in TX mode").standby() {
+            Ok(standby) => standby,
+            Err((rx, e)) => {
+                self.rx = Some(rx);
+                return Err(e);
+            }
+        };
+        let mut tx = match standby.tx() {
+            Ok(tx) => tx,
+            Err((device, e)) => {
+                self.rx = Some(RxMode::new(device));
+                return Err(e);
+            }
+        };
+        let result = self.transmit(&mut tx, address, packet, resends);
+        let mut rx = match tx.into_standby().and_then(|standby| standby.rx()) {
+            Ok(rx) => rx,
+            Err((device, e)) => {
+                self.rx = Some(RxMode::new(device));
+                return Err(e);
+            }
+        };
+        // The ACKs arrived on pipe 0
+        let restored = if address != self.pipe0 {
+            rx.set_rx_addr(0, &self.pipe0)
+        } else {
+            Ok(())
+        };
+        self.rx = Some(rx);
+        let sent = result?;
+        restored?;
+        Ok(sent)
+    }
+
+    fn transmit(
+        &mut self,
+        tx: &mut TxMode<D>,
+        address: [u8; MAX_ADDR_BYTES],
+        packet: &[u8],
+        resends: u8,
+    ) -> Result<bool, D::Error> {
+        if self.tx_addr != Some(address) {
+            tx.set_tx_addr(&address)?;
+            self.tx_addr = Some(address);
+        }
+        if address != self.pipe0 {
+            tx.set_rx_addr(0, &address)?;
+        }
+        for _ in 0..=resends {
+            tx.send(packet)?;
+            if nb::block!(tx.poll_send())? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Dynamic payload lengths and auto-ack on all pipes
+fn setup<C: Configuration>(
+    radio: &mut C,
+) -> Result<(), <<C as Configuration>::Inner as Device>::Error> {
+    radio.set_pipes_rx_lengths(&[None; PIPES_COUNT])?;
+    radio.set_auto_ack(&[true; PIPES_COUNT])
+}
+
+fn packet(kind: u8, peer: u8, session: u8, seq: u8, payload: &[u8]) -> ([u8; PACKET_LEN], usize) {
+    let mut packet = [0; PACKET_LEN];
+    packet[..HEADER_LEN].copy_from_slice(&[kind, peer, session, seq]);
+    packet[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+    (packet, HEADER_LEN + payload.len())
+}
+
+#[derive(Default, Clone, Copy)]
+struct NodeState {
+    joined: bool,
+    last_seen_ms: u32,
+    // Session and sequence number of the last packet
+    last_seq: Option<(u8, u8)>,
+    stats: NodeStats,
+}
+
+/// Center of the star, wraps an [`RxMode`]
+pub struct Hub<D: Device> {
+    radio: HalfDuplex<D>,
+    base: [u8; MAX_ADDR_BYTES],
+    config: NetworkConfig,
+    nodes: [NodeState; MAX_NODES],
+    session: u8,
+    seq: u8,
+    // Message that arrived with a join
+    pending: Option<Event>,
+}
+
+impl<D: Device> fmt::Debug for Hub<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hub")
+    }
+}
+
+impl<D: Device> Hub<D> {
+    /// Listens on the addresses of all nodes
+    ///
+    /// `session` must differ from the one before the last reset of the
+    /// hub, e.g. a boot counter or a random number.
+    pub fn new(
+        mut rx: RxMode<D>,
+        base: [u8; MAX_ADDR_BYTES],
+        session: u8,
+    ) -> Result<Self, D::Error> {
+        setup(&mut rx)?;
+        for id in 0..MAX_NODES {
+            let address = node_address(&base, id as NodeId);
+            let len = if id < 2 { MAX_ADDR_BYTES } else { 1 };
+            rx.set_rx_addr(id, &address[..len])?;
+        }
+        rx.set_pipes_rx_enable(&[true; PIPES_COUNT])?;
+        Ok(Hub {
+            radio: HalfDuplex::new(rx, node_address(&base, 0)),
+            base,
+            config: NetworkConfig::default(),
+            nodes: [NodeState::default(); MAX_NODES],
+            session,
+            seq: 0,
+            pending: None,
+        })
+    }
+
+    /// Replace the default settings
+    pub fn with_config(mut self, config: NetworkConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Give back the RX mode
+    pub fn release(self) -> RxMode<D> {
+        self.radio.release()
+    }
+
+    /// Has the node joined and not left since?
+    pub fn is_joined(&self, id: NodeId) -> bool {
+        self.nodes.get(id as usize).is_some_and(|node| node.joined)
+    }
+
+    /// The joined nodes
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        (0..MAX_NODES as NodeId).filter(move |&id| self.is_joined(id))
+    }
+
+    /// Counters of node `id`, `None` if it is not below [`MAX_NODES`]
+    pub fn stats(&self, id: NodeId) -> Option<NodeStats> {
+        self.nodes.get(id as usize).map(|node| node.stats)
+    }
+
+    /// Send `payload` to node `id`, returns whether it was acknowledged
+    pub fn send(&mut self, id: NodeId, payload: &[u8]) -> Result<bool, NetworkError<D::Error>> {
+        self.send_from(id, Peer::Hub, payload)
+    }
+
+    /// Poll for the next event, `now_ms` is the current time
+    pub fn poll(&mut self, now_ms: u32) -> Result<Option<Event>, NetworkError<D::Error>> {
+        if let Some(event) = self.pending.take() {
+            return Ok(Some(event));
+        }
+        for (id, node) in self.nodes.iter_mut().enumerate() {
+            if node.joined && now_ms.wrapping_sub(node.last_seen_ms) > self.config.timeout_ms {
+                node.joined = false;
+                node.last_seq = None;
+                return Ok(Some(Event::Left(id as NodeId)));
+            }
+        }
+
+        while let Some(pipe) = self.radio.rx().can_read()? {
+            let Some(payload) = self.radio.rx().read()? else {
+                continue;
+            };
+            let Some(packet) = Packet::parse(&payload) else {
+                continue;
+            };
+            let from = pipe as NodeId;
+            let node = &mut self.nodes[pipe as usize];
+            node.last_seen_ms = now_ms;
+            let joined = !node.joined;
+            node.joined = true;
+            let last_seq = Some((packet.session, packet.seq));
+            if core::mem::replace(&mut node.last_seq, last_seq) == last_seq {
+                node.stats.duplicates += 1;
+                continue;
+            }
+
+            let event = match (packet.kind, Peer::from_byte(packet.peer)) {
+                (KIND_DATA, Some(Peer::Hub)) => {
+                    node.stats.received += 1;
+                    Some(Event::Message {
+                        from,
+                        payload: packet.payload,
+                    })
+                }
+                (KIND_DATA, Some(Peer::Node(to))) => {
+                    if self.send_from(to, Peer::Node(from), &packet.payload)? {
+                        self.nodes[pipe as usize].stats.forwarded += 1;
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if joined {
+                self.pending = event;
+                return Ok(Some(Event::Joined(from)));
+            }
+            if event.is_some() {
+                return Ok(event);
+            }
+        }
+        Ok(None)
+    }
+
+    fn send_from(
+        &mut self,
+        id: NodeId,
+        origin: Peer,
+        payload: &[u8],
+    ) -> Result<bool, NetworkError<D::Error>> {
+        if id as usize >= MAX_NODES {
+            return Err(NetworkError::NoSuchNode);
+        }
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(NetworkError::TooLong);
+        }
+        let (packet, len) = packet(KIND_DATA, origin.to_byte(), self.session, self.seq, payload);
+        self.seq = self.seq.wrapping_add(1);
+
+        let address = node_address(&self.base, id);
+        let sent = self
+            .radio
+            .send(address, &packet[..len], self.config.resends)?;
+        let stats = &mut self.nodes[id as usize].stats;
+        if sent {
+            stats.sent += 1;
+        } else {
+            stats.lost += 1;
+        }
+        Ok(sent)
+    }
+}
+
+/// Leaf of the star, wraps an [`RxMode`]
+pub struct Node<D: Device> {
+    radio: HalfDuplex<D>,
+    id: NodeId,
+    config: NetworkConfig,
+    session: u8,
+    seq: u8,
+    // Session and sequence number of the last packet from the hub
+    last_seq: Option<(u8, u8)>,
+    last_heartbeat_ms: Option<u32>,
+    connected: bool,
+    stats: NodeStats,
+}
+
+impl<D: Device> fmt::Debug for Node<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Node({})", self.id)
+    }
+}
+
+impl<D: Device> Node<D> {
+    /// Sends to and listens on the address of node `id`
+    ///
+    /// `session` must differ from the one before the last reset of the
+    /// node, e.g. a boot counter or a random number.
+    pub fn new(
+        mut rx: RxMode<D>,
+        base: [u8; MAX_ADDR_BYTES],
+        id: NodeId,
+        session: u8,
+    ) -> Result<Self, NetworkError<D::Error>> {
+        if id as usize >= MAX_NODES {
+            return Err(NetworkError::NoSuchNode);
+        }
+        let address = node_address(&base, id);
+        setup(&mut rx)?;
+        rx.set_rx_addr(0, &address)?;
+        let mut pipes = [false; PIPES_COUNT];
+        pipes[0] = true;
+        rx.set_pipes_rx_enable(&pipes)?;
+        Ok(Node {
+            radio: HalfDuplex::new(rx, address),
+            id,
+            config: NetworkConfig::default(),
+            session,
+            seq: 0,
+            last_seq: None,
+            last_heartbeat_ms: None,
+            connected: false,
+            stats: NodeStats::default(),
+        })
+    }
+
+    /// Replace the default settings
+    pub fn with_config(mut self, config: NetworkConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Give back the RX mode
+    pub fn release(self) -> RxMode<D> {
+        self.radio.release()
+    }
+
+    /// Number of this node
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Did the hub acknowledge the last packet?
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Counters since construction
+    pub fn stats(&self) -> NodeStats {
+        self.stats
+    }
+
+    /// Send `payload` to the hub, returns whether it was acknowledged
+    pub fn send(&mut self, payload: &[u8]) -> Result<bool, NetworkError<D::Error>> {
+        self.send_packet(KIND_DATA, Peer::Hub, payload)
+    }
+
+    /// Send `payload` to node `id` through the hub
+    ///
+    /// Returns whether the hub acknowledged it, not the other node.
+    pub fn send_to(&mut self, id: NodeId, payload: &[u8]) -> Result<bool, NetworkError<D::Error>> {
+        if id as usize >= MAX_NODES {
+            return Err(NetworkError::NoSuchNode);
+        }
+        self.send_packet(KIND_DATA, Peer::Node(id), payload)
+    }
+
+    /// Send a heartbeat when it is due and poll for a payload, `now_ms`
+    /// is the current time
+    pub fn poll(&mut self, now_ms: u32) -> Result<Option<(Peer, Payload)>, NetworkError<D::Error>> {
+        let due = match self.last_heartbeat_ms {
+            Some(last) => now_ms.wrapping_sub(last) >= self.config.heartbeat_ms,
+            None => true,
+        };
+        if due {
+            self.last_heartbeat_ms = Some(now_ms);
+            self.send_packet(KIND_HEARTBEAT, Peer::Hub, &[])?;
+        }
+
+        while self.radio.rx().can_read()?.is_some() {
+            let Some(payload) = self.radio.rx().read()? else {
+                continue;
+            };
+            let Some(packet) = Packet::parse(&payload) else {
+                continue;
+            };
+            let last_seq = Some((packet.session, packet.seq));
+            if core::mem::replace(&mut self.last_seq, last_seq) == last_seq {
+                self.stats.duplicates += 1;
+                continue;
+            }
+            if let (KIND_DATA, Some(from)) = (packet.kind, Peer::from_byte(packet.peer)) {
+                self.stats.received += 1;
+                return Ok(Some((from, packet.payload)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn send_packet(
+        &mut self,
+        kind: u8,
+        to: Peer,
+        payload: &[u8],
+    ) -> Result<bool, NetworkError<D::Error>> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(NetworkError::TooLong);
+        }
+        let (packet, len) = packet(kind, to.to_byte(), self.session, self.seq, payload);
+        self.seq = self.seq.wrapping_add(1);
+
+        let address = self.radio.pipe0;
+        let sent = self
+            .radio
+            .send(address, &packet[..len], self.config.resends)?;
+        self.connected = sent;
+        if sent {
+            self.stats.sent += 1;
+        } else {
+            self.stats.lost += 1;
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_differ_in_the_first_byte() {
+        let base = *b"\xA0star";
+        assert_eq!(node_address(&base, 0), base);
+        assert_eq!(node_address(&base, 5), *b"\xA5star");
+        assert_eq!(node_address(&[0xFF; 5], 1), [0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn peers_round_trip() {
+        for peer in [Peer::Hub, Peer::Node(0), Peer::Node(5)] {
+            assert_eq!(Peer::from_byte(peer.to_byte()), Some(peer));
+        }
+        assert_eq!(Peer::from_byte(MAX_NODES as u8), None);
+    }
+}
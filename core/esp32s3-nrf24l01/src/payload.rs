@@ -1,18 +1,24 @@
+use crate::command::{FlushRx, ReadRxPayload, ReadRxPayloadWidth};
+use crate::device::Device;
+use crate::registers::Status;
 use core::ops::Deref;
 
+/// Maximum packet length
+pub const PACKET_LEN: usize = 32;
+
 /// Represents a received packet. Stores 32 bytes and the actual length.
 ///
 /// Use [`as_ref()`](#method.as_ref) or [`Deref`](#impl-Deref) to
 /// obtain a slice of the content.
 pub struct Payload {
-    data: [u8; 32],
+    data: [u8; PACKET_LEN],
     len: usize,
 }
 
 impl Payload {
     /// Copy a slice
     pub fn new(source: &[u8]) -> Self {
-        let mut data = [0; 32];
+        let mut data = [0; PACKET_LEN];
         let len = source.len().min(data.len());
         data[0..len].copy_from_slice(&source[0..len]);
         Payload { data, len }
@@ -37,3 +43,21 @@ impl Deref for Payload {
         self.as_ref()
     }
 }
+
+/// Read the next packet from the RX FIFO
+///
+/// A width over 32 bytes means the packet is corrupted. The datasheet
+/// demands a flush of the RX FIFO, `RX_DR` is cleared with it so the IRQ
+/// does not stay active for a packet that is gone.
+pub(crate) fn read_payload<D: Device>(device: &mut D) -> Result<Option<Payload>, D::Error> {
+    let (_, payload_width) = device.send_command(&ReadRxPayloadWidth)?;
+    if payload_width as usize > PACKET_LEN {
+        device.send_command(&FlushRx)?;
+        let mut clear = Status(0);
+        clear.set_rx_dr(true);
+        device.write_register(clear)?;
+        return Ok(None);
+    }
+    let (_, payload) = device.send_command(&ReadRxPayload::new(payload_width as usize))?;
+    Ok(Some(payload))
+}
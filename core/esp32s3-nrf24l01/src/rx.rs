@@ -1,9 +1,10 @@
-use crate::command::{ReadRxPayload, ReadRxPayloadWidth};
+use crate::command::WriteAckPayload;
 use crate::config::Configuration;
 use crate::device::Device;
-use crate::payload::Payload;
+use crate::payload::{read_payload, Payload, PACKET_LEN};
 use crate::registers::{FifoStatus, Status, CD};
 use crate::standby::StandbyMode;
+use crate::PIPES_COUNT;
 use core::fmt;
 
 /// Represents **RX Mode**
@@ -25,8 +26,8 @@ impl<D: Device> RxMode<D> {
     }
 
     /// Disable `CE` so that you can switch into TX mode.
-    pub fn standby(self) -> StandbyMode<D> {
-        StandbyMode::from_rx_tx(self.device)
+    pub fn standby(self) -> Result<StandbyMode<D>, (Self, D::Error)> {
+        StandbyMode::from_rx_tx(self.device).map_err(|(device, e)| (RxMode::new(device), e))
     }
 
     /// Is there any incoming data to read? Return the pipe number.
@@ -82,12 +83,34 @@ impl<D: Device> RxMode<D> {
     }
 
     /// Read the next received packet
-    pub fn read(&mut self) -> Result<Payload, D::Error> {
-        let (_, payload_width) = self.device.send_command(&ReadRxPayloadWidth)?;
-        let (_, payload) = self
-            .device
-            .send_command(&ReadRxPayload::new(payload_width as usize))?;
-        Ok(payload)
+    ///
+    /// Returns `None` when the packet had a corrupted width and was
+    /// flushed.
+    pub fn read(&mut self) -> Result<Option<Payload>, D::Error> {
+        read_payload(&mut self.device)
+    }
+
+    /// Queue a payload for the acknowledgement on `pipe`
+    ///
+    /// Needs ACK payloads enabled with
+    /// [`set_features()`](trait.Configuration.html#method.set_features).
+    /// The payload goes out with the ACK of the next packet received on
+    /// `pipe` and is dropped once the transmitter sends a new packet.
+    ///
+    /// Returns `false` if the TX FIFO is full, and without touching the
+    /// FIFO if `pipe` is no RX pipe or `data` is longer than 32 bytes.
+    pub fn queue_ack_payload(&mut self, pipe: u8, data: &[u8]) -> Result<bool, D::Error> {
+        if pipe as usize >= PIPES_COUNT || data.len() > PACKET_LEN {
+            return Ok(false);
+        }
+
+        let (_, fifo_status) = self.device.read_register::<FifoStatus>()?;
+        if fifo_status.tx_full() {
+            return Ok(false);
+        }
+        self.device
+            .send_command(&WriteAckPayload::new(pipe, data))?;
+        Ok(true)
     }
 }
 
@@ -0,0 +1,139 @@
+//! Spectrum scan with the received power detector
+//!
+//! [`scan`] tunes an [`RxMode`] to every RF channel in turn and samples
+//! `RPD`, which is set while a signal above -64 dBm is in the air, e.g.
+//! Wi-Fi, Bluetooth or another nRF24L01. Repeated sweeps add up to a
+//! [`Histogram`] of how often each channel was occupied, which tells the
+//! quiet channels for [`HopSequence`](../hopping/struct.HopSequence.html).
+//!
+//! ```ignore
+//! let histogram = scan::scan(&mut rx, &mut delay, 100)?;
+//! let quiet = histogram.quiet_channels(0);
+//! ```
+use crate::config::Configuration;
+use crate::device::Device;
+use crate::rx::RxMode;
+use core::fmt;
+use embedded_hal::delay::DelayUs;
+
+/// Number of RF channels, 2400 MHz to 2525 MHz
+pub const CHANNELS: usize = 126;
+
+/// Wait after tuning: 130μs PLL settling plus 40μs for `RPD`
+pub const DWELL_US: u32 = 170;
+
+/// Occupancy of every RF channel over a number of sweeps
+#[derive(Clone, PartialEq, Eq)]
+pub struct Histogram {
+    hits: [u16; CHANNELS],
+    sweeps: u16,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            hits: [0; CHANNELS],
+            sweeps: 0,
+        }
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Histogram")
+            .field("sweeps", &self.sweeps)
+            .field("hits", &&self.hits[..])
+            .finish()
+    }
+}
+
+impl Histogram {
+    /// Number of sweeps that detected a carrier on `channel`, `None` if
+    /// it is not below [`CHANNELS`]
+    pub fn hits(&self, channel: u8) -> Option<u16> {
+        self.hits.get(channel as usize).copied()
+    }
+
+    /// Number of sweeps
+    pub fn sweeps(&self) -> u16 {
+        self.sweeps
+    }
+
+    /// Share of the sweeps that detected a carrier on `channel`, `0.0`
+    /// to `1.0`, `None` if it is not below [`CHANNELS`]
+    pub fn occupancy(&self, channel: u8) -> Option<f32> {
+        let hits = self.hits(channel)?;
+        if self.sweeps == 0 {
+            return Some(0.0);
+        }
+        Some(hits as f32 / self.sweeps as f32)
+    }
+
+    /// The channel with the fewest hits, the lowest one on a tie
+    pub fn quietest(&self) -> u8 {
+        (0..CHANNELS as u8)
+            .min_by_key(|&channel| self.hits[channel as usize])
+            .unwrap_or(0)
+    }
+
+    /// Channels with at most `max_hits` hits, in ascending order
+    pub fn quiet_channels(&self, max_hits: u16) -> impl Iterator<Item = u8> + '_ {
+        (0..CHANNELS as u8).filter(move |&channel| self.hits[channel as usize] <= max_hits)
+    }
+
+    fn add(&mut self, channel: u8, carrier: bool) {
+        if carrier {
+            self.hits[channel as usize] = self.hits[channel as usize].saturating_add(1);
+        }
+    }
+}
+
+/// Sweep all channels `sweeps` times
+///
+/// The radio keeps listening during the scan, packets received on the
+/// way are flushed. It returns to its channel afterwards.
+pub fn scan<D: Device, DL: DelayUs>(
+    rx: &mut RxMode<D>,
+    delay: &mut DL,
+    sweeps: u16,
+) -> Result<Histogram, D::Error> {
+    let mut histogram = Histogram::default();
+    let channel = rx.get_frequency()?;
+    for _ in 0..sweeps {
+        sweep(rx, delay, &mut histogram)?;
+    }
+    tune(rx, channel)?;
+    rx.flush_rx()?;
+    rx.clear_interrupts()?;
+    Ok(histogram)
+}
+
+/// Sweep all channels once, adding to `histogram`
+///
+/// Leaves the radio on the last channel.
+pub fn sweep<D: Device, DL: DelayUs>(
+    rx: &mut RxMode<D>,
+    delay: &mut DL,
+    histogram: &mut Histogram,
+) -> Result<(), D::Error> {
+    for channel in 0..CHANNELS as u8 {
+        tune(rx, channel)?;
+        delay.delay_us(DWELL_US);
+        let carrier = rx.has_carrier()?;
+        histogram.add(channel, carrier);
+    }
+    histogram.sweeps = histogram.sweeps.saturating_add(1);
+    Ok(())
+}
+
+/// Change the channel of a listening radio, the PLL is only programmed
+/// when leaving standby
+pub(crate) fn tune<C: Configuration>(
+    radio: &mut C,
+    channel: u8,
+) -> Result<(), <<C as Configuration>::Inner as Device>::Error> {
+    radio.device().ce_disable()?;
+    radio.set_frequency(channel)?;
+    radio.device().ce_enable()?;
+    Ok(())
+}
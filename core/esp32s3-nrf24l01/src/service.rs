@@ -0,0 +1,373 @@
+//! Interrupt driven radio service
+//!
+//! [`Radio`] moves a configured [`StandbyMode`] into a worker thread. The
+//! worker stays in RX mode and sleeps on the IRQ pin of the nRF24L01
+//! instead of polling the status register over SPI, so the CPU and the
+//! bus stay idle until something happens on the air.
+//!
+//! * Received packets go to a bounded channel, together with the number
+//!   of the pipe they arrived on. When the application does not keep up,
+//!   further packets are dropped and counted, see [`Radio::dropped`].
+//! * Outbound packets come from a bounded queue. Each one reports whether
+//!   it was acknowledged through the [`Pending`] handle returned by
+//!   [`Radio::send`].
+//!
+//! The IRQ pin is optional, see [`Irq`]. Without one, [`Polling`] checks
+//! the radio at a fixed interval.
+//!
+//! ACK payloads end up in the RX FIFO, they are delivered like packets
+//! received on pipe 0.
+//!
+//! Needs the `std` feature.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::config::Configuration;
+use crate::device::Device;
+use crate::payload::{Payload, PACKET_LEN};
+use crate::rx::RxMode;
+use crate::standby::StandbyMode;
+
+/// IRQ pin of the nRF24L01, active low
+///
+/// The interrupts must not be masked in the `CONFIG` register, which is
+/// the default after [`NRF24L01::new`](../struct.NRF24L01.html).
+pub trait Irq {
+    /// Error of the pin
+    type Error;
+
+    /// Block until the IRQ pin is asserted, or `timeout` passed.
+    /// Returns `false` on timeout.
+    ///
+    /// Spurious wakeups are fine, the worker reads the status register
+    /// after every `true`.
+    fn wait(&mut self, timeout: Duration) -> Result<bool, Self::Error>;
+}
+
+/// No IRQ pin connected: sleep for the given interval, then check the
+/// radio
+#[derive(Debug, Clone, Copy)]
+pub struct Polling(pub Duration);
+
+impl Irq for Polling {
+    type Error = Infallible;
+
+    fn wait(&mut self, timeout: Duration) -> Result<bool, Self::Error> {
+        thread::sleep(self.0.min(timeout));
+        Ok(true)
+    }
+}
+
+/// Error of the worker thread
+#[derive(Debug)]
+pub enum Error<DE, IE> {
+    /// Error of the device
+    Device(DE),
+    /// Error of the IRQ pin
+    Irq(IE),
+}
+
+/// Error of [`Radio::send`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The packet is longer than 32 bytes
+    TooLong,
+    /// The worker thread is not running anymore
+    Stopped,
+}
+
+/// Outcome of one outbound packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxResult {
+    /// Acknowledged after `retransmits` auto retransmits
+    Sent {
+        /// Value of `ARC_CNT`
+        retransmits: u8,
+    },
+    /// Not acknowledged after the maximum amount of retries
+    MaxRetries,
+    /// The IRQ did not signal completion within
+    /// [`ServiceConfig::tx_timeout`], the packet was flushed
+    Timeout,
+}
+
+/// A packet received by the worker
+pub struct Received {
+    /// The pipe the packet arrived on
+    pub pipe: u8,
+    /// The content
+    pub payload: Payload,
+}
+
+impl fmt::Debug for Received {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Received")
+            .field("pipe", &self.pipe)
+            .field("payload", &self.payload.as_ref())
+            .finish()
+    }
+}
+
+/// Settings of the [`Radio`] service
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// Received packets buffered for the application
+    pub rx_capacity: usize,
+    /// Outbound packets queued before [`Radio::send`] blocks
+    pub tx_capacity: usize,
+    /// Longest wait for the IRQ after a packet was sent
+    pub tx_timeout: Duration,
+    /// Longest sleep on the IRQ pin, bounds the latency of outbound
+    /// packets and of [`Radio::stop`]
+    pub idle_timeout: Duration,
+    /// Stack size of the worker thread
+    pub stack_size: usize,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        ServiceConfig {
+            rx_capacity: 16,
+            tx_capacity: 8,
+            tx_timeout: Duration::from_millis(100),
+            idle_timeout: Duration::from_millis(10),
+            stack_size: 8 * 1024,
+        }
+    }
+}
+
+/// Handle to the result of one outbound packet
+#[derive(Debug)]
+pub struct Pending(Receiver<TxResult>);
+
+impl Pending {
+    /// Block until the packet was sent. Returns `None` if the worker
+    /// stopped before.
+    pub fn wait(self) -> Option<TxResult> {
+        self.0.recv().ok()
+    }
+
+    /// The result, if the packet was sent already
+    pub fn try_result(&self) -> Option<TxResult> {
+        self.0.try_recv().ok()
+    }
+}
+
+struct Outbound {
+    payload: Payload,
+    done: SyncSender<TxResult>,
+}
+
+#[derive(Default)]
+struct Shared {
+    stop: AtomicBool,
+    dropped: AtomicU32,
+}
+
+type Outcome<D, IE> = Result<StandbyMode<D>, Error<<D as Device>::Error, IE>>;
+
+/// Radio service running in its own thread
+///
+/// Dropping it stops the worker without waiting for it, use
+/// [`Radio::stop`] to get the device back.
+pub struct Radio<D: Device, IE> {
+    outbound: SyncSender<Outbound>,
+    inbound: Receiver<Received>,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<Outcome<D, IE>>>,
+}
+
+impl<D: Device, IE> fmt::Debug for Radio<D, IE> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "service::Radio")
+    }
+}
+
+impl<D, IE> Radio<D, IE>
+where
+    D: Device + Send + 'static,
+    D::Error: Send + 'static,
+    IE: Send + 'static,
+{
+    /// Start the worker thread with a configured device
+    ///
+    /// `irq` creates the IRQ pin on the worker thread, as the GPIO ISR
+    /// notifies the task that subscribed to it.
+    pub fn spawn<I, F>(standby: StandbyMode<D>, irq: F, config: ServiceConfig) -> io::Result<Self>
+    where
+        I: Irq<Error = IE>,
+        F: FnOnce() -> Result<I, IE> + Send + 'static,
+    {
+        let (outbound, outbound_rx) = mpsc::sync_channel(config.tx_capacity);
+        let (inbound_tx, inbound) = mpsc::sync_channel(config.rx_capacity);
+        let shared = Arc::new(Shared::default());
+
+        let worker_shared = shared.clone();
+        let worker = thread::Builder::new()
+            .name("nrf24l01".into())
+            .stack_size(config.stack_size)
+            .spawn(move || {
+                let irq = irq().map_err(Error::Irq)?;
+                let worker = Worker {
+                    irq,
+                    outbound: outbound_rx,
+                    inbound: inbound_tx,
+                    shared: worker_shared,
+                    config,
+                };
+                worker.run(standby)
+            })?;
+
+        Ok(Radio {
+            outbound,
+            inbound,
+            shared,
+            worker: Some(worker),
+        })
+    }
+
+    /// Queue a packet, blocks while the outbound queue is full
+    pub fn send(&self, packet: &[u8]) -> Result<Pending, SendError> {
+        if packet.len() > PACKET_LEN {
+            return Err(SendError::TooLong);
+        }
+        let (done, result) = mpsc::sync_channel(1);
+        let outbound = Outbound {
+            payload: Payload::new(packet),
+            done,
+        };
+        self.outbound
+            .send(outbound)
+            .map_err(|_| SendError::Stopped)?;
+        Ok(Pending(result))
+    }
+
+    /// Block until a packet arrives. Returns `None` if the worker stopped.
+    pub fn recv(&self) -> Option<Received> {
+        self.inbound.recv().ok()
+    }
+
+    /// Wait at most `timeout` for a packet
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Received> {
+        self.inbound.recv_timeout(timeout).ok()
+    }
+
+    /// A packet, if one arrived already
+    pub fn try_recv(&self) -> Option<Received> {
+        self.inbound.try_recv().ok()
+    }
+
+    /// Number of received packets dropped because the channel was full
+    pub fn dropped(&self) -> u32 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop the worker and give back the device in standby mode, or the
+    /// error that stopped the worker
+    ///
+    /// Packets still in the outbound queue are not sent.
+    pub fn stop(mut self) -> Outcome<D, IE> {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        let worker = self.worker.take().expect("worker joined twice");
+        match worker.join() {
+            Ok(outcome) => outcome,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl<D: Device, IE> Drop for Radio<D, IE> {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Worker<I> {
+    irq: I,
+    outbound: Receiver<Outbound>,
+    inbound: SyncSender<Received>,
+    shared: Arc<Shared>,
+    config: ServiceConfig,
+}
+
+impl<I: Irq> Worker<I> {
+    fn run<D: Device>(mut self, standby: StandbyMode<D>) -> Outcome<D, I::Error> {
+        let mut rx = standby.rx().map_err(|(_, e)| Error::Device(e))?;
+        // packets may have arrived before the IRQ was subscribed
+        let mut pending = true;
+        while !self.shared.stop.load(Ordering::Relaxed) {
+            match self.outbound.try_recv() {
+                Ok(packet) => {
+                    rx = self.transmit(rx, packet)?;
+                    pending = true;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => {}
+            }
+            if pending {
+                self.receive(&mut rx).map_err(Error::Device)?;
+            }
+            // a timeout leaves the bus alone
+            pending = self
+                .irq
+                .wait(self.config.idle_timeout)
+                .map_err(Error::Irq)?;
+        }
+        rx.standby().map_err(|(_, e)| Error::Device(e))
+    }
+
+    /// Empty the RX FIFO into the channel, which clears the IRQ
+    fn receive<D: Device>(&mut self, rx: &mut RxMode<D>) -> Result<(), D::Error> {
+        while let Some(pipe) = rx.can_read()? {
+            let Some(payload) = rx.read()? else {
+                continue;
+            };
+            if let Err(TrySendError::Full(_)) = self.inbound.try_send(Received { pipe, payload }) {
+                self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    fn transmit<D: Device>(
+        &mut self,
+        rx: RxMode<D>,
+        packet: Outbound,
+    ) -> Result<RxMode<D>, Error<D::Error, I::Error>> {
+        let standby = rx.standby().map_err(|(_, e)| Error::Device(e))?;
+        let mut tx = standby.tx().map_err(|(_, e)| Error::Device(e))?;
+        tx.send(&packet.payload).map_err(Error::Device)?;
+
+        let deadline = Instant::now() + self.config.tx_timeout;
+        let result = loop {
+            match tx.poll_send() {
+                Ok(true) => {
+                    let retransmits = tx.observe().map_err(Error::Device)?.arc_cnt();
+                    break TxResult::Sent { retransmits };
+                }
+                Ok(false) => break TxResult::MaxRetries,
+                Err(nb::Error::Other(e)) => return Err(Error::Device(e)),
+                Err(nb::Error::WouldBlock) => {}
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                tx.flush_tx().map_err(Error::Device)?;
+                break TxResult::Timeout;
+            }
+            self.irq.wait(left).map_err(Error::Irq)?;
+        };
+        // the application may not care about the result
+        let _ = packet.done.send(result);
+
+        let standby = tx.standby().map_err(Error::Device)?;
+        standby.rx().map_err(|(_, e)| Error::Device(e))
+    }
+}
@@ -1,5 +1,5 @@
 //! Setup parameters for SPI
-use esp_idf_hal::spi::config::{Mode, Phase, Polarity};
+use embedded_hal::spi::{Mode, Phase, Polarity};
 
 /// SPI setup parameters
 pub fn spi_mode() -> Mode {
@@ -37,9 +37,11 @@ impl<D: Device> StandbyMode<D> {
         }
     }
 
-    pub(crate) fn from_rx_tx(mut device: D) -> Self {
-        device.ce_disable();
-        StandbyMode { device }
+    pub(crate) fn from_rx_tx(mut device: D) -> Result<Self, (D, D::Error)> {
+        match device.ce_disable() {
+            Ok(()) => Ok(StandbyMode { device }),
+            Err(e) => Err((device, e)),
+        }
     }
 
     /// Go into RX mode
@@ -47,10 +49,10 @@ impl<D: Device> StandbyMode<D> {
         let mut device = self.device;
 
         match device.update_config(|config| config.set_prim_rx(true)) {
-            Ok(()) => {
-                device.ce_enable();
-                Ok(RxMode::new(device))
-            }
+            Ok(()) => match device.ce_enable() {
+                Ok(()) => Ok(RxMode::new(device)),
+                Err(e) => Err((device, e)),
+            },
             Err(e) => Err((device, e)),
         }
     }
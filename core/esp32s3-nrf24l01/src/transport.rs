@@ -0,0 +1,465 @@
+//! Reliable messages of any length up to [`MAX_MESSAGE_LEN`]
+//!
+//! The [`Sender`] splits a message into fragments which fit into one
+//! packet. Every fragment starts with a header of [`HEADER_LEN`] bytes:
+//!
+//! | Byte | Content                                        |
+//! |------|------------------------------------------------|
+//! | 0    | Session of the sender                          |
+//! | 1    | Sequence number of the message                 |
+//! | 2    | Index of the fragment                          |
+//! | 3    | Number of fragments, `0` for a confirmation poll |
+//!
+//! A CRC-16/CCITT of the message follows its last byte. Fragments are
+//! sent with auto-acknowledgement; when the auto retransmits run out
+//! (`MAX_RT`) the sender backs off and sends the fragment again. After
+//! the last fragment it polls the [`Receiver`] until the ACK payload
+//! `[session, sequence number, status]` confirms the message.
+//!
+//! The receiver reassembles one message per pipe, drops fragments and
+//! messages it has seen before, and queues the ACK payload once a
+//! message is complete. A sender starts over at sequence number `0`
+//! after a reset; the session tells its messages apart from the ones
+//! before, see [`Sender::new`].
+//!
+//! Both ends need dynamic payload lengths and ACK payloads, which
+//! [`Sender::new`] and [`Receiver::new`] enable. As usual the sender
+//! must receive on pipe 0 with its TX address to get the ACKs.
+use crate::config::Configuration;
+use crate::device::Device;
+use crate::rx::RxMode;
+use crate::tx::TxMode;
+use crate::{PACKET_LEN, PIPES_COUNT};
+use core::fmt;
+use embedded_hal::delay::DelayUs;
+
+/// Length of the fragment header
+pub const HEADER_LEN: usize = 4;
+/// Message bytes per fragment
+pub const FRAGMENT_LEN: usize = PACKET_LEN - HEADER_LEN;
+/// Length of the message CRC
+pub const CRC_LEN: usize = 2;
+/// Longest message, limited by the number of fragments
+pub const MAX_MESSAGE_LEN: usize = u8::MAX as usize * FRAGMENT_LEN - CRC_LEN;
+
+// Status in the ACK payload
+const STATUS_OK: u8 = 0;
+const STATUS_CRC_ERROR: u8 = 1;
+
+/// CRC-16/CCITT-FALSE
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Enable dynamic payload lengths, ACK payloads and auto-ack on all pipes
+fn enable_ack_payloads<C: Configuration>(
+    radio: &mut C,
+) -> Result<(), <<C as Configuration>::Inner as Device>::Error> {
+    radio.set_pipes_rx_lengths(&[None; PIPES_COUNT])?;
+    radio.set_features(true, true, false)?;
+    radio.set_auto_ack(&[true; PIPES_COUNT])
+}
+
+/// Transport errors
+#[derive(Debug)]
+pub enum TransportError<E> {
+    /// Error of the radio
+    Device(E),
+    /// The message is longer than [`MAX_MESSAGE_LEN`]
+    TooLong,
+    /// A fragment was lost after all retries
+    MaxRetries,
+    /// The receiver did not confirm the message
+    NotConfirmed,
+}
+
+impl<E> From<E> for TransportError<E> {
+    fn from(e: E) -> Self {
+        TransportError::Device(e)
+    }
+}
+
+/// Retries of the [`Sender`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries of a fragment after `MAX_RT`
+    pub max_retries: u8,
+    /// Wait before the first retry, doubled for every further retry
+    pub backoff_us: u32,
+    /// Upper bound of the wait
+    pub max_backoff_us: u32,
+    /// Polls for the confirmation of a message
+    pub max_polls: u8,
+    /// Resends of a message that was not confirmed
+    pub max_resends: u8,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 10,
+            backoff_us: 500,
+            max_backoff_us: 16_000,
+            max_polls: 8,
+            max_resends: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u8) -> u32 {
+        let factor = 1_u32 << attempt.min(16);
+        self.backoff_us
+            .saturating_mul(factor)
+            .min(self.max_backoff_us)
+    }
+}
+
+/// Counters of a [`Sender`] or a [`Receiver`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Messages confirmed or received
+    pub messages: u32,
+    /// Fragments acknowledged or received
+    pub fragments: u32,
+    /// Auto retransmits, from `ARC_CNT` of `OBSERVE_TX`
+    pub retransmits: u32,
+    /// Packets lost after all auto retransmits (`MAX_RT`)
+    pub lost: u32,
+    /// Fragments received again
+    pub duplicates: u32,
+    /// Fragments out of order or beyond the reassembly buffer
+    pub dropped: u32,
+    /// Messages with a CRC mismatch
+    pub crc_errors: u32,
+}
+
+/// Sending end of the transport, wraps a [`TxMode`]
+pub struct Sender<D: Device, DL> {
+    tx: TxMode<D>,
+    delay: DL,
+    policy: RetryPolicy,
+    session: u8,
+    seq: u8,
+    // The last ACK payload, `[session, sequence number, status]`
+    status: Option<[u8; 3]>,
+    stats: Stats,
+}
+
+impl<D: Device, DL> fmt::Debug for Sender<D, DL> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sender")
+    }
+}
+
+impl<D: Device, DL: DelayUs> Sender<D, DL> {
+    /// Enables dynamic payload lengths and ACK payloads
+    ///
+    /// `delay` waits between retries. `session` must differ from the
+    /// one before the last reset of the sender, e.g. a boot counter or
+    /// a random number. Otherwise the receiver takes the first message
+    /// for a repeat of the last one it got, confirms it and drops it.
+    pub fn new(mut tx: TxMode<D>, delay: DL, session: u8) -> Result<Self, D::Error> {
+        enable_ack_payloads(&mut tx)?;
+        Ok(Sender {
+            tx,
+            delay,
+            policy: RetryPolicy::default(),
+            session,
+            seq: 0,
+            status: None,
+            stats: Stats::default(),
+        })
+    }
+
+    /// Replace the default retry policy
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Give back the TX mode and the delay
+    pub fn release(self) -> (TxMode<D>, DL) {
+        (self.tx, self.delay)
+    }
+
+    /// Counters since construction
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Send `message` and wait for its confirmation
+    pub fn send(&mut self, message: &[u8]) -> Result<(), TransportError<D::Error>> {
+        if message.len() > MAX_MESSAGE_LEN {
+            return Err(TransportError::TooLong);
+        }
+        let seq = self.seq;
+        self.seq = seq.wrapping_add(1);
+        self.status = None;
+
+        let crc = crc16(message).to_be_bytes();
+        let len = message.len() + CRC_LEN;
+        let count = len.div_ceil(FRAGMENT_LEN) as u8;
+        for _ in 0..=self.policy.max_resends {
+            for index in 0..count {
+                let start = index as usize * FRAGMENT_LEN;
+                let end = (start + FRAGMENT_LEN).min(len);
+                let mut frame = [0; PACKET_LEN];
+                frame[..HEADER_LEN].copy_from_slice(&[self.session, seq, index, count]);
+                let bytes = message.iter().chain(&crc).skip(start);
+                for (byte, src) in frame[HEADER_LEN..].iter_mut().zip(bytes) {
+                    *byte = *src;
+                }
+                self.send_frame(&frame[..HEADER_LEN + end - start])?;
+                self.stats.fragments += 1;
+            }
+
+            match self.confirm(seq)? {
+                Some(STATUS_OK) => {
+                    self.stats.messages += 1;
+                    return Ok(());
+                }
+                Some(_) => self.stats.crc_errors += 1,
+                None => {}
+            }
+        }
+        Err(TransportError::NotConfirmed)
+    }
+
+    /// Poll until the ACK payload carries the status of message `seq`
+    fn confirm(&mut self, seq: u8) -> Result<Option<u8>, TransportError<D::Error>> {
+        for poll in 0..self.policy.max_polls {
+            self.send_frame(&[self.session, seq, 0, 0])?;
+            match self.status {
+                Some([session, status_seq, status])
+                    if session == self.session && status_seq == seq =>
+                {
+                    return Ok(Some(status))
+                }
+                _ => self.delay.delay_us(self.policy.backoff(poll)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Send one packet, retrying with backoff after `MAX_RT`
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), TransportError<D::Error>> {
+        let mut attempt = 0;
+        loop {
+            self.tx.send(frame)?;
+            let sent = nb::block!(self.tx.poll_send())?;
+            self.stats.retransmits += self.tx.observe()?.arc_cnt() as u32;
+            self.read_ack_payloads()?;
+            if sent {
+                return Ok(());
+            }
+
+            self.stats.lost += 1;
+            if attempt == self.policy.max_retries {
+                return Err(TransportError::MaxRetries);
+            }
+            self.delay.delay_us(self.policy.backoff(attempt));
+            attempt += 1;
+        }
+    }
+
+    /// Keep the last status among the ACK payloads
+    fn read_ack_payloads(&mut self) -> Result<(), D::Error> {
+        while let Some(payload) = self.tx.read_ack_payload()? {
+            if let [session, seq, status] = payload[..] {
+                self.status = Some([session, seq, status]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reassembly of the messages of one pipe
+struct Reassembly<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    session: u8,
+    seq: u8,
+    // Number of fragments, `0` when idle
+    count: u8,
+    // Index of the next fragment
+    next: u8,
+    // Session and sequence number of the last complete message
+    last: Option<(u8, u8)>,
+}
+
+impl<const N: usize> Reassembly<N> {
+    fn new() -> Self {
+        Reassembly {
+            buf: [0; N],
+            len: 0,
+            session: 0,
+            seq: 0,
+            count: 0,
+            next: 0,
+            last: None,
+        }
+    }
+}
+
+/// Receiving end of the transport, wraps an [`RxMode`]
+///
+/// Every pipe has a reassembly buffer of `N` bytes, which holds a
+/// message and its CRC, longer messages are dropped.
+pub struct Receiver<D: Device, const N: usize> {
+    rx: RxMode<D>,
+    pipes: [Reassembly<N>; PIPES_COUNT],
+    stats: Stats,
+}
+
+impl<D: Device, const N: usize> fmt::Debug for Receiver<D, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Receiver")
+    }
+}
+
+impl<D: Device, const N: usize> Receiver<D, N> {
+    /// Enables dynamic payload lengths and ACK payloads
+    pub fn new(mut rx: RxMode<D>) -> Result<Self, D::Error> {
+        enable_ack_payloads(&mut rx)?;
+        Ok(Receiver {
+            rx,
+            pipes: core::array::from_fn(|_| Reassembly::new()),
+            stats: Stats::default(),
+        })
+    }
+
+    /// Give back the RX mode
+    pub fn release(self) -> RxMode<D> {
+        self.rx
+    }
+
+    /// Counters since construction
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Process the received fragments until a message is complete
+    ///
+    /// Returns the pipe and the message, or `None` once the RX FIFO is
+    /// empty. Call it again until it returns `None`.
+    pub fn receive(&mut self) -> Result<Option<(u8, &[u8])>, D::Error> {
+        while let Some(pipe) = self.rx.can_read()? {
+            let Some(payload) = self.rx.read()? else {
+                continue;
+            };
+            if let Some(len) = self.process(pipe, &payload)? {
+                return Ok(Some((pipe, &self.pipes[pipe as usize].buf[..len])));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the length of a complete message
+    fn process(&mut self, pipe: u8, frame: &[u8]) -> Result<Option<usize>, D::Error> {
+        let &[session, seq, index, count, ref data @ ..] = frame else {
+            self.stats.dropped += 1;
+            return Ok(None);
+        };
+        // Confirmation poll, the ACK payload answers it
+        if count == 0 {
+            return Ok(None);
+        }
+        self.stats.fragments += 1;
+
+        let state = &mut self.pipes[pipe as usize];
+        if state.last == Some((session, seq)) {
+            // The confirmation got lost and the sender repeats the message
+            self.stats.duplicates += 1;
+            if index + 1 == count {
+                queue_status(&mut self.rx, pipe, [session, seq, STATUS_OK])?;
+            }
+            return Ok(None);
+        }
+        let current = state.count != 0 && session == state.session && seq == state.seq;
+        if current && index < state.next {
+            self.stats.duplicates += 1;
+            return Ok(None);
+        }
+        if index == 0 {
+            state.session = session;
+            state.seq = seq;
+            state.count = count;
+            state.next = 0;
+            state.len = 0;
+        } else if !current || index != state.next {
+            state.count = 0;
+            self.stats.dropped += 1;
+            return Ok(None);
+        }
+        if state.len + data.len() > N {
+            state.count = 0;
+            self.stats.dropped += 1;
+            return Ok(None);
+        }
+
+        state.buf[state.len..state.len + data.len()].copy_from_slice(data);
+        state.len += data.len();
+        state.next += 1;
+        if state.next < state.count {
+            return Ok(None);
+        }
+
+        state.count = 0;
+        let len = state.len.saturating_sub(CRC_LEN);
+        let (message, crc) = state.buf[..state.len].split_at(len);
+        if crc16(message).to_be_bytes() == crc {
+            state.last = Some((session, seq));
+            self.stats.messages += 1;
+            queue_status(&mut self.rx, pipe, [session, seq, STATUS_OK])?;
+            Ok(Some(len))
+        } else {
+            self.stats.crc_errors += 1;
+            queue_status(&mut self.rx, pipe, [session, seq, STATUS_CRC_ERROR])?;
+            Ok(None)
+        }
+    }
+}
+
+/// Queue the ACK payload `[session, seq, status]` for `pipe`
+fn queue_status<D: Device>(rx: &mut RxMode<D>, pipe: u8, status: [u8; 3]) -> Result<(), D::Error> {
+    // Statuses nobody polled for are outdated
+    if !rx.queue_ack_payload(pipe, &status)? {
+        rx.flush_tx()?;
+        rx.queue_ack_payload(pipe, &status)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_test() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn backoff_test() {
+        let policy = RetryPolicy {
+            backoff_us: 100,
+            max_backoff_us: 700,
+            ..RetryPolicy::default()
+        };
+        let waits: [u32; 5] = core::array::from_fn(|attempt| policy.backoff(attempt as u8));
+        assert_eq!(waits, [100, 200, 400, 700, 700]);
+    }
+}
@@ -1,6 +1,7 @@
-use crate::command::{FlushTx, WriteTxPayload};
+use crate::command::{FlushTx, WriteTxPayload, WriteTxPayloadNoack};
 use crate::config::Configuration;
 use crate::device::Device;
+use crate::payload::{read_payload, Payload};
 use crate::registers::{FifoStatus, ObserveTx, Status};
 use crate::standby::StandbyMode;
 use core::fmt;
@@ -37,7 +38,13 @@ impl<D: Device> TxMode<D> {
     pub fn standby(mut self) -> Result<StandbyMode<D>, D::Error> {
         self.wait_empty()?;
 
-        Ok(StandbyMode::from_rx_tx(self.device))
+        StandbyMode::from_rx_tx(self.device).map_err(|(_, e)| e)
+    }
+
+    /// Disable `CE` without waiting for the TX FIFO, keeps the device
+    /// after errors
+    pub(crate) fn into_standby(self) -> Result<StandbyMode<D>, (D, D::Error)> {
+        StandbyMode::from_rx_tx(self.device)
     }
 
     /// Is TX FIFO empty?
@@ -61,10 +68,37 @@ impl<D: Device> TxMode<D> {
     /// Send asynchronously
     pub fn send(&mut self, packet: &[u8]) -> Result<(), D::Error> {
         self.device.send_command(&WriteTxPayload::new(packet))?;
-        self.device.ce_enable();
+        self.device.ce_enable()?;
+        Ok(())
+    }
+
+    /// Send asynchronously without requesting an acknowledgement
+    ///
+    /// Needs `dynamic_ack` enabled with
+    /// [`set_features()`](trait.Configuration.html#method.set_features).
+    pub fn send_no_ack(&mut self, packet: &[u8]) -> Result<(), D::Error> {
+        self.device
+            .send_command(&WriteTxPayloadNoack::new(packet))?;
+        self.device.ce_enable()?;
         Ok(())
     }
 
+    /// Read the next payload that arrived with an acknowledgement
+    ///
+    /// ACK payloads are put into the RX FIFO and raise `RX_DR`, which is
+    /// cleared once the FIFO is empty.
+    pub fn read_ack_payload(&mut self) -> Result<Option<Payload>, D::Error> {
+        let (_, fifo_status) = self.device.read_register::<FifoStatus>()?;
+        if fifo_status.rx_empty() {
+            let mut clear = Status(0);
+            clear.set_rx_dr(true);
+            self.device.write_register(clear)?;
+            return Ok(None);
+        }
+
+        read_payload(&mut self.device)
+    }
+
     /// Poll completion of one or multiple send operations and check whether transmission was
     /// successful.
     ///
@@ -84,7 +118,7 @@ impl<D: Device> TxMode<D> {
             self.clear_interrupts_and_ce()?;
             Ok(true)
         } else {
-            self.device.ce_enable();
+            self.device.ce_enable()?;
             Err(nb::Error::WouldBlock)
         }
     }
@@ -96,7 +130,7 @@ impl<D: Device> TxMode<D> {
         self.device.write_register(clear)?;
 
         // Can save power now
-        self.device.ce_disable();
+        self.device.ce_disable()?;
 
         Ok(())
     }
@@ -112,7 +146,7 @@ impl<D: Device> TxMode<D> {
             let (status, fifo_status) = self.device.read_register::<FifoStatus>()?;
             empty = fifo_status.tx_empty();
             if !empty {
-                self.device.ce_enable();
+                self.device.ce_enable()?;
             }
 
             // TX won't continue while MAX_RT is set
@@ -128,7 +162,7 @@ impl<D: Device> TxMode<D> {
             }
         }
         // Can save power now
-        self.device.ce_disable();
+        self.device.ce_disable()?;
 
         Ok(())
     }
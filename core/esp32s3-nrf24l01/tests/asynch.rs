@@ -0,0 +1,154 @@
+//! Host tests of the async modes against the radio simulator
+#![cfg(feature = "async")]
+use std::convert::Infallible;
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::digital::InputPin;
+use embedded_hal_async::digital::Wait;
+use esp32s3_nrf24l01::asynch::{RxMode, TxMode};
+use futures::executor::block_on;
+use nrf24l01_sim::{Ether, IrqPin, Radio};
+
+mod common;
+use common::{standby, wait_until, TIMEOUT};
+
+const ADDR: &[u8] = b"node1";
+
+/// IRQ pin of the simulator, blocks the executor like a sleeping task
+///
+/// The simulated pin is level-triggered, so an edge is the wait for the
+/// level after it.
+struct SimIrq {
+    pin: IrqPin,
+    waits: usize,
+}
+
+impl SimIrq {
+    fn new(radio: &Radio) -> Self {
+        SimIrq {
+            pin: radio.irq_pin(),
+            waits: 0,
+        }
+    }
+}
+
+impl embedded_hal::digital::ErrorType for SimIrq {
+    type Error = Infallible;
+}
+
+impl Wait for SimIrq {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.waits += 1;
+        // the simulator only signals the active level, poll for the other
+        wait_until(|| self.pin.is_high().unwrap());
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.waits += 1;
+        assert!(self.pin.wait_active(TIMEOUT), "the IRQ pin never went low");
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_high().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_low().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        if self.pin.is_high()? {
+            self.wait_for_low().await
+        } else {
+            self.wait_for_high().await
+        }
+    }
+}
+
+#[test]
+fn it_sends() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut rx = standby(&rx_radio, ADDR).rx().unwrap();
+    let tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut tx = TxMode::new(tx, SimIrq::new(&tx_radio));
+
+    assert!(block_on(tx.send(b"hello")).unwrap());
+    assert!(block_on(tx.send(b"world")).unwrap());
+    assert_eq!(tx_radio.tx_fifo_len(), 0);
+    assert!(!tx_radio.is_irq_active());
+    // Can save power now
+    assert!(!tx_radio.is_ce_high());
+
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"hello");
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"world");
+}
+
+#[test]
+fn it_flushes_after_max_retransmits() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut tx = TxMode::new(tx, SimIrq::new(&tx_radio));
+
+    // nobody listens yet
+    assert!(!block_on(tx.send(b"lost")).unwrap());
+    assert_eq!(tx_radio.tx_fifo_len(), 0);
+    assert!(!tx_radio.is_irq_active());
+
+    let mut rx = standby(&rx_radio, ADDR).rx().unwrap();
+    assert!(block_on(tx.send(b"sent")).unwrap());
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"sent");
+    assert!(rx.is_empty().unwrap());
+}
+
+#[test]
+fn it_empties_the_tx_fifo_before_standby() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut rx = standby(&rx_radio, ADDR).rx().unwrap();
+    let mut inner = standby(&tx_radio, ADDR).tx().unwrap();
+    inner.send(b"one").unwrap();
+    inner.send(b"two").unwrap();
+    inner.send(b"three").unwrap();
+    let tx = TxMode::new(inner, SimIrq::new(&tx_radio));
+
+    let (_standby, _irq) = block_on(tx.standby()).unwrap();
+    assert_eq!(tx_radio.tx_fifo_len(), 0);
+    assert!(!tx_radio.is_irq_active());
+    assert!(!tx_radio.is_ce_high());
+
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"one");
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"two");
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"three");
+}
+
+#[test]
+fn it_waits_for_the_irq_to_read() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let rx = standby(&rx_radio, ADDR).rx().unwrap();
+    let mut rx = RxMode::new(rx, SimIrq::new(&rx_radio));
+    let mut tx = standby(&tx_radio, ADDR).tx().unwrap();
+
+    let sender = thread::spawn(move || {
+        for packet in [&b"ping"[..], b"pong"] {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(packet).unwrap();
+            assert!(nb::block!(tx.poll_send()).unwrap());
+        }
+    });
+
+    assert_eq!(block_on(rx.wait_read()).unwrap(), 0);
+    assert_eq!(block_on(rx.read()).unwrap().as_ref(), b"ping");
+    assert_eq!(block_on(rx.read()).unwrap().as_ref(), b"pong");
+    sender.join().unwrap();
+    assert!(!rx_radio.is_irq_active());
+
+    let (_standby, irq) = rx.standby().unwrap();
+    assert!(irq.waits > 0);
+    assert!(!rx_radio.is_ce_high());
+}
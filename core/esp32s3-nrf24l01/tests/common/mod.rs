@@ -0,0 +1,56 @@
+//! Fixtures shared by the host tests
+#![allow(dead_code)]
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_hal::delay::DelayUs;
+use esp32s3_nrf24l01::{Configuration, StandbyMode, NRF24L01};
+use nrf24l01_sim::{CePin, Radio};
+
+pub type Device = NRF24L01<Radio, CePin>;
+
+/// How long a test waits for another thread
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Powered up radio with dynamic payload lengths, sending to and
+/// receiving on `addr`, the ACKs arrive on pipe 0
+pub fn standby(radio: &Radio, addr: &[u8]) -> StandbyMode<Device> {
+    let mut nrf24 = NRF24L01::new(radio.ce_pin(), radio.clone()).unwrap();
+    nrf24.set_pipes_rx_lengths(&[None; 6]).unwrap();
+    nrf24.set_rx_addr(0, addr).unwrap();
+    nrf24.set_tx_addr(addr).unwrap();
+    nrf24
+}
+
+/// Poll `done` until it holds, fails the test after [`TIMEOUT`]
+pub fn wait_until(mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Counts and sums up the waits
+#[derive(Default)]
+pub struct MockDelay {
+    pub waits: u32,
+    pub waited_us: u32,
+}
+
+impl DelayUs for MockDelay {
+    fn delay_us(&mut self, us: u32) {
+        self.waits += 1;
+        self.waited_us += us;
+    }
+}
+
+/// Runs the receiver whenever the sender waits, so that both ends of a
+/// link can share one thread
+pub struct Pump<F>(pub F);
+
+impl<F: FnMut()> DelayUs for Pump<F> {
+    fn delay_us(&mut self, _us: u32) {
+        (self.0)()
+    }
+}
@@ -0,0 +1,197 @@
+//! Host tests of the spectrum scan and of frequency hopping with simulated noise
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use esp32s3_nrf24l01::hopping::{HopConfig, HopError, HopRx, HopSequence, HopTx};
+use esp32s3_nrf24l01::scan::{self, CHANNELS};
+use esp32s3_nrf24l01::Configuration;
+use nrf24l01_sim::{Ether, Frame};
+
+mod common;
+use common::{standby, Device, MockDelay, Pump};
+
+const ADDR: &[u8] = b"hop01";
+const SEED: u32 = 0x2401;
+
+fn sequence() -> HopSequence {
+    HopSequence::new(SEED, 10..20).unwrap()
+}
+
+fn config() -> HopConfig {
+    HopConfig {
+        resync_polls: 4,
+        ..HopConfig::default()
+    }
+}
+
+/// The receiving end, polled by the pump of the sender
+#[derive(Clone)]
+struct Inbox {
+    rx: Rc<RefCell<HopRx<Device>>>,
+    received: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+impl Inbox {
+    fn new(rx: HopRx<Device>) -> Self {
+        Inbox {
+            rx: Rc::new(RefCell::new(rx)),
+            received: Rc::default(),
+        }
+    }
+
+    fn poll(&self) {
+        let mut rx = self.rx.borrow_mut();
+        while let Some((_, payload)) = rx.receive().unwrap() {
+            self.received.borrow_mut().push(payload.to_vec());
+        }
+    }
+}
+
+fn link(ether: &Ether) -> (HopTx<Device, Pump<impl FnMut()>>, Inbox) {
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let rx = HopRx::new(standby(&rx_radio, ADDR).rx().unwrap(), sequence()).unwrap();
+    let inbox = Inbox::new(rx.with_config(config()));
+    let pump = Pump({
+        let inbox = inbox.clone();
+        move || inbox.poll()
+    });
+    let tx = HopTx::new(standby(&tx_radio, ADDR).tx().unwrap(), pump, sequence()).unwrap();
+    (tx.with_config(config()), inbox)
+}
+
+#[test]
+fn it_scans_the_spectrum() {
+    let ether = Ether::new();
+    let radio = ether.add_radio();
+    let mut nrf24 = standby(&radio, ADDR);
+    nrf24.set_frequency(76).unwrap();
+    let mut rx = nrf24.rx().unwrap();
+    ether.set_noise(1, true);
+    ether.set_noise(76, true);
+
+    let mut delay = MockDelay::default();
+    let histogram = scan::scan(&mut rx, &mut delay, 3).unwrap();
+    assert_eq!(histogram.sweeps(), 3);
+    assert_eq!(delay.waits, 3 * CHANNELS as u32);
+    assert_eq!((histogram.hits(1), histogram.hits(76)), (Some(3), Some(3)));
+    assert_eq!(histogram.occupancy(76), Some(1.0));
+    assert!(histogram.hits(CHANNELS as u8).is_none());
+    assert_eq!(histogram.quietest(), 0);
+    let quiet: Vec<u8> = histogram.quiet_channels(0).collect();
+    assert_eq!(quiet.len(), CHANNELS - 2);
+    assert!(!quiet.contains(&1) && !quiet.contains(&76));
+
+    // back on its channel
+    assert_eq!(rx.get_frequency().unwrap(), 76);
+    assert!(radio.is_ce_high());
+}
+
+#[test]
+fn it_hops_in_sync() {
+    let ether = Ether::new();
+    let (mut tx, inbox) = link(&ether);
+
+    let messages: Vec<Vec<u8>> = (0..25_u8).map(|i| vec![i; i as usize % 8]).collect();
+    for message in &messages {
+        tx.send(message).unwrap();
+        inbox.poll();
+    }
+    assert_eq!(*inbox.received.borrow(), messages);
+    assert_eq!(tx.slot(), 25);
+    assert_eq!(inbox.rx.borrow().slot(), 25);
+
+    // 25 slots over 10 channels
+    let stats = tx.stats();
+    for (slot, &channel) in sequence().channels().iter().enumerate() {
+        let sent = if slot < 5 { 3 } else { 2 };
+        assert_eq!(stats.channel(channel).unwrap().sent, sent);
+        assert_eq!(stats.channel(channel).unwrap().lost, 0);
+    }
+    assert_eq!(stats.resyncs, 0);
+    let rx = inbox.rx.borrow();
+    assert_eq!(
+        rx.stats().channel(sequence().channel(0)).unwrap().received,
+        3
+    );
+}
+
+#[test]
+fn it_drops_payloads_sent_again() {
+    let ether = Ether::new();
+    let (mut tx, inbox) = link(&ether);
+    // the receiver gets the first packet, but the ACKs of all auto
+    // retransmits are lost, the payload goes out again in the next slots
+    let acks = Arc::new(AtomicU32::new(0));
+    let counter = acks.clone();
+    ether.set_filter(move |_, _, frame| {
+        frame == Frame::Data || counter.fetch_add(1, Ordering::Relaxed) >= 4
+    });
+
+    tx.send(b"once").unwrap();
+    tx.send(b"twice").unwrap();
+    inbox.poll();
+    assert_eq!(
+        *inbox.received.borrow(),
+        vec![b"once".to_vec(), b"twice".to_vec()]
+    );
+    assert!(tx.stats().channel(sequence().channel(0)).unwrap().lost > 0);
+    let rx = inbox.rx.borrow();
+    assert_eq!(rx.stats().duplicates, 1);
+}
+
+#[test]
+fn it_resyncs_around_a_noisy_channel() {
+    let ether = Ether::new();
+    let (mut tx, inbox) = link(&ether);
+    let noisy = sequence().channel(3);
+    ether.set_noise(noisy, true);
+
+    let messages: Vec<Vec<u8>> = (0..15_u8).map(|i| vec![i; 4]).collect();
+    for message in &messages {
+        tx.send(message).unwrap();
+        inbox.poll();
+    }
+    assert_eq!(*inbox.received.borrow(), messages);
+
+    // slot 3 and 13 are lost, then slot 4 and 14 on the wrong channel
+    let stats = tx.stats();
+    assert_eq!(stats.channel(noisy).unwrap().lost, 2);
+    assert_eq!(stats.channel(noisy).unwrap().loss_ratio(), 1.0);
+    assert_eq!(stats.channel(sequence().channel(4)).unwrap().lost, 2);
+    assert_eq!(stats.resyncs, 2);
+    assert!(stats.channel(config().sync_channel).unwrap().lost > 0);
+    assert!(stats.channel(CHANNELS as u8).is_none());
+    let rx = inbox.rx.borrow();
+    assert_eq!(rx.stats().resyncs, 2);
+    assert_eq!(rx.stats().beacons, 2);
+    assert!(!rx.is_syncing());
+}
+
+#[test]
+fn it_gives_up_without_receiver() {
+    let ether = Ether::new();
+    let radio = ether.add_radio();
+    let config = HopConfig {
+        max_attempts: 3,
+        max_beacons: 2,
+        ..HopConfig::default()
+    };
+    let mut tx = HopTx::new(
+        standby(&radio, ADDR).tx().unwrap(),
+        MockDelay::default(),
+        sequence(),
+    )
+    .unwrap()
+    .with_config(config);
+
+    assert!(matches!(tx.send(&[0; 30]), Err(HopError::TooLong)));
+    assert!(matches!(tx.send(b"hello"), Err(HopError::MaxAttempts)));
+    assert_eq!(tx.slot(), 3);
+    let stats = tx.stats();
+    assert_eq!((stats.resyncs, stats.beacons), (2, 4));
+
+    let (_, delay) = tx.release();
+    assert_eq!(delay.waits, 4);
+}
@@ -0,0 +1,181 @@
+//! Host tests of the star network, one hub and several nodes in one simulated ether
+use esp32s3_nrf24l01::network::{
+    Event, Hub, NetworkConfig, NetworkError, Node, NodeId, Peer, MAX_NODES,
+};
+use esp32s3_nrf24l01::NRF24L01;
+use nrf24l01_sim::{Ether, Frame};
+
+mod common;
+use common::Device;
+
+const BASE: [u8; 5] = *b"\x10star";
+
+fn config() -> NetworkConfig {
+    NetworkConfig {
+        heartbeat_ms: 100,
+        timeout_ms: 350,
+        resends: 1,
+    }
+}
+
+fn hub(ether: &Ether) -> Hub<Device> {
+    let radio = ether.add_radio();
+    let nrf24 = NRF24L01::new(radio.ce_pin(), radio).unwrap();
+    Hub::new(nrf24.rx().unwrap(), BASE, 1)
+        .unwrap()
+        .with_config(config())
+}
+
+fn node(ether: &Ether, id: NodeId) -> Node<Device> {
+    let radio = ether.add_radio();
+    let nrf24 = NRF24L01::new(radio.ce_pin(), radio).unwrap();
+    Node::new(nrf24.rx().unwrap(), BASE, id, 1)
+        .unwrap()
+        .with_config(config())
+}
+
+/// All events the hub has at `now_ms`
+fn events(hub: &mut Hub<Device>, now_ms: u32) -> Vec<String> {
+    let mut events = Vec::new();
+    while let Some(event) = hub.poll(now_ms).unwrap() {
+        events.push(match event {
+            Event::Joined(id) => format!("joined {id}"),
+            Event::Left(id) => format!("left {id}"),
+            Event::Message { from, payload } => {
+                format!("{from}: {}", String::from_utf8_lossy(&payload))
+            }
+        });
+    }
+    events
+}
+
+#[test]
+fn it_reports_joins_and_sources() {
+    let ether = Ether::new();
+    let mut hub = hub(&ether);
+    let mut nodes: Vec<_> = [0, 2, 5].into_iter().map(|id| node(&ether, id)).collect();
+
+    for node in &mut nodes {
+        // the first poll sends a heartbeat
+        assert!(node.poll(0).unwrap().is_none());
+        assert!(node.is_connected());
+        events(&mut hub, 0);
+    }
+    assert_eq!(hub.nodes().collect::<Vec<_>>(), vec![0, 2, 5]);
+
+    for node in &mut nodes {
+        let text = format!("hello from {}", node.id());
+        assert!(node.send(text.as_bytes()).unwrap());
+        assert_eq!(
+            events(&mut hub, 10),
+            vec![format!("{}: hello from {}", node.id(), node.id())]
+        );
+    }
+    assert_eq!(hub.stats(2).unwrap().received, 1);
+}
+
+#[test]
+fn it_reports_a_message_with_the_join() {
+    let ether = Ether::new();
+    let mut hub = hub(&ether);
+    let mut node = node(&ether, 3);
+
+    assert!(node.send(b"first").unwrap());
+    assert_eq!(events(&mut hub, 0), vec!["joined 3", "3: first"]);
+}
+
+#[test]
+fn it_routes_to_nodes() {
+    let ether = Ether::new();
+    let mut hub = hub(&ether);
+    let mut node1 = node(&ether, 1);
+    let mut node4 = node(&ether, 4);
+
+    assert!(hub.send(1, b"to one").unwrap());
+    assert!(hub.send(4, b"to four").unwrap());
+    let (from, payload) = node1.poll(0).unwrap().unwrap();
+    assert_eq!((from, payload.as_ref()), (Peer::Hub, &b"to one"[..]));
+    let (from, payload) = node4.poll(0).unwrap().unwrap();
+    assert_eq!((from, payload.as_ref()), (Peer::Hub, &b"to four"[..]));
+
+    // nobody at node 2
+    assert!(!hub.send(2, b"anybody?").unwrap());
+    assert_eq!(hub.stats(2).unwrap().lost, 1);
+    assert!(matches!(
+        hub.send(MAX_NODES as NodeId, b"x"),
+        Err(NetworkError::NoSuchNode)
+    ));
+    assert!(hub.stats(MAX_NODES as NodeId).is_none());
+    assert!(matches!(hub.send(1, &[0; 30]), Err(NetworkError::TooLong)));
+
+    // node to node through the hub
+    assert!(node1.send_to(4, b"psst").unwrap());
+    assert_eq!(events(&mut hub, 0), vec!["joined 1", "joined 4"]);
+    let (from, payload) = node4.poll(0).unwrap().unwrap();
+    assert_eq!((from, payload.as_ref()), (Peer::Node(1), &b"psst"[..]));
+    assert_eq!(hub.stats(1).unwrap().forwarded, 1);
+}
+
+#[test]
+fn it_detects_nodes_leaving() {
+    let ether = Ether::new();
+    let mut hub = hub(&ether);
+    let mut node0 = node(&ether, 0);
+    let mut node1 = node(&ether, 1);
+
+    let mut log = Vec::new();
+    for now in (0..=1000).step_by(50) {
+        node0.poll(now).unwrap();
+        // node 1 goes quiet after 200 ms
+        if now <= 200 {
+            node1.poll(now).unwrap();
+        }
+        for event in events(&mut hub, now) {
+            log.push(format!("{now} {event}"));
+        }
+    }
+    assert_eq!(log, vec!["0 joined 0", "0 joined 1", "600 left 1"]);
+    assert_eq!(hub.nodes().collect::<Vec<_>>(), vec![0]);
+    // heartbeats every 100 ms
+    assert_eq!(hub.stats(0).unwrap().received, 0);
+    assert_eq!(node0.stats().sent, 11);
+
+    // and coming back
+    node1.poll(1000).unwrap();
+    assert_eq!(events(&mut hub, 1000), vec!["joined 1"]);
+}
+
+#[test]
+fn it_drops_resent_packets() {
+    let ether = Ether::new();
+    let mut hub = hub(&ether);
+    let mut node = node(&ether, 2);
+    let hub_id = 0;
+
+    // the hub gets the packet, but the ACKs of all auto retransmits get
+    // lost, the node sends it once more
+    ether.set_filter(move |from, _, frame| !(frame == Frame::Ack && from == hub_id));
+    assert!(!node.send(b"once").unwrap());
+    ether.clear_filter();
+    assert_eq!(node.stats().lost, 1);
+
+    assert_eq!(events(&mut hub, 0), vec!["joined 2", "2: once"]);
+    assert_eq!(hub.stats(2).unwrap().duplicates, 1);
+}
+
+#[test]
+fn it_takes_a_reset_node_for_a_new_sender() {
+    let ether = Ether::new();
+    let mut hub = hub(&ether);
+    let mut node = node(&ether, 2);
+
+    assert!(node.send(b"before").unwrap());
+    assert_eq!(events(&mut hub, 0), vec!["joined 2", "2: before"]);
+
+    // after the reset the sequence number starts over at the same value
+    let rx = node.release();
+    let mut node = Node::new(rx, BASE, 2, 2).unwrap().with_config(config());
+    assert!(node.send(b"after").unwrap());
+    assert_eq!(events(&mut hub, 10), vec!["2: after"]);
+    assert_eq!(hub.stats(2).unwrap().duplicates, 0);
+}
@@ -0,0 +1,171 @@
+//! Host tests of the blocking driver against the radio simulator
+use std::convert::Infallible;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use esp32s3_nrf24l01::{Configuration, Error, NRF24L01};
+use nrf24l01_sim::{Ether, Radio};
+
+mod common;
+use common::standby;
+
+const ADDR: &[u8] = b"node1";
+
+/// SPI bus without a radio, MISO is pulled low
+struct FloatingBus;
+
+impl ErrorType for FloatingBus {
+    type Error = Infallible;
+}
+
+impl SpiDevice for FloatingBus {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        for operation in operations {
+            match operation {
+                Operation::Read(words) | Operation::TransferInPlace(words) => words.fill(0x00),
+                Operation::Transfer(read, _) => read.fill(0x00),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Radio whose `R_RX_PL_WID` answers with a corrupted width
+struct CorruptWidth(Radio);
+
+impl ErrorType for CorruptWidth {
+    type Error = <Radio as ErrorType>::Error;
+}
+
+impl SpiDevice for CorruptWidth {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.0.transaction(operations)?;
+        for operation in operations {
+            if let Operation::Transfer(read, [0x60, ..]) = operation {
+                read[1] = 40;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn it_reports_missing_radio() {
+    let ce = Radio::new().ce_pin();
+    assert!(matches!(
+        NRF24L01::new(ce, FloatingBus),
+        Err(Error::NotConnected)
+    ));
+}
+
+#[test]
+fn it_powers_up() {
+    let radio = Radio::new();
+    let mut nrf24 = NRF24L01::new(radio.ce_pin(), radio.clone()).unwrap();
+    // PWR_UP in CONFIG, CE stays low in standby
+    assert_eq!(radio.register(0x00) & 0x02, 0x02);
+    assert!(!radio.is_ce_high());
+    assert_eq!(nrf24.get_address_width().unwrap(), 5);
+
+    let (spi, _ce) = nrf24.power_down().unwrap().release();
+    assert_eq!(spi.register(0x00) & 0x02, 0x00);
+}
+
+#[test]
+fn it_sends_and_receives() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut rx = standby(&rx_radio, ADDR).rx().unwrap();
+
+    tx.send(b"hello").unwrap();
+    assert!(nb::block!(tx.poll_send()).unwrap());
+    assert_eq!(tx.observe().unwrap().arc_cnt(), 0);
+
+    assert_eq!(rx.can_read().unwrap(), Some(0));
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"hello");
+    assert!(rx.is_empty().unwrap());
+}
+
+#[test]
+fn it_flushes_a_corrupted_payload() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut nrf24 = NRF24L01::new(rx_radio.ce_pin(), CorruptWidth(rx_radio.clone())).unwrap();
+    nrf24.set_pipes_rx_lengths(&[None; 6]).unwrap();
+    nrf24.set_rx_addr(0, ADDR).unwrap();
+    let mut rx = nrf24.rx().unwrap();
+
+    tx.send(b"hello").unwrap();
+    assert!(nb::block!(tx.poll_send()).unwrap());
+    assert!(rx_radio.is_irq_active());
+    assert!(rx.read().unwrap().is_none());
+    assert_eq!(rx_radio.rx_fifo_len(), 0);
+    assert!(!rx_radio.is_irq_active());
+}
+
+#[test]
+fn it_reports_max_retransmits() {
+    let radio = Radio::new();
+    let mut nrf24 = standby(&radio, ADDR);
+    nrf24.set_auto_retransmit(2, 5).unwrap();
+    let mut tx = nrf24.tx().unwrap();
+
+    tx.send(b"nobody").unwrap();
+    assert!(!nb::block!(tx.poll_send()).unwrap());
+    let observe = tx.observe().unwrap();
+    assert_eq!((observe.plos_cnt(), observe.arc_cnt()), (1, 5));
+    // the packet was flushed
+    assert!(tx.is_empty().unwrap());
+}
+
+#[test]
+fn it_returns_ack_payloads() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut nrf24 = standby(&tx_radio, ADDR);
+    nrf24.set_features(true, true, false).unwrap();
+    assert_eq!(nrf24.get_features().unwrap(), (true, true, false));
+    let mut tx = nrf24.tx().unwrap();
+    let mut nrf24 = standby(&rx_radio, ADDR);
+    nrf24.set_features(true, true, false).unwrap();
+    let mut rx = nrf24.rx().unwrap();
+
+    assert!(rx.queue_ack_payload(0, b"pong").unwrap());
+    assert!(rx.queue_ack_payload(0, b"pong2").unwrap());
+    assert!(rx.queue_ack_payload(1, b"other").unwrap());
+    // the TX FIFO is full
+    assert!(!rx.queue_ack_payload(0, b"lost").unwrap());
+    // no such pipe, too long
+    assert!(!rx.queue_ack_payload(6, b"pong").unwrap());
+    assert!(!rx.queue_ack_payload(0, &[0; 33]).unwrap());
+
+    tx.send(b"ping").unwrap();
+    assert!(nb::block!(tx.poll_send()).unwrap());
+    assert_eq!(tx.read_ack_payload().unwrap().unwrap().as_ref(), b"pong");
+    assert!(tx.read_ack_payload().unwrap().is_none());
+    assert!(!tx_radio.is_irq_active());
+
+    // the new packet drops the first payload
+    tx.send(b"ping2").unwrap();
+    assert!(nb::block!(tx.poll_send()).unwrap());
+    assert_eq!(tx.read_ack_payload().unwrap().unwrap().as_ref(), b"pong2");
+    assert_eq!(rx_radio.tx_fifo_len(), 2);
+
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"ping");
+    assert_eq!(rx.read().unwrap().unwrap().as_ref(), b"ping2");
+}
+
+#[test]
+fn it_sends_without_ack() {
+    let radio = Radio::new();
+    let mut nrf24 = standby(&radio, ADDR);
+    nrf24.set_features(false, false, true).unwrap();
+    let mut tx = nrf24.tx().unwrap();
+
+    // nobody listens, but no ACK is expected either
+    tx.send_no_ack(b"fire").unwrap();
+    assert!(nb::block!(tx.poll_send()).unwrap());
+    assert_eq!(tx.observe().unwrap().arc_cnt(), 0);
+}
@@ -0,0 +1,127 @@
+//! Host tests of the radio service, the simulated IRQ pin wakes the worker
+#![cfg(feature = "std")]
+use std::convert::Infallible;
+use std::time::Duration;
+
+use esp32s3_nrf24l01::service::{Irq, Polling, Radio, SendError, ServiceConfig, TxResult};
+use esp32s3_nrf24l01::{Configuration, StandbyMode, TxMode};
+use nrf24l01_sim::{Ether, IrqPin};
+
+mod common;
+use common::{wait_until, Device, TIMEOUT};
+
+const SERVICE: &[u8] = b"serv1";
+const REMOTE: &[u8] = b"node1";
+
+struct SimIrq(IrqPin);
+
+impl Irq for SimIrq {
+    type Error = Infallible;
+
+    fn wait(&mut self, timeout: Duration) -> Result<bool, Infallible> {
+        Ok(self.0.wait_active(timeout))
+    }
+}
+
+/// Listens on pipe 1 at `rx`, sends to `tx` with the ACKs on pipe 0
+fn standby(radio: &nrf24l01_sim::Radio, rx: &[u8], tx: &[u8]) -> StandbyMode<Device> {
+    let mut nrf24 = common::standby(radio, tx);
+    nrf24.set_rx_addr(1, rx).unwrap();
+    nrf24
+}
+
+fn spawn(radio: &nrf24l01_sim::Radio, config: ServiceConfig) -> Radio<Device, Infallible> {
+    let pin = radio.irq_pin();
+    let service = Radio::spawn(
+        standby(radio, SERVICE, REMOTE),
+        move || Ok(SimIrq(pin)),
+        config,
+    )
+    .unwrap();
+    wait_until(|| radio.is_ce_high());
+    service
+}
+
+/// Returns whether the service acknowledged `packet`
+fn remote_send(remote: &mut TxMode<Device>, packet: &[u8]) -> bool {
+    remote.send(packet).unwrap();
+    nb::block!(remote.poll_send()).unwrap()
+}
+
+#[test]
+fn it_delivers_received_packets() {
+    let ether = Ether::new();
+    let (radio, remote_radio) = (ether.add_radio(), ether.add_radio());
+    let service = spawn(&radio, ServiceConfig::default());
+    let mut remote = standby(&remote_radio, REMOTE, SERVICE).tx().unwrap();
+
+    assert!(remote_send(&mut remote, b"ping"));
+    assert!(remote_send(&mut remote, b"pong"));
+    let first = service.recv_timeout(TIMEOUT).unwrap();
+    let second = service.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!((first.pipe, first.payload.as_ref()), (1, &b"ping"[..]));
+    assert_eq!((second.pipe, second.payload.as_ref()), (1, &b"pong"[..]));
+    assert!(service.try_recv().is_none());
+
+    service.stop().unwrap();
+    assert!(!radio.is_irq_active());
+}
+
+#[test]
+fn it_reports_tx_results() {
+    let ether = Ether::new();
+    let (radio, remote_radio) = (ether.add_radio(), ether.add_radio());
+    let service = spawn(&radio, ServiceConfig::default());
+
+    // nobody listens yet
+    let lost = service.send(b"anybody?").unwrap();
+    assert_eq!(lost.wait(), Some(TxResult::MaxRetries));
+
+    let remote = standby(&remote_radio, REMOTE, SERVICE).rx().unwrap();
+    let sent = service.send(b"hello").unwrap();
+    assert_eq!(sent.wait(), Some(TxResult::Sent { retransmits: 0 }));
+    assert_eq!(remote_radio.rx_fifo_len(), 1);
+    drop(remote);
+
+    assert_eq!(service.send(&[0; 33]).unwrap_err(), SendError::TooLong);
+    service.stop().unwrap();
+}
+
+#[test]
+fn it_drops_packets_when_the_channel_is_full() {
+    let ether = Ether::new();
+    let (radio, remote_radio) = (ether.add_radio(), ether.add_radio());
+    let config = ServiceConfig {
+        rx_capacity: 1,
+        ..ServiceConfig::default()
+    };
+    let service = spawn(&radio, config);
+    let mut remote = standby(&remote_radio, REMOTE, SERVICE).tx().unwrap();
+
+    for packet in [b"one", b"two", b"six"] {
+        assert!(remote_send(&mut remote, packet));
+    }
+    wait_until(|| service.dropped() == 2);
+    assert_eq!(service.recv().unwrap().payload.as_ref(), b"one");
+    assert!(service.try_recv().is_none());
+    service.stop().unwrap();
+}
+
+#[test]
+fn it_polls_without_irq_pin() {
+    let ether = Ether::new();
+    let (radio, remote_radio) = (ether.add_radio(), ether.add_radio());
+    let service = Radio::spawn(
+        standby(&radio, SERVICE, REMOTE),
+        || Ok(Polling(Duration::from_millis(1))),
+        ServiceConfig::default(),
+    )
+    .unwrap();
+    wait_until(|| radio.is_ce_high());
+    let mut remote = standby(&remote_radio, REMOTE, SERVICE).tx().unwrap();
+
+    assert!(remote_send(&mut remote, b"polled"));
+    let received = service.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(received.payload.as_ref(), b"polled");
+    service.stop().unwrap();
+}
@@ -0,0 +1,199 @@
+//! Host tests of the transport with a simulated radio pair
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use esp32s3_nrf24l01::transport::{
+    Receiver, RetryPolicy, Sender, TransportError, FRAGMENT_LEN, MAX_MESSAGE_LEN,
+};
+use esp32s3_nrf24l01::{Configuration, NRF24L01};
+use nrf24l01_sim::{Ether, Frame, Radio};
+
+mod common;
+use common::{standby, Device, MockDelay, Pump};
+
+const ADDR: &[u8] = b"node1";
+
+/// Receives on pipe 1 from `ADDR`
+fn receiver(radio: &Radio) -> Receiver<Device, 512> {
+    let mut nrf24 = NRF24L01::new(radio.ce_pin(), radio.clone()).unwrap();
+    nrf24.set_rx_addr(1, ADDR).unwrap();
+    Receiver::new(nrf24.rx().unwrap()).unwrap()
+}
+
+/// Collects the complete messages of `receiver`
+fn drain(receiver: &mut Receiver<Device, 512>, messages: &mut Vec<(u8, Vec<u8>)>) {
+    while let Some((pipe, message)) = receiver.receive().unwrap() {
+        messages.push((pipe, message.to_vec()));
+    }
+}
+
+#[test]
+fn it_sends_a_short_message() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut receiver = receiver(&rx_radio);
+    let mut messages = Vec::new();
+
+    let tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut sender = Sender::new(tx, Pump(|| drain(&mut receiver, &mut messages)), 1).unwrap();
+    sender.send(b"hello").unwrap();
+    let stats = sender.stats();
+    assert_eq!((stats.messages, stats.fragments, stats.lost), (1, 1, 0));
+
+    drop(sender);
+    assert_eq!(messages, vec![(1, b"hello".to_vec())]);
+    let stats = receiver.stats();
+    assert_eq!((stats.messages, stats.fragments), (1, 1));
+}
+
+#[test]
+fn it_fragments_long_messages() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut receiver = receiver(&rx_radio);
+    let mut received = Vec::new();
+    let messages: Vec<Vec<u8>> = vec![
+        (0..300).map(|i| i as u8).collect(),
+        vec![],
+        // the CRC starts a new fragment
+        vec![0xAB; FRAGMENT_LEN],
+    ];
+
+    let tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut sender = Sender::new(tx, Pump(|| drain(&mut receiver, &mut received)), 1).unwrap();
+    for message in &messages {
+        sender.send(message).unwrap();
+    }
+    let stats = sender.stats();
+    assert_eq!((stats.messages, stats.fragments), (3, 11 + 1 + 2));
+    // the RX FIFO only holds three fragments, the rest was retried
+    assert!(stats.lost > 0);
+    assert!(stats.retransmits >= 3 * stats.lost);
+
+    drop(sender);
+    let received: Vec<Vec<u8>> = received.into_iter().map(|(_, m)| m).collect();
+    assert_eq!(received, messages);
+    assert_eq!(receiver.stats().duplicates, 0);
+}
+
+#[test]
+fn it_suppresses_duplicates() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut receiver = receiver(&rx_radio);
+    let mut messages = Vec::new();
+
+    // the receiver gets the first fragment, but all ACKs of the auto
+    // retransmits are lost
+    let acks = Arc::new(AtomicU32::new(0));
+    let counter = acks.clone();
+    ether.set_filter(move |_, _, frame| {
+        frame == Frame::Data || counter.fetch_add(1, Ordering::Relaxed) >= 4
+    });
+
+    let tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut sender = Sender::new(tx, Pump(|| drain(&mut receiver, &mut messages)), 1).unwrap();
+    let message: Vec<u8> = (0..40).collect();
+    sender.send(&message).unwrap();
+    let stats = sender.stats();
+    assert_eq!((stats.messages, stats.lost), (1, 1));
+    assert!(acks.load(Ordering::Relaxed) > 4);
+
+    drop(sender);
+    assert_eq!(messages, vec![(1, message)]);
+    let stats = receiver.stats();
+    assert_eq!((stats.messages, stats.duplicates), (1, 1));
+}
+
+#[test]
+fn it_tells_a_reset_sender_from_a_repeat() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut receiver = receiver(&rx_radio);
+    let mut messages = Vec::new();
+
+    let tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let mut sender = Sender::new(tx, Pump(|| drain(&mut receiver, &mut messages)), 1).unwrap();
+    sender.send(b"before").unwrap();
+    let (tx, _) = sender.release();
+    assert_eq!(messages, vec![(1, b"before".to_vec())]);
+    messages.clear();
+
+    // after the reset the sequence number starts over at the same value
+    let mut sender = Sender::new(tx, Pump(|| drain(&mut receiver, &mut messages)), 2).unwrap();
+    sender.send(b"after").unwrap();
+    drop(sender);
+    assert_eq!(messages, vec![(1, b"after".to_vec())]);
+    let stats = receiver.stats();
+    assert_eq!((stats.messages, stats.duplicates), (2, 0));
+}
+
+#[test]
+fn it_repeats_unconfirmed_messages() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut receiver = receiver(&rx_radio);
+    let mut messages = Vec::new();
+
+    let tx = standby(&tx_radio, ADDR).tx().unwrap();
+    let policy = RetryPolicy {
+        max_polls: 1,
+        ..RetryPolicy::default()
+    };
+    let mut sender = Sender::new(tx, Pump(|| drain(&mut receiver, &mut messages)), 1)
+        .unwrap()
+        .with_policy(policy);
+    // one poll is too early for the confirmation, the message is sent
+    // again and confirmed as a duplicate
+    sender.send(b"again").unwrap();
+    assert_eq!(sender.stats().fragments, 2);
+
+    drop(sender);
+    assert_eq!(messages, vec![(1, b"again".to_vec())]);
+    assert_eq!(receiver.receive().unwrap(), None);
+    assert_eq!(receiver.stats().duplicates, 1);
+}
+
+#[test]
+fn it_drops_corrupted_messages() {
+    let ether = Ether::new();
+    let (tx_radio, rx_radio) = (ether.add_radio(), ether.add_radio());
+    let mut receiver = receiver(&rx_radio);
+
+    let mut tx = standby(&tx_radio, ADDR).tx().unwrap();
+    // one fragment with a wrong CRC
+    tx.send(&[1, 0, 0, 1, b'x', 0x00, 0x00]).unwrap();
+    assert!(nb::block!(tx.poll_send()).unwrap());
+
+    assert_eq!(receiver.receive().unwrap(), None);
+    assert_eq!(receiver.stats().crc_errors, 1);
+}
+
+#[test]
+fn it_gives_up_without_receiver() {
+    let radio = Radio::new();
+    let tx = standby(&radio, ADDR).tx().unwrap();
+    let policy = RetryPolicy {
+        max_retries: 2,
+        backoff_us: 100,
+        max_backoff_us: 150,
+        ..RetryPolicy::default()
+    };
+    let mut sender = Sender::new(tx, MockDelay::default(), 1)
+        .unwrap()
+        .with_policy(policy);
+
+    assert!(matches!(
+        sender.send(&[0; MAX_MESSAGE_LEN + 1]),
+        Err(TransportError::TooLong)
+    ));
+    assert!(matches!(
+        sender.send(b"anybody?"),
+        Err(TransportError::MaxRetries)
+    ));
+    let stats = sender.stats();
+    assert_eq!((stats.lost, stats.retransmits), (3, 3 * 3));
+
+    let (_, delay) = sender.release();
+    assert_eq!(delay.waited_us, 100 + 150);
+}
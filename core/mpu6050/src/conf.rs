@@ -5,6 +5,8 @@ pub const DEFAULT_SLAVE_ADDR: u8 = 0x68;
 pub const MPU6050_SMPLRT_DIV: u8 = 0x19;
 // 低通滤波频率，典型值：0x06(5Hz)
 pub const MPU6050_CONFIG: u8 = 0x1A;
+// DLPF_CFG 位掩码, 其余位为 EXT_SYNC_SET
+pub const MPU6050_CONFIG_DLPF_MASK: u8 = 0x07;
 // 陀螺仪自检及测量范围，典型值：0x18(不自检，2000deg/s)
 pub const MPU6050_GYRO_CONFIG: u8 = 0x1B;
 // 加速计自检、测量范围及高通滤波频率，典型值：0x01(不自检，2G，5Hz)
@@ -35,3 +37,12 @@ pub const MPU6050_PWR_MGMT_1: u8 = 0x6B;
 pub const MPU6050_PWR_MGMT_2: u8 = 0x6C;
 // IIC地址寄存器(默认数值0x68，只读)
 pub const MPU6050_WHO_AM_I: u8 = 0x75;
+
+// wake_up 配置的量程下的灵敏度
+// 加速度计 ±16g: 2048 LSB/g
+pub const MPU6050_ACCEL_SENSITIVITY: f32 = 2048.0;
+// 陀螺仪 ±2000deg/s: 16.4 LSB/(deg/s)
+pub const MPU6050_GYRO_SENSITIVITY: f32 = 16.4;
+
+// wake_up 配置的采样率, 单位 Hz
+pub const MPU6050_DEFAULT_SAMPLE_RATE: u32 = 100;
@@ -0,0 +1,432 @@
+//! 姿态解算
+//!
+//! 以固定的采样周期融合加速度计和陀螺仪数据, 输出姿态四元数和欧拉角 (roll/pitch/yaw)。
+//! 支持互补滤波、Madgwick 和 Mahony 三种算法, 可以在运行时切换。
+//! MPU6050 没有磁力计, yaw 只由陀螺仪积分得到, 零偏残差会使其缓慢漂移。
+use core::ops::Mul;
+use core::time::Duration;
+
+/// 四元数, 表示从机体坐标系到参考坐标系的旋转
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::IDENTITY
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    /// 四元数乘法 (Hamilton 积)
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
+
+impl Quaternion {
+    /// 单位四元数, 机体坐标系与参考坐标系重合
+    pub const IDENTITY: Quaternion = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+
+    pub const fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
+        Quaternion { w, x, y, z }
+    }
+
+    /// 由欧拉角计算四元数, 旋转顺序为 ZYX (先 yaw, 再 pitch, 最后 roll)
+    pub fn from_euler(euler: Euler) -> Self {
+        let (sr, cr) = (euler.roll / 2.0).sin_cos();
+        let (sp, cp) = (euler.pitch / 2.0).sin_cos();
+        let (sy, cy) = (euler.yaw / 2.0).sin_cos();
+
+        Quaternion {
+            w: cr * cp * cy + sr * sp * sy,
+            x: sr * cp * cy - cr * sp * sy,
+            y: cr * sp * cy + sr * cp * sy,
+            z: cr * cp * sy - sr * sp * cy,
+        }
+    }
+
+    /// 换算为欧拉角, 旋转顺序为 ZYX
+    pub fn to_euler(&self) -> Euler {
+        let Quaternion { w, x, y, z } = *self;
+
+        Euler {
+            roll: (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y)),
+            pitch: (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin(),
+            yaw: (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z)),
+        }
+    }
+
+    /// 模长
+    pub fn norm(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// 点积
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// 归一化, 模长为 0 时返回单位四元数
+    pub fn normalize(&self) -> Self {
+        let norm = self.norm();
+        if norm == 0.0 || !norm.is_finite() {
+            return Quaternion::IDENTITY;
+        }
+        self.scale(1.0 / norm)
+    }
+
+    /// 共轭, 单位四元数的共轭表示相反的旋转
+    pub fn conjugate(&self) -> Self {
+        Quaternion::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    fn scale(&self, k: f32) -> Self {
+        Quaternion::new(self.w * k, self.x * k, self.y * k, self.z * k)
+    }
+
+    fn add(&self, other: &Quaternion) -> Self {
+        Quaternion::new(
+            self.w + other.w,
+            self.x + other.x,
+            self.y + other.y,
+            self.z + other.z,
+        )
+    }
+
+    /// 线性插值并归一化, t 为 other 的权重
+    fn nlerp(&self, other: &Quaternion, t: f32) -> Self {
+        // q 和 -q 表示同一个旋转, 取夹角较小的一个
+        let other = if self.dot(other) < 0.0 {
+            other.scale(-1.0)
+        } else {
+            *other
+        };
+        self.scale(1.0 - t).add(&other.scale(t)).normalize()
+    }
+
+    /// 四元数的导数, gyro 为机体坐标系下的角速度
+    fn derivative(&self, gyro: [f32; 3]) -> Self {
+        (*self * Quaternion::new(0.0, gyro[0], gyro[1], gyro[2])).scale(0.5)
+    }
+}
+
+/// 欧拉角, 单位为弧度
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Euler {
+    /// 横滚角, 绕 X 轴
+    pub roll: f32,
+    /// 俯仰角, 绕 Y 轴
+    pub pitch: f32,
+    /// 偏航角, 绕 Z 轴
+    pub yaw: f32,
+}
+
+impl Euler {
+    /// 换算为角度
+    pub fn to_degrees(&self) -> Euler {
+        Euler {
+            roll: self.roll.to_degrees(),
+            pitch: self.pitch.to_degrees(),
+            yaw: self.yaw.to_degrees(),
+        }
+    }
+}
+
+/// 姿态解算算法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    /// 互补滤波: 陀螺仪积分与加速度计得到的姿态按权重混合
+    /// alpha: 陀螺仪积分的权重, 典型值 0.98
+    Complementary { alpha: f32 },
+    /// Madgwick 梯度下降
+    /// beta: 加速度计修正的增益, 典型值 0.1
+    Madgwick { beta: f32 },
+    /// Mahony 显式互补滤波
+    /// kp: 比例增益, 典型值 1.0
+    /// ki: 积分增益, 积分项用于补偿陀螺仪零偏, 典型值 0.1
+    Mahony { kp: f32, ki: f32 },
+}
+
+impl Algorithm {
+    /// 使用典型参数的互补滤波
+    pub const fn complementary() -> Self {
+        Algorithm::Complementary { alpha: 0.98 }
+    }
+
+    /// 使用典型参数的 Madgwick
+    pub const fn madgwick() -> Self {
+        Algorithm::Madgwick { beta: 0.1 }
+    }
+
+    /// 使用典型参数的 Mahony
+    pub const fn mahony() -> Self {
+        Algorithm::Mahony { kp: 1.0, ki: 0.1 }
+    }
+}
+
+/// 陀螺仪零偏跟踪
+/// 静止时对去除零偏后的陀螺仪读数做低通滤波, 更新零偏估计
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiasTracking {
+    /// 判断为静止的最大角速度, 单位 rad/s
+    pub gyro_threshold: f32,
+    /// 判断为静止时加速度模长与 1g 的最大偏差, 单位 g
+    pub acc_threshold: f32,
+    /// 低通滤波的时间常数
+    pub time_constant: Duration,
+}
+
+impl Default for BiasTracking {
+    fn default() -> Self {
+        BiasTracking {
+            gyro_threshold: 0.05,
+            acc_threshold: 0.05,
+            time_constant: Duration::from_secs(2),
+        }
+    }
+}
+
+/// 姿态解算器
+pub struct Fusion {
+    algorithm: Algorithm,
+    // 采样周期, 单位秒
+    sample_period: f32,
+    quaternion: Quaternion,
+    gyro_bias: [f32; 3],
+    bias_tracking: Option<BiasTracking>,
+    // Mahony 的积分项, 与零偏符号相反
+    integral: [f32; 3],
+    // 第一个采样用加速度计初始化姿态
+    initialized: bool,
+}
+
+impl Fusion {
+    /// 创建对象, 默认开启零偏跟踪
+    /// sample_period: 调用 update 的周期
+    pub fn new(algorithm: Algorithm, sample_period: Duration) -> Self {
+        Fusion {
+            algorithm,
+            sample_period: sample_period.as_secs_f32(),
+            quaternion: Quaternion::IDENTITY,
+            gyro_bias: [0.0; 3],
+            bias_tracking: Some(BiasTracking::default()),
+            integral: [0.0; 3],
+            initialized: false,
+        }
+    }
+
+    /// 设置零偏跟踪, None 为关闭
+    pub fn with_bias_tracking(mut self, bias_tracking: Option<BiasTracking>) -> Self {
+        self.bias_tracking = bias_tracking;
+        self
+    }
+
+    /// 当前算法
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// 切换算法, 保留当前的姿态和零偏估计
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        // Mahony 积分项估计的零偏合并到零偏估计中
+        self.gyro_bias = self.gyro_bias();
+        self.integral = [0.0; 3];
+        self.algorithm = algorithm;
+    }
+
+    /// 采样周期
+    pub fn sample_period(&self) -> Duration {
+        Duration::from_secs_f32(self.sample_period)
+    }
+
+    /// 设置采样周期
+    pub fn set_sample_period(&mut self, sample_period: Duration) {
+        self.sample_period = sample_period.as_secs_f32();
+    }
+
+    /// 姿态四元数
+    pub fn quaternion(&self) -> Quaternion {
+        self.quaternion
+    }
+
+    /// 欧拉角, 单位为弧度
+    pub fn euler(&self) -> Euler {
+        self.quaternion.to_euler()
+    }
+
+    /// 陀螺仪零偏估计, 单位 rad/s
+    pub fn gyro_bias(&self) -> [f32; 3] {
+        sub(self.gyro_bias, self.integral)
+    }
+
+    /// 设置陀螺仪零偏, 例如使用校准的结果
+    pub fn set_gyro_bias(&mut self, gyro_bias: [f32; 3]) {
+        self.gyro_bias = gyro_bias;
+        self.integral = [0.0; 3];
+    }
+
+    /// 重置姿态, 下一个采样重新用加速度计初始化, 保留零偏估计
+    pub fn reset(&mut self) {
+        self.set_gyro_bias(self.gyro_bias());
+        self.quaternion = Quaternion::IDENTITY;
+        self.initialized = false;
+    }
+
+    /// 输入一个采样, 更新并返回姿态
+    /// acc: 加速度, 单位 g
+    /// gyro: 角速度, 单位 rad/s
+    pub fn update(&mut self, acc: [f32; 3], gyro: [f32; 3]) -> Quaternion {
+        let acc_norm = norm(acc);
+        if !self.initialized {
+            self.initialized = true;
+            if acc_norm > 0.0 {
+                self.quaternion = accel_quaternion(acc, 0.0);
+                return self.quaternion;
+            }
+        }
+
+        self.track_bias(acc_norm, gyro);
+        let gyro = sub(gyro, self.gyro_bias);
+        // 加速度为 0 时 (例如自由落体) 只积分陀螺仪
+        let acc = if acc_norm > 0.0 {
+            Some(scale(acc, 1.0 / acc_norm))
+        } else {
+            None
+        };
+
+        self.quaternion = match self.algorithm {
+            Algorithm::Complementary { alpha } => self.complementary(acc, gyro, alpha),
+            Algorithm::Madgwick { beta } => self.madgwick(acc, gyro, beta),
+            Algorithm::Mahony { kp, ki } => self.mahony(acc, gyro, kp, ki),
+        };
+        self.quaternion
+    }
+
+    /// 静止时更新零偏估计
+    fn track_bias(&mut self, acc_norm: f32, gyro: [f32; 3]) {
+        let Some(tracking) = self.bias_tracking else {
+            return;
+        };
+        let residual = sub(gyro, self.gyro_bias());
+        if norm(residual) > tracking.gyro_threshold
+            || (acc_norm - 1.0).abs() > tracking.acc_threshold
+        {
+            return;
+        }
+
+        let time_constant = tracking.time_constant.as_secs_f32();
+        let k = (self.sample_period / (time_constant + self.sample_period)).min(1.0);
+        self.gyro_bias = add(self.gyro_bias, scale(residual, k));
+    }
+
+    /// 陀螺仪积分
+    fn integrate(&self, gyro: [f32; 3]) -> Quaternion {
+        let derivative = self.quaternion.derivative(gyro);
+        self.quaternion
+            .add(&derivative.scale(self.sample_period))
+            .normalize()
+    }
+
+    fn complementary(&self, acc: Option<[f32; 3]>, gyro: [f32; 3], alpha: f32) -> Quaternion {
+        let q = self.integrate(gyro);
+        let Some(acc) = acc else {
+            return q;
+        };
+        // 加速度计只能得到 roll 和 pitch, yaw 使用陀螺仪积分的结果
+        let q_acc = accel_quaternion(acc, q.to_euler().yaw);
+        q.nlerp(&q_acc, 1.0 - alpha)
+    }
+
+    fn madgwick(&self, acc: Option<[f32; 3]>, gyro: [f32; 3], beta: f32) -> Quaternion {
+        let q = self.quaternion;
+        let mut derivative = q.derivative(gyro);
+
+        if let Some([ax, ay, az]) = acc {
+            let Quaternion { w, x, y, z } = q;
+            // 估计的重力方向与测量值之差
+            let f1 = 2.0 * (x * z - w * y) - ax;
+            let f2 = 2.0 * (w * x + y * z) - ay;
+            let f3 = 1.0 - 2.0 * (x * x + y * y) - az;
+            // 梯度 J^T * f
+            let step = Quaternion::new(
+                -2.0 * y * f1 + 2.0 * x * f2,
+                2.0 * z * f1 + 2.0 * w * f2 - 4.0 * x * f3,
+                -2.0 * w * f1 + 2.0 * z * f2 - 4.0 * y * f3,
+                2.0 * x * f1 + 2.0 * y * f2,
+            );
+            if step.norm() > 0.0 {
+                derivative = derivative.add(&step.normalize().scale(-beta));
+            }
+        }
+
+        q.add(&derivative.scale(self.sample_period)).normalize()
+    }
+
+    fn mahony(&mut self, acc: Option<[f32; 3]>, gyro: [f32; 3], kp: f32, ki: f32) -> Quaternion {
+        let mut gyro = gyro;
+
+        if let Some(acc) = acc {
+            let Quaternion { w, x, y, z } = self.quaternion;
+            // 估计的重力方向
+            let v = [
+                2.0 * (x * z - w * y),
+                2.0 * (w * x + y * z),
+                w * w - x * x - y * y + z * z,
+            ];
+            // 误差为测量值与估计值的叉积
+            let error = cross(acc, v);
+            if ki > 0.0 {
+                self.integral = add(self.integral, scale(error, ki * self.sample_period));
+            }
+            gyro = add(gyro, scale(error, kp));
+        }
+
+        self.integrate(add(gyro, self.integral))
+    }
+}
+
+/// 由加速度计计算姿态, yaw 由调用者给出
+fn accel_quaternion(acc: [f32; 3], yaw: f32) -> Quaternion {
+    let [ax, ay, az] = acc;
+    Quaternion::from_euler(Euler {
+        roll: ay.atan2(az),
+        pitch: (-ax).atan2((ay * ay + az * az).sqrt()),
+        yaw,
+    })
+}
+
+fn norm(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(v: [f32; 3], k: f32) -> [f32; 3] {
+    [v[0] * k, v[1] * k, v[2] * k]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
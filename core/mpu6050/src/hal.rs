@@ -1,25 +1,32 @@
 //! HAL 库版本实现
+//!
+//! 驱动基于 embedded-hal 的 `I2c`, 可以运行在 ESP 的硬件 I2C 上,
+//! 也可以运行在 `reg` 模块的软件 I2C 上, 或者在主机上使用模拟的总线测试。
 
 use super::conf::*;
+pub use super::sample_rate::DlpfBandwidth;
+use super::sample_rate::{output_data_rate, sample_rate_divider};
 pub use super::AccelGyroData;
 
+use embedded_hal::i2c::I2c;
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::{
-    delay::BLOCK,
     gpio::{InputPin, OutputPin},
-    i2c::{I2c, I2cConfig, I2cDriver},
+    i2c::{I2c as I2cPeripheral, I2cConfig, I2cDriver},
     peripheral::Peripheral,
     prelude::FromValueType,
     sys::EspError,
 };
 
 /// MPU6050 芯片
-pub struct Mpu6050<'d> {
-    i2c: I2cDriver<'d>,
+pub struct Mpu6050<I2C> {
+    i2c: I2C,
 }
 
-impl<'d> Mpu6050<'d> {
-    ///  初始化 MPU6050
-    pub fn new<I2C: I2c>(
+#[cfg(target_os = "espidf")]
+impl<'d> Mpu6050<I2cDriver<'d>> {
+    ///  使用 ESP 的 I2C 外设和引脚初始化 MPU6050
+    pub fn from_pins<I2C: I2cPeripheral>(
         i2c: impl Peripheral<P = I2C> + 'd,
         sda: impl Peripheral<P = impl InputPin + OutputPin> + 'd,
         scl: impl Peripheral<P = impl InputPin + OutputPin> + 'd,
@@ -27,80 +34,107 @@ impl<'d> Mpu6050<'d> {
         let config = I2cConfig::new().baudrate(10.kHz().into());
         let i2c = I2cDriver::new(i2c, sda, scl, &config)?;
 
-        let mpu = Mpu6050 { i2c };
-        Ok(mpu)
+        Ok(Mpu6050::new(i2c))
     }
+}
 
-    /// 唤醒 MPU6050
-    pub fn wake_up(&mut self) -> Result<(), EspError> {
-        // 解除休眠状态
-        self.i2c
-            .write(DEFAULT_SLAVE_ADDR, &[MPU6050_PWR_MGMT_1, 0x01], BLOCK)?;
-        self.i2c
-            .write(DEFAULT_SLAVE_ADDR, &[MPU6050_PWR_MGMT_2, 0x00], BLOCK)?;
-        // 陀螺仪采样率，典型值：0x07(125Hz)
+impl<I2C> Mpu6050<I2C>
+where
+    I2C: I2c,
+{
+    /// 使用任意实现了 `I2c` 的总线创建对象
+    pub fn new(i2c: I2C) -> Self {
+        Mpu6050 { i2c }
+    }
+
+    /// 释放 I2C 总线
+    pub fn release(self) -> I2C {
         self.i2c
-            .write(DEFAULT_SLAVE_ADDR, &[MPU6050_SMPLRT_DIV, 0x09], BLOCK)?;
+    }
 
-        // 低通滤波频率，典型值：0x06(5Hz)
+    /// MPU6050 写寄存器函数
+    /// reg_address：寄存器地址
+    /// data：待写入寄存器值
+    pub fn write_reg(&mut self, reg_address: u8, data: u8) -> Result<(), I2C::Error> {
+        self.i2c.write(DEFAULT_SLAVE_ADDR, &[reg_address, data])
+    }
+
+    /// 从 reg_address 开始连续读取多个寄存器
+    pub fn read_regs(&mut self, reg_address: u8, buffer: &mut [u8]) -> Result<(), I2C::Error> {
         self.i2c
-            .write(DEFAULT_SLAVE_ADDR, &[MPU6050_CONFIG, 0x06], BLOCK)?;
+            .write_read(DEFAULT_SLAVE_ADDR, &[reg_address], buffer)
+    }
+
+    /// 读取寄存器
+    pub fn read_reg(&mut self, reg_address: u8) -> Result<u8, I2C::Error> {
+        let mut buffer = [0; 1];
+        self.read_regs(reg_address, &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    /// 唤醒 MPU6050
+    pub fn wake_up(&mut self) -> Result<(), I2C::Error> {
+        // 解除休眠状态
+        self.write_reg(MPU6050_PWR_MGMT_1, 0x01)?;
+        self.write_reg(MPU6050_PWR_MGMT_2, 0x00)?;
+        // 陀螺仪采样率，100Hz
+        let dlpf = DlpfBandwidth::Hz5;
+        let divider = sample_rate_divider(dlpf, MPU6050_DEFAULT_SAMPLE_RATE);
+        self.write_reg(MPU6050_SMPLRT_DIV, divider)?;
+
+        // 低通滤波频率，5Hz
+        self.write_reg(MPU6050_CONFIG, dlpf.to_register())?;
         // 陀螺仪自检及测量范围，典型值：0x18(不自检，2000deg/s)
-        self.i2c
-            .write(DEFAULT_SLAVE_ADDR, &[MPU6050_GYRO_CONFIG, 0x18], BLOCK)?;
+        self.write_reg(MPU6050_GYRO_CONFIG, 0x18)?;
         // 加速计自检、测量范围及高通滤波频率，典型值：0x01(不自检，2G，5Hz
-        self.i2c
-            .write(DEFAULT_SLAVE_ADDR, &[MPU6050_ACCEL_CONFIG, 0x18], BLOCK)?;
+        self.write_reg(MPU6050_ACCEL_CONFIG, 0x18)?;
 
         Ok(())
     }
 
-    /// 获取 MPU6050 ID
-    pub fn get_id(&mut self) -> Result<u8, EspError> {
-        // 创建一个缓冲区用于存储数据
-        let mut buffer: [u8; 14] = [0; 14];
+    /// 设置数字低通滤波器带宽, 保留 EXT_SYNC_SET 位
+    /// 陀螺仪输出频率会随之变化, 需要重新调用 set_sample_rate
+    pub fn set_dlpf(&mut self, dlpf: DlpfBandwidth) -> Result<(), I2C::Error> {
+        let config = self.read_reg(MPU6050_CONFIG)?;
+        let config = (config & !MPU6050_CONFIG_DLPF_MASK) | dlpf.to_register();
+        self.write_reg(MPU6050_CONFIG, config)
+    }
 
-        // 检查mpu6050的设备ID是否正确
-        self.i2c.write_read(
-            DEFAULT_SLAVE_ADDR,
-            &[MPU6050_WHO_AM_I],
-            &mut buffer[0..1],
-            BLOCK,
-        )?;
+    /// 获取数字低通滤波器带宽
+    pub fn get_dlpf(&mut self) -> Result<DlpfBandwidth, I2C::Error> {
+        let config = self.read_reg(MPU6050_CONFIG)?;
+        Ok(DlpfBandwidth::from_register(config))
+    }
 
-        // assert_eq!(buffer[0], DEFAULT_SLAVE_ADDR);
-        Ok(buffer[0])
+    /// 按当前 DLPF 对应的陀螺仪输出频率设置采样率, 单位 Hz
+    /// 分频后的实际采样率可通过 get_sample_rate 获取
+    pub fn set_sample_rate(&mut self, hz: u32) -> Result<(), I2C::Error> {
+        let dlpf = self.get_dlpf()?;
+        self.write_reg(MPU6050_SMPLRT_DIV, sample_rate_divider(dlpf, hz))
+    }
+
+    /// 获取实际的采样率 (输出数据频率), 单位 Hz
+    pub fn get_sample_rate(&mut self) -> Result<f32, I2C::Error> {
+        let dlpf = self.get_dlpf()?;
+        let divider = self.read_reg(MPU6050_SMPLRT_DIV)?;
+        Ok(output_data_rate(dlpf, divider))
+    }
+
+    /// 获取 MPU6050 ID
+    /// 0x68: 代表MPU6050芯片
+    pub fn get_id(&mut self) -> Result<u8, I2C::Error> {
+        self.read_reg(MPU6050_WHO_AM_I)
     }
 
     /// 获取 MPU6050 数据
     /// 读取加速度和角速度数据
-    pub fn get_data(&mut self) -> Result<AccelGyroData, EspError> {
+    pub fn get_data(&mut self) -> Result<AccelGyroData, I2C::Error> {
         // 创建一个缓冲区用于存储数据
         let mut buffer: [u8; 14] = [0; 14];
 
-        // 从mpu6050中读取14个字节的数据，包括加速度和角速度
-        self.i2c.write_read(
-            DEFAULT_SLAVE_ADDR,
-            &[MPU6050_ACCEL_XOUT_H],
-            &mut buffer,
-            BLOCK,
-        )?;
-
-        // 将数据转换为有符号的16位整数
-        let acc_x = (buffer[0] as i16) << 8 | buffer[1] as i16;
-        let acc_y = (buffer[2] as i16) << 8 | buffer[3] as i16;
-        let acc_z = (buffer[4] as i16) << 8 | buffer[5] as i16;
-        let gyro_x = (buffer[8] as i16) << 8 | buffer[9] as i16;
-        let gyro_y = (buffer[10] as i16) << 8 | buffer[11] as i16;
-        let gyro_z = (buffer[12] as i16) << 8 | buffer[13] as i16;
-
-        Ok(AccelGyroData {
-            acc_x,
-            acc_y,
-            acc_z,
-            gyro_x,
-            gyro_y,
-            gyro_z,
-        })
+        // 从mpu6050中连续读取14个字节的数据，包括加速度、温度和角速度
+        self.read_regs(MPU6050_ACCEL_XOUT_H, &mut buffer)?;
+
+        Ok(AccelGyroData::from_bytes(&buffer))
     }
 }
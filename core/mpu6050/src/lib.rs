@@ -1,9 +1,11 @@
 pub mod conf;
+pub mod fusion;
 pub mod hal;
 pub mod reg;
+pub mod sample_rate;
 
 /// 加速度和角速度数据
-#[derive(Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct AccelGyroData {
     pub acc_x: i16,
     pub acc_y: i16,
@@ -12,3 +14,39 @@ pub struct AccelGyroData {
     pub gyro_y: i16,
     pub gyro_z: i16,
 }
+
+impl AccelGyroData {
+    /// 从 ACCEL_XOUT_H 开始的 14 个寄存器解码
+    /// 每个测量值为高字节在前的有符号16位整数, 中间的 2 个字节为温度
+    pub fn from_bytes(buffer: &[u8; 14]) -> Self {
+        let word = |i: usize| i16::from_be_bytes([buffer[i], buffer[i + 1]]);
+        AccelGyroData {
+            acc_x: word(0),
+            acc_y: word(2),
+            acc_z: word(4),
+            gyro_x: word(8),
+            gyro_y: word(10),
+            gyro_z: word(12),
+        }
+    }
+
+    /// 加速度, 单位 g, 按照 wake_up 配置的 ±16g 量程换算
+    pub fn acc_g(&self) -> [f32; 3] {
+        let k = 1.0 / conf::MPU6050_ACCEL_SENSITIVITY;
+        [
+            self.acc_x as f32 * k,
+            self.acc_y as f32 * k,
+            self.acc_z as f32 * k,
+        ]
+    }
+
+    /// 角速度, 单位 rad/s, 按照 wake_up 配置的 ±2000deg/s 量程换算
+    pub fn gyro_rad(&self) -> [f32; 3] {
+        let k = (1.0 / conf::MPU6050_GYRO_SENSITIVITY).to_radians();
+        [
+            self.gyro_x as f32 * k,
+            self.gyro_y as f32 * k,
+            self.gyro_z as f32 * k,
+        ]
+    }
+}
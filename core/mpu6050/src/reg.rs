@@ -1,59 +1,123 @@
 //! 寄存器版本实现
-
-use super::conf::*;
-pub use super::AccelGyroData;
-
+//!
+//! 使用 GPIO 软件模拟 I2C 时序, 实现 embedded-hal 的 `I2c`。
+//! 寄存器读写与 HAL 库版本共用 `hal::Mpu6050`, 这里只负责起始/结束信号、按位收发和应答。
+use core::fmt::Debug;
+use core::time::Duration;
+use std::time::Instant;
+
+use embedded_hal::{
+    digital::{InputPin, OutputPin},
+    i2c::{self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress},
+};
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::{
-    gpio::{InputOutput, InputPin, Output, OutputPin, Pin, PinDriver},
+    gpio::{AnyIOPin, InputOutput, Output, PinDriver},
     sys::EspError,
 };
 
-const DEFAULT_SLAVE_ADDR: u8 = 0xD0;
+#[cfg(target_os = "espidf")]
+use super::hal;
 
-/// MPU6050 芯片
-pub struct Mpu6050<'d, SclPin, SdaPin>
-where
-    SclPin: Pin + OutputPin,
-    SdaPin: Pin + InputPin + OutputPin,
-{
-    scl: PinDriver<'d, SclPin, Output>,
-    sda: PinDriver<'d, SdaPin, InputOutput>,
+/// 软件 I2C 的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoftI2cError<E> {
+    /// 引脚操作错误
+    Pin(E),
+    /// 没有收到从机的应答
+    NoAcknowledge(NoAcknowledgeSource),
+}
+
+impl<E> From<E> for SoftI2cError<E> {
+    fn from(e: E) -> Self {
+        SoftI2cError::Pin(e)
+    }
+}
+
+impl<E: Debug> i2c::Error for SoftI2cError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            SoftI2cError::Pin(_) => ErrorKind::Other,
+            SoftI2cError::NoAcknowledge(source) => ErrorKind::NoAcknowledge(*source),
+        }
+    }
+}
+
+/// 使用 GPIO 模拟的 I2C 总线
+/// SCL 和 SDA 需要配置为开漏输出并上拉, SDA 输出高电平时释放总线, 可以读取从机的数据
+pub struct SoftI2c<SCL, SDA> {
+    scl: SCL,
+    sda: SDA,
+    // 半个时钟周期的延时
+    clock_delay: Duration,
 }
 
-impl<'d, SclPin, SdaPin> Mpu6050<'d, SclPin, SdaPin>
+impl<SCL, SDA, E> SoftI2c<SCL, SDA>
 where
-    SclPin: Pin + OutputPin,
-    SdaPin: Pin + InputPin + OutputPin,
+    SCL: OutputPin<Error = E>,
+    SDA: OutputPin<Error = E> + InputPin<Error = E>,
+    E: Debug,
 {
-    fn i2c_w_scl(&mut self, bit_value: u8) -> Result<(), EspError> {
+    /// 创建对象并释放总线
+    pub fn new(scl: SCL, sda: SDA) -> Result<Self, E> {
+        let mut i2c = SoftI2c {
+            scl,
+            sda,
+            clock_delay: Duration::ZERO,
+        };
+        // I2C 初始化
+        i2c.i2c_w_scl(1)?;
+        i2c.i2c_w_sda(1)?;
+        Ok(i2c)
+    }
+
+    /// 设置半个时钟周期的延时, 用于降低时钟频率
+    pub fn with_clock_delay(mut self, clock_delay: Duration) -> Self {
+        self.clock_delay = clock_delay;
+        self
+    }
+
+    /// 释放引脚
+    pub fn release(self) -> (SCL, SDA) {
+        (self.scl, self.sda)
+    }
+
+    /// 等待半个时钟周期
+    fn delay(&self) {
+        if self.clock_delay.is_zero() {
+            return;
+        }
+        let start = Instant::now();
+        while start.elapsed() < self.clock_delay {}
+    }
+
+    fn i2c_w_scl(&mut self, bit_value: u8) -> Result<(), E> {
         if bit_value == 0 {
             self.scl.set_low()?;
         } else {
             self.scl.set_high()?;
         }
+        self.delay();
         Ok(())
     }
 
-    fn i2c_w_sda(&mut self, bit_value: u8) -> Result<(), EspError> {
+    fn i2c_w_sda(&mut self, bit_value: u8) -> Result<(), E> {
         if bit_value == 0 {
             self.sda.set_low()?;
         } else {
             self.sda.set_high()?;
         }
+        self.delay();
         Ok(())
     }
 
-    fn i2c_r_sda(&mut self) -> Result<u8, EspError> {
-        let bit_value = self.sda.is_high();
-        if bit_value {
-            Ok(1)
-        } else {
-            Ok(0)
-        }
+    fn i2c_r_sda(&mut self) -> Result<u8, E> {
+        let bit_value = self.sda.is_high()?;
+        Ok(bit_value as u8)
     }
 
-    /// 产生 I2C 协议起始信号
-    fn i2c_start(&mut self) -> Result<(), EspError> {
+    /// 产生 I2C 协议起始信号, 也用作重复起始信号
+    fn i2c_start(&mut self) -> Result<(), E> {
         self.i2c_w_sda(1)?;
         self.i2c_w_scl(1)?;
         self.i2c_w_sda(0)?;
@@ -62,7 +126,7 @@ where
     }
 
     /// 产生 I2C 协议结束信号
-    fn i2c_stop(&mut self) -> Result<(), EspError> {
+    fn i2c_stop(&mut self) -> Result<(), E> {
         self.i2c_w_sda(0)?;
         self.i2c_w_scl(1)?;
         self.i2c_w_sda(1)?;
@@ -70,7 +134,7 @@ where
     }
 
     /// 发送八位数据（不包含应答）
-    fn i2c_send_byte(&mut self, byte: u8) -> Result<(), EspError> {
+    fn i2c_send_byte(&mut self, byte: u8) -> Result<(), E> {
         for i in 0..8 {
             self.i2c_w_sda(byte & (0x80 >> i))?;
             self.i2c_w_scl(1)?;
@@ -80,7 +144,7 @@ where
     }
 
     /// 读取八位数据（不包含应答）
-    fn i2c_receive_byte(&mut self) -> Result<u8, EspError> {
+    fn i2c_receive_byte(&mut self) -> Result<u8, E> {
         self.i2c_w_sda(1)?;
 
         let mut byte = 0x00;
@@ -94,16 +158,16 @@ where
         Ok(byte)
     }
 
-    /// 发送应答信号
-    fn i2c_send_ack(&mut self, ack_bit: u8) -> Result<(), EspError> {
+    /// 发送应答信号, 0 为应答, 1 为非应答
+    fn i2c_send_ack(&mut self, ack_bit: u8) -> Result<(), E> {
         self.i2c_w_sda(ack_bit)?;
         self.i2c_w_scl(1)?;
         self.i2c_w_scl(0)?;
         Ok(())
     }
 
-    /// 接收应答信号
-    fn i2c_receive_ack(&mut self) -> Result<u8, EspError> {
+    /// 接收应答信号, 0 为应答, 1 为非应答
+    fn i2c_receive_ack(&mut self) -> Result<u8, E> {
         self.i2c_w_sda(1)?;
         self.i2c_w_scl(1)?;
         let ack_bit = self.i2c_r_sda()?;
@@ -111,141 +175,104 @@ where
         Ok(ack_bit)
     }
 
-    /// I2C 初始化
-    fn init_i2c(&mut self) -> Result<(), EspError> {
-        self.i2c_w_scl(1)?;
-        self.i2c_w_sda(1)?;
-        Ok(())
-    }
-}
-
-impl<'d, SclPin, SdaPin> Mpu6050<'d, SclPin, SdaPin>
-where
-    SclPin: Pin + OutputPin,
-    SdaPin: Pin + InputPin + OutputPin,
-{
-    pub fn new(scl: SclPin, sda: SdaPin) -> Result<Self, EspError> {
-        let scl_driver = PinDriver::output_od(scl)?;
-        let sda_driver = PinDriver::input_output(sda)?;
-        let mut mpu = Mpu6050 {
-            scl: scl_driver,
-            sda: sda_driver,
-        };
-
-        // I2C 初始化
-        mpu.init_i2c()?;
-
-        // MPU6050 初始化
-        mpu.init_mpu6050()?;
-        Ok(mpu)
-    }
-
-    /// MPU6050 初始化
-    pub fn init_mpu6050(&mut self) -> Result<(), EspError> {
-        // 解除休眠状态
-        self.write_reg(MPU6050_PWR_MGMT_1, 0x01)?;
-        self.write_reg(MPU6050_PWR_MGMT_2, 0x00)?;
-        // 陀螺仪采样率，典型值：0x07(125Hz)
-        self.write_reg(MPU6050_SMPLRT_DIV, 0x09)?;
-        // 低通滤波频率，典型值：0x06(5Hz)
-        self.write_reg(MPU6050_CONFIG, 0x06)?;
-        // 陀螺仪自检及测量范围，典型值：0x18(不自检，2000deg/s)
-        self.write_reg(MPU6050_GYRO_CONFIG, 0x18)?;
-        // 加速计自检、测量范围及高通滤波频率，典型值：0x01(不自检，2G，5Hz)
-        self.write_reg(MPU6050_ACCEL_CONFIG, 0x18)?;
-
+    /// 发送一个字节并检查应答
+    fn write_byte(&mut self, byte: u8, source: NoAcknowledgeSource) -> Result<(), SoftI2cError<E>> {
+        self.i2c_send_byte(byte)?;
+        if self.i2c_receive_ack()? != 0 {
+            return Err(SoftI2cError::NoAcknowledge(source));
+        }
         Ok(())
     }
 
-    /// MPU6050 写寄存器函数
-    /// reg_address：寄存器地址
-    /// data：待写入寄存器值
-    pub fn write_reg(&mut self, reg_address: u8, data: u8) -> Result<(), EspError> {
-        // 发送起始信号
-        self.i2c_start()?;
-
-        // 发送设备地址
-        self.i2c_send_byte(DEFAULT_SLAVE_ADDR)?;
-        self.i2c_receive_ack()?;
-
-        // 发送寄存器地址
-        self.i2c_send_byte(reg_address)?;
-        self.i2c_receive_ack()?;
-
-        // 写数据到寄存器
-        self.i2c_send_byte(data)?;
-        self.i2c_receive_ack()?;
-
-        self.i2c_stop()?;
-
+    /// 执行一次传输, 不包含结束信号
+    fn operations(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SoftI2cError<E>> {
+        let mut last_read = None;
+        for i in 0..operations.len() {
+            let read = matches!(operations[i], Operation::Read(_));
+            // 相邻的同类操作合并, 读写切换时发送重复起始信号
+            if last_read != Some(read) {
+                self.i2c_start()?;
+                self.write_byte(address << 1 | read as u8, NoAcknowledgeSource::Address)?;
+            }
+            let next_read = matches!(operations.get(i + 1), Some(Operation::Read(_)));
+
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    for byte in bytes.iter() {
+                        self.write_byte(*byte, NoAcknowledgeSource::Data)?;
+                    }
+                }
+                Operation::Read(buffer) => {
+                    let len = buffer.len();
+                    for (j, byte) in buffer.iter_mut().enumerate() {
+                        *byte = self.i2c_receive_byte()?;
+                        // 连续读取的最后一个字节发送非应答
+                        let last = j + 1 == len && !next_read;
+                        self.i2c_send_ack(last as u8)?;
+                    }
+                }
+            }
+            last_read = Some(read);
+        }
         Ok(())
     }
+}
 
-    /// 读取寄存器
-    pub fn read_reg(&mut self, reg_address: u8) -> Result<i16, EspError> {
-        // 发送起始信号
-        self.i2c_start()?;
-
-        // 发送设备地址
-        self.i2c_send_byte(DEFAULT_SLAVE_ADDR)?;
-        self.i2c_receive_ack()?;
-
-        // 发送寄存器地址
-        self.i2c_send_byte(reg_address)?;
-        self.i2c_receive_ack()?;
-
-        // 发送重复起始信号
-        self.i2c_start()?;
-        // 发送读模式设备地址
-        self.i2c_send_byte(DEFAULT_SLAVE_ADDR | 0x01)?;
-        self.i2c_receive_ack()?;
-
-        // 读寄存器数据
-        let data = self.i2c_receive_byte()?;
-        // 非应答信号
-        self.i2c_send_ack(1)?;
+impl<SCL, SDA, E> ErrorType for SoftI2c<SCL, SDA>
+where
+    SCL: OutputPin<Error = E>,
+    SDA: OutputPin<Error = E> + InputPin<Error = E>,
+    E: Debug,
+{
+    type Error = SoftI2cError<E>;
+}
 
+impl<SCL, SDA, E> I2c for SoftI2c<SCL, SDA>
+where
+    SCL: OutputPin<Error = E>,
+    SDA: OutputPin<Error = E> + InputPin<Error = E>,
+    E: Debug,
+{
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let result = self.operations(address, operations);
+        // 出错时也要发送结束信号, 释放总线
         self.i2c_stop()?;
-
-        Ok(data as i16)
-    }
-
-    /// 获取 MPU6050 ID
-    pub fn get_id(&mut self) -> Result<u8, EspError> {
-        let data = self.read_reg(MPU6050_WHO_AM_I)?;
-
-        Ok(data as u8)
+        result
     }
+}
 
-    /// 基本数据读取
-    /// 连续读两个寄存器并合成 16 位数据
-    pub fn get_data(&mut self) -> Result<AccelGyroData, EspError> {
-        let mut data = AccelGyroData::default();
-
-        let data_h = self.read_reg(MPU6050_ACCEL_XOUT_H)?;
-        let data_l = self.read_reg(MPU6050_ACCEL_XOUT_L)?;
-        data.acc_x = (data_h << 8) | data_l;
-
-        let data_h = self.read_reg(MPU6050_ACCEL_YOUT_H)?;
-        let data_l = self.read_reg(MPU6050_ACCEL_YOUT_L)?;
-        data.acc_y = (data_h << 8) | data_l;
-
-        let data_h = self.read_reg(MPU6050_ACCEL_ZOUT_H)?;
-        let data_l = self.read_reg(MPU6050_ACCEL_ZOUT_L)?;
-        data.acc_z = (data_h << 8) | data_l;
-
-        let data_h = self.read_reg(MPU6050_GYRO_XOUT_H)?;
-        let data_l = self.read_reg(MPU6050_GYRO_XOUT_L)?;
-        data.gyro_x = (data_h << 8) | data_l;
-
-        let data_h = self.read_reg(MPU6050_GYRO_YOUT_H)?;
-        let data_l = self.read_reg(MPU6050_GYRO_YOUT_L)?;
-        data.gyro_y = (data_h << 8) | data_l;
-
-        let data_h = self.read_reg(MPU6050_GYRO_ZOUT_H)?;
-        let data_l = self.read_reg(MPU6050_GYRO_ZOUT_L)?;
-        data.gyro_z = (data_h << 8) | data_l;
-
-        Ok(data)
+/// ESP 的 GPIO 模拟的 I2C 总线
+#[cfg(target_os = "espidf")]
+pub type EspSoftI2c<'d> =
+    SoftI2c<PinDriver<'d, AnyIOPin, Output>, PinDriver<'d, AnyIOPin, InputOutput>>;
+
+/// 使用 GPIO 模拟 I2C 的 MPU6050
+#[cfg(target_os = "espidf")]
+pub type Mpu6050<'d> = hal::Mpu6050<EspSoftI2c<'d>>;
+
+#[cfg(target_os = "espidf")]
+impl<'d> hal::Mpu6050<EspSoftI2c<'d>> {
+    /// 使用 GPIO 初始化 MPU6050
+    pub fn from_soft_pins(scl: AnyIOPin, sda: AnyIOPin) -> Result<Self, EspError> {
+        let mut scl = PinDriver::output_od(scl)?;
+        let mut sda = PinDriver::input_output_od(sda)?;
+        // 释放总线
+        scl.set_high()?;
+        sda.set_high()?;
+
+        let i2c = SoftI2c {
+            scl,
+            sda,
+            clock_delay: Duration::ZERO,
+        };
+        Ok(hal::Mpu6050::new(i2c))
     }
 }
@@ -0,0 +1,93 @@
+//! 采样率与数字低通滤波器
+//!
+//! 陀螺仪输出频率由 DLPF 决定: 关闭 DLPF (260Hz) 时为 8kHz, 其余为 1kHz。
+//! 采样率 = 陀螺仪输出频率 / (1 + SMPLRT_DIV), 加速度计输出频率固定为 1kHz。
+
+/// 数字低通滤波器带宽, CONFIG 寄存器的 DLPF_CFG 位
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DlpfBandwidth {
+    /// 加速度计 260Hz, 陀螺仪 256Hz, 即关闭 DLPF
+    #[default]
+    Hz260 = 0,
+    /// 加速度计 184Hz, 陀螺仪 188Hz
+    Hz184 = 1,
+    /// 加速度计 94Hz, 陀螺仪 98Hz
+    Hz94 = 2,
+    /// 加速度计 44Hz, 陀螺仪 42Hz
+    Hz44 = 3,
+    /// 加速度计 21Hz, 陀螺仪 20Hz
+    Hz21 = 4,
+    /// 加速度计 10Hz, 陀螺仪 10Hz
+    Hz10 = 5,
+    /// 加速度计 5Hz, 陀螺仪 5Hz
+    Hz5 = 6,
+}
+
+impl DlpfBandwidth {
+    /// 由 DLPF_CFG 的值转换, 保留值 7 按关闭 DLPF 处理
+    pub fn from_register(value: u8) -> Self {
+        match value & 0x07 {
+            1 => DlpfBandwidth::Hz184,
+            2 => DlpfBandwidth::Hz94,
+            3 => DlpfBandwidth::Hz44,
+            4 => DlpfBandwidth::Hz21,
+            5 => DlpfBandwidth::Hz10,
+            6 => DlpfBandwidth::Hz5,
+            _ => DlpfBandwidth::Hz260,
+        }
+    }
+
+    /// DLPF_CFG 的值
+    pub fn to_register(self) -> u8 {
+        self as u8
+    }
+
+    /// 陀螺仪输出频率, 单位 Hz
+    pub fn gyro_output_rate(self) -> u32 {
+        match self {
+            DlpfBandwidth::Hz260 => 8000,
+            _ => 1000,
+        }
+    }
+
+    /// 加速度计带宽, 单位 Hz
+    pub fn accel_bandwidth(self) -> u16 {
+        match self {
+            DlpfBandwidth::Hz260 => 260,
+            DlpfBandwidth::Hz184 => 184,
+            DlpfBandwidth::Hz94 => 94,
+            DlpfBandwidth::Hz44 => 44,
+            DlpfBandwidth::Hz21 => 21,
+            DlpfBandwidth::Hz10 => 10,
+            DlpfBandwidth::Hz5 => 5,
+        }
+    }
+
+    /// 陀螺仪带宽, 单位 Hz
+    pub fn gyro_bandwidth(self) -> u16 {
+        match self {
+            DlpfBandwidth::Hz260 => 256,
+            DlpfBandwidth::Hz184 => 188,
+            DlpfBandwidth::Hz94 => 98,
+            DlpfBandwidth::Hz44 => 42,
+            DlpfBandwidth::Hz21 => 20,
+            DlpfBandwidth::Hz10 => 10,
+            DlpfBandwidth::Hz5 => 5,
+        }
+    }
+}
+
+/// 计算最接近目标采样率的 SMPLRT_DIV
+/// 超出范围时取能达到的最高或最低采样率
+pub fn sample_rate_divider(dlpf: DlpfBandwidth, hz: u32) -> u8 {
+    let gyro_rate = dlpf.gyro_output_rate();
+    let hz = hz.clamp(1, gyro_rate);
+    // 四舍五入
+    let divisor = (gyro_rate + hz / 2) / hz;
+    (divisor.clamp(1, 256) - 1) as u8
+}
+
+/// 实际的采样率 (输出数据频率), 单位 Hz
+pub fn output_data_rate(dlpf: DlpfBandwidth, divider: u8) -> f32 {
+    dlpf.gyro_output_rate() as f32 / (1.0 + divider as f32)
+}
@@ -0,0 +1,245 @@
+//! 使用合成的 IMU 数据测试姿态解算
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use mpu6050::fusion::{Algorithm, BiasTracking, Euler, Fusion, Quaternion};
+
+const RATE: f32 = 100.0;
+
+fn algorithms() -> [Algorithm; 3] {
+    [
+        Algorithm::complementary(),
+        Algorithm::madgwick(),
+        Algorithm::mahony(),
+    ]
+}
+
+fn fusion(algorithm: Algorithm) -> Fusion {
+    Fusion::new(algorithm, Duration::from_secs_f32(1.0 / RATE))
+}
+
+/// 机体坐标系下的重力加速度读数, 静止时加速度计测量到的是支撑力, 方向向上
+fn gravity(euler: Euler) -> [f32; 3] {
+    let q = Quaternion::from_euler(euler);
+    let up = q.conjugate() * Quaternion::new(0.0, 0.0, 0.0, 1.0) * q;
+    [up.x, up.y, up.z]
+}
+
+/// 两个角度的差, 范围 -PI ~ PI
+fn angle_diff(a: f32, b: f32) -> f32 {
+    let d = (a - b) % (2.0 * PI);
+    if d > PI {
+        d - 2.0 * PI
+    } else if d < -PI {
+        d + 2.0 * PI
+    } else {
+        d
+    }
+}
+
+/// 输入 seconds 秒的数据, trace 返回 t 时刻的真实姿态和角速度, 返回跳过前 settle 秒后 roll/pitch 的最大误差
+fn run(
+    fusion: &mut Fusion,
+    seconds: f32,
+    settle: f32,
+    bias: [f32; 3],
+    trace: impl Fn(f32) -> (Euler, [f32; 3]),
+) -> f32 {
+    let mut max_error: f32 = 0.0;
+    for i in 0..(seconds * RATE) as usize {
+        let t = i as f32 / RATE;
+        let (euler, gyro) = trace(t);
+        let gyro = [gyro[0] + bias[0], gyro[1] + bias[1], gyro[2] + bias[2]];
+        fusion.update(gravity(euler), gyro);
+
+        if t >= settle {
+            let estimate = fusion.euler();
+            max_error = max_error
+                .max(angle_diff(estimate.roll, euler.roll).abs())
+                .max(angle_diff(estimate.pitch, euler.pitch).abs());
+        }
+    }
+    max_error
+}
+
+fn level(_t: f32) -> (Euler, [f32; 3]) {
+    (Euler::default(), [0.0; 3])
+}
+
+#[test]
+fn it_quaternion_euler_round_trip() {
+    let euler = Euler {
+        roll: 0.3,
+        pitch: -0.7,
+        yaw: 2.5,
+    };
+    let result = Quaternion::from_euler(euler).to_euler();
+    assert!((result.roll - euler.roll).abs() < 1e-5);
+    assert!((result.pitch - euler.pitch).abs() < 1e-5);
+    assert!((result.yaw - euler.yaw).abs() < 1e-5);
+    assert!((Quaternion::from_euler(euler).norm() - 1.0).abs() < 1e-6);
+
+    let degrees = Euler {
+        roll: PI,
+        pitch: PI / 2.0,
+        yaw: -PI / 4.0,
+    }
+    .to_degrees();
+    assert!((degrees.roll - 180.0).abs() < 1e-4);
+    assert!((degrees.pitch - 90.0).abs() < 1e-4);
+    assert!((degrees.yaw + 45.0).abs() < 1e-4);
+}
+
+#[test]
+fn it_initializes_from_accelerometer() {
+    let tilt = Euler {
+        roll: 0.4,
+        pitch: -0.3,
+        yaw: 0.0,
+    };
+    for algorithm in algorithms() {
+        let mut fusion = fusion(algorithm);
+        fusion.update(gravity(tilt), [0.0; 3]);
+        let euler = fusion.euler();
+        assert!((euler.roll - tilt.roll).abs() < 1e-4, "{:?}", algorithm);
+        assert!((euler.pitch - tilt.pitch).abs() < 1e-4, "{:?}", algorithm);
+    }
+}
+
+#[test]
+fn it_tracks_gyro_bias_while_stationary() {
+    let bias = [0.02, -0.015, 0.01];
+    for algorithm in algorithms() {
+        let mut fusion = fusion(algorithm);
+        let error = run(&mut fusion, 30.0, 0.0, bias, level);
+
+        assert!(error < 1f32.to_radians(), "{:?}: {}", algorithm, error);
+        // 零偏收敛前 yaw 会有少量漂移
+        assert!(
+            fusion.euler().yaw.abs() < 3f32.to_radians(),
+            "{:?}",
+            algorithm
+        );
+        for (estimate, bias) in fusion.gyro_bias().iter().zip(bias) {
+            assert!((estimate - bias).abs() < 1e-3, "{:?}", algorithm);
+        }
+    }
+}
+
+#[test]
+fn it_corrects_gyro_drift_with_accelerometer() {
+    // 关闭零偏跟踪, 只靠加速度计修正 roll 的漂移
+    let tilt = Euler {
+        roll: 20f32.to_radians(),
+        pitch: 0.0,
+        yaw: 0.0,
+    };
+    for algorithm in algorithms() {
+        let mut fusion = fusion(algorithm).with_bias_tracking(None);
+        let error = run(&mut fusion, 30.0, 10.0, [0.05, 0.0, 0.0], |_| {
+            (tilt, [0.0; 3])
+        });
+        assert!(error < 3f32.to_radians(), "{:?}: {}", algorithm, error);
+    }
+
+    // Mahony 的积分项估计出零偏
+    let mut fusion = fusion(Algorithm::mahony()).with_bias_tracking(None);
+    run(&mut fusion, 60.0, 0.0, [0.05, -0.03, 0.0], |_| {
+        (tilt, [0.0; 3])
+    });
+    let bias = fusion.gyro_bias();
+    assert!((bias[0] - 0.05).abs() < 5e-3, "{:?}", bias);
+    assert!((bias[1] + 0.03).abs() < 5e-3, "{:?}", bias);
+}
+
+#[test]
+fn it_integrates_yaw() {
+    for algorithm in algorithms() {
+        let mut fusion = fusion(algorithm);
+        let error = run(&mut fusion, 2.0, 0.0, [0.0; 3], |t| {
+            let euler = Euler {
+                yaw: 0.5 * t,
+                ..Default::default()
+            };
+            (euler, [0.0, 0.0, 0.5])
+        });
+
+        assert!(error < 0.5f32.to_radians(), "{:?}: {}", algorithm, error);
+        // 第一个采样用于初始化, 共积分 1.99s
+        let yaw = fusion.euler().yaw;
+        assert!((yaw - 0.5 * 1.99).abs() < 0.01, "{:?}: {}", algorithm, yaw);
+    }
+}
+
+#[test]
+fn it_follows_roll_and_pitch_motion() {
+    // roll 和 pitch 分别以 0.5Hz 和 0.3Hz 摆动
+    let trace = |t: f32| {
+        let (wr, wp) = (2.0 * PI * 0.5, 2.0 * PI * 0.3);
+        let euler = Euler {
+            roll: 0.5 * (wr * t).sin(),
+            pitch: 0.3 * (wp * t).sin(),
+            yaw: 0.0,
+        };
+        let roll_rate = 0.5 * wr * (wr * t).cos();
+        let pitch_rate = 0.3 * wp * (wp * t).cos();
+        // yaw 为 0 时, 机体角速度 = (roll' , pitch' * cos(roll), -pitch' * sin(roll))
+        let gyro = [
+            roll_rate,
+            pitch_rate * euler.roll.cos(),
+            -pitch_rate * euler.roll.sin(),
+        ];
+        (euler, gyro)
+    };
+
+    for algorithm in algorithms() {
+        let mut fusion = fusion(algorithm);
+        let error = run(&mut fusion, 20.0, 1.0, [0.0; 3], trace);
+        assert!(error < 2f32.to_radians(), "{:?}: {}", algorithm, error);
+    }
+}
+
+#[test]
+fn it_switches_algorithm_at_runtime() {
+    let tilt = Euler {
+        roll: -0.3,
+        pitch: 0.2,
+        yaw: 0.0,
+    };
+    let mut fusion = fusion(Algorithm::mahony()).with_bias_tracking(None);
+    run(&mut fusion, 20.0, 0.0, [0.02, 0.02, 0.0], |_| {
+        (tilt, [0.0; 3])
+    });
+
+    for algorithm in [Algorithm::madgwick(), Algorithm::complementary()] {
+        let before = fusion.euler();
+        let bias = fusion.gyro_bias();
+        fusion.set_algorithm(algorithm);
+        assert_eq!(fusion.algorithm(), algorithm);
+        // 切换时保留姿态和零偏
+        assert_eq!(fusion.euler(), before);
+        assert_eq!(fusion.gyro_bias(), bias);
+
+        let error = run(&mut fusion, 5.0, 0.0, [0.02, 0.02, 0.0], |_| {
+            (tilt, [0.0; 3])
+        });
+        assert!(error < 1f32.to_radians(), "{:?}: {}", algorithm, error);
+    }
+
+    fusion.reset();
+    assert_eq!(fusion.quaternion(), Quaternion::IDENTITY);
+}
+
+#[test]
+fn it_skips_correction_without_acceleration() {
+    for algorithm in algorithms() {
+        let mut fusion = fusion(algorithm).with_bias_tracking(Some(BiasTracking::default()));
+        fusion.update([0.0, 0.0, 1.0], [0.0; 3]);
+        // 自由落体时只积分陀螺仪
+        for _ in 0..100 {
+            fusion.update([0.0; 3], [1.0, 0.0, 0.0]);
+        }
+        let roll = fusion.euler().roll;
+        assert!((roll - 1.0).abs() < 0.01, "{:?}: {}", algorithm, roll);
+    }
+}
@@ -0,0 +1,179 @@
+//! 在主机上使用按脚本应答的 I2C 总线测试寄存器读写
+use std::collections::VecDeque;
+
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, Operation, SevenBitAddress};
+use mpu6050::conf::*;
+use mpu6050::hal::{AccelGyroData, DlpfBandwidth, Mpu6050};
+
+/// 期望的一次 I2C 传输
+#[derive(Debug)]
+enum Expect {
+    /// 写入的数据
+    Write(u8, Vec<u8>),
+    /// 写入的数据和读取时返回的数据
+    WriteRead(u8, Vec<u8>, Vec<u8>),
+}
+
+/// 按照脚本检查传输并返回数据的 I2C 总线
+struct MockI2c {
+    expects: VecDeque<Expect>,
+}
+
+impl MockI2c {
+    fn new(expects: Vec<Expect>) -> Self {
+        MockI2c {
+            expects: expects.into(),
+        }
+    }
+
+    /// 检查脚本中的传输都已经执行
+    fn done(&self) {
+        assert!(self.expects.is_empty(), "{:?}", self.expects);
+    }
+}
+
+impl ErrorType for MockI2c {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockI2c {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let expect = self.expects.pop_front().expect("unexpected transaction");
+        match (expect, operations) {
+            (Expect::Write(addr, data), [Operation::Write(bytes)]) => {
+                assert_eq!(address, addr);
+                assert_eq!(*bytes, &data[..]);
+            }
+            (
+                Expect::WriteRead(addr, data, response),
+                [Operation::Write(bytes), Operation::Read(buffer)],
+            ) => {
+                assert_eq!(address, addr);
+                assert_eq!(*bytes, &data[..]);
+                buffer.copy_from_slice(&response);
+            }
+            (expect, operations) => {
+                panic!("expect {:?}, got {} operations", expect, operations.len())
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn it_wake_up() {
+    let i2c = MockI2c::new(vec![
+        Expect::Write(0x68, vec![MPU6050_PWR_MGMT_1, 0x01]),
+        Expect::Write(0x68, vec![MPU6050_PWR_MGMT_2, 0x00]),
+        Expect::Write(0x68, vec![MPU6050_SMPLRT_DIV, 0x09]),
+        Expect::Write(0x68, vec![MPU6050_CONFIG, 0x06]),
+        Expect::Write(0x68, vec![MPU6050_GYRO_CONFIG, 0x18]),
+        Expect::Write(0x68, vec![MPU6050_ACCEL_CONFIG, 0x18]),
+    ]);
+    let mut mpu = Mpu6050::new(i2c);
+
+    mpu.wake_up().unwrap();
+    mpu.release().done();
+}
+
+#[test]
+fn it_get_id() {
+    let i2c = MockI2c::new(vec![Expect::WriteRead(
+        0x68,
+        vec![MPU6050_WHO_AM_I],
+        vec![0x68],
+    )]);
+    let mut mpu = Mpu6050::new(i2c);
+
+    assert_eq!(mpu.get_id().unwrap(), 0x68);
+    mpu.release().done();
+}
+
+#[test]
+fn it_set_dlpf() {
+    let i2c = MockI2c::new(vec![
+        // EXT_SYNC_SET 位保持不变
+        Expect::WriteRead(0x68, vec![MPU6050_CONFIG], vec![0x0E]),
+        Expect::Write(0x68, vec![MPU6050_CONFIG, 0x0B]),
+        Expect::WriteRead(0x68, vec![MPU6050_CONFIG], vec![0x0B]),
+    ]);
+    let mut mpu = Mpu6050::new(i2c);
+
+    mpu.set_dlpf(DlpfBandwidth::Hz44).unwrap();
+    assert_eq!(mpu.get_dlpf().unwrap(), DlpfBandwidth::Hz44);
+    mpu.release().done();
+}
+
+#[test]
+fn it_set_sample_rate() {
+    let i2c = MockI2c::new(vec![
+        // DLPF 打开时陀螺仪输出 1kHz
+        Expect::WriteRead(0x68, vec![MPU6050_CONFIG], vec![0x06]),
+        Expect::Write(0x68, vec![MPU6050_SMPLRT_DIV, 19]),
+        // DLPF 关闭时陀螺仪输出 8kHz
+        Expect::WriteRead(0x68, vec![MPU6050_CONFIG], vec![0x00]),
+        Expect::Write(0x68, vec![MPU6050_SMPLRT_DIV, 159]),
+        Expect::WriteRead(0x68, vec![MPU6050_CONFIG], vec![0x00]),
+        Expect::WriteRead(0x68, vec![MPU6050_SMPLRT_DIV], vec![159]),
+    ]);
+    let mut mpu = Mpu6050::new(i2c);
+
+    mpu.set_sample_rate(50).unwrap();
+    mpu.set_sample_rate(50).unwrap();
+    assert_eq!(mpu.get_sample_rate().unwrap(), 50.0);
+    mpu.release().done();
+}
+
+#[test]
+fn it_get_data() {
+    let i2c = MockI2c::new(vec![Expect::WriteRead(
+        0x68,
+        vec![MPU6050_ACCEL_XOUT_H],
+        vec![
+            0x12, 0x34, // ACCEL_X
+            0xFF, 0xFE, // ACCEL_Y
+            0x80, 0x00, // ACCEL_Z
+            0xAA, 0xAA, // TEMP
+            0x7F, 0xFF, // GYRO_X
+            0x00, 0x01, // GYRO_Y
+            0xF0, 0x00, // GYRO_Z
+        ],
+    )]);
+    let mut mpu = Mpu6050::new(i2c);
+
+    let data = mpu.get_data().unwrap();
+    assert_eq!(
+        data,
+        AccelGyroData {
+            acc_x: 0x1234,
+            acc_y: -2,
+            acc_z: i16::MIN,
+            gyro_x: i16::MAX,
+            gyro_y: 1,
+            gyro_z: -4096,
+        }
+    );
+    mpu.release().done();
+}
+
+#[test]
+fn it_scale_data() {
+    let data = AccelGyroData {
+        acc_x: 2048,
+        acc_y: -1024,
+        acc_z: 0,
+        gyro_x: 164,
+        gyro_y: 0,
+        gyro_z: -1640,
+    };
+
+    assert_eq!(data.acc_g(), [1.0, -0.5, 0.0]);
+    let gyro = data.gyro_rad();
+    assert!((gyro[0] - 10f32.to_radians()).abs() < 1e-6);
+    assert_eq!(gyro[1], 0.0);
+    assert!((gyro[2] + 100f32.to_radians()).abs() < 1e-5);
+}
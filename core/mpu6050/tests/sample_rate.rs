@@ -0,0 +1,37 @@
+//! 采样率分频计算
+use mpu6050::sample_rate::{output_data_rate, sample_rate_divider, DlpfBandwidth};
+
+#[test]
+fn it_gyro_output_rate() {
+    assert_eq!(DlpfBandwidth::Hz260.gyro_output_rate(), 8000);
+    assert_eq!(DlpfBandwidth::Hz5.gyro_output_rate(), 1000);
+    // 保留值 7 同样为 8kHz
+    assert_eq!(DlpfBandwidth::from_register(0x07), DlpfBandwidth::Hz260);
+    for value in 0..7 {
+        assert_eq!(DlpfBandwidth::from_register(value).to_register(), value);
+    }
+    // 忽略 EXT_SYNC_SET 位
+    assert_eq!(DlpfBandwidth::from_register(0x3A), DlpfBandwidth::Hz94);
+}
+
+#[test]
+fn it_sample_rate_divider() {
+    assert_eq!(sample_rate_divider(DlpfBandwidth::Hz5, 100), 9);
+    assert_eq!(sample_rate_divider(DlpfBandwidth::Hz5, 1000), 0);
+    assert_eq!(sample_rate_divider(DlpfBandwidth::Hz260, 1000), 7);
+    // 取最接近的分频: 1000 / 3 = 333.3Hz
+    assert_eq!(sample_rate_divider(DlpfBandwidth::Hz44, 300), 2);
+    // 超出范围时取最高和最低采样率
+    assert_eq!(sample_rate_divider(DlpfBandwidth::Hz44, 5000), 0);
+    assert_eq!(sample_rate_divider(DlpfBandwidth::Hz44, 1), 255);
+    assert_eq!(sample_rate_divider(DlpfBandwidth::Hz44, 0), 255);
+}
+
+#[test]
+fn it_output_data_rate() {
+    assert_eq!(output_data_rate(DlpfBandwidth::Hz5, 9), 100.0);
+    assert_eq!(output_data_rate(DlpfBandwidth::Hz260, 0), 8000.0);
+    assert_eq!(output_data_rate(DlpfBandwidth::Hz184, 255), 1000.0 / 256.0);
+    let divider = sample_rate_divider(DlpfBandwidth::Hz21, 300);
+    assert!((output_data_rate(DlpfBandwidth::Hz21, divider) - 333.33).abs() < 0.01);
+}
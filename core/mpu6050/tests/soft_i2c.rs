@@ -0,0 +1,276 @@
+//! 在主机上测试软件 I2C 的时序
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal::i2c::NoAcknowledgeSource;
+use mpu6050::conf::*;
+use mpu6050::hal::{AccelGyroData, Mpu6050};
+use mpu6050::reg::{SoftI2c, SoftI2cError};
+
+/// 应答位由谁发送
+#[derive(Clone, Copy, PartialEq)]
+enum AckSlot {
+    None,
+    Slave,
+    Master,
+}
+
+/// 引脚级的 I2C 寄存器从机, SDA 为线与
+struct Slave {
+    address: u8,
+    registers: [u8; 128],
+    pointer: u8,
+    scl: bool,
+    master_sda: bool,
+    slave_sda: bool,
+    // 是否被寻址
+    selected: bool,
+    reading: bool,
+    // 写传输的第一个字节为寄存器地址
+    first_write: bool,
+    // 接收的第一个字节为从机地址
+    address_phase: bool,
+    bits: u32,
+    shift: u8,
+    ack_slot: AckSlot,
+    master_ack: bool,
+}
+
+impl Slave {
+    fn new(address: u8) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Slave {
+            address,
+            registers: [0; 128],
+            pointer: 0,
+            scl: true,
+            master_sda: true,
+            slave_sda: true,
+            selected: false,
+            reading: false,
+            first_write: false,
+            address_phase: false,
+            bits: 0,
+            shift: 0,
+            ack_slot: AckSlot::None,
+            master_ack: false,
+        }))
+    }
+
+    fn sda(&self) -> bool {
+        self.master_sda && self.slave_sda
+    }
+
+    fn set_sda(&mut self, level: bool) {
+        let old = self.sda();
+        self.master_sda = level;
+        let new = self.sda();
+        if !self.scl || old == new {
+            return;
+        }
+        if new {
+            // 结束信号
+            self.selected = false;
+            self.address_phase = false;
+        } else {
+            // 起始信号
+            self.address_phase = true;
+            self.selected = false;
+            self.bits = 0;
+            self.shift = 0;
+            self.ack_slot = AckSlot::None;
+        }
+        self.slave_sda = true;
+    }
+
+    /// 装载下一个要发送的字节并输出最高位
+    fn load(&mut self) {
+        self.shift = self.registers[self.pointer as usize];
+        self.pointer += 1;
+        self.bits = 0;
+        self.slave_sda = self.shift & 0x80 != 0;
+    }
+
+    fn set_scl(&mut self, level: bool) {
+        if level == self.scl {
+            return;
+        }
+        self.scl = level;
+        if !self.selected && !self.address_phase {
+            return;
+        }
+
+        if level {
+            match self.ack_slot {
+                AckSlot::Master => self.master_ack = !self.sda(),
+                AckSlot::Slave => {}
+                AckSlot::None => {
+                    if !self.reading || self.address_phase {
+                        self.shift = self.shift << 1 | self.sda() as u8;
+                    }
+                    self.bits += 1;
+                }
+            }
+            return;
+        }
+
+        match self.ack_slot {
+            AckSlot::Slave => {
+                self.ack_slot = AckSlot::None;
+                self.bits = 0;
+                self.shift = 0;
+                if self.reading {
+                    self.load();
+                } else {
+                    self.slave_sda = true;
+                }
+            }
+            AckSlot::Master => {
+                self.ack_slot = AckSlot::None;
+                if self.master_ack {
+                    self.load();
+                } else {
+                    self.slave_sda = true;
+                    self.selected = false;
+                }
+            }
+            AckSlot::None if self.bits == 8 => self.byte_done(),
+            AckSlot::None => {
+                if self.reading && !self.address_phase {
+                    self.slave_sda = self.shift & (0x80 >> self.bits) != 0;
+                }
+            }
+        }
+    }
+
+    /// 一个字节传输完成
+    fn byte_done(&mut self) {
+        if self.address_phase {
+            self.address_phase = false;
+            if self.shift >> 1 != self.address {
+                self.slave_sda = true;
+                return;
+            }
+            self.selected = true;
+            self.reading = self.shift & 1 == 1;
+            self.first_write = true;
+            self.ack_slot = AckSlot::Slave;
+            self.slave_sda = false;
+        } else if self.reading {
+            // 释放总线, 等待主机应答
+            self.ack_slot = AckSlot::Master;
+            self.slave_sda = true;
+        } else {
+            if self.first_write {
+                self.pointer = self.shift;
+                self.first_write = false;
+            } else {
+                self.registers[self.pointer as usize] = self.shift;
+                self.pointer += 1;
+            }
+            self.ack_slot = AckSlot::Slave;
+            self.slave_sda = false;
+        }
+    }
+}
+
+struct SclPin(Rc<RefCell<Slave>>);
+
+impl ErrorType for SclPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for SclPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_scl(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_scl(true);
+        Ok(())
+    }
+}
+
+struct SdaPin(Rc<RefCell<Slave>>);
+
+impl ErrorType for SdaPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for SdaPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_sda(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().set_sda(true);
+        Ok(())
+    }
+}
+
+impl InputPin for SdaPin {
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.0.borrow().sda())
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.0.borrow().sda())
+    }
+}
+
+fn mpu6050(slave: &Rc<RefCell<Slave>>) -> Mpu6050<SoftI2c<SclPin, SdaPin>> {
+    let i2c = SoftI2c::new(SclPin(slave.clone()), SdaPin(slave.clone())).unwrap();
+    Mpu6050::new(i2c)
+}
+
+#[test]
+fn it_write_and_read_registers() {
+    let slave = Slave::new(DEFAULT_SLAVE_ADDR);
+    slave.borrow_mut().registers[MPU6050_WHO_AM_I as usize] = 0x68;
+    let mut mpu = mpu6050(&slave);
+
+    mpu.wake_up().unwrap();
+    {
+        let slave = slave.borrow();
+        assert_eq!(slave.registers[MPU6050_PWR_MGMT_1 as usize], 0x01);
+        assert_eq!(slave.registers[MPU6050_SMPLRT_DIV as usize], 0x09);
+        assert_eq!(slave.registers[MPU6050_CONFIG as usize], 0x06);
+        assert_eq!(slave.registers[MPU6050_ACCEL_CONFIG as usize], 0x18);
+    }
+
+    assert_eq!(mpu.get_id().unwrap(), 0x68);
+
+    let data = [
+        0x01, 0x02, 0xFF, 0xFF, 0x40, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x10, 0xC0, 0x00,
+    ];
+    let start = MPU6050_ACCEL_XOUT_H as usize;
+    slave.borrow_mut().registers[start..start + 14].copy_from_slice(&data);
+    assert_eq!(
+        mpu.get_data().unwrap(),
+        AccelGyroData {
+            acc_x: 0x0102,
+            acc_y: -1,
+            acc_z: 0x4000,
+            gyro_x: -32767,
+            gyro_y: 0x10,
+            gyro_z: -16384,
+        }
+    );
+    // 传输结束后释放总线
+    assert!(slave.borrow().sda());
+}
+
+#[test]
+fn it_no_acknowledge() {
+    let slave = Slave::new(DEFAULT_SLAVE_ADDR + 1);
+    let mut mpu = mpu6050(&slave);
+
+    assert_eq!(
+        mpu.get_id().unwrap_err(),
+        SoftI2cError::NoAcknowledge(NoAcknowledgeSource::Address)
+    );
+    assert!(slave.borrow().sda());
+}
@@ -0,0 +1,248 @@
+//! 灯珠芯片的时序和颜色顺序
+//!
+//! 各芯片都是单线归零码, 每个数据位是一个高电平加一个低电平, 区别在于
+//! 高低电平时长、复位时长和颜色通道的发送顺序. 这里的编码与 RMT 无关,
+//! 只产生 `(高电平, 低电平)` 的计数值, 因此可以在主机上测试.
+
+use crate::color::{Channel, Color};
+
+/// 单线协议的时序, 单位为 ns, 复位单位为 us
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// 数据位 0 的高电平时长
+    pub t0h: u32,
+    /// 数据位 0 的低电平时长
+    pub t0l: u32,
+    /// 数据位 1 的高电平时长
+    pub t1h: u32,
+    /// 数据位 1 的低电平时长
+    pub t1l: u32,
+    /// 复位 (latch) 低电平时长
+    pub reset_us: u32,
+}
+
+impl Timing {
+    /// 按计数时钟换算为计数值, 四舍五入
+    pub fn ticks(&self, clock_hz: u32) -> BitTicks {
+        let ticks = |nanos: u32| -> u16 {
+            let ticks = (nanos as u64 * clock_hz as u64 + 500_000_000) / 1_000_000_000;
+            ticks.min(u16::MAX as u64) as u16
+        };
+        // 复位信号分为两个低电平脉冲, 单个脉冲的计数值有上限
+        let reset = ticks(self.reset_us.saturating_mul(1000).div_ceil(2));
+        BitTicks {
+            bit0: (ticks(self.t0h), ticks(self.t0l)),
+            bit1: (ticks(self.t1h), ticks(self.t1l)),
+            reset: (reset, reset),
+        }
+    }
+}
+
+/// 按计数时钟换算后的时序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitTicks {
+    /// 数据位 0: (T0H, T0L)
+    pub bit0: (u16, u16),
+    /// 数据位 1: (T1H, T1L)
+    pub bit1: (u16, u16),
+    /// 复位信号的两个低电平脉冲
+    pub reset: (u16, u16),
+}
+
+impl BitTicks {
+    /// 数据位对应的 `(高电平, 低电平)` 计数值
+    pub fn bit(&self, bit: bool) -> (u16, u16) {
+        if bit {
+            self.bit1
+        } else {
+            self.bit0
+        }
+    }
+}
+
+/// 颜色通道的发送顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorOrder {
+    Rgb,
+    Grb,
+    Grbw,
+}
+
+impl ColorOrder {
+    /// 按发送顺序排列的通道
+    pub fn channels(self) -> &'static [Channel] {
+        match self {
+            ColorOrder::Rgb => &[Channel::R, Channel::G, Channel::B],
+            ColorOrder::Grb => &[Channel::G, Channel::R, Channel::B],
+            ColorOrder::Grbw => &[Channel::G, Channel::R, Channel::B, Channel::W],
+        }
+    }
+
+    /// 每个灯珠的数据位数
+    pub fn bits_per_led(self) -> usize {
+        self.channels().len() * 8
+    }
+
+    /// 颜色格式 `C` 的通道数是否与发送顺序一致
+    pub fn fits<C: Color>(self) -> bool {
+        self.channels().len() == C::CHANNELS
+    }
+}
+
+/// 支持的灯珠芯片
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LedChipset {
+    /// WS2812/WS2812B, GRB
+    #[default]
+    Ws2812b,
+    /// WS2811 (800kHz), RGB
+    Ws2811,
+    /// SK6812, GRB
+    Sk6812,
+    /// SK6812-RGBW, 带白光灯珠, GRBW
+    Sk6812Rgbw,
+    /// WS2815 (12V), GRB
+    Ws2815,
+    /// APA106, RGB
+    Apa106,
+}
+
+impl LedChipset {
+    /// 芯片时序, 取自各芯片手册的典型值
+    pub fn timing(self) -> Timing {
+        let (t0h, t0l, t1h, t1l, reset_us) = match self {
+            LedChipset::Ws2812b => (350, 800, 700, 600, 300),
+            LedChipset::Ws2811 => (250, 1000, 600, 650, 300),
+            LedChipset::Sk6812 | LedChipset::Sk6812Rgbw => (300, 900, 600, 600, 100),
+            LedChipset::Ws2815 => (300, 1090, 1090, 320, 300),
+            LedChipset::Apa106 => (350, 1360, 1360, 350, 100),
+        };
+        Timing {
+            t0h,
+            t0l,
+            t1h,
+            t1l,
+            reset_us,
+        }
+    }
+
+    /// 颜色通道的发送顺序
+    pub fn order(self) -> ColorOrder {
+        match self {
+            LedChipset::Ws2811 | LedChipset::Apa106 => ColorOrder::Rgb,
+            LedChipset::Ws2812b | LedChipset::Sk6812 | LedChipset::Ws2815 => ColorOrder::Grb,
+            LedChipset::Sk6812Rgbw => ColorOrder::Grbw,
+        }
+    }
+}
+
+/// 一个灯珠的数据位, 按芯片的通道顺序, 每个通道高位在前
+pub fn bits<C: Color>(color: &C, order: ColorOrder) -> impl Iterator<Item = bool> + '_ {
+    bits_with(color, order, |value| value)
+}
+
+/// 同 [`bits`], 每个通道的亮度先经过 `map`, 例如亮度和伽马校正
+pub fn bits_with<'a, C, F>(
+    color: &'a C,
+    order: ColorOrder,
+    map: F,
+) -> impl Iterator<Item = bool> + 'a
+where
+    C: Color,
+    F: Fn(u8) -> u8 + 'a,
+{
+    order.channels().iter().flat_map(move |&channel| {
+        let value = map(color.channel(channel));
+        (0..8).rev().map(move |i| value & (1 << i) != 0)
+    })
+}
+
+/// 一个灯珠的脉冲序列, 每项为 `(高电平, 低电平)` 计数值, 不含复位信号
+pub fn pulses<'a, C: Color>(
+    color: &'a C,
+    order: ColorOrder,
+    ticks: &'a BitTicks,
+) -> impl Iterator<Item = (u16, u16)> + 'a {
+    bits(color, order).map(move |bit| ticks.bit(bit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{Rgb, Rgbw};
+
+    /// RMT 时钟分频为 1 时的计数时钟
+    const CLOCK_HZ: u32 = 80_000_000;
+
+    #[test]
+    fn ws2812b_sends_red_as_grb() {
+        let ticks = LedChipset::Ws2812b.timing().ticks(CLOCK_HZ);
+        assert_eq!(ticks.bit0, (28, 64));
+        assert_eq!(ticks.bit1, (56, 48));
+        assert_eq!(ticks.reset, (12000, 12000));
+
+        let red = Rgb::new(255, 0, 0);
+        let sent: Vec<_> = pulses(&red, LedChipset::Ws2812b.order(), &ticks).collect();
+        let mut expected = vec![ticks.bit0; 8];
+        expected.extend([ticks.bit1; 8]);
+        expected.extend([ticks.bit0; 8]);
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn it_sends_rgb_order_msb_first() {
+        let chipset = LedChipset::Ws2811;
+        let ticks = chipset.timing().ticks(CLOCK_HZ);
+        assert_eq!(ticks.bit0, (20, 80));
+        assert_eq!(ticks.bit1, (48, 52));
+
+        // 0x81 = 1000_0001, 0x00, 0x0F = 0000_1111
+        let color = Rgb::new(0x81, 0x00, 0x0F);
+        let sent: Vec<bool> = bits(&color, chipset.order()).collect();
+        let mut expected = vec![true, false, false, false, false, false, false, true];
+        expected.extend([false; 8]);
+        expected.extend([false, false, false, false, true, true, true, true]);
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn sk6812_rgbw_sends_white_last() {
+        let chipset = LedChipset::Sk6812Rgbw;
+        let ticks = chipset.timing().ticks(CLOCK_HZ);
+        assert_eq!(chipset.order().bits_per_led(), 32);
+
+        let color = Rgbw::new(0, 0xFF, 0, 0x80);
+        let sent: Vec<_> = pulses(&color, chipset.order(), &ticks).collect();
+        assert_eq!(sent.len(), 32);
+        // G
+        assert_eq!(sent[..8], [ticks.bit1; 8]);
+        // R, B
+        assert_eq!(sent[8..24], [ticks.bit0; 16]);
+        // W = 1000_0000
+        assert_eq!(sent[24], ticks.bit1);
+        assert_eq!(sent[25..], [ticks.bit0; 7]);
+    }
+
+    #[test]
+    fn it_maps_channels_before_encoding() {
+        let color = Rgb::new(0xFF, 0x10, 0x00);
+        let sent: Vec<bool> = bits_with(&color, ColorOrder::Rgb, |value| value / 2).collect();
+        let expected: Vec<bool> = bits(&Rgb::new(0x7F, 0x08, 0x00), ColorOrder::Rgb).collect();
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn color_must_fit_the_order() {
+        assert!(LedChipset::Ws2812b.order().fits::<Rgb>());
+        assert!(LedChipset::Sk6812Rgbw.order().fits::<Rgbw>());
+        assert!(!LedChipset::Sk6812Rgbw.order().fits::<Rgb>());
+        assert!(!LedChipset::Ws2811.order().fits::<Rgbw>());
+    }
+
+    #[test]
+    fn rgb_has_no_white_channel() {
+        let ticks = LedChipset::Sk6812Rgbw.timing().ticks(CLOCK_HZ);
+        let sent: Vec<_> = pulses(&Rgb::new(1, 1, 1), ColorOrder::Grbw, &ticks).collect();
+        assert_eq!(sent[24..], [ticks.bit0; 8]);
+    }
+}
@@ -0,0 +1,258 @@
+//! 灯珠颜色格式
+
+use anyhow::{bail, Result};
+
+/// 颜色通道
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    W,
+}
+
+/// 灯带的颜色格式, 按通道取出亮度, 由芯片决定发送顺序
+pub trait Color: Clone + Default {
+    /// 通道数, 必须与芯片的 [`ColorOrder`](crate::ColorOrder) 一致
+    const CHANNELS: usize;
+
+    /// 通道亮度, 不存在的通道返回 0
+    fn channel(&self, channel: Channel) -> u8;
+}
+
+/// RGB 结构体
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub(crate) r: u8,
+    pub(crate) g: u8,
+    pub(crate) b: u8,
+}
+
+impl Rgb {
+    /// 创建 RGB 对象
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// 红色分量
+    pub fn r(&self) -> u8 {
+        self.r
+    }
+
+    /// 绿色分量
+    pub fn g(&self) -> u8 {
+        self.g
+    }
+
+    /// 蓝色分量
+    pub fn b(&self) -> u8 {
+        self.b
+    }
+
+    /// 按比例缩放亮度, `factor` 为 255 时不变, 为 0 时全暗
+    pub fn scale(self, factor: u8) -> Self {
+        Self::new(
+            scale8(self.r, factor),
+            scale8(self.g, factor),
+            scale8(self.b, factor),
+        )
+    }
+
+    /// 逐通道相加, 超过 255 时取 255
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self::new(
+            self.r.saturating_add(other.r),
+            self.g.saturating_add(other.g),
+            self.b.saturating_add(other.b),
+        )
+    }
+
+    /// 与 `other` 混合, `amount` 为 0 时是自身, 为 255 时是 `other`
+    pub fn blend(self, other: Self, amount: u8) -> Self {
+        let mix = |a: u8, b: u8| {
+            let (a, b, amount) = (a as u16, b as u16, amount as u16);
+            ((a * (255 - amount) + b * amount + 127) / 255) as u8
+        };
+        Self::new(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+        )
+    }
+
+    /// 线性插值, `t` 取 0.0 到 1.0, 超出范围时截断
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        let amount = (t.clamp(0.0, 1.0) * 255.0).round() as u8;
+        self.blend(other, amount)
+    }
+
+    /// Converts hue, saturation, value to RGB
+    pub fn from_hsv(h: u32, s: u32, v: u32) -> Result<Self> {
+        if h > 360 || s > 100 || v > 100 {
+            bail!("The given HSV values are not in valid range");
+        }
+        let s = s as f64 / 100.0;
+        let v = v as f64 / 100.0;
+        let c = s * v;
+        let x = c * (1.0 - (((h as f64 / 60.0) % 2.0) - 1.0).abs());
+        let m = v - c;
+        let (r, g, b) = match h {
+            0..=59 => (c, x, 0.0),
+            60..=119 => (x, c, 0.0),
+            120..=179 => (0.0, c, x),
+            180..=239 => (0.0, x, c),
+            240..=299 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        Ok(Self {
+            r: ((r + m) * 255.0) as u8,
+            g: ((g + m) * 255.0) as u8,
+            b: ((b + m) * 255.0) as u8,
+        })
+    }
+
+    /// Converts RGB to hue (0 ~ 359), saturation (0 ~ 100), value (0 ~ 100)
+    ///
+    /// 与 [`Rgb::from_hsv`] 使用相同的取值范围, 结果四舍五入.
+    pub fn to_hsv(&self) -> (u32, u32, u32) {
+        let (r, g, b) = (self.r as f64, self.g as f64, self.b as f64);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * (((g - b) / delta).rem_euclid(6.0))
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let s = if max == 0.0 { 0.0 } else { delta / max };
+        let v = max / 255.0;
+        (
+            h.round() as u32 % 360,
+            (s * 100.0).round() as u32,
+            (v * 100.0).round() as u32,
+        )
+    }
+}
+
+impl Color for Rgb {
+    const CHANNELS: usize = 3;
+
+    fn channel(&self, channel: Channel) -> u8 {
+        match channel {
+            Channel::R => self.r,
+            Channel::G => self.g,
+            Channel::B => self.b,
+            Channel::W => 0,
+        }
+    }
+}
+
+/// RGBW 结构体, 用于带白光灯珠的 SK6812-RGBW
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgbw {
+    pub(crate) r: u8,
+    pub(crate) g: u8,
+    pub(crate) b: u8,
+    pub(crate) w: u8,
+}
+
+impl Rgbw {
+    /// 创建 RGBW 对象
+    pub fn new(r: u8, g: u8, b: u8, w: u8) -> Self {
+        Self { r, g, b, w }
+    }
+
+    /// 红色分量
+    pub fn r(&self) -> u8 {
+        self.r
+    }
+
+    /// 绿色分量
+    pub fn g(&self) -> u8 {
+        self.g
+    }
+
+    /// 蓝色分量
+    pub fn b(&self) -> u8 {
+        self.b
+    }
+
+    /// 白光分量
+    pub fn w(&self) -> u8 {
+        self.w
+    }
+}
+
+impl From<Rgb> for Rgbw {
+    /// 白光通道关闭
+    fn from(rgb: Rgb) -> Self {
+        Self::new(rgb.r, rgb.g, rgb.b, 0)
+    }
+}
+
+impl Color for Rgbw {
+    const CHANNELS: usize = 4;
+
+    fn channel(&self, channel: Channel) -> u8 {
+        match channel {
+            Channel::R => self.r,
+            Channel::G => self.g,
+            Channel::B => self.b,
+            Channel::W => self.w,
+        }
+    }
+}
+
+/// `value * factor / 255`, 向下取整, `factor` 为 255 时不变
+pub(crate) fn scale8(value: u8, factor: u8) -> u8 {
+    (value as u16 * factor as u16 / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_scales_and_adds() {
+        let color = Rgb::new(200, 100, 10);
+        assert_eq!(color.scale(255), color);
+        assert_eq!(color.scale(0), Rgb::default());
+        assert_eq!(color.scale(128), Rgb::new(100, 50, 5));
+        assert_eq!(
+            color.saturating_add(Rgb::new(100, 100, 100)),
+            Rgb::new(255, 200, 110)
+        );
+    }
+
+    #[test]
+    fn it_blends() {
+        let (black, white) = (Rgb::new(0, 0, 0), Rgb::new(255, 255, 255));
+        assert_eq!(black.blend(white, 0), black);
+        assert_eq!(black.blend(white, 255), white);
+        assert_eq!(black.blend(white, 51), Rgb::new(51, 51, 51));
+        assert_eq!(black.lerp(white, 0.5), Rgb::new(128, 128, 128));
+        assert_eq!(black.lerp(white, 2.0), white);
+        assert_eq!(
+            Rgb::new(255, 0, 0).lerp(Rgb::new(0, 0, 255), 0.25),
+            Rgb::new(191, 0, 64)
+        );
+    }
+
+    #[test]
+    fn it_converts_to_hsv() {
+        assert_eq!(Rgb::new(0, 0, 0).to_hsv(), (0, 0, 0));
+        assert_eq!(Rgb::new(255, 255, 255).to_hsv(), (0, 0, 100));
+        assert_eq!(Rgb::new(255, 0, 0).to_hsv(), (0, 100, 100));
+        assert_eq!(Rgb::new(0, 255, 0).to_hsv(), (120, 100, 100));
+        assert_eq!(Rgb::new(0, 0, 255).to_hsv(), (240, 100, 100));
+        assert_eq!(Rgb::new(255, 0, 128).to_hsv(), (330, 100, 100));
+        for h in (0..360).step_by(30) {
+            let rgb = Rgb::from_hsv(h, 100, 100).unwrap();
+            assert_eq!(rgb.to_hsv(), (h, 100, 100));
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! 亮度和伽马校正
+//!
+//! 人眼对亮度的感知不是线性的, 低亮度时细微变化很明显, 高亮度时几乎分辨不出.
+//! [`Correction`] 把全局亮度上限和伽马曲线合成一张 256 项的查找表,
+//! 在 `show` 编码时逐通道查表, 不修改应用设置的颜色.
+
+use anyhow::{bail, Result};
+
+/// 常用的 LED 伽马值
+pub const GAMMA: f32 = 2.8;
+
+/// 全局亮度和伽马校正
+#[derive(Debug, Clone)]
+pub struct Correction {
+    brightness: u8,
+    gamma: f32,
+    lut: [u8; 256],
+}
+
+impl Default for Correction {
+    /// 最大亮度, 不做伽马校正
+    fn default() -> Self {
+        let mut correction = Correction {
+            brightness: u8::MAX,
+            gamma: 1.0,
+            lut: [0; 256],
+        };
+        correction.update();
+        correction
+    }
+}
+
+impl Correction {
+    /// 创建校正, `gamma` 为 1.0 时不做伽马校正
+    pub fn new(brightness: u8, gamma: f32) -> Result<Self> {
+        let mut correction = Correction {
+            brightness,
+            ..Correction::default()
+        };
+        correction.set_gamma(gamma)?;
+        Ok(correction)
+    }
+
+    /// 全局亮度上限, 255 为不限制
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// 设置全局亮度上限
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+        self.update();
+    }
+
+    /// 伽马值
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    /// 设置伽马值, 必须大于 0
+    pub fn set_gamma(&mut self, gamma: f32) -> Result<()> {
+        if !(gamma > 0.0 && gamma.is_finite()) {
+            bail!("The gamma must be a positive number");
+        }
+        self.gamma = gamma;
+        self.update();
+        Ok(())
+    }
+
+    /// 校正一个通道的亮度
+    pub fn apply(&self, value: u8) -> u8 {
+        self.lut[value as usize]
+    }
+
+    /// 重新计算查找表: (value / 255) ^ gamma * brightness
+    fn update(&mut self) {
+        let brightness = self.brightness as f32;
+        for (value, item) in self.lut.iter_mut().enumerate() {
+            let level = (value as f32 / 255.0).powf(self.gamma);
+            *item = (level * brightness).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_identity() {
+        let correction = Correction::default();
+        for value in 0..=255 {
+            assert_eq!(correction.apply(value), value);
+        }
+    }
+
+    #[test]
+    fn it_limits_brightness_and_applies_gamma() {
+        let mut correction = Correction::new(128, 1.0).unwrap();
+        assert_eq!(correction.apply(255), 128);
+        assert_eq!(correction.apply(0), 0);
+        assert_eq!(correction.apply(100), 50);
+
+        correction.set_brightness(255);
+        correction.set_gamma(GAMMA).unwrap();
+        assert_eq!(correction.apply(255), 255);
+        assert_eq!(correction.apply(128), 37);
+        assert_eq!(correction.apply(10), 0);
+        assert!(correction.set_gamma(0.0).is_err());
+        assert!(correction.set_gamma(f32::NAN).is_err());
+        assert_eq!(correction.gamma(), GAMMA);
+    }
+}
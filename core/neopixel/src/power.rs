@@ -0,0 +1,111 @@
+//! 电流估算和限流
+//!
+//! 每个灯珠的电流近似为静态电流加上各通道电流, 通道电流与占空比成正比.
+//! 整条灯带全白时电流很大 (WS2812B 每颗约 60mA), 超过电源能力会导致
+//! 电压跌落、颜色失真甚至复位. 设置电流上限后, `show` 按估算值整体调暗该帧.
+
+use crate::color::{scale8, Channel, Color};
+use crate::correction::Correction;
+
+const CHANNELS: [Channel; 4] = [Channel::R, Channel::G, Channel::B, Channel::W];
+
+/// 灯珠的电流模型, 单位为 mA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerModel {
+    /// 单个通道满亮度时的电流
+    pub channel_ma: u32,
+    /// 每个灯珠全暗时的静态电流
+    pub idle_ma: u32,
+}
+
+impl Default for PowerModel {
+    /// WS2812B 的典型值
+    fn default() -> Self {
+        PowerModel {
+            channel_ma: 20,
+            idle_ma: 1,
+        }
+    }
+}
+
+impl PowerModel {
+    /// 估算一帧的电流, 颜色先经过 `correction` 校正
+    pub fn estimate<C: Color>(&self, colors: &[C], correction: &Correction) -> u32 {
+        self.estimate_dimmed(colors, correction, u8::MAX)
+    }
+
+    /// 电流上限 `max_ma` 下该帧的调暗系数, 255 表示不需要调暗
+    ///
+    /// 静态电流已超过上限时返回 0.
+    pub fn dim_factor<C: Color>(&self, colors: &[C], correction: &Correction, max_ma: u32) -> u8 {
+        let dynamic = self.dynamic(colors, correction, u8::MAX);
+        let allowed = max_ma.saturating_sub(self.idle(colors)) as u64 * 255;
+        if dynamic <= allowed {
+            return u8::MAX;
+        }
+        (allowed * 255 / dynamic) as u8
+    }
+
+    /// 估算调暗 `dim` 后一帧的电流
+    pub fn estimate_dimmed<C: Color>(&self, colors: &[C], correction: &Correction, dim: u8) -> u32 {
+        let dynamic = self.dynamic(colors, correction, dim).div_ceil(255);
+        self.idle(colors)
+            .saturating_add(dynamic.min(u32::MAX as u64) as u32)
+    }
+
+    fn idle<C>(&self, colors: &[C]) -> u32 {
+        self.idle_ma.saturating_mul(colors.len() as u32)
+    }
+
+    /// 通道电流之和, 单位为 mA / 255
+    fn dynamic<C: Color>(&self, colors: &[C], correction: &Correction, dim: u8) -> u64 {
+        let duty: u64 = colors
+            .iter()
+            .flat_map(|color| CHANNELS.iter().map(move |&channel| color.channel(channel)))
+            .map(|value| scale8(correction.apply(value), dim) as u64)
+            .sum();
+        duty * self.channel_ma as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::{Rgb, Rgbw};
+
+    #[test]
+    fn it_estimates_the_current() {
+        let power = PowerModel::default();
+        let correction = Correction::default();
+        assert_eq!(power.estimate(&[Rgb::default(); 10], &correction), 10);
+        assert_eq!(
+            power.estimate(&[Rgb::new(255, 255, 255); 10], &correction),
+            610
+        );
+        assert_eq!(
+            power.estimate(&[Rgbw::new(0, 0, 0, 255); 2], &correction),
+            42
+        );
+
+        let half = Correction::new(128, 1.0).unwrap();
+        assert_eq!(power.estimate(&[Rgb::new(255, 0, 0); 10], &half), 111);
+    }
+
+    #[test]
+    fn it_dims_to_the_current_limit() {
+        let power = PowerModel::default();
+        let correction = Correction::default();
+        let white = [Rgb::new(255, 255, 255); 30];
+        // 30 * 61mA = 1830mA
+        assert_eq!(power.dim_factor(&white, &correction, 2000), 255);
+
+        let dim = power.dim_factor(&white, &correction, 500);
+        assert!(dim < 255);
+        let current = power.estimate_dimmed(&white, &correction, dim);
+        assert!(current <= 500, "{current}mA");
+        assert!(current > 450, "{current}mA");
+
+        assert_eq!(power.dim_factor(&white, &correction, 10), 0);
+        assert_eq!(power.estimate_dimmed(&white, &correction, 0), 30);
+    }
+}
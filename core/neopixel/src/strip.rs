@@ -0,0 +1,268 @@
+//! 基于 RMT 的灯带驱动
+//!
+//! 整条灯带的颜色数据被编码进一个 [`VariableLengthSignal`],
+//! 作为一个 RMT 帧发送, 灯珠之间没有间隙, 帧尾附带一个复位 (latch) 低电平.
+//! 脉冲在创建对象时按芯片时序预先计算, 信号缓冲区也预先分配, 因此 `show` 不再分配内存.
+
+use anyhow::{bail, Result};
+use esp_idf_hal::rmt::RmtChannel;
+use esp_idf_hal::{
+    delay::BLOCK,
+    gpio::OutputPin,
+    peripheral::Peripheral,
+    rmt::{config::TransmitConfig, PinState, Pulse, PulseTicks, TxRmtDriver, VariableLengthSignal},
+    sys::{esp, rmt_wait_tx_done, rmt_write_items, EspError},
+};
+
+use crate::chipset::{bits_with, ColorOrder, LedChipset};
+use crate::color::{scale8, Color, Rgb};
+use crate::correction::Correction;
+use crate::power::PowerModel;
+
+/// 预先计算的脉冲表
+struct PulseTable {
+    /// 数据位 0: (T0H, T0L)
+    bit0: [Pulse; 2],
+    /// 数据位 1: (T1H, T1L)
+    bit1: [Pulse; 2],
+    /// 复位信号, 分为两个低电平脉冲
+    reset: [Pulse; 2],
+}
+
+impl PulseTable {
+    /// 按 RMT 计数时钟计算芯片的时序
+    fn new(tx: &TxRmtDriver, chipset: LedChipset) -> Result<Self, EspError> {
+        let ticks = chipset.timing().ticks(tx.counter_clock()?.0);
+        let pair = |(high, low): (u16, u16), state| -> Result<[Pulse; 2], EspError> {
+            Ok([
+                Pulse::new(state, PulseTicks::new(high)?),
+                Pulse::new(PinState::Low, PulseTicks::new(low)?),
+            ])
+        };
+        Ok(PulseTable {
+            bit0: pair(ticks.bit0, PinState::High)?,
+            bit1: pair(ticks.bit1, PinState::High)?,
+            reset: pair(ticks.reset, PinState::Low)?,
+        })
+    }
+}
+
+/// 灯带, `C` 为颜色格式, 例如 SK6812-RGBW 使用 [`Rgbw`](crate::Rgbw)
+pub struct NeoPixel<'d, C: Color = Rgb> {
+    tx: TxRmtDriver<'d>,
+    data: Vec<C>, // 灯珠颜色集合
+    order: ColorOrder,
+    pulses: PulseTable,
+    signal: VariableLengthSignal, // 整条灯带的 RMT 帧
+    busy: bool,                   // RMT 正在发送 signal
+    correction: Correction,       // 亮度和伽马校正
+    power: PowerModel,
+    max_current_ma: Option<u32>, // 电流上限
+    dim: u8,                     // 上一帧的限流调暗系数
+}
+
+impl<'d> NeoPixel<'d> {
+    /// 创建对象, 灯珠为 WS2812B
+    pub fn new<PIN, OP, CH, RC>(
+        pin: PIN,    // RGB LED 灯的引脚
+        channel: CH, // RMT 通道
+        num: usize,  // RGB LED 灯的数量
+    ) -> Result<Self>
+    where
+        PIN: Peripheral<P = OP> + 'd,
+        OP: OutputPin,
+        CH: Peripheral<P = RC> + 'd,
+        RC: RmtChannel,
+    {
+        Self::with_chipset(pin, channel, num, LedChipset::Ws2812b)
+    }
+}
+
+impl<'d, C: Color> NeoPixel<'d, C> {
+    /// 创建指定芯片的灯带
+    pub fn with_chipset<PIN, OP, CH, RC>(
+        pin: PIN,    // RGB LED 灯的引脚
+        channel: CH, // RMT 通道
+        num: usize,  // RGB LED 灯的数量
+        chipset: LedChipset,
+    ) -> Result<Self>
+    where
+        PIN: Peripheral<P = OP> + 'd,
+        OP: OutputPin,
+        CH: Peripheral<P = RC> + 'd,
+        RC: RmtChannel,
+    {
+        let order = chipset.order();
+        if !order.fits::<C>() {
+            bail!(
+                "颜色格式有 {} 个通道, 芯片 {:?} 需要 {} 个",
+                C::CHANNELS,
+                chipset,
+                order.channels().len()
+            );
+        }
+
+        // Onboard RGB LED pin
+        // ESP32-C3-DevKitC-02 gpio8
+        // ESP32-C3-DevKit-RUST-1 gpio2
+        // ESP32-S3-DevKitC-1 gpio48
+        // let led = peripherals.pins.gpio2;
+        // let channel = peripherals.rmt.channel0;
+        let config = TransmitConfig::new().clock_divider(1);
+        let tx = TxRmtDriver::new(channel, pin, &config)?;
+        let pulses = PulseTable::new(&tx, chipset)?;
+
+        // 容量按脉冲计: 每个数据位是高电平和低电平两个脉冲, 复位信号也是两个
+        let signal = VariableLengthSignal::with_capacity(2 * (num * order.bits_per_led() + 1));
+
+        let data = vec![C::default(); num];
+        Ok(NeoPixel {
+            tx,
+            data,
+            order,
+            pulses,
+            signal,
+            busy: false,
+            correction: Correction::default(),
+            power: PowerModel::default(),
+            max_current_ma: None,
+            dim: u8::MAX,
+        })
+    }
+
+    /// 设置指定灯珠颜色
+    pub fn set_color(&mut self, index: usize, color: C) -> Result<()> {
+        if index >= self.data.len() {
+            bail!("索引超出范围");
+        }
+        self.data[index] = color;
+        Ok(())
+    }
+
+    /// 设置所有灯珠颜色
+    pub fn set_all_color(&mut self, color: C) {
+        for item in self.data.iter_mut() {
+            *item = color.clone()
+        }
+    }
+
+    // 用于设置某个 RGB LED 灯的颜色，并立即显示
+    pub fn set_color_and_show(&mut self, index: usize, color: C) -> Result<()> {
+        // 设置颜色数据
+        self.set_color(index, color)?;
+        // 显示颜色数据
+        self.show()?;
+        // 返回成功
+        Ok(())
+    }
+
+    /// 全局亮度上限, 255 为不限制
+    pub fn brightness(&self) -> u8 {
+        self.correction.brightness()
+    }
+
+    /// 设置全局亮度上限, 在下一次 `show` 时生效
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.correction.set_brightness(brightness);
+    }
+
+    /// 设置伽马值, 1.0 为不校正, 常用 [`GAMMA`](crate::GAMMA)
+    pub fn set_gamma(&mut self, gamma: f32) -> Result<()> {
+        self.correction.set_gamma(gamma)
+    }
+
+    /// 设置灯珠的电流模型, 默认为 WS2812B 的典型值
+    pub fn set_power_model(&mut self, power: PowerModel) {
+        self.power = power;
+    }
+
+    /// 设置整条灯带的电流上限 (mA), 超过时 `show` 自动调暗该帧, `None` 为不限制
+    pub fn set_max_current(&mut self, max_ma: Option<u32>) {
+        self.max_current_ma = max_ma;
+    }
+
+    /// 估算当前颜色的电流 (mA), 已计入亮度和伽马校正, 未计入限流调暗
+    pub fn estimate_current(&self) -> u32 {
+        self.power.estimate(&self.data, &self.correction)
+    }
+
+    /// 上一帧的限流调暗系数, 255 表示没有调暗
+    pub fn dim_factor(&self) -> u8 {
+        self.dim
+    }
+
+    /// 显示所有 RGB LED 灯的颜色, 阻塞到整帧 (含复位信号) 发送完成
+    pub fn show(&mut self) -> Result<()> {
+        self.show_nonblocking()?;
+        self.wait()
+    }
+
+    /// 显示所有 RGB LED 灯的颜色, RMT 在后台发送, 立即返回
+    ///
+    /// 上一帧尚未发送完成时先等待它. 发送期间可以继续修改颜色,
+    /// 修改在下一次 `show` 时生效.
+    pub fn show_nonblocking(&mut self) -> Result<()> {
+        // RMT 发送期间不能修改 signal
+        self.wait()?;
+        self.encode()?;
+
+        let items = self.signal.as_slice();
+        // Safety: signal 在发送完成前不会被修改或释放, 见 wait() 和 Drop
+        esp!(unsafe {
+            rmt_write_items(self.tx.channel(), items.as_ptr(), items.len() as i32, false)
+        })?;
+        self.busy = true;
+        Ok(())
+    }
+
+    /// 等待上一帧发送完成
+    pub fn wait(&mut self) -> Result<()> {
+        if self.busy {
+            esp!(unsafe { rmt_wait_tx_done(self.tx.channel(), BLOCK) })?;
+            self.busy = false;
+        }
+        Ok(())
+    }
+
+    // 用于清除所有 RGB LED 灯的颜色
+    pub fn clear(&mut self) -> Result<()> {
+        // 将所有颜色数据设置为黑色（全暗）
+        self.set_all_color(C::default());
+        self.show()?;
+
+        Ok(())
+    }
+
+    /// 将整条灯带按芯片的通道顺序编码进 signal, 最后是复位信号
+    ///
+    /// 每个通道先做亮度和伽马校正, 超过电流上限时再整体调暗.
+    fn encode(&mut self) -> Result<(), EspError> {
+        self.dim = match self.max_current_ma {
+            Some(max_ma) => self.power.dim_factor(&self.data, &self.correction, max_ma),
+            None => u8::MAX,
+        };
+        let (correction, dim) = (&self.correction, self.dim);
+        let map = |value| scale8(correction.apply(value), dim);
+
+        self.signal.clear();
+        for color in self.data.iter() {
+            for bit in bits_with(color, self.order, map) {
+                let pulses = if bit {
+                    &self.pulses.bit1
+                } else {
+                    &self.pulses.bit0
+                };
+                self.signal.push(pulses)?;
+            }
+        }
+        self.signal.push(&self.pulses.reset)?;
+        Ok(())
+    }
+}
+
+impl<C: Color> Drop for NeoPixel<'_, C> {
+    /// 释放 signal 之前等待 RMT 发送完成
+    fn drop(&mut self) {
+        let _ = self.wait();
+    }
+}
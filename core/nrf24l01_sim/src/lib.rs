@@ -0,0 +1,762 @@
+//! nRF24L01+ radio simulator
+//!
+//! Simulates nRF24L01+ transceivers on the host so that the
+//! `esp32s3-nrf24l01` driver and the protocols built on top of it run in
+//! `cargo test`. A [`Radio`] implements embedded-hal's `SpiDevice`, its
+//! [`CePin`] implements `OutputPin` and its [`IrqPin`] `InputPin`.
+//!
+//! One `transaction` is one CSN low to high cycle: the STATUS register
+//! and the register contents are shifted out while the command is
+//! shifted in, writes take effect when CSN goes high.
+//!
+//! All radios added to the same [`Ether`] share the air. There is no
+//! notion of time: a radio in TX mode with CE high sends its TX FIFO
+//! right away, including auto-acknowledgement and retransmits, and the
+//! radios in RX mode on the same channel, data rate and address receive
+//! the packets immediately.
+//!
+//! Noise can be put on single channels with [`Ether::set_noise`]: it
+//! sets the received power detector (`RPD`) of the radios listening
+//! there and destroys every frame sent on the channel.
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use embedded_hal::digital::{ErrorType as PinErrorType, InputPin, OutputPin};
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+/// Number of RX pipes
+pub const PIPES_COUNT: usize = 6;
+/// Depth of the TX and the RX FIFO
+pub const FIFO_DEPTH: usize = 3;
+/// Maximum payload length
+pub const MAX_PAYLOAD_LEN: usize = 32;
+/// Number of RF channels
+pub const CHANNELS: u8 = 126;
+
+// Register map
+const CONFIG: u8 = 0x00;
+const EN_AA: u8 = 0x01;
+const EN_RXADDR: u8 = 0x02;
+const SETUP_AW: u8 = 0x03;
+const SETUP_RETR: u8 = 0x04;
+const RF_CH: u8 = 0x05;
+const RF_SETUP: u8 = 0x06;
+const STATUS: u8 = 0x07;
+const OBSERVE_TX: u8 = 0x08;
+const RPD: u8 = 0x09;
+const RX_ADDR_P0: u8 = 0x0A;
+const RX_ADDR_P1: u8 = 0x0B;
+const TX_ADDR: u8 = 0x10;
+const RX_PW_P0: u8 = 0x11;
+const FIFO_STATUS: u8 = 0x17;
+const DYNPD: u8 = 0x1C;
+const FEATURE: u8 = 0x1D;
+
+// Commands
+const R_RX_PL_WID: u8 = 0x60;
+const R_RX_PAYLOAD: u8 = 0x61;
+const W_TX_PAYLOAD: u8 = 0xA0;
+const W_TX_PAYLOAD_NOACK: u8 = 0xB0;
+const FLUSH_TX: u8 = 0xE1;
+const FLUSH_RX: u8 = 0xE2;
+
+// CONFIG bits
+const PRIM_RX: u8 = 1 << 0;
+const PWR_UP: u8 = 1 << 1;
+// STATUS interrupt flags, masked by the same bits of CONFIG
+const MAX_RT: u8 = 1 << 4;
+const TX_DS: u8 = 1 << 5;
+const RX_DR: u8 = 1 << 6;
+const INTERRUPTS: u8 = RX_DR | TX_DS | MAX_RT;
+// FEATURE bits
+const EN_DYN_ACK: u8 = 1 << 0;
+const EN_ACK_PAY: u8 = 1 << 1;
+const EN_DPL: u8 = 1 << 2;
+// RF_SETUP data rate bits
+const RF_DR: u8 = 0b0010_1000;
+
+#[derive(Clone)]
+struct TxPacket {
+    payload: Vec<u8>,
+    // Packet identity, tells retransmits from new packets
+    pid: u8,
+    // Written with `W_TX_PAYLOAD_NOACK`
+    no_ack: bool,
+    // Written with `W_ACK_PAYLOAD` for this pipe
+    ack_pipe: Option<usize>,
+}
+
+struct RxPacket {
+    pipe: usize,
+    payload: Vec<u8>,
+}
+
+/// Registers and FIFOs of one chip
+struct Chip {
+    regs: [u8; 0x20],
+    rx_addr_p0: [u8; 5],
+    rx_addr_p1: [u8; 5],
+    tx_addr: [u8; 5],
+    tx_fifo: VecDeque<TxPacket>,
+    rx_fifo: VecDeque<RxPacket>,
+    ce: bool,
+    next_pid: u8,
+    // PID and payload of the last packet received on each pipe
+    last_rx: [Option<(u8, Vec<u8>)>; PIPES_COUNT],
+    // The ACK payload at the head of the pipe was sent, it is dropped
+    // once the PTX sends a new packet
+    ack_sent: [bool; PIPES_COUNT],
+    // Bytes shifted in during the current transaction
+    command: Vec<u8>,
+}
+
+impl Chip {
+    /// Reset values of the datasheet
+    fn new() -> Self {
+        let mut regs = [0; 0x20];
+        regs[CONFIG as usize] = 0x08;
+        regs[EN_AA as usize] = 0x3F;
+        regs[EN_RXADDR as usize] = 0x03;
+        regs[SETUP_AW as usize] = 0x03;
+        regs[SETUP_RETR as usize] = 0x03;
+        regs[RF_CH as usize] = 0x02;
+        regs[RF_SETUP as usize] = 0x0E;
+        regs[0x0C..0x10].copy_from_slice(&[0xC3, 0xC4, 0xC5, 0xC6]);
+        Chip {
+            regs,
+            rx_addr_p0: [0xE7; 5],
+            rx_addr_p1: [0xC2; 5],
+            tx_addr: [0xE7; 5],
+            tx_fifo: VecDeque::new(),
+            rx_fifo: VecDeque::new(),
+            ce: false,
+            next_pid: 0,
+            last_rx: Default::default(),
+            ack_sent: [false; PIPES_COUNT],
+            command: Vec::new(),
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let rx_p_no = self
+            .rx_fifo
+            .front()
+            .map_or(0b111, |packet| packet.pipe as u8);
+        let tx_full = self.tx_fifo.len() == FIFO_DEPTH;
+        self.regs[STATUS as usize] & INTERRUPTS | rx_p_no << 1 | tx_full as u8
+    }
+
+    fn fifo_status(&self) -> u8 {
+        let tx_full = self.tx_fifo.len() == FIFO_DEPTH;
+        let tx_empty = self.tx_fifo.is_empty();
+        let rx_full = self.rx_fifo.len() == FIFO_DEPTH;
+        let rx_empty = self.rx_fifo.is_empty();
+        (tx_full as u8) << 5 | (tx_empty as u8) << 4 | (rx_full as u8) << 1 | rx_empty as u8
+    }
+
+    /// Value of a one byte register
+    fn register(&self, reg: u8) -> u8 {
+        match reg {
+            STATUS => self.status(),
+            FIFO_STATUS => self.fifo_status(),
+            _ => self.regs[reg as usize],
+        }
+    }
+
+    fn read_register(&self, reg: u8, offset: usize) -> u8 {
+        match reg {
+            RX_ADDR_P0 => self.rx_addr_p0.get(offset).copied(),
+            RX_ADDR_P1 => self.rx_addr_p1.get(offset).copied(),
+            TX_ADDR => self.tx_addr.get(offset).copied(),
+            _ if offset == 0 => Some(self.register(reg)),
+            _ => None,
+        }
+        .unwrap_or(0x00)
+    }
+
+    fn write_register(&mut self, reg: u8, data: &[u8]) {
+        let Some(&value) = data.first() else {
+            return;
+        };
+        let copy_addr = |addr: &mut [u8; 5]| {
+            let len = data.len().min(addr.len());
+            addr[..len].copy_from_slice(&data[..len]);
+        };
+        match reg {
+            // Write 1 to clear
+            STATUS => self.regs[STATUS as usize] &= !(value & INTERRUPTS),
+            RX_ADDR_P0 => copy_addr(&mut self.rx_addr_p0),
+            RX_ADDR_P1 => copy_addr(&mut self.rx_addr_p1),
+            TX_ADDR => copy_addr(&mut self.tx_addr),
+            RF_CH => {
+                self.regs[RF_CH as usize] = value & 0x7F;
+                // Changing the channel resets PLOS_CNT
+                self.regs[OBSERVE_TX as usize] &= 0x0F;
+            }
+            // Read only
+            OBSERVE_TX | RPD | FIFO_STATUS => {}
+            _ => self.regs[reg as usize] = value,
+        }
+    }
+
+    fn feature(&self, bit: u8) -> bool {
+        self.regs[FEATURE as usize] & bit != 0
+    }
+
+    fn push_tx(&mut self, payload: &[u8], no_ack: bool, ack_pipe: Option<usize>) {
+        if payload.is_empty() || payload.len() > MAX_PAYLOAD_LEN || self.tx_fifo.len() == FIFO_DEPTH
+        {
+            return;
+        }
+        self.tx_fifo.push_back(TxPacket {
+            payload: payload.to_vec(),
+            pid: self.next_pid,
+            no_ack,
+            ack_pipe,
+        });
+        self.next_pid = (self.next_pid + 1) & 0b11;
+    }
+
+    fn select(&mut self) {
+        self.command.clear();
+    }
+
+    fn swap_byte(&mut self, mosi: u8) -> u8 {
+        self.command.push(mosi);
+        let Some(offset) = self.command.len().checked_sub(2) else {
+            // The STATUS register is shifted out with the command byte
+            return self.status();
+        };
+        match self.command[0] {
+            command @ 0x00..=0x1F => self.read_register(command, offset),
+            R_RX_PL_WID => self
+                .rx_fifo
+                .front()
+                .map_or(0, |packet| packet.payload.len() as u8),
+            R_RX_PAYLOAD => self
+                .rx_fifo
+                .front()
+                .and_then(|packet| packet.payload.get(offset))
+                .copied()
+                .unwrap_or(0x00),
+            _ => 0x00,
+        }
+    }
+
+    fn deselect(&mut self) {
+        let command = std::mem::take(&mut self.command);
+        let Some((&first, data)) = command.split_first() else {
+            return;
+        };
+        match first {
+            0x20..=0x3F => self.write_register(first & 0x1F, data),
+            R_RX_PAYLOAD if !data.is_empty() => {
+                self.rx_fifo.pop_front();
+            }
+            W_TX_PAYLOAD => self.push_tx(data, false, None),
+            W_TX_PAYLOAD_NOACK if self.feature(EN_DYN_ACK) => self.push_tx(data, true, None),
+            // W_ACK_PAYLOAD
+            0xA8..=0xAD if self.feature(EN_ACK_PAY) => {
+                self.push_tx(data, false, Some((first & 0x07) as usize))
+            }
+            FLUSH_TX => {
+                self.tx_fifo.clear();
+                self.ack_sent = [false; PIPES_COUNT];
+            }
+            FLUSH_RX => self.rx_fifo.clear(),
+            _ => {}
+        }
+    }
+
+    fn config(&self, bit: u8) -> bool {
+        self.regs[CONFIG as usize] & bit != 0
+    }
+
+    fn irq_active(&self) -> bool {
+        self.regs[STATUS as usize] & !self.regs[CONFIG as usize] & INTERRUPTS != 0
+    }
+
+    /// Standby-II or TX mode with a packet to send
+    fn can_transmit(&self) -> bool {
+        self.config(PWR_UP)
+            && !self.config(PRIM_RX)
+            && self.ce
+            // Sending stops until MAX_RT is cleared
+            && self.regs[STATUS as usize] & MAX_RT == 0
+            && self.tx_fifo.iter().any(|packet| packet.ack_pipe.is_none())
+    }
+
+    /// RX mode on `channel` with the data rate `rf_dr`
+    fn is_receiving(&self) -> bool {
+        self.config(PWR_UP) && self.config(PRIM_RX) && self.ce
+    }
+
+    fn is_listening(&self, channel: u8, rf_dr: u8) -> bool {
+        self.is_receiving()
+            && self.regs[RF_CH as usize] == channel
+            && self.regs[RF_SETUP as usize] & RF_DR == rf_dr
+    }
+
+    fn address_width(&self) -> usize {
+        match self.regs[SETUP_AW as usize] & 0b11 {
+            0b01 => 3,
+            0b10 => 4,
+            _ => 5,
+        }
+    }
+
+    /// Pipes 2 to 5 only have their own least significant byte
+    fn pipe_address(&self, pipe: usize) -> &[u8] {
+        let width = self.address_width();
+        match pipe {
+            0 => &self.rx_addr_p0[..width],
+            _ => &self.rx_addr_p1[..width],
+        }
+    }
+
+    fn matches(&self, pipe: usize, address: &[u8]) -> bool {
+        let own = self.pipe_address(pipe);
+        match pipe {
+            0 | 1 => own == address,
+            _ => {
+                own.len() == address.len()
+                    && address[0] == self.regs[RX_ADDR_P0 as usize + pipe]
+                    && own[1..] == address[1..]
+            }
+        }
+    }
+
+    fn payload_width(&self, pipe: usize) -> Option<usize> {
+        if self.feature(EN_DPL) && self.regs[DYNPD as usize] & 1 << pipe != 0 {
+            None
+        } else {
+            Some((self.regs[RX_PW_P0 as usize + pipe] & 0x3F) as usize)
+        }
+    }
+
+    /// Receive a packet sent to `address`
+    ///
+    /// Returns the pipe and whether the packet is new, or `None` when it
+    /// is not for this radio or does not fit into the RX FIFO.
+    fn receive(&mut self, address: &[u8], packet: &TxPacket) -> Option<(usize, bool)> {
+        let pipe = (0..PIPES_COUNT).find(|&pipe| {
+            self.regs[EN_RXADDR as usize] & 1 << pipe != 0 && self.matches(pipe, address)
+        })?;
+        // A static payload width that does not match fails the CRC
+        if let Some(width) = self.payload_width(pipe) {
+            if width != packet.payload.len() {
+                return None;
+            }
+        }
+
+        let new = !matches!(
+            &self.last_rx[pipe],
+            Some((pid, payload)) if *pid == packet.pid && *payload == packet.payload
+        );
+        if new {
+            if self.rx_fifo.len() == FIFO_DEPTH {
+                return None;
+            }
+            self.rx_fifo.push_back(RxPacket {
+                pipe,
+                payload: packet.payload.clone(),
+            });
+            self.last_rx[pipe] = Some((packet.pid, packet.payload.clone()));
+            self.regs[STATUS as usize] |= RX_DR;
+        }
+        Some((pipe, new))
+    }
+
+    /// Answer a packet on `pipe`, returns the ACK payload
+    fn acknowledge(&mut self, pipe: usize, new: bool) -> Option<Vec<u8>> {
+        let position = |chip: &Chip| {
+            chip.tx_fifo
+                .iter()
+                .position(|packet| packet.ack_pipe == Some(pipe))
+        };
+        if new && self.ack_sent[pipe] {
+            if let Some(index) = position(self) {
+                self.tx_fifo.remove(index);
+            }
+            self.ack_sent[pipe] = false;
+        }
+        if !self.feature(EN_ACK_PAY) {
+            return None;
+        }
+        let index = position(self)?;
+        self.ack_sent[pipe] = true;
+        self.regs[STATUS as usize] |= TX_DS;
+        Some(self.tx_fifo[index].payload.clone())
+    }
+
+    /// The head of the TX FIFO was acknowledged after `retransmits`
+    fn sent(&mut self, index: usize, retransmits: u8, ack_payload: Option<Vec<u8>>) {
+        self.tx_fifo.remove(index);
+        let observe_tx = &mut self.regs[OBSERVE_TX as usize];
+        *observe_tx = *observe_tx & 0xF0 | retransmits;
+        self.regs[STATUS as usize] |= TX_DS;
+        if let Some(payload) = ack_payload {
+            if self.rx_fifo.len() < FIFO_DEPTH {
+                self.rx_fifo.push_back(RxPacket { pipe: 0, payload });
+                self.regs[STATUS as usize] |= RX_DR;
+            }
+        }
+    }
+
+    /// No acknowledgement after `retransmits`, the packet stays in the FIFO
+    fn lost(&mut self, retransmits: u8) {
+        let plos_cnt = (self.regs[OBSERVE_TX as usize] >> 4)
+            .saturating_add(1)
+            .min(0x0F);
+        self.regs[OBSERVE_TX as usize] = plos_cnt << 4 | retransmits;
+        self.regs[STATUS as usize] |= MAX_RT;
+    }
+}
+
+/// Kind of a frame on the air, see [`Ether::set_filter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    /// Packet of the PTX
+    Data,
+    /// Acknowledgement of the PRX
+    Ack,
+}
+
+type Filter = Box<dyn FnMut(usize, usize, Frame) -> bool + Send>;
+
+#[derive(Default)]
+struct EtherState {
+    chips: Vec<Chip>,
+    filter: Option<Filter>,
+    noise: HashSet<u8>,
+}
+
+impl EtherState {
+    fn delivers(&mut self, from: usize, to: usize, frame: Frame) -> bool {
+        match self.filter.as_mut() {
+            Some(filter) => filter(from, to, frame),
+            None => true,
+        }
+    }
+
+    /// Sends the next packet of radio `tx` to all radios listening
+    fn transmit(&mut self, tx: usize) {
+        let chip = &self.chips[tx];
+        let Some(index) = chip
+            .tx_fifo
+            .iter()
+            .position(|packet| packet.ack_pipe.is_none())
+        else {
+            return;
+        };
+        let packet = chip.tx_fifo[index].clone();
+        let address = chip.tx_addr[..chip.address_width()].to_vec();
+        let channel = chip.regs[RF_CH as usize];
+        let rf_dr = chip.regs[RF_SETUP as usize] & RF_DR;
+        let auto_ack = !packet.no_ack && chip.regs[EN_AA as usize] & 1 != 0;
+        let retransmits = if auto_ack {
+            chip.regs[SETUP_RETR as usize] & 0x0F
+        } else {
+            0
+        };
+        // The ACK is received on pipe 0
+        let hears_ack = chip.pipe_address(0) == address.as_slice();
+
+        let noisy = self.noise.contains(&channel);
+        for attempt in 0..=retransmits {
+            let mut ack = None;
+            for rx in 0..self.chips.len() {
+                if noisy
+                    || rx == tx
+                    || !self.chips[rx].is_listening(channel, rf_dr)
+                    || !self.delivers(tx, rx, Frame::Data)
+                {
+                    continue;
+                }
+                let chip = &mut self.chips[rx];
+                let Some((pipe, new)) = chip.receive(&address, &packet) else {
+                    continue;
+                };
+                let acks = chip.regs[EN_AA as usize] & 1 << pipe != 0;
+                if auto_ack && acks && ack.is_none() {
+                    let ack_payload = chip.acknowledge(pipe, new);
+                    if self.delivers(rx, tx, Frame::Ack) {
+                        ack = Some(ack_payload);
+                    }
+                }
+            }
+            match ack {
+                Some(ack_payload) if hears_ack => {
+                    self.chips[tx].sent(index, attempt, ack_payload);
+                    return;
+                }
+                _ if !auto_ack => {
+                    self.chips[tx].sent(index, 0, None);
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.chips[tx].lost(retransmits);
+    }
+
+    /// Let every radio in TX mode send its FIFO
+    fn run(&mut self) {
+        // RPD keeps its value outside of RX mode
+        for chip in self.chips.iter_mut().filter(|chip| chip.is_receiving()) {
+            let channel = chip.regs[RF_CH as usize];
+            chip.regs[RPD as usize] = self.noise.contains(&channel) as u8;
+        }
+        for tx in 0..self.chips.len() {
+            while self.chips[tx].can_transmit() {
+                self.transmit(tx);
+            }
+        }
+    }
+}
+
+/// The air shared by the simulated radios
+#[derive(Clone, Default)]
+pub struct Ether {
+    state: Arc<Mutex<EtherState>>,
+    // notified whenever a radio changed, for the IRQ pins
+    changed: Arc<Condvar>,
+}
+
+impl Ether {
+    /// Empty air
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Power on a new radio with the reset register values
+    pub fn add_radio(&self) -> Radio {
+        let mut state = self.lock();
+        state.chips.push(Chip::new());
+        Radio {
+            ether: self.clone(),
+            id: state.chips.len() - 1,
+        }
+    }
+
+    /// Decide which frames get through
+    ///
+    /// `filter(from, to, frame)` is called with the [`Radio::id`] of the
+    /// sender and the receiver for every frame on the air, including
+    /// every retransmit, and drops the frame by returning `false`.
+    pub fn set_filter<F>(&self, filter: F)
+    where
+        F: FnMut(usize, usize, Frame) -> bool + Send + 'static,
+    {
+        self.lock().filter = Some(Box::new(filter));
+    }
+
+    /// Deliver all frames again
+    pub fn clear_filter(&self) {
+        self.lock().filter = None;
+    }
+
+    /// Put noise on `channel`, or take it away
+    ///
+    /// Radios listening on a noisy channel detect a carrier, frames sent
+    /// there are lost.
+    pub fn set_noise(&self, channel: u8, noisy: bool) {
+        assert!(channel < CHANNELS);
+        let mut state = self.lock();
+        if noisy {
+            state.noise.insert(channel);
+        } else {
+            state.noise.remove(&channel);
+        }
+        self.run(state);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, EtherState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Run the air and wake up the threads waiting for an IRQ
+    fn run(&self, mut state: MutexGuard<'_, EtherState>) {
+        state.run();
+        drop(state);
+        self.changed.notify_all();
+    }
+}
+
+/// SPI side of a simulated radio
+///
+/// Clones are handles to the same chip.
+#[derive(Clone)]
+pub struct Radio {
+    ether: Ether,
+    id: usize,
+}
+
+impl Default for Radio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Radio {
+    /// A radio alone in its own ether
+    pub fn new() -> Self {
+        Ether::new().add_radio()
+    }
+
+    /// Index of this radio in its [`Ether`]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The CE pin of this radio
+    pub fn ce_pin(&self) -> CePin {
+        CePin {
+            radio: self.clone(),
+        }
+    }
+
+    /// The IRQ pin of this radio
+    pub fn irq_pin(&self) -> IrqPin {
+        IrqPin {
+            radio: self.clone(),
+        }
+    }
+
+    /// Value of a one byte register, as read by `R_REGISTER`
+    pub fn register(&self, reg: u8) -> u8 {
+        self.with_chip(|chip| chip.register(reg))
+    }
+
+    /// Is the CE pin high?
+    pub fn is_ce_high(&self) -> bool {
+        self.with_chip(|chip| chip.ce)
+    }
+
+    /// Is the IRQ pin asserted (low)?
+    pub fn is_irq_active(&self) -> bool {
+        self.with_chip(|chip| chip.irq_active())
+    }
+
+    /// Number of packets in the TX FIFO
+    pub fn tx_fifo_len(&self) -> usize {
+        self.with_chip(|chip| chip.tx_fifo.len())
+    }
+
+    /// Number of packets in the RX FIFO
+    pub fn rx_fifo_len(&self) -> usize {
+        self.with_chip(|chip| chip.rx_fifo.len())
+    }
+
+    fn with_chip<R>(&self, f: impl FnOnce(&mut Chip) -> R) -> R {
+        f(&mut self.ether.lock().chips[self.id])
+    }
+}
+
+impl ErrorType for Radio {
+    type Error = Infallible;
+}
+
+impl SpiDevice for Radio {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut state = self.ether.lock();
+        let chip = &mut state.chips[self.id];
+        chip.select();
+        for operation in operations {
+            match operation {
+                Operation::Read(words) => {
+                    for word in words.iter_mut() {
+                        *word = chip.swap_byte(0x00);
+                    }
+                }
+                Operation::Write(words) => {
+                    for word in words.iter() {
+                        chip.swap_byte(*word);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    for i in 0..read.len().max(write.len()) {
+                        let byte = chip.swap_byte(write.get(i).copied().unwrap_or(0x00));
+                        if let Some(word) = read.get_mut(i) {
+                            *word = byte;
+                        }
+                    }
+                }
+                Operation::TransferInPlace(words) => {
+                    for word in words.iter_mut() {
+                        *word = chip.swap_byte(*word);
+                    }
+                }
+                // Delays have no effect on the simulator
+                _ => {}
+            }
+        }
+        chip.deselect();
+        self.ether.run(state);
+        Ok(())
+    }
+}
+
+/// CE pin of a simulated radio
+#[derive(Clone)]
+pub struct CePin {
+    radio: Radio,
+}
+
+impl PinErrorType for CePin {
+    type Error = Infallible;
+}
+
+impl OutputPin for CePin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.radio.with_chip(|chip| chip.ce = false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let mut state = self.radio.ether.lock();
+        state.chips[self.radio.id].ce = true;
+        self.radio.ether.run(state);
+        Ok(())
+    }
+}
+
+/// IRQ pin of a simulated radio, active low
+///
+/// Other threads may drive the radios of the same [`Ether`], so
+/// [`IrqPin::wait_active`] blocks like a GPIO interrupt would.
+#[derive(Clone)]
+pub struct IrqPin {
+    radio: Radio,
+}
+
+impl IrqPin {
+    /// Block until the IRQ pin is asserted, or `timeout` passed.
+    /// Returns `false` on timeout.
+    pub fn wait_active(&self, timeout: Duration) -> bool {
+        let ether = &self.radio.ether;
+        let (state, _) = ether
+            .changed
+            .wait_timeout_while(ether.lock(), timeout, |state| {
+                !state.chips[self.radio.id].irq_active()
+            })
+            .unwrap();
+        state.chips[self.radio.id].irq_active()
+    }
+}
+
+impl PinErrorType for IrqPin {
+    type Error = Infallible;
+}
+
+impl InputPin for IrqPin {
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(!self.radio.is_irq_active())
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(self.radio.is_irq_active())
+    }
+}
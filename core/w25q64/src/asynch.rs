@@ -0,0 +1,544 @@
+//! 异步版本实现
+//!
+//! 驱动基于 embedded-hal-async 的 `SpiDevice` 和 `DelayUs`, 可以运行在 embassy 等异步执行器上。
+//! 编程和擦除后等待芯片空闲时, 每次轮询之间 `.await` 定时器, 不会阻塞同一执行器中的其他任务。
+//! 没有系统时钟, 超时按照已等待的轮询间隔累计计算。
+//!
+//! 除双线/四线读取外, 命令与阻塞版本相同。embedded-hal-async 没有多线传输的 SPI 抽象,
+//! 异步驱动只支持单线的标准读取和快速读取, 双线/四线读取使用 `hal::W25Q64::new_multi_io`。
+use core::ops::Range;
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayUs;
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+use super::command::{self, Erase};
+use super::conf::*;
+use super::crc::Crc32;
+use super::error::W25q64Error;
+use super::read::ReadMode;
+use super::status::StatusRegister;
+use super::storage::{check_erase, check_slice};
+
+// 校验时每次读取的字节数
+const VERIFY_CHUNK_SIZE: usize = W25Q64_PAGE_SIZE;
+
+/// 异步的 W25Q64 驱动, 命令与 `hal::W25Q64` 相同
+pub struct W25Q64<SPI, D> {
+    spi: SPI,
+    delay: D,
+    // 芯片容量, 首次使用时从JEDEC设备ID中读取
+    capacity: Option<u32>,
+    // 受块保护的地址范围, 首次编程或擦除时从状态寄存器中读取
+    protected: Option<Range<u32>>,
+    // 等待芯片空闲时的轮询间隔
+    poll_interval: Duration,
+    // 读取数据的模式
+    read_mode: ReadMode,
+}
+
+impl<SPI, D> W25Q64<SPI, D>
+where
+    SPI: SpiDevice,
+    D: DelayUs,
+{
+    /// 使用任意实现了异步 `SpiDevice` 的 SPI 设备和定时器创建对象
+    pub fn new(spi: SPI, delay: D) -> Self {
+        W25Q64 {
+            spi,
+            delay,
+            capacity: None,
+            protected: None,
+            poll_interval: W25Q64_POLL_INTERVAL,
+            read_mode: ReadMode::Standard,
+        }
+    }
+
+    /// 设置等待芯片空闲时的轮询间隔, 最小 1us
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// 释放 SPI 设备和定时器
+    pub fn release(self) -> (SPI, D) {
+        (self.spi, self.delay)
+    }
+
+    /// 当前读取数据的模式
+    pub fn read_mode(&self) -> ReadMode {
+        self.read_mode
+    }
+
+    /// 设置读取数据的模式, 只支持标准读取和快速读取
+    pub fn set_read_mode(&mut self, mode: ReadMode) -> Result<(), W25q64Error<SPI::Error>> {
+        if mode.is_multi_io() {
+            return Err(W25q64Error::UnsupportedReadMode { mode });
+        }
+        self.read_mode = mode;
+        Ok(())
+    }
+
+    /// 四线使能位 QE 是否置位
+    pub async fn quad_enable(&mut self) -> Result<bool, W25q64Error<SPI::Error>> {
+        Ok(self.read_status_register().await?.qe)
+    }
+
+    /// 设置四线使能位 QE, 该位是非易失的, 掉电后保持
+    pub async fn set_quad_enable(&mut self, enable: bool) -> Result<(), W25q64Error<SPI::Error>> {
+        let mut status = self.read_status_register().await?;
+        if status.qe == enable {
+            return Ok(());
+        }
+        status.qe = enable;
+        self.write_status_register(&status).await
+    }
+
+    /// 启用写入功能
+    pub async fn write_enable(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_WRITE_ENABLE]).await?;
+        Ok(())
+    }
+
+    /// 禁用写入功能
+    pub async fn write_disable(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_WRITE_DISABLE]).await?;
+        Ok(())
+    }
+
+    /// 读取芯片的JEDEC设备ID
+    pub async fn read_jedec_device_id(&mut self) -> Result<(u8, u8, u8), W25q64Error<SPI::Error>> {
+        let mut buf = command::command_buf(W25Q64_JEDEC_DEVICE_ID);
+        self.spi.transfer_in_place(&mut buf).await?;
+
+        Ok(command::jedec_device_id(&buf))
+    }
+
+    /// 芯片容量, 单位字节
+    pub async fn capacity(&mut self) -> Result<u32, W25q64Error<SPI::Error>> {
+        if let Some(capacity) = self.capacity {
+            return Ok(capacity);
+        }
+
+        let (_, _, capacity) = self.read_jedec_device_id().await?;
+        let capacity = command::capacity(capacity);
+        self.capacity = Some(capacity);
+        Ok(capacity)
+    }
+
+    /// 检查读写范围是否超出芯片容量
+    async fn check_range(
+        &mut self,
+        address: u32,
+        len: usize,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity().await?;
+        check_slice(address, len, capacity)
+    }
+
+    /// 读取芯片的制造商和设备ID
+    /// 0xEF16: 代表W25Q64芯片
+    pub async fn read_manufacturer_device_id(
+        &mut self,
+    ) -> Result<(u16, u16), W25q64Error<SPI::Error>> {
+        let mut buf = command::command_buf(W25Q64_MANUFACTURER_DEVICE_ID);
+        self.spi.transfer_in_place(&mut buf).await?;
+
+        Ok(command::manufacturer_device_id(&buf))
+    }
+
+    /// 读取单个寄存器
+    async fn read_register(&mut self, cmd: u8) -> Result<u8, W25q64Error<SPI::Error>> {
+        let mut buf = [cmd, 0];
+        self.spi.transfer_in_place(&mut buf).await?;
+
+        Ok(buf[1])
+    }
+
+    /// 读取状态寄存器1和2
+    pub async fn read_status_register(
+        &mut self,
+    ) -> Result<StatusRegister, W25q64Error<SPI::Error>> {
+        let sr1 = self.read_register(W25Q64_READ_STATUS_REGISTER_1).await?;
+        let sr2 = self.read_register(W25Q64_READ_STATUS_REGISTER_2).await?;
+        Ok(StatusRegister::from_bits(sr1, sr2))
+    }
+
+    /// 写入状态寄存器1和2
+    pub async fn write_status_register(
+        &mut self,
+        status: &StatusRegister,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.write_enable().await?;
+
+        let cmd = [W25Q64_WRITE_STATUS_REGISTER, status.sr1(), status.sr2()];
+        self.spi.write(&cmd).await?;
+        // 块保护位可能已经改变
+        self.protected = None;
+
+        self.wait_for_idle(W25Q64_WRITE_STATUS_REGISTER_TIMEOUT)
+            .await
+    }
+
+    /// 读取状态寄存器3
+    /// 包含输出驱动能力(DRV1/DRV0)和写保护选择(WPS)
+    pub async fn read_status_register_3(&mut self) -> Result<u8, W25q64Error<SPI::Error>> {
+        self.read_register(W25Q64_READ_STATUS_REGISTER_3).await
+    }
+
+    /// 写入状态寄存器3
+    pub async fn write_status_register_3(
+        &mut self,
+        value: u8,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.write_enable().await?;
+
+        let cmd = [W25Q64_WRITE_STATUS_REGISTER_3, value];
+        self.spi.write(&cmd).await?;
+
+        self.wait_for_idle(W25Q64_WRITE_STATUS_REGISTER_TIMEOUT)
+            .await
+    }
+
+    /// 检查是否有写保护标志
+    /// SRP0 或 SRP1 置位时, 状态寄存器受到保护
+    pub async fn check_write_protect(&mut self) -> Result<bool, W25q64Error<SPI::Error>> {
+        let status = self.read_status_register().await?;
+        Ok(status.srp0 || status.srp1)
+    }
+
+    /// 当前受块保护的地址范围, 没有保护时返回空范围
+    pub async fn protected_range(&mut self) -> Result<Range<u32>, W25q64Error<SPI::Error>> {
+        let capacity = self.capacity().await?;
+        let status = self.read_status_register().await?;
+        let range = status.protected_range(capacity);
+        self.protected = Some(range.clone());
+        Ok(range)
+    }
+
+    /// 检查 [address, address + len) 是否可以编程或擦除
+    async fn check_writable(
+        &mut self,
+        address: u32,
+        len: u32,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        let protected = match &self.protected {
+            Some(protected) => protected.clone(),
+            None => self.protected_range().await?,
+        };
+        command::check_writable(&protected, address, len)
+    }
+
+    /// 设置块保护, 使受保护的地址范围恰好为 range
+    pub async fn protect(&mut self, range: Range<u32>) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity().await?;
+        let mut status = self.read_status_register().await?;
+        if !status.set_protected_range(range.clone(), capacity) {
+            return Err(W25q64Error::UnsupportedProtectRange {
+                start: range.start,
+                end: range.end,
+            });
+        }
+        self.write_status_register(&status).await
+    }
+
+    /// 等待W25Q64芯片空闲
+    /// 每次轮询之间等待一个轮询间隔, 累计等待超过 timeout 仍然忙碌时返回 `W25q64Error::BusyTimeout`
+    pub async fn wait_for_idle(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        let interval = self.poll_interval.max(Duration::from_micros(1));
+        let mut elapsed = Duration::ZERO;
+
+        loop {
+            // 检查状态寄存器1的最低位，如果为0表示空闲，否则表示忙碌
+            let status = self.read_register(W25Q64_READ_STATUS_REGISTER_1).await?;
+            if !command::is_busy(status) {
+                return Ok(());
+            }
+
+            if elapsed >= timeout {
+                return Err(W25q64Error::BusyTimeout { timeout });
+            }
+            let wait = interval.min(timeout - elapsed);
+            self.delay.delay_us(wait.as_micros().max(1) as u32).await;
+            elapsed += wait;
+        }
+    }
+
+    /// 页编程, 写入数据
+    /// page_address: 设定页地址
+    /// data: 要写入的数据, 不超过一页, 跨越页边界时回绕到页首
+    pub async fn page_program(
+        &mut self,
+        page_address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        command::check_page(page_address, data.len())?;
+        self.check_writable(page_address, data.len() as u32).await?;
+        self.write_enable().await?;
+
+        let cmd = command::address_command(W25Q64_PAGE_PROGRAM, page_address);
+
+        // 指令和数据需要在同一次片选内发送
+        self.spi
+            .transaction(&mut [Operation::Write(&cmd), Operation::Write(data)])
+            .await?;
+
+        self.wait_for_idle(W25Q64_PAGE_PROGRAM_TIMEOUT).await
+    }
+
+    /// 擦除地址所在的扇区
+    pub async fn sector_erase(&mut self, address: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_command(Erase::Sector, address).await
+    }
+
+    /// 擦除地址所在的 32KB 块
+    pub async fn block_erase_32kb(&mut self, address: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_command(Erase::Block32Kb, address).await
+    }
+
+    /// 擦除地址所在的 64KB 块
+    pub async fn block_erase_64kb(&mut self, address: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_command(Erase::Block64Kb, address).await
+    }
+
+    /// 发送带地址的擦除指令, 并等待擦除完成
+    async fn erase_command(
+        &mut self,
+        erase: Erase,
+        address: u32,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.check_writable(erase.align(address), erase.size() as u32)
+            .await?;
+        self.write_enable().await?;
+
+        let cmd = command::address_command(erase.command(), address);
+        self.spi.write(&cmd).await?;
+
+        self.wait_for_idle(erase.timeout()).await
+    }
+
+    /// 暂停正在进行的扇区/块擦除, 暂停期间可以读取其他扇区
+    pub async fn erase_suspend(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_ERASE_SUSPEND]).await?;
+        Ok(())
+    }
+
+    /// 恢复被暂停的擦除
+    pub async fn erase_resume(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_ERASE_RESUME]).await?;
+        Ok(())
+    }
+
+    /// 擦除闪存芯片上的所有扇区
+    /// 整片擦除需要数十秒, 等待期间执行器可以运行其他任务
+    pub async fn erase_chip(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity().await?;
+        self.check_writable(0, capacity).await?;
+        self.write_enable().await?;
+
+        self.spi.write(&[W25Q64_CHIP_ERASE]).await?;
+
+        self.wait_for_idle(W25Q64_CHIP_ERASE_TIMEOUT).await
+    }
+
+    /// 擦除范围 [range.start, range.end), 范围需要按扇区(4KB)对齐
+    /// 对齐的部分使用块擦除, 减少擦除次数
+    pub async fn erase_range(&mut self, range: Range<u32>) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity().await?;
+        check_erase(range.start, range.end, capacity)?;
+
+        let mut address = range.start;
+        while address < range.end {
+            let erase = Erase::fit(address, (range.end - address) as usize);
+            self.erase_command(erase, address).await?;
+            address += erase.size() as u32;
+        }
+        Ok(())
+    }
+
+    /// 进入掉电模式, 掉电期间只响应 `release_power_down`
+    pub async fn power_down(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_POWER_DOWN]).await?;
+        Ok(())
+    }
+
+    /// 退出掉电模式, 并读取设备ID
+    /// 0x16: 代表W25Q64芯片
+    pub async fn release_power_down(&mut self) -> Result<u8, W25q64Error<SPI::Error>> {
+        let mut buf = [W25Q64_RELEASE_POWER_DOWN_HPM_DEVICE_ID, 0, 0, 0, 0];
+        self.spi.transfer_in_place(&mut buf).await?;
+
+        Ok(buf[4])
+    }
+
+    /// 读取芯片出厂时写入的64位唯一ID
+    pub async fn read_unique_id(&mut self) -> Result<u64, W25q64Error<SPI::Error>> {
+        // 指令之后需要4个虚拟字节
+        let mut buf = command::command_buf(W25Q64_READ_UNIQUE_ID);
+        self.spi.transfer_in_place(&mut buf).await?;
+
+        Ok(command::unique_id(&buf))
+    }
+
+    /// 按当前的读取模式读取数据
+    /// read_address: 目标地址
+    /// data: 用于存放数据
+    pub async fn read_data(
+        &mut self,
+        read_address: u32,
+        data: &mut [u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        let mode = self.read_mode;
+        let (cmd, len) = command::read_command(mode, read_address);
+        // 指令和数据需要在同一次片选内传输
+        self.spi
+            .transaction(&mut [Operation::Write(&cmd[..len]), Operation::Read(data)])
+            .await?;
+
+        Ok(())
+    }
+
+    /// 写入任意长度的数据
+    /// 按页(256字节)边界拆分成多次页编程, 写入前需要保证目标区域已擦除
+    pub async fn write(
+        &mut self,
+        address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.check_range(address, data.len()).await?;
+
+        for (page_address, chunk) in command::page_chunks(address, data) {
+            self.page_program(page_address, chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// 写入任意长度的数据, 并读回校验
+    /// 写入前需要保证目标区域已擦除, 校验失败时返回第一个不一致的地址
+    pub async fn program_and_verify(
+        &mut self,
+        address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.write(address, data).await?;
+        self.verify(address, data).await
+    }
+
+    /// 读回数据并与 data 比较, 不一致时返回第一个不一致的地址
+    pub async fn verify(
+        &mut self,
+        address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        let mut buf = [0; VERIFY_CHUNK_SIZE];
+        let end = address.saturating_add(data.len() as u32);
+        self.read_chunks(address..end, &mut buf, |chunk_address, actual| {
+            let offset = (chunk_address - address) as usize;
+            command::compare(chunk_address, data[offset..].iter().copied(), actual)
+        })
+        .await
+    }
+
+    /// 擦除范围 [range.start, range.end) 并检查是否全部为 0xFF
+    /// 范围需要按扇区(4KB)对齐, 校验失败时返回第一个不为 0xFF 的地址
+    pub async fn erase_and_verify(
+        &mut self,
+        range: Range<u32>,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_range(range.clone()).await?;
+        self.blank_check(range).await
+    }
+
+    /// 检查范围 [range.start, range.end) 是否全部为 0xFF
+    pub async fn blank_check(&mut self, range: Range<u32>) -> Result<(), W25q64Error<SPI::Error>> {
+        let mut buf = [0; VERIFY_CHUNK_SIZE];
+        self.read_chunks(range, &mut buf, |address, actual| {
+            command::compare(address, core::iter::repeat(0xFF), actual)
+        })
+        .await
+    }
+
+    /// 计算范围 [range.start, range.end) 的 CRC32 (IEEE)
+    /// 分块读取, 不需要把整个区域读入内存
+    pub async fn crc32(&mut self, range: Range<u32>) -> Result<u32, W25q64Error<SPI::Error>> {
+        let mut buf = [0; VERIFY_CHUNK_SIZE];
+        let mut crc = Crc32::new();
+        self.read_chunks(range, &mut buf, |_, data| {
+            crc.update(data);
+            Ok(())
+        })
+        .await?;
+        Ok(crc.finish())
+    }
+
+    /// 以 buf 的大小分块读取范围 [range.start, range.end), 每块数据交给 f 处理
+    async fn read_chunks<F>(
+        &mut self,
+        range: Range<u32>,
+        buf: &mut [u8],
+        mut f: F,
+    ) -> Result<(), W25q64Error<SPI::Error>>
+    where
+        F: FnMut(u32, &[u8]) -> Result<(), W25q64Error<SPI::Error>>,
+    {
+        let len = range.end.saturating_sub(range.start) as usize;
+        self.check_range(range.start, len).await?;
+
+        let mut address = range.start;
+        while address < range.end {
+            let len = ((range.end - address) as usize).min(buf.len());
+            let chunk = &mut buf[..len];
+            self.read_data(address, chunk).await?;
+            f(address, chunk)?;
+            address += chunk.len() as u32;
+        }
+        Ok(())
+    }
+
+    /// 写入任意长度的数据, 并保留扇区中的其他数据
+    /// 对涉及到的每个扇区(4KB)执行 读取-修改-擦除-写入,
+    /// 如果写入只需要把 1 写成 0, 则跳过擦除直接写入
+    pub async fn write_preserving(
+        &mut self,
+        address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.check_range(address, data.len()).await?;
+
+        let mut sector_buf = vec![0; W25Q64_SECTOR_SIZE];
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let sector_address = address - address % W25Q64_SECTOR_SIZE as u32;
+            let offset = (address - sector_address) as usize;
+            let (chunk, rest) = data.split_at((W25Q64_SECTOR_SIZE - offset).min(data.len()));
+
+            // 读取整个扇区
+            self.read_data(sector_address, &mut sector_buf).await?;
+            let old = &sector_buf[offset..offset + chunk.len()];
+            if old == chunk {
+                // 数据相同, 无需写入
+            } else if old.iter().zip(chunk).all(|(old, new)| old & new == *new) {
+                // 只需要把 1 写成 0, 无需擦除
+                self.write(address, chunk).await?;
+            } else {
+                // 修改扇区数据后擦除并写回, 跳过全部为 0xFF 的页
+                sector_buf[offset..offset + chunk.len()].copy_from_slice(chunk);
+                self.sector_erase(sector_address).await?;
+                for (i, page) in sector_buf.chunks(W25Q64_PAGE_SIZE).enumerate() {
+                    if page.iter().any(|&byte| byte != 0xFF) {
+                        self.page_program(sector_address + (i * W25Q64_PAGE_SIZE) as u32, page)
+                            .await?;
+                    }
+                }
+            }
+
+            address += chunk.len() as u32;
+            data = rest;
+        }
+        Ok(())
+    }
+}
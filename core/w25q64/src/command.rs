@@ -0,0 +1,171 @@
+//! 指令编码
+//!
+//! 阻塞版本和异步版本的驱动共用的指令编码和应答解析, 驱动中只保留 SPI 传输。
+use core::ops::Range;
+use core::time::Duration;
+
+use super::conf::*;
+use super::error::W25q64Error;
+use super::read::ReadMode;
+
+/// 以指令开头的收发缓冲区, 其余字节为 0
+pub(crate) fn command_buf<const N: usize>(cmd: u8) -> [u8; N] {
+    let mut buf = [0; N];
+    buf[0] = cmd;
+    buf
+}
+
+/// 指令和24位地址
+pub(crate) fn address_command(cmd: u8, address: u32) -> [u8; 4] {
+    [
+        cmd,                   // 指令
+        (address >> 16) as u8, // 地址23~16位
+        (address >> 8) as u8,  // 地址15~8位
+        address as u8,         // 地址7~0位
+    ]
+}
+
+/// 单线读取的指令, 返回指令缓冲区和需要发送的字节数
+/// 快速读取在地址之后发送1个虚拟字节(8个虚拟时钟)
+pub(crate) fn read_command(mode: ReadMode, address: u32) -> ([u8; 5], usize) {
+    let [cmd, a2, a1, a0] = address_command(mode.command(), address);
+    let len = 4 + mode.dummy_cycles() as usize / 8;
+    ([cmd, a2, a1, a0, W25Q64_DUMMY_BYTE], len)
+}
+
+/// 解析JEDEC设备ID的应答: (制造商ID, 存储器类型, 容量)
+pub(crate) fn jedec_device_id(buf: &[u8; 4]) -> (u8, u8, u8) {
+    (buf[1], buf[2], buf[3])
+}
+
+/// 由JEDEC设备ID的容量字节计算芯片容量, 例如 0x17 表示 2^23 = 8MB
+pub(crate) fn capacity(capacity_id: u8) -> u32 {
+    1u32.checked_shl(capacity_id as u32).unwrap_or(0)
+}
+
+/// 解析制造商和设备ID的应答
+pub(crate) fn manufacturer_device_id(buf: &[u8; 7]) -> (u16, u16) {
+    let manufacturer_id = buf[4] as u16;
+    let device_id = (buf[5] as u16) << 8 | buf[6] as u16;
+    (manufacturer_id, device_id)
+}
+
+/// 解析64位唯一ID的应答, 指令之后有4个虚拟字节
+pub(crate) fn unique_id(buf: &[u8; 13]) -> u64 {
+    let mut id = [0; 8];
+    id.copy_from_slice(&buf[5..]);
+    u64::from_be_bytes(id)
+}
+
+/// 状态寄存器1的最低位为1表示芯片忙碌
+pub(crate) fn is_busy(sr1: u8) -> bool {
+    sr1 & 0x01 != 0
+}
+
+/// 检查 [address, address + len) 是否与受保护的范围重叠
+pub(crate) fn check_writable<E>(
+    protected: &Range<u32>,
+    address: u32,
+    len: u32,
+) -> Result<(), W25q64Error<E>> {
+    let start = address.max(protected.start);
+    let end = address.saturating_add(len).min(protected.end);
+    if start < end {
+        return Err(W25q64Error::WriteProtected { address: start });
+    }
+    Ok(())
+}
+
+/// 擦除的粒度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Erase {
+    Sector,
+    Block32Kb,
+    Block64Kb,
+}
+
+impl Erase {
+    /// 擦除 [address, address + remain) 时, 从 address 开始可以使用的最大粒度
+    pub(crate) fn fit(address: u32, remain: usize) -> Self {
+        [Erase::Block64Kb, Erase::Block32Kb]
+            .into_iter()
+            .find(|erase| address as usize % erase.size() == 0 && remain >= erase.size())
+            .unwrap_or(Erase::Sector)
+    }
+
+    /// 擦除的指令
+    pub(crate) fn command(self) -> u8 {
+        match self {
+            Erase::Sector => W25Q64_SECTOR_ERASE_4KB,
+            Erase::Block32Kb => W25Q64_BLOCK_ERASE_32KB,
+            Erase::Block64Kb => W25Q64_BLOCK_ERASE_64KB,
+        }
+    }
+
+    /// 擦除的大小
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Erase::Sector => W25Q64_SECTOR_SIZE,
+            Erase::Block32Kb => W25Q64_BLOCK_32KB_SIZE,
+            Erase::Block64Kb => W25Q64_BLOCK_64KB_SIZE,
+        }
+    }
+
+    /// 等待擦除完成的时间
+    pub(crate) fn timeout(self) -> Duration {
+        match self {
+            Erase::Sector => W25Q64_SECTOR_ERASE_TIMEOUT,
+            Erase::Block32Kb => W25Q64_BLOCK_ERASE_32KB_TIMEOUT,
+            Erase::Block64Kb => W25Q64_BLOCK_ERASE_64KB_TIMEOUT,
+        }
+    }
+
+    /// 地址所在的擦除单元的起始地址
+    pub(crate) fn align(self, address: u32) -> u32 {
+        address - address % self.size() as u32
+    }
+}
+
+/// 检查页编程的数据是否超过一页
+pub(crate) fn check_page<E>(address: u32, len: usize) -> Result<(), W25q64Error<E>> {
+    if len > W25Q64_PAGE_SIZE {
+        return Err(W25q64Error::PageOverflow { address, len });
+    }
+    Ok(())
+}
+
+/// 按页(256字节)边界拆分写入, 每项为 (页地址, 数据)
+pub(crate) fn page_chunks(address: u32, data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut address = address;
+    let mut data = data;
+    core::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+        // 当前页剩余的空间
+        let page_remain = W25Q64_PAGE_SIZE - address as usize % W25Q64_PAGE_SIZE;
+        let (chunk, rest) = data.split_at(page_remain.min(data.len()));
+        let item = (address, chunk);
+        address += chunk.len() as u32;
+        data = rest;
+        Some(item)
+    })
+}
+
+/// 比较期望的数据和读回的数据, 返回第一个不一致的地址
+pub(crate) fn compare<E>(
+    address: u32,
+    expected: impl Iterator<Item = u8>,
+    actual: &[u8],
+) -> Result<(), W25q64Error<E>> {
+    for (i, (expected, &actual)) in expected.zip(actual).enumerate() {
+        if expected != actual {
+            return Err(W25q64Error::VerifyMismatch {
+                address: address + i as u32,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
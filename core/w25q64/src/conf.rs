@@ -1,5 +1,6 @@
 //! 配置
 //! 定义W25Q64芯片的相关命令和参数
+use core::time::Duration;
 
 // 写使能命令
 pub const W25Q64_WRITE_ENABLE: u8 = 0x06;
@@ -8,7 +9,9 @@ pub const W25Q64_WRITE_DISABLE: u8 = 0x04;
 // 读状态寄存器1命令
 pub const W25Q64_READ_STATUS_REGISTER_1: u8 = 0x05;
 pub const W25Q64_READ_STATUS_REGISTER_2: u8 = 0x35;
+pub const W25Q64_READ_STATUS_REGISTER_3: u8 = 0x15;
 pub const W25Q64_WRITE_STATUS_REGISTER: u8 = 0x01;
+pub const W25Q64_WRITE_STATUS_REGISTER_3: u8 = 0x11;
 // 页编程命令
 pub const W25Q64_PAGE_PROGRAM: u8 = 0x02;
 pub const W25Q64_QUAD_PAGE_PROGRAM: u8 = 0x32;
@@ -40,5 +43,31 @@ pub const W25Q64_OCTAL_WORD_READ_QUAD_IO: u8 = 0xE3;
 
 pub const W25Q64_DUMMY_BYTE: u8 = 0xFF;
 
+// 32KB 块大小
+pub const W25Q64_BLOCK_32KB_SIZE: usize = 32 * 1024;
+// 64KB 块大小
+pub const W25Q64_BLOCK_64KB_SIZE: usize = 64 * 1024;
+
 // 页大小为256字节
 pub const W25Q64_PAGE_SIZE: usize = 256;
+// 扇区大小为4KB
+pub const W25Q64_SECTOR_SIZE: usize = 4096;
+// 芯片容量为8MB
+pub const W25Q64_CAPACITY: usize = 8 * 1024 * 1024;
+
+// 各操作的最大耗时(数据手册), 用作等待芯片空闲的默认超时时间
+// 写状态寄存器
+pub const W25Q64_WRITE_STATUS_REGISTER_TIMEOUT: Duration = Duration::from_millis(15);
+// 页编程
+pub const W25Q64_PAGE_PROGRAM_TIMEOUT: Duration = Duration::from_millis(3);
+// 扇区擦除
+pub const W25Q64_SECTOR_ERASE_TIMEOUT: Duration = Duration::from_millis(400);
+// 32KB 块擦除
+pub const W25Q64_BLOCK_ERASE_32KB_TIMEOUT: Duration = Duration::from_millis(1600);
+// 64KB 块擦除
+pub const W25Q64_BLOCK_ERASE_64KB_TIMEOUT: Duration = Duration::from_millis(2000);
+// 整片擦除
+pub const W25Q64_CHIP_ERASE_TIMEOUT: Duration = Duration::from_secs(100);
+
+// 等待芯片空闲时的默认轮询间隔
+pub const W25Q64_POLL_INTERVAL: Duration = Duration::from_millis(1);
@@ -0,0 +1,65 @@
+//! CRC32 校验
+//!
+//! 使用 IEEE 802.3 多项式(与 zlib 相同), 支持分段流式计算。
+
+// 反转后的多项式
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+// 查找表, 编译期生成
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// 流式 CRC32 计算
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    /// 创建对象
+    pub fn new() -> Self {
+        Crc32 { crc: 0xFFFF_FFFF }
+    }
+
+    /// 追加数据
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = TABLE[((self.crc ^ byte as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    /// 获取校验值
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+/// 计算一段数据的 CRC32
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
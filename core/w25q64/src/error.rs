@@ -0,0 +1,166 @@
+//! 错误类型
+use core::fmt::Debug;
+use core::time::Duration;
+
+use super::conf::W25Q64_PAGE_SIZE;
+use super::read::ReadMode;
+
+/// W25Q64 驱动的所有错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum W25q64Error<E> {
+    /// SPI 通信错误
+    Spi(E),
+
+    /// 读写范围超出芯片容量
+    AddressOutOfRange {
+        /// 起始地址
+        address: u32,
+        /// 数据长度
+        len: usize,
+        /// 芯片容量
+        capacity: u32,
+    },
+
+    /// 地址没有按扇区对齐
+    NotAligned {
+        /// 未对齐的地址
+        address: u32,
+    },
+
+    /// 等待芯片空闲超时, 芯片可能没有连接或已损坏
+    BusyTimeout {
+        /// 等待的时间
+        timeout: Duration,
+    },
+
+    /// 目标地址受块保护, 芯片会忽略编程和擦除指令
+    WriteProtected {
+        /// 受保护的地址
+        address: u32,
+    },
+
+    /// 校验失败, 读回的数据与期望的数据不一致
+    VerifyMismatch {
+        /// 第一个不一致的地址
+        address: u32,
+        /// 期望的值
+        expected: u8,
+        /// 读回的值
+        actual: u8,
+    },
+
+    /// SPI 设备不支持该读取模式, 双线/四线读取需要实现 `MultiIoSpi` 的 SPI 设备
+    UnsupportedReadMode {
+        /// 读取模式
+        mode: ReadMode,
+    },
+
+    /// 页编程的数据超过一页(256字节)
+    PageOverflow {
+        /// 页编程的地址
+        address: u32,
+        /// 数据长度
+        len: usize,
+    },
+
+    /// 块保护位无法表示该保护范围
+    UnsupportedProtectRange {
+        /// 起始地址
+        start: u32,
+        /// 结束地址(不包含)
+        end: u32,
+    },
+}
+
+impl<E> W25q64Error<E> {
+    /// 转换 SPI 错误的类型, 其他错误保持不变
+    pub fn map_spi<F>(self, f: impl FnOnce(E) -> F) -> W25q64Error<F> {
+        match self {
+            W25q64Error::Spi(e) => W25q64Error::Spi(f(e)),
+            W25q64Error::AddressOutOfRange {
+                address,
+                len,
+                capacity,
+            } => W25q64Error::AddressOutOfRange {
+                address,
+                len,
+                capacity,
+            },
+            W25q64Error::NotAligned { address } => W25q64Error::NotAligned { address },
+            W25q64Error::BusyTimeout { timeout } => W25q64Error::BusyTimeout { timeout },
+            W25q64Error::WriteProtected { address } => W25q64Error::WriteProtected { address },
+            W25q64Error::VerifyMismatch {
+                address,
+                expected,
+                actual,
+            } => W25q64Error::VerifyMismatch {
+                address,
+                expected,
+                actual,
+            },
+            W25q64Error::UnsupportedReadMode { mode } => W25q64Error::UnsupportedReadMode { mode },
+            W25q64Error::PageOverflow { address, len } => {
+                W25q64Error::PageOverflow { address, len }
+            }
+            W25q64Error::UnsupportedProtectRange { start, end } => {
+                W25q64Error::UnsupportedProtectRange { start, end }
+            }
+        }
+    }
+}
+
+impl<E> From<E> for W25q64Error<E> {
+    fn from(e: E) -> Self {
+        W25q64Error::Spi(e)
+    }
+}
+
+impl<E: Debug> std::fmt::Display for W25q64Error<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            W25q64Error::Spi(e) => write!(f, "Spi Error: {:?}", e),
+            W25q64Error::AddressOutOfRange {
+                address,
+                len,
+                capacity,
+            } => write!(
+                f,
+                "Address out of range error: {:#08X} + {} > {:#08X}",
+                address, len, capacity
+            ),
+            W25q64Error::NotAligned { address } => {
+                write!(f, "Address not aligned error: {:#08X}", address)
+            }
+            W25q64Error::BusyTimeout { timeout } => {
+                write!(f, "Busy timeout error: {:?}", timeout)
+            }
+            W25q64Error::WriteProtected { address } => {
+                write!(f, "Write protected error: {:#08X}", address)
+            }
+            W25q64Error::VerifyMismatch {
+                address,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Verify mismatch error: {:#08X} expected {:#04X}, actual {:#04X}",
+                address, expected, actual
+            ),
+            W25q64Error::UnsupportedReadMode { mode } => {
+                write!(f, "Unsupported read mode error: {:?}", mode)
+            }
+            W25q64Error::PageOverflow { address, len } => write!(
+                f,
+                "Page overflow error: {:#08X} + {} > {}",
+                address, len, W25Q64_PAGE_SIZE
+            ),
+            W25q64Error::UnsupportedProtectRange { start, end } => write!(
+                f,
+                "Unsupported protect range error: {:#08X}..{:#08X}",
+                start, end
+            ),
+        }
+    }
+}
+
+impl<E: Debug> std::error::Error for W25q64Error<E> {}
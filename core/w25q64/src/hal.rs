@@ -1,33 +1,160 @@
+//! HAL 库版本实现
+//!
+//! 驱动基于 embedded-hal 的 `SpiDevice`, 可以运行在 ESP 的硬件 SPI 上,
+//! 也可以运行在主机的闪存模拟器上。
+//!
+//! 编程和擦除后会等待芯片空闲, 超过数据手册给出的最大耗时后返回
+//! `W25q64Error::BusyTimeout`。等待较长时间的操作(如整片擦除)时,
+//! 每次轮询之间调用休眠函数 (默认为 `thread::sleep`), 让出 CPU 给其他 FreeRTOS 任务。
+//! 不阻塞任务的等待见 `asynch` 模块。
+use core::ops::Range;
+use core::time::Duration;
+use std::thread;
+use std::time::Instant;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::{
     gpio::AnyIOPin,
     peripheral::Peripheral,
     prelude::FromValueType,
     spi::{
         config::{BitOrder, MODE_0},
-        SpiAnyPins, SpiConfig, SpiDeviceDriver, SpiDriver, SpiDriverConfig,
+        Dma, SpiAnyPins, SpiConfig, SpiDeviceDriver, SpiDriver, SpiDriverConfig,
     },
     sys::EspError,
+    units::Hertz,
 };
 
+use super::command::{self, Erase};
 use super::conf::*;
+use super::crc::Crc32;
+use super::error::W25q64Error;
+#[cfg(target_os = "espidf")]
+use super::qspi::QspiDevice;
+use super::read::{MultiIoSpi, ReadMode};
+use super::status::StatusRegister;
+use super::storage::{check_erase, check_slice};
+
+// 校验时每次读取的字节数
+const VERIFY_CHUNK_SIZE: usize = W25Q64_PAGE_SIZE;
+
+/// 多线读取函数, 由 `MultiIoSpi` 提供
+type MultiIoRead<SPI> =
+    fn(&mut SPI, ReadMode, u32, &mut [u8]) -> Result<(), <SPI as ErrorType>::Error>;
+
+pub struct W25Q64<SPI: ErrorType> {
+    spi: SPI,
+    // 芯片容量, 首次使用时从JEDEC设备ID中读取
+    capacity: Option<u32>,
+    // 受块保护的地址范围, 首次编程或擦除时从状态寄存器中读取
+    protected: Option<Range<u32>>,
+    // 等待芯片空闲时的轮询间隔
+    poll_interval: Duration,
+    // 轮询之间的休眠函数
+    sleep: fn(Duration),
+    // 读取数据的模式
+    read_mode: ReadMode,
+    // SPI 设备支持的数据线数量
+    data_lines: u8,
+    // SPI 设备的多线读取函数
+    read_multi_io: Option<MultiIoRead<SPI>>,
+}
+
+#[cfg(target_os = "espidf")]
+impl<'d> W25Q64<SpiDeviceDriver<'d, SpiDriver<'d>>> {
+    /// 使用 ESP 的 SPI 外设和引脚创建对象, 时钟 1MHz, 标准读取
+    pub fn from_pins<SPI: SpiAnyPins>(
+        spi: impl Peripheral<P = SPI> + 'd,
+        cs: AnyIOPin,
+        sck: AnyIOPin,
+        mosi: AnyIOPin,
+        miso: AnyIOPin,
+    ) -> Result<Self, W25q64Error<EspError>> {
+        W25Q64Builder::new().build(spi, cs, sck, mosi, miso)
+    }
+}
+
+/// 使用 ESP 的 SPI 外设创建对象
+///
+/// ```ignore
+/// let mut w25q = W25Q64Builder::new()
+///     .baudrate(40.MHz().into())
+///     .read_mode(ReadMode::QuadIO)
+///     .dma(4096)
+///     .build_multi_io(spi2, cs, sck, io0, io1, Some(io2), Some(io3))?;
+/// ```
+#[cfg(target_os = "espidf")]
+pub struct W25Q64Builder {
+    baudrate: Hertz,
+    read_mode: ReadMode,
+    dma: Option<usize>,
+}
 
-pub struct W25Q64<'d> {
-    spi: SpiDeviceDriver<'d, SpiDriver<'d>>,
+#[cfg(target_os = "espidf")]
+impl Default for W25Q64Builder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<'d> W25Q64<'d> {
-    pub fn new<SPI: SpiAnyPins>(
+#[cfg(target_os = "espidf")]
+impl W25Q64Builder {
+    /// 默认时钟 1MHz, 标准读取, 不使用 DMA
+    pub fn new() -> Self {
+        W25Q64Builder {
+            baudrate: 1.MHz().into(),
+            read_mode: ReadMode::Standard,
+            dma: None,
+        }
+    }
+
+    /// 设置 SPI 时钟频率
+    /// 标准读取最高 50MHz, 其他读取模式最高 133MHz, 受 ESP32-S3 限制最高 80MHz
+    pub fn baudrate(mut self, baudrate: Hertz) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+
+    /// 设置读取数据的模式
+    pub fn read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// 使用 DMA 传输, 大块读取时不占用 CPU
+    /// max_transfer_size: 单次传输的最大字节数, 更长的读取会拆分成多次传输
+    pub fn dma(mut self, max_transfer_size: usize) -> Self {
+        self.dma = Some(max_transfer_size);
+        self
+    }
+
+    /// 使用 MOSI/MISO 单线连接, 支持标准读取和快速读取
+    pub fn build<'d, SPI: SpiAnyPins>(
+        self,
         spi: impl Peripheral<P = SPI> + 'd,
         cs: AnyIOPin,
         sck: AnyIOPin,
         mosi: AnyIOPin,
         miso: AnyIOPin,
-    ) -> Result<Self, EspError> {
+    ) -> Result<W25Q64<SpiDeviceDriver<'d, SpiDriver<'d>>>, W25q64Error<EspError>> {
+        if self.read_mode.is_multi_io() {
+            return Err(W25q64Error::UnsupportedReadMode {
+                mode: self.read_mode,
+            });
+        }
+
+        let dma = match self.dma {
+            Some(max_transfer_size) => Dma::Auto(max_transfer_size),
+            None => Dma::Disabled,
+        };
         // 创建一个Spi实例
-        let spi_driver = SpiDriver::new(spi, sck, miso, Some(mosi), &SpiDriverConfig::new())?;
+        let spi_driver =
+            SpiDriver::new(spi, sck, miso, Some(mosi), &SpiDriverConfig::new().dma(dma))
+                .map_err(W25q64Error::Spi)?;
 
         let config = SpiConfig::new()
-            .baudrate(1.MHz().into())
+            .baudrate(self.baudrate)
             // 先行位，选择高位先行
             .bit_order(BitOrder::MsbFirst)
             .allow_pre_post_delays(true)
@@ -35,166 +162,558 @@ impl<'d> W25Q64<'d> {
             .data_mode(MODE_0)
             // 设置默认电平, SS默认高电平
             .cs_active_high();
-        let spi_device_driver = SpiDeviceDriver::new(spi_driver, Some(cs), &config)?;
+        let spi_device_driver =
+            SpiDeviceDriver::new(spi_driver, Some(cs), &config).map_err(W25q64Error::Spi)?;
 
-        let w25q = W25Q64 {
-            spi: spi_device_driver,
-        };
+        let mut w25q = W25Q64::new(spi_device_driver);
+        w25q.read_mode = self.read_mode;
         Ok(w25q)
     }
 
+    /// 使用 IO0~IO3 多线连接, 支持所有读取模式
+    /// 没有连接 IO2(WP) 和 IO3(HOLD) 时只支持双线读取
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_multi_io<'d, SPI: SpiAnyPins>(
+        self,
+        spi: impl Peripheral<P = SPI> + 'd,
+        cs: AnyIOPin,
+        sck: AnyIOPin,
+        io0: AnyIOPin,
+        io1: AnyIOPin,
+        io2: Option<AnyIOPin>,
+        io3: Option<AnyIOPin>,
+    ) -> Result<W25Q64<QspiDevice<'d>>, W25q64Error<EspError>> {
+        let device = QspiDevice::new(spi, cs, sck, io0, io1, io2, io3, self.baudrate, self.dma)
+            .map_err(W25q64Error::Spi)?;
+
+        let mut w25q = W25Q64::new_multi_io(device);
+        w25q.set_read_mode(self.read_mode).map_err(|e| e.map_spi(|e| e.0))?;
+        Ok(w25q)
+    }
+}
+
+impl<SPI> W25Q64<SPI>
+where
+    SPI: MultiIoSpi,
+{
+    /// 使用支持双线/四线读取的 SPI 设备创建对象
+    pub fn new_multi_io(spi: SPI) -> Self {
+        let data_lines = spi.data_lines();
+        let mut w25q = W25Q64::new(spi);
+        w25q.data_lines = data_lines;
+        w25q.read_multi_io = Some(SPI::read_multi_io);
+        w25q
+    }
+}
+
+impl<SPI> W25Q64<SPI>
+where
+    SPI: SpiDevice,
+{
+    /// 使用任意实现了 `SpiDevice` 的 SPI 设备创建对象
+    pub fn new(spi: SPI) -> Self {
+        W25Q64 {
+            spi,
+            capacity: None,
+            protected: None,
+            poll_interval: W25Q64_POLL_INTERVAL,
+            sleep: thread::sleep,
+            read_mode: ReadMode::Standard,
+            data_lines: 1,
+            read_multi_io: None,
+        }
+    }
+
+    /// 设置等待芯片空闲时的轮询间隔
+    /// 忙等待一个间隔后, 每次轮询之间休眠一个间隔, 为 0 时一直忙等待
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// 设置轮询之间的休眠函数, 默认为 `thread::sleep`
+    /// ESP-IDF 上不足一个 FreeRTOS tick 的 `thread::sleep` 会忙等待,
+    /// 可以换成按 tick 阻塞任务的延时, 例如 `FreeRtos::delay_ms`
+    pub fn with_sleep(mut self, sleep: fn(Duration)) -> Self {
+        self.sleep = sleep;
+        self
+    }
+
+    /// 释放 SPI 设备
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+
+    /// 当前读取数据的模式
+    pub fn read_mode(&self) -> ReadMode {
+        self.read_mode
+    }
+
+    /// 设置读取数据的模式
+    /// 四线读取会先置位状态寄存器2的 QE 位, 之后 WP 和 HOLD 引脚用作 IO2 和 IO3
+    pub fn set_read_mode(&mut self, mode: ReadMode) -> Result<(), W25q64Error<SPI::Error>> {
+        if mode.data_lines() > self.data_lines {
+            return Err(W25q64Error::UnsupportedReadMode { mode });
+        }
+        if mode.requires_quad_enable() {
+            self.set_quad_enable(true)?;
+        }
+        self.read_mode = mode;
+        Ok(())
+    }
+
+    /// 四线使能位 QE 是否置位
+    pub fn quad_enable(&mut self) -> Result<bool, W25q64Error<SPI::Error>> {
+        Ok(self.read_status_register()?.qe)
+    }
+
+    /// 设置四线使能位 QE, 该位是非易失的, 掉电后保持
+    pub fn set_quad_enable(&mut self, enable: bool) -> Result<(), W25q64Error<SPI::Error>> {
+        let mut status = self.read_status_register()?;
+        if status.qe == enable {
+            return Ok(());
+        }
+        status.qe = enable;
+        self.write_status_register(&status)
+    }
+
     /// 启用写入功能
-    pub fn write_enable(&mut self) -> Result<(), EspError> {
-        self.spi.write(&[W25Q64_WRITE_ENABLE])
+    pub fn write_enable(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_WRITE_ENABLE])?;
+        Ok(())
     }
 
     /// 禁用写入功能
-    pub fn write_disable(&mut self) -> Result<(), EspError> {
-        self.spi.write(&[W25Q64_WRITE_DISABLE])
+    pub fn write_disable(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_WRITE_DISABLE])?;
+        Ok(())
     }
 
     /// 读取芯片的JEDEC设备ID
     /// 使用Spi实例和片选引脚来发送和接收命令和数据
-    pub fn read_jedec_device_id(&mut self) -> Result<(u8, u8, u8), EspError> {
-        let mut buf = [W25Q64_JEDEC_DEVICE_ID, 0, 0, 0];
+    pub fn read_jedec_device_id(&mut self) -> Result<(u8, u8, u8), W25q64Error<SPI::Error>> {
+        let mut buf = command::command_buf(W25Q64_JEDEC_DEVICE_ID);
         self.spi.transfer_in_place(&mut buf)?;
 
-        let manufacturer_id = buf[1];
-        let memory_type = buf[2];
-        let capacity = buf[3];
-        Ok((manufacturer_id, memory_type, capacity))
+        Ok(command::jedec_device_id(&buf))
+    }
+
+    /// 芯片容量, 单位字节
+    /// 由JEDEC设备ID的容量字节计算得到, 例如 0x17 表示 2^23 = 8MB
+    pub fn capacity(&mut self) -> Result<u32, W25q64Error<SPI::Error>> {
+        if let Some(capacity) = self.capacity {
+            return Ok(capacity);
+        }
+
+        let (_, _, capacity) = self.read_jedec_device_id()?;
+        let capacity = command::capacity(capacity);
+        self.capacity = Some(capacity);
+        Ok(capacity)
+    }
+
+    /// 已读取的芯片容量
+    pub(crate) fn cached_capacity(&self) -> Option<u32> {
+        self.capacity
+    }
+
+    /// 检查读写范围是否超出芯片容量
+    pub(crate) fn check_range(
+        &mut self,
+        address: u32,
+        len: usize,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity()?;
+        check_slice(address, len, capacity)
     }
 
     /// 读取芯片的制造商和设备ID
     ///
     /// 使用Spi实例和片选引脚来发送和接收命令和数据
     /// 0xEF16: 代表W25Q64芯片
-    pub fn read_manufacturer_device_id(&mut self) -> Result<(u16, u16), EspError> {
-        let mut buf = [0; 7];
-        buf[0] = W25Q64_MANUFACTURER_DEVICE_ID;
+    pub fn read_manufacturer_device_id(&mut self) -> Result<(u16, u16), W25q64Error<SPI::Error>> {
+        let mut buf = command::command_buf(W25Q64_MANUFACTURER_DEVICE_ID);
 
         // 发送读取制造商和设备ID的命令
         self.spi.transfer_in_place(&mut buf)?;
 
-        let manufacturer_id = buf[4] as u16;
-        let device_id = (buf[5] as u16) << 8 | buf[6] as u16;
-        Ok((manufacturer_id, device_id))
+        Ok(command::manufacturer_device_id(&buf))
     }
 
-    /// 读取状态寄存器1
-    pub fn read_status_register_1(&mut self) -> Result<u8, EspError> {
-        let mut buf = [W25Q64_READ_STATUS_REGISTER_1, 0];
+    /// 读取单个寄存器
+    fn read_register(&mut self, cmd: u8) -> Result<u8, W25q64Error<SPI::Error>> {
+        let mut buf = [cmd, 0];
         self.spi.transfer_in_place(&mut buf)?;
 
-        self.spi.read(&mut buf)?;
         Ok(buf[1])
     }
 
+    /// 读取状态寄存器1和2
+    pub fn read_status_register(&mut self) -> Result<StatusRegister, W25q64Error<SPI::Error>> {
+        let sr1 = self.read_register(W25Q64_READ_STATUS_REGISTER_1)?;
+        let sr2 = self.read_register(W25Q64_READ_STATUS_REGISTER_2)?;
+        Ok(StatusRegister::from_bits(sr1, sr2))
+    }
+
+    /// 写入状态寄存器1和2
+    /// 只有 BP/TB/SEC/SRP0/SRP1/QE/LB/CMP 可以写入, 其他标志位会被忽略
+    pub fn write_status_register(
+        &mut self,
+        status: &StatusRegister,
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.write_enable()?;
+
+        let cmd = [W25Q64_WRITE_STATUS_REGISTER, status.sr1(), status.sr2()];
+        self.spi.write(&cmd)?;
+        // 块保护位可能已经改变
+        self.protected = None;
+
+        self.wait_for_idle(W25Q64_WRITE_STATUS_REGISTER_TIMEOUT)
+    }
+
+    /// 读取状态寄存器3
+    /// 包含输出驱动能力(DRV1/DRV0)和写保护选择(WPS)
+    pub fn read_status_register_3(&mut self) -> Result<u8, W25q64Error<SPI::Error>> {
+        self.read_register(W25Q64_READ_STATUS_REGISTER_3)
+    }
+
+    /// 写入状态寄存器3
+    pub fn write_status_register_3(&mut self, value: u8) -> Result<(), W25q64Error<SPI::Error>> {
+        self.write_enable()?;
+
+        let cmd = [W25Q64_WRITE_STATUS_REGISTER_3, value];
+        self.spi.write(&cmd)?;
+
+        self.wait_for_idle(W25Q64_WRITE_STATUS_REGISTER_TIMEOUT)
+    }
+
     /// 检查是否有写保护标志
-    pub fn check_write_protect(&mut self) -> Result<bool, EspError> {
-        let status = self.read_status_register_1()?;
-        let srp0 = status & 0x80;
-        let srp1 = status & 0x04;
-        if srp0 == 0 && srp1 == 0 {
-            // 没有写保护
-            Ok(false)
-        } else {
-            // 有写保护
-            Ok(true)
-        }
+    /// SRP0 或 SRP1 置位时, 状态寄存器受到保护
+    pub fn check_write_protect(&mut self) -> Result<bool, W25q64Error<SPI::Error>> {
+        let status = self.read_status_register()?;
+        Ok(status.srp0 || status.srp1)
+    }
+
+    /// 当前受块保护的地址范围, 没有保护时返回空范围
+    pub fn protected_range(&mut self) -> Result<Range<u32>, W25q64Error<SPI::Error>> {
+        let capacity = self.capacity()?;
+        let status = self.read_status_register()?;
+        let range = status.protected_range(capacity);
+        self.protected = Some(range.clone());
+        Ok(range)
+    }
+
+    /// 检查 [address, address + len) 是否可以编程或擦除
+    /// 受保护的范围会被缓存, 通过本驱动修改状态寄存器时重新读取
+    fn check_writable(&mut self, address: u32, len: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        let protected = match &self.protected {
+            Some(protected) => protected.clone(),
+            None => self.protected_range()?,
+        };
+        command::check_writable(&protected, address, len)
     }
 
-    /// 定义一个辅助函数，用于等待W25Q64芯片空闲
-    pub fn wait_for_idle(&mut self) -> Result<(), EspError> {
-        // 发送读状态寄存器1命令
-        let mut buf = [W25Q64_READ_STATUS_REGISTER_1, 0x00];
+    /// 设置块保护, 使受保护的地址范围恰好为 range
+    /// 可以保护顶部或底部的 4KB~32KB、容量的 1/64~1/2、以及它们的补集, 空范围表示取消保护
+    pub fn protect(&mut self, range: Range<u32>) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity()?;
+        let mut status = self.read_status_register()?;
+        if !status.set_protected_range(range.clone(), capacity) {
+            return Err(W25q64Error::UnsupportedProtectRange {
+                start: range.start,
+                end: range.end,
+            });
+        }
+        self.write_status_register(&status)?;
+        Ok(())
+    }
 
-        // 给定超时计数时间
-        let mut timeout = 100000;
+    /// 等待W25Q64芯片空闲
+    /// 超过 timeout 仍然忙碌时返回 `W25q64Error::BusyTimeout`
+    pub fn wait_for_idle(&mut self, timeout: Duration) -> Result<(), W25q64Error<SPI::Error>> {
+        let start = Instant::now();
 
         // 循环等待忙标志位
         loop {
-            // 接收状态寄存器1的值
-            self.spi.transfer_in_place(&mut buf)?;
-            if buf[1] & 0x01 == 0 {
-                // 检查状态寄存器1的最低位，如果为0表示空闲，否则表示忙碌
-                break;
+            // 检查状态寄存器1的最低位，如果为0表示空闲，否则表示忙碌
+            let status = self.read_register(W25Q64_READ_STATUS_REGISTER_1)?;
+            if !command::is_busy(status) {
+                return Ok(());
+            }
+
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Err(W25q64Error::BusyTimeout { timeout });
             }
-            timeout -= 1;
-            if timeout == 0 {
-                break;
+            // 短时间的操作忙等待, 较长时间的操作让出 CPU
+            if elapsed >= self.poll_interval {
+                (self.sleep)(self.poll_interval.min(timeout - elapsed));
             }
         }
-        Ok(())
     }
 
     /// 页编程, 写入数据
     /// page_address: 设定页地址
-    /// data: 要写入的数据
-    pub fn page_program(&mut self, page_address: u32, data: &[u8]) -> Result<(), EspError> {
-        assert!(data.len() <= 256); // A page is 256 bytes
-
+    /// data: 要写入的数据, 不超过一页, 跨越页边界时回绕到页首
+    pub fn page_program(
+        &mut self,
+        page_address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        command::check_page(page_address, data.len())?;
+        self.check_writable(page_address, data.len() as u32)?;
         self.write_enable()?;
 
-        let cmd = [
-            W25Q64_PAGE_PROGRAM,        // 页编程的指令
-            (page_address >> 16) as u8, // 地址23~16位
-            (page_address >> 8) as u8,  // 地址15~8位
-            page_address as u8,         // 地址7~0位
-        ];
+        let cmd = command::address_command(W25Q64_PAGE_PROGRAM, page_address);
 
-        self.spi.write(&cmd)?;
-        self.spi.write(data)?;
+        // 指令和数据需要在同一次片选内发送
+        self.spi
+            .transaction(&mut [Operation::Write(&cmd), Operation::Write(data)])?;
 
         // 等待W25Q64芯片空闲
-        self.wait_for_idle()?;
-        Ok(())
+        self.wait_for_idle(W25Q64_PAGE_PROGRAM_TIMEOUT)
     }
 
     /// 擦除地址所在的扇区
-    pub fn sector_erase(&mut self, address: u32) -> Result<(), EspError> {
+    pub fn sector_erase(&mut self, address: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_command(Erase::Sector, address)
+    }
+
+    /// 擦除地址所在的 32KB 块
+    pub fn block_erase_32kb(&mut self, address: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_command(Erase::Block32Kb, address)
+    }
+
+    /// 擦除地址所在的 64KB 块
+    pub fn block_erase_64kb(&mut self, address: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_command(Erase::Block64Kb, address)
+    }
+
+    /// 发送带地址的擦除指令, 并等待擦除完成
+    fn erase_command(&mut self, erase: Erase, address: u32) -> Result<(), W25q64Error<SPI::Error>> {
+        self.check_writable(erase.align(address), erase.size() as u32)?;
         self.write_enable()?;
 
-        let cmd = [
-            W25Q64_SECTOR_ERASE_4KB, // 扇区擦除的指令
-            (address >> 16) as u8,   // 地址23~16位
-            (address >> 8) as u8,    // 地址15~8位
-            address as u8,           // 地址7~0位
-        ];
+        let cmd = command::address_command(erase.command(), address);
         self.spi.write(&cmd)?;
 
-        self.wait_for_idle()?;
+        self.wait_for_idle(erase.timeout())
+    }
+
+    /// 暂停正在进行的扇区/块擦除, 暂停期间可以读取其他扇区
+    pub fn erase_suspend(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_ERASE_SUSPEND])?;
         Ok(())
     }
 
+    /// 恢复被暂停的擦除
+    pub fn erase_resume(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_ERASE_RESUME])?;
+        Ok(())
+    }
+
+    /// 进入掉电模式, 掉电期间只响应 `release_power_down`
+    pub fn power_down(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        self.spi.write(&[W25Q64_POWER_DOWN])?;
+        Ok(())
+    }
+
+    /// 退出掉电模式, 并读取设备ID
+    /// 0x16: 代表W25Q64芯片
+    pub fn release_power_down(&mut self) -> Result<u8, W25q64Error<SPI::Error>> {
+        let mut buf = [W25Q64_RELEASE_POWER_DOWN_HPM_DEVICE_ID, 0, 0, 0, 0];
+        self.spi.transfer_in_place(&mut buf)?;
+
+        Ok(buf[4])
+    }
+
+    /// 读取芯片出厂时写入的64位唯一ID
+    pub fn read_unique_id(&mut self) -> Result<u64, W25q64Error<SPI::Error>> {
+        // 指令之后需要4个虚拟字节
+        let mut buf = command::command_buf(W25Q64_READ_UNIQUE_ID);
+        self.spi.transfer_in_place(&mut buf)?;
+
+        Ok(command::unique_id(&buf))
+    }
+
     /// 擦除闪存芯片上的所有扇区
     /// 这是一项非常昂贵的手术
-    pub fn erase_chip(&mut self) -> Result<(), EspError> {
+    /// 任何区域受块保护时芯片会忽略该指令
+    pub fn erase_chip(&mut self) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity()?;
+        self.check_writable(0, capacity)?;
         self.write_enable()?;
 
         let cmd = [W25Q64_CHIP_ERASE];
         self.spi.write(&cmd)?;
 
-        self.wait_for_idle()?;
-        Ok(())
+        // 整片擦除需要数十秒, 等待期间会让出 CPU
+        self.wait_for_idle(W25Q64_CHIP_ERASE_TIMEOUT)
     }
 
-    /// 读取数据
+    /// 按当前的读取模式读取数据
     /// read_address: 目标地址
     /// data: 用于存放数据
-    pub fn read_data(&mut self, read_address: u32, data: &mut [u8]) -> Result<(), EspError> {
-        // 使用fill方法来赋值为虚拟字节
-        // data.fill(W25Q64_DUMMY_BYTE);
-
-        let cmd = [
-            W25Q64_READ_DATA,           // 读取数据的指令
-            (read_address >> 16) as u8, // 地址23~16位
-            (read_address >> 8) as u8,  // 地址15~8位
-            read_address as u8,         // 地址7~0位
-        ];
-        self.spi.write(&cmd)?;
-        self.spi.read(data)?;
+    pub fn read_data(
+        &mut self,
+        read_address: u32,
+        data: &mut [u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        let mode = self.read_mode;
+        if let (true, Some(read_multi_io)) = (mode.is_multi_io(), self.read_multi_io) {
+            read_multi_io(&mut self.spi, mode, read_address, data)?;
+            return Ok(());
+        }
+
+        let (cmd, len) = command::read_command(mode, read_address);
+        // 指令和数据需要在同一次片选内传输
+        self.spi
+            .transaction(&mut [Operation::Write(&cmd[..len]), Operation::Read(data)])?;
+
+        Ok(())
+    }
+
+    /// 写入任意长度的数据
+    /// 按页(256字节)边界拆分成多次页编程, 写入前需要保证目标区域已擦除
+    /// address: 起始地址
+    /// data: 要写入的数据
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<(), W25q64Error<SPI::Error>> {
+        self.check_range(address, data.len())?;
+
+        for (page_address, chunk) in command::page_chunks(address, data) {
+            self.page_program(page_address, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// 写入任意长度的数据, 并读回校验
+    /// 写入前需要保证目标区域已擦除, 校验失败时返回第一个不一致的地址
+    pub fn program_and_verify(
+        &mut self,
+        address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.write(address, data)?;
+        self.verify(address, data)
+    }
+
+    /// 读回数据并与 data 比较, 不一致时返回第一个不一致的地址
+    pub fn verify(&mut self, address: u32, data: &[u8]) -> Result<(), W25q64Error<SPI::Error>> {
+        let mut buf = [0; VERIFY_CHUNK_SIZE];
+        let end = address.saturating_add(data.len() as u32);
+        self.read_chunks(address..end, &mut buf, |chunk_address, actual| {
+            let offset = (chunk_address - address) as usize;
+            command::compare(chunk_address, data[offset..].iter().copied(), actual)
+        })
+    }
+
+    /// 擦除范围 [range.start, range.end), 范围需要按扇区(4KB)对齐
+    /// 对齐的部分使用块擦除, 减少擦除次数
+    pub fn erase_range(&mut self, range: Range<u32>) -> Result<(), W25q64Error<SPI::Error>> {
+        let capacity = self.capacity()?;
+        check_erase(range.start, range.end, capacity)?;
+
+        let mut address = range.start;
+        while address < range.end {
+            let erase = Erase::fit(address, (range.end - address) as usize);
+            self.erase_command(erase, address)?;
+            address += erase.size() as u32;
+        }
+        Ok(())
+    }
 
+    /// 擦除范围 [range.start, range.end) 并检查是否全部为 0xFF
+    /// 范围需要按扇区(4KB)对齐, 校验失败时返回第一个不为 0xFF 的地址
+    pub fn erase_and_verify(&mut self, range: Range<u32>) -> Result<(), W25q64Error<SPI::Error>> {
+        self.erase_range(range.clone())?;
+        self.blank_check(range)
+    }
+
+    /// 检查范围 [range.start, range.end) 是否全部为 0xFF
+    pub fn blank_check(&mut self, range: Range<u32>) -> Result<(), W25q64Error<SPI::Error>> {
+        let mut buf = [0; VERIFY_CHUNK_SIZE];
+        self.read_chunks(range, &mut buf, |address, actual| {
+            command::compare(address, core::iter::repeat(0xFF), actual)
+        })
+    }
+
+    /// 计算范围 [range.start, range.end) 的 CRC32 (IEEE)
+    /// 分块读取, 不需要把整个区域读入内存
+    pub fn crc32(&mut self, range: Range<u32>) -> Result<u32, W25q64Error<SPI::Error>> {
+        let mut buf = [0; VERIFY_CHUNK_SIZE];
+        let mut crc = Crc32::new();
+        self.read_chunks(range, &mut buf, |_, data| {
+            crc.update(data);
+            Ok(())
+        })?;
+        Ok(crc.finish())
+    }
+
+    /// 以 buf 的大小分块读取范围 [range.start, range.end), 每块数据交给 f 处理
+    fn read_chunks<F>(
+        &mut self,
+        range: Range<u32>,
+        buf: &mut [u8],
+        mut f: F,
+    ) -> Result<(), W25q64Error<SPI::Error>>
+    where
+        F: FnMut(u32, &[u8]) -> Result<(), W25q64Error<SPI::Error>>,
+    {
+        let len = range.end.saturating_sub(range.start) as usize;
+        self.check_range(range.start, len)?;
+
+        let mut address = range.start;
+        while address < range.end {
+            let len = ((range.end - address) as usize).min(buf.len());
+            let chunk = &mut buf[..len];
+            self.read_data(address, chunk)?;
+            f(address, chunk)?;
+            address += chunk.len() as u32;
+        }
+        Ok(())
+    }
+
+    /// 写入任意长度的数据, 并保留扇区中的其他数据
+    /// 对涉及到的每个扇区(4KB)执行 读取-修改-擦除-写入,
+    /// 如果写入只需要把 1 写成 0, 则跳过擦除直接写入
+    /// address: 起始地址
+    /// data: 要写入的数据
+    pub fn write_preserving(
+        &mut self,
+        address: u32,
+        data: &[u8],
+    ) -> Result<(), W25q64Error<SPI::Error>> {
+        self.check_range(address, data.len())?;
+
+        let mut sector_buf = vec![0; W25Q64_SECTOR_SIZE];
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let sector_address = address - address % W25Q64_SECTOR_SIZE as u32;
+            let offset = (address - sector_address) as usize;
+            let (chunk, rest) = data.split_at((W25Q64_SECTOR_SIZE - offset).min(data.len()));
+
+            // 读取整个扇区
+            self.read_data(sector_address, &mut sector_buf)?;
+            let old = &sector_buf[offset..offset + chunk.len()];
+            if old == chunk {
+                // 数据相同, 无需写入
+            } else if old.iter().zip(chunk).all(|(old, new)| old & new == *new) {
+                // 只需要把 1 写成 0, 无需擦除
+                self.write(address, chunk)?;
+            } else {
+                // 修改扇区数据后擦除并写回, 跳过全部为 0xFF 的页
+                sector_buf[offset..offset + chunk.len()].copy_from_slice(chunk);
+                self.sector_erase(sector_address)?;
+                for (i, page) in sector_buf.chunks(W25Q64_PAGE_SIZE).enumerate() {
+                    if page.iter().any(|&byte| byte != 0xFF) {
+                        self.page_program(sector_address + (i * W25Q64_PAGE_SIZE) as u32, page)?;
+                    }
+                }
+            }
+
+            address += chunk.len() as u32;
+            data = rest;
+        }
         Ok(())
     }
 }
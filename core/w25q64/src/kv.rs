@@ -0,0 +1,613 @@
+//! 键值存储
+//!
+//! 基于日志结构的键值存储, 可以运行在任意实现了 `NorFlash` 的存储器上, 例如 `hal::W25Q64`。
+//!
+//! - 存储区域由若干连续的扇区组成, 其中始终保留一个空闲扇区用于垃圾回收;
+//! - 每个扇区以扇区头开始, 扇区头中的序号表示扇区的新旧顺序;
+//! - 写入和删除都以记录的形式追加到当前扇区, 每条记录都带有 CRC32 校验;
+//! - 当前扇区写满后切换到下一个空闲扇区, 扇区轮流使用以均衡磨损;
+//! - 空闲扇区不足时回收最旧的扇区, 把其中仍然有效的记录复制到空闲扇区后擦除;
+//! - 挂载时扫描所有扇区重建索引, 丢弃掉电导致的不完整记录, 并恢复未完成的垃圾回收。
+//!
+//! 写入过程中发生存储器错误(例如掉电)后, 需要重新挂载。
+use core::fmt::Debug;
+use core::ops::Range;
+use std::collections::HashMap;
+
+use embedded_storage::nor_flash::{MultiwriteNorFlash, NorFlash};
+
+use super::crc::{crc32, Crc32};
+
+// 扇区头魔数 "KV01"
+const SECTOR_MAGIC: u32 = 0x3130_564B;
+// 扇区头: 魔数(4) + 序号(4) + CRC32(4) + 状态(4)
+const SECTOR_HEADER_SIZE: usize = 16;
+// 扇区头中状态字段的偏移
+const SECTOR_STATE_OFFSET: usize = 12;
+// 扇区已回收的状态, 回收时先写入该状态再擦除
+const SECTOR_RETIRED: [u8; 4] = [0x00; 4];
+// 记录头: 键长度(1) + 保留(1) + 值长度(2) + CRC32(4)
+const RECORD_HEADER_SIZE: usize = 8;
+// 值长度为该值时表示删除记录
+const TOMBSTONE: u16 = 0xFFFF;
+
+/// 键的最大长度
+pub const MAX_KEY_LEN: usize = 0xFE;
+
+/// 键值存储的所有错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvError<E> {
+    /// 存储器错误
+    Flash(E),
+
+    /// 扇区范围或存储器参数不支持
+    InvalidConfig,
+
+    /// 键为空或超过最大长度
+    InvalidKey,
+
+    /// 值太大, 一个扇区放不下
+    ValueTooLarge,
+
+    /// 有效数据已占满存储区域
+    StoreFull,
+}
+
+impl<E> From<E> for KvError<E> {
+    fn from(e: E) -> Self {
+        KvError::Flash(e)
+    }
+}
+
+impl<E: Debug> std::fmt::Display for KvError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KvError::Flash(e) => write!(f, "Flash Error: {:?}", e),
+            KvError::InvalidConfig => write!(f, "Invalid config error"),
+            KvError::InvalidKey => write!(f, "Invalid key error"),
+            KvError::ValueTooLarge => write!(f, "Value too large error"),
+            KvError::StoreFull => write!(f, "Store full error"),
+        }
+    }
+}
+
+impl<E: Debug> std::error::Error for KvError<E> {}
+
+/// 扇区状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SectorState {
+    /// 空闲, erased 表示是否已确认处于擦除状态
+    Free { erased: bool },
+    /// 使用中
+    Active {
+        seq: u32,
+        // 下一条记录的写入位置
+        write_offset: usize,
+        // 是否不能再写入
+        full: bool,
+    },
+}
+
+/// 记录在存储区域中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    sector: usize,
+    offset: usize,
+    key_len: usize,
+    value_len: usize,
+}
+
+/// 扫描得到的一条记录
+struct Record {
+    key: Vec<u8>,
+    // None 表示删除记录
+    value_len: Option<usize>,
+    size: usize,
+}
+
+/// 日志结构的键值存储
+pub struct KvStore<F> {
+    flash: F,
+    first_sector: u32,
+    sector_size: usize,
+    sectors: Vec<SectorState>,
+    // 当前写入的扇区
+    head: Option<usize>,
+    next_seq: u32,
+    index: HashMap<Vec<u8>, Location>,
+}
+
+impl<F> KvStore<F>
+where
+    F: NorFlash + MultiwriteNorFlash,
+{
+    /// 挂载键值存储
+    /// flash: 存储器
+    /// sectors: 使用的扇区编号范围, 至少需要两个扇区
+    pub fn mount(flash: F, sectors: Range<u32>) -> Result<Self, KvError<F::Error>> {
+        let sector_size = F::ERASE_SIZE;
+        if sectors.end <= sectors.start
+            || sectors.len() < 2
+            || sectors.end as usize * sector_size > flash.capacity()
+            || F::READ_SIZE != 1
+            || SECTOR_RETIRED.len() % F::WRITE_SIZE != 0
+        {
+            return Err(KvError::InvalidConfig);
+        }
+
+        let mut store = KvStore {
+            flash,
+            first_sector: sectors.start,
+            sector_size,
+            sectors: vec![SectorState::Free { erased: false }; sectors.len()],
+            head: None,
+            next_seq: 0,
+            index: HashMap::new(),
+        };
+        store.scan()?;
+        Ok(store)
+    }
+
+    /// 释放存储器
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// 键的数量
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// 是否包含指定的键
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// 所有的键, 顺序不固定
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.index.keys().map(|key| key.as_slice())
+    }
+
+    /// 读取键对应的值
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, KvError<F::Error>> {
+        let location = match self.index.get(key) {
+            Some(location) => *location,
+            None => return Ok(None),
+        };
+        self.read_value(location).map(Some)
+    }
+
+    /// 写入键值
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<(), KvError<F::Error>> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(KvError::InvalidKey);
+        }
+        if value.len() >= TOMBSTONE as usize
+            || self.record_size(key.len(), value.len()) > self.sector_size - SECTOR_HEADER_SIZE
+        {
+            return Err(KvError::ValueTooLarge);
+        }
+
+        // 值没有变化时不写入, 减少磨损
+        if let Some(location) = self.index.get(key).copied() {
+            if location.value_len == value.len() && self.read_value(location)? == value {
+                return Ok(());
+            }
+        }
+
+        self.append(key, Some(value))
+    }
+
+    /// 删除键
+    pub fn remove(&mut self, key: &[u8]) -> Result<(), KvError<F::Error>> {
+        if !self.index.contains_key(key) {
+            return Ok(());
+        }
+        self.append(key, None)
+    }
+
+    /// 遍历所有键值, 按键排序
+    pub fn iter(&mut self) -> KvIter<'_, F> {
+        let mut keys: Vec<Vec<u8>> = self.index.keys().cloned().collect();
+        keys.sort();
+        KvIter {
+            store: self,
+            keys: keys.into_iter(),
+        }
+    }
+
+    /// 扫描所有扇区, 重建索引
+    fn scan(&mut self) -> Result<(), KvError<F::Error>> {
+        let mut header = [0; SECTOR_HEADER_SIZE];
+        for sector in 0..self.sectors.len() {
+            self.flash.read(self.address(sector, 0), &mut header)?;
+            self.sectors[sector] = match parse_sector_header(&header) {
+                Some(seq) => SectorState::Active {
+                    seq,
+                    write_offset: SECTOR_HEADER_SIZE,
+                    full: true,
+                },
+                None => SectorState::Free { erased: false },
+            };
+        }
+
+        // 没有空闲扇区, 说明垃圾回收在复制记录时掉电,
+        // 此时最新的扇区中只有最旧扇区中记录的副本, 直接擦除即可
+        if self.free_count() == 0 {
+            if let Some(newest) = self.active_sectors().last().copied() {
+                self.erase_sector(newest)?;
+            }
+        }
+
+        // 按从旧到新的顺序回放记录
+        let active = self.active_sectors();
+        for &sector in active.iter() {
+            let mut offset = SECTOR_HEADER_SIZE;
+            // 遇到空白或者损坏的记录时停止, 该扇区之后的内容都不可信
+            while let Some(record) = self.read_record(sector, offset)? {
+                match record.value_len {
+                    Some(value_len) => {
+                        let location = Location {
+                            sector,
+                            offset,
+                            key_len: record.key.len(),
+                            value_len,
+                        };
+                        self.index.insert(record.key, location);
+                    }
+                    None => {
+                        self.index.remove(&record.key);
+                    }
+                }
+                offset += record.size;
+            }
+            self.set_write_state(sector, offset, true);
+        }
+
+        // 只有最新的扇区可以继续写入, 并且剩余的空间必须处于擦除状态
+        self.head = active.last().copied();
+        if let Some(head) = self.head {
+            if let SectorState::Active { write_offset, .. } = self.sectors[head] {
+                let clean = self.is_erased(head, write_offset..self.sector_size)?;
+                self.set_write_state(
+                    head,
+                    write_offset,
+                    !clean || write_offset >= self.sector_size,
+                );
+            }
+            self.next_seq = self.sector_seq(head).wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// 读取一条记录, 空白或者损坏时返回 None
+    fn read_record(
+        &mut self,
+        sector: usize,
+        offset: usize,
+    ) -> Result<Option<Record>, KvError<F::Error>> {
+        if offset + RECORD_HEADER_SIZE > self.sector_size {
+            return Ok(None);
+        }
+
+        let mut header = [0; RECORD_HEADER_SIZE];
+        self.flash.read(self.address(sector, offset), &mut header)?;
+        if header.iter().all(|&byte| byte == 0xFF) {
+            return Ok(None);
+        }
+
+        let key_len = header[0] as usize;
+        let value_len = u16::from_le_bytes([header[2], header[3]]);
+        let data_len = if value_len == TOMBSTONE {
+            key_len
+        } else {
+            key_len + value_len as usize
+        };
+        let size = self.record_size(key_len, data_len - key_len);
+        if key_len == 0
+            || key_len > MAX_KEY_LEN
+            || header[1] != 0
+            || offset + size > self.sector_size
+        {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; data_len];
+        self.flash
+            .read(self.address(sector, offset + RECORD_HEADER_SIZE), &mut data)?;
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if crc != record_crc(&header, &data) {
+            return Ok(None);
+        }
+
+        data.truncate(key_len);
+        Ok(Some(Record {
+            key: data,
+            value_len: (value_len != TOMBSTONE).then_some(value_len as usize),
+            size,
+        }))
+    }
+
+    /// 读取记录中的值
+    fn read_value(&mut self, location: Location) -> Result<Vec<u8>, KvError<F::Error>> {
+        let mut value = vec![0; location.value_len];
+        let offset = location.offset + RECORD_HEADER_SIZE + location.key_len;
+        self.flash
+            .read(self.address(location.sector, offset), &mut value)?;
+        Ok(value)
+    }
+
+    /// 追加一条记录, value 为 None 时表示删除
+    fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<(), KvError<F::Error>> {
+        let value_len = value.map_or(0, |value| value.len());
+        let size = self.record_size(key.len(), value_len);
+        let sector = self.reserve(size)?;
+        self.write_record(sector, key, value)?;
+        Ok(())
+    }
+
+    /// 把记录写入扇区的末尾, 并更新索引
+    fn write_record(
+        &mut self,
+        sector: usize,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<(), KvError<F::Error>> {
+        let value_len = value.map_or(0, |value| value.len());
+        let size = self.record_size(key.len(), value_len);
+        let offset = match self.sectors[sector] {
+            SectorState::Active { write_offset, .. } => write_offset,
+            SectorState::Free { .. } => unreachable!("write to a free sector"),
+        };
+
+        let mut header = [0; RECORD_HEADER_SIZE];
+        header[0] = key.len() as u8;
+        let len_field = value.map_or(TOMBSTONE, |value| value.len() as u16);
+        header[2..4].copy_from_slice(&len_field.to_le_bytes());
+
+        let mut buf = Vec::with_capacity(size);
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(value.unwrap_or_default());
+        let crc = record_crc(&header, &buf[RECORD_HEADER_SIZE..]);
+        buf[4..8].copy_from_slice(&crc.to_le_bytes());
+        buf.resize(size, 0xFF);
+
+        // 先标记为写满, 写入失败时扇区末尾的内容不可信
+        self.set_write_state(sector, offset, true);
+        self.flash.write(self.address(sector, offset), &buf)?;
+        self.set_write_state(sector, offset + size, offset + size >= self.sector_size);
+
+        match value {
+            Some(value) => {
+                let location = Location {
+                    sector,
+                    offset,
+                    key_len: key.len(),
+                    value_len: value.len(),
+                };
+                self.index.insert(key.to_vec(), location);
+            }
+            None => {
+                self.index.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    /// 找到能放下指定大小记录的扇区, 必要时切换扇区或回收旧扇区
+    fn reserve(&mut self, size: usize) -> Result<usize, KvError<F::Error>> {
+        for _ in 0..=self.sectors.len() {
+            if let Some(head) = self.head {
+                if let SectorState::Active {
+                    write_offset,
+                    full: false,
+                    ..
+                } = self.sectors[head]
+                {
+                    if write_offset + size <= self.sector_size {
+                        return Ok(head);
+                    }
+                }
+            }
+
+            if self.free_count() >= 2 {
+                let sector = self.next_free_sector().ok_or(KvError::StoreFull)?;
+                self.open_sector(sector)?;
+            } else {
+                self.collect_garbage()?;
+            }
+        }
+        Err(KvError::StoreFull)
+    }
+
+    /// 回收最旧的扇区: 把其中有效的记录复制到空闲扇区, 然后擦除
+    fn collect_garbage(&mut self) -> Result<(), KvError<F::Error>> {
+        let victim = match self.active_sectors().first() {
+            Some(&sector) => sector,
+            None => return Err(KvError::StoreFull),
+        };
+        let target = self.next_free_sector().ok_or(KvError::StoreFull)?;
+        self.open_sector(target)?;
+
+        let mut live: Vec<(Vec<u8>, Location)> = self
+            .index
+            .iter()
+            .filter(|(_, location)| location.sector == victim)
+            .map(|(key, location)| (key.clone(), *location))
+            .collect();
+        live.sort_by_key(|(_, location)| location.offset);
+        for (key, location) in live {
+            let value = self.read_value(location)?;
+            self.write_record(target, &key, Some(&value))?;
+        }
+
+        // 先标记为已回收再擦除, 擦除时掉电也不会把它当作有效扇区
+        self.flash
+            .write(self.address(victim, SECTOR_STATE_OFFSET), &SECTOR_RETIRED)?;
+        self.erase_sector(victim)?;
+        Ok(())
+    }
+
+    /// 打开一个空闲扇区作为当前写入的扇区
+    fn open_sector(&mut self, sector: usize) -> Result<(), KvError<F::Error>> {
+        let erased = matches!(self.sectors[sector], SectorState::Free { erased: true });
+        if !erased && !self.is_erased(sector, 0..self.sector_size)? {
+            self.erase_sector(sector)?;
+        }
+
+        let seq = self.next_seq;
+        let mut header = [0xFF; SECTOR_HEADER_SIZE];
+        header[0..4].copy_from_slice(&SECTOR_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&seq.to_le_bytes());
+        let crc = crc32(&header[0..8]);
+        header[8..12].copy_from_slice(&crc.to_le_bytes());
+
+        self.sectors[sector] = SectorState::Free { erased: false };
+        self.flash.write(self.address(sector, 0), &header)?;
+        self.sectors[sector] = SectorState::Active {
+            seq,
+            write_offset: SECTOR_HEADER_SIZE,
+            full: false,
+        };
+        self.next_seq = seq.wrapping_add(1);
+        self.head = Some(sector);
+        Ok(())
+    }
+
+    /// 擦除扇区
+    fn erase_sector(&mut self, sector: usize) -> Result<(), KvError<F::Error>> {
+        let from = self.address(sector, 0);
+        self.sectors[sector] = SectorState::Free { erased: false };
+        if self.head == Some(sector) {
+            self.head = None;
+        }
+        self.flash.erase(from, from + self.sector_size as u32)?;
+        self.sectors[sector] = SectorState::Free { erased: true };
+        Ok(())
+    }
+
+    /// 检查扇区中的一段区域是否处于擦除状态
+    fn is_erased(&mut self, sector: usize, range: Range<usize>) -> Result<bool, KvError<F::Error>> {
+        let mut buf = [0; 256];
+        let mut offset = range.start;
+        while offset < range.end {
+            let len = buf.len().min(range.end - offset);
+            self.flash
+                .read(self.address(sector, offset), &mut buf[..len])?;
+            if buf[..len].iter().any(|&byte| byte != 0xFF) {
+                return Ok(false);
+            }
+            offset += len;
+        }
+        Ok(true)
+    }
+
+    /// 更新扇区的写入位置
+    fn set_write_state(&mut self, sector: usize, offset: usize, is_full: bool) {
+        if let SectorState::Active {
+            write_offset, full, ..
+        } = &mut self.sectors[sector]
+        {
+            *write_offset = offset;
+            *full = is_full;
+        }
+    }
+
+    /// 使用中的扇区, 按从旧到新排序
+    fn active_sectors(&self) -> Vec<usize> {
+        let mut active: Vec<usize> = (0..self.sectors.len())
+            .filter(|&sector| matches!(self.sectors[sector], SectorState::Active { .. }))
+            .collect();
+        // 序号回绕时按照与最新序号的距离排序
+        let newest = active
+            .iter()
+            .map(|&sector| self.sector_seq(sector))
+            .max()
+            .unwrap_or(0);
+        active.sort_by_key(|&sector| {
+            core::cmp::Reverse(newest.wrapping_sub(self.sector_seq(sector)))
+        });
+        active
+    }
+
+    /// 扇区序号
+    fn sector_seq(&self, sector: usize) -> u32 {
+        match self.sectors[sector] {
+            SectorState::Active { seq, .. } => seq,
+            SectorState::Free { .. } => 0,
+        }
+    }
+
+    /// 空闲扇区的数量
+    fn free_count(&self) -> usize {
+        self.sectors
+            .iter()
+            .filter(|state| matches!(state, SectorState::Free { .. }))
+            .count()
+    }
+
+    /// 当前扇区之后的下一个空闲扇区, 扇区轮流使用以均衡磨损
+    fn next_free_sector(&self) -> Option<usize> {
+        let count = self.sectors.len();
+        let start = self.head.map_or(0, |head| head + 1);
+        (0..count)
+            .map(|i| (start + i) % count)
+            .find(|&sector| matches!(self.sectors[sector], SectorState::Free { .. }))
+    }
+
+    /// 记录占用的空间, 按存储器的写入单位对齐
+    fn record_size(&self, key_len: usize, value_len: usize) -> usize {
+        let size = RECORD_HEADER_SIZE + key_len + value_len;
+        size.div_ceil(F::WRITE_SIZE) * F::WRITE_SIZE
+    }
+
+    /// 扇区内偏移对应的存储器地址
+    fn address(&self, sector: usize, offset: usize) -> u32 {
+        ((self.first_sector as usize + sector) * self.sector_size + offset) as u32
+    }
+}
+
+/// 解析扇区头, 返回扇区序号
+fn parse_sector_header(header: &[u8; SECTOR_HEADER_SIZE]) -> Option<u32> {
+    let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let seq = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    let crc = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+    let active = header[SECTOR_STATE_OFFSET..] == [0xFF; 4];
+    (magic == SECTOR_MAGIC && crc == crc32(&header[0..8]) && active).then_some(seq)
+}
+
+/// 记录的校验值, 覆盖记录头的前4个字节、键和值
+fn record_crc(header: &[u8], data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&header[0..4]);
+    crc.update(data);
+    crc.finish()
+}
+
+/// 键值迭代器
+pub struct KvIter<'a, F> {
+    store: &'a mut KvStore<F>,
+    keys: std::vec::IntoIter<Vec<u8>>,
+}
+
+impl<'a, F> Iterator for KvIter<'a, F>
+where
+    F: NorFlash + MultiwriteNorFlash,
+{
+    type Item = Result<(Vec<u8>, Vec<u8>), KvError<F::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.keys.next()?;
+        match self.store.get(&key) {
+            Ok(Some(value)) => Some(Ok((key, value))),
+            Ok(None) => self.next(),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
@@ -1,3 +1,18 @@
+#[cfg(feature = "async")]
+pub mod asynch;
+mod command;
 pub mod conf;
+pub mod crc;
+pub mod error;
 pub mod hal;
+pub mod kv;
+#[cfg(target_os = "espidf")]
+pub mod qspi;
+pub mod read;
 pub mod reg;
+pub mod status;
+mod storage;
+
+pub use error::W25q64Error;
+pub use read::{MultiIoSpi, ReadMode};
+pub use status::StatusRegister;
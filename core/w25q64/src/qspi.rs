@@ -0,0 +1,330 @@
+//! ESP 的多线 SPI 设备
+//!
+//! esp-idf-hal 的 `SpiDeviceDriver` 只支持单线传输, 这里直接使用 ESP-IDF 的 SPI Master 驱动:
+//! 全双工设备执行普通命令, 半双工设备执行双线/四线读取。
+//! 两个设备共用同一条总线, 片选由 GPIO 控制。
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr;
+
+use embedded_hal::spi::{Error, ErrorKind, ErrorType, Operation, SpiDevice};
+use esp_idf_hal::{
+    delay::Ets,
+    gpio::{AnyIOPin, Output, Pin, PinDriver},
+    peripheral::Peripheral,
+    spi::SpiAnyPins,
+    sys::*,
+    units::Hertz,
+};
+
+use super::read::{MultiIoSpi, ReadMode};
+
+// 不使用 DMA 时单次传输的最大字节数
+const NO_DMA_MAX_TRANSFER_SIZE: usize = 64;
+
+/// 多线 SPI 设备的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QspiError(pub EspError);
+
+impl From<EspError> for QspiError {
+    fn from(e: EspError) -> Self {
+        QspiError(e)
+    }
+}
+
+impl Error for QspiError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl fmt::Display for QspiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Qspi Error: {}", self.0)
+    }
+}
+
+impl std::error::Error for QspiError {}
+
+/// 支持双线/四线读取的 ESP SPI 设备
+pub struct QspiDevice<'d> {
+    host: spi_host_device_t,
+    // 执行普通命令的全双工设备
+    device: spi_device_handle_t,
+    // 执行多线读取的半双工设备
+    multi_io_device: spi_device_handle_t,
+    cs: PinDriver<'d, AnyIOPin, Output>,
+    data_lines: u8,
+    max_transfer_size: usize,
+    // 总线占用的引脚
+    _pins: Vec<AnyIOPin>,
+    // 在设备释放前独占 SPI 外设, 与 esp-idf-hal 的 SpiDriver 相同
+    _spi: PhantomData<&'d mut ()>,
+}
+
+impl<'d> QspiDevice<'d> {
+    /// 初始化 SPI 总线
+    /// io2/io3: 没有连接时只支持双线读取
+    /// dma: 使用 DMA 时单次传输的最大字节数
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<SPI: SpiAnyPins>(
+        _spi: impl Peripheral<P = SPI> + 'd,
+        cs: AnyIOPin,
+        sck: AnyIOPin,
+        io0: AnyIOPin,
+        io1: AnyIOPin,
+        io2: Option<AnyIOPin>,
+        io3: Option<AnyIOPin>,
+        baudrate: Hertz,
+        dma: Option<usize>,
+    ) -> Result<Self, EspError> {
+        let host = SPI::device();
+        let quad = io2.is_some() && io3.is_some();
+        let pin_of = |pin: &Option<AnyIOPin>| pin.as_ref().map(|pin| pin.pin()).unwrap_or(-1);
+        let max_transfer_size = dma.unwrap_or(NO_DMA_MAX_TRANSFER_SIZE);
+
+        let bus_config = spi_bus_config_t {
+            sclk_io_num: sck.pin(),
+            __bindgen_anon_1: spi_bus_config_t__bindgen_ty_1 {
+                mosi_io_num: io0.pin(),
+            },
+            __bindgen_anon_2: spi_bus_config_t__bindgen_ty_2 {
+                miso_io_num: io1.pin(),
+            },
+            __bindgen_anon_3: spi_bus_config_t__bindgen_ty_3 {
+                quadwp_io_num: if quad { pin_of(&io2) } else { -1 },
+            },
+            __bindgen_anon_4: spi_bus_config_t__bindgen_ty_4 {
+                quadhd_io_num: if quad { pin_of(&io3) } else { -1 },
+            },
+            data4_io_num: -1,
+            data5_io_num: -1,
+            data6_io_num: -1,
+            data7_io_num: -1,
+            max_transfer_sz: max_transfer_size as i32,
+            flags: SPICOMMON_BUSFLAG_MASTER
+                | if quad {
+                    SPICOMMON_BUSFLAG_QUAD
+                } else {
+                    SPICOMMON_BUSFLAG_DUAL
+                },
+            ..Default::default()
+        };
+        let dma_chan = if dma.is_some() {
+            spi_common_dma_t_SPI_DMA_CH_AUTO
+        } else {
+            spi_common_dma_t_SPI_DMA_DISABLED
+        };
+        esp!(unsafe { spi_bus_initialize(host, &bus_config, dma_chan) })?;
+
+        let device_config = |flags: u32| spi_device_interface_config_t {
+            mode: 0,
+            clock_speed_hz: baudrate.0 as i32,
+            // 片选由 GPIO 控制
+            spics_io_num: -1,
+            queue_size: 1,
+            flags,
+            ..Default::default()
+        };
+        let mut device = ptr::null_mut();
+        let mut multi_io_device = ptr::null_mut();
+        let result = esp!(unsafe { spi_bus_add_device(host, &device_config(0), &mut device) })
+            .and_then(|_| {
+                esp!(unsafe {
+                    spi_bus_add_device(
+                        host,
+                        &device_config(SPI_DEVICE_HALFDUPLEX),
+                        &mut multi_io_device,
+                    )
+                })
+            });
+        if let Err(e) = result {
+            unsafe {
+                if !device.is_null() {
+                    spi_bus_remove_device(device);
+                }
+                spi_bus_free(host);
+            }
+            return Err(e);
+        }
+
+        // SS默认高电平
+        let mut cs = PinDriver::output(cs)?;
+        cs.set_high()?;
+
+        let mut pins = vec![sck, io0, io1];
+        pins.extend(io2);
+        pins.extend(io3);
+        Ok(QspiDevice {
+            host,
+            device,
+            multi_io_device,
+            cs,
+            data_lines: if quad { 4 } else { 2 },
+            max_transfer_size,
+            _pins: pins,
+            _spi: PhantomData,
+        })
+    }
+
+    /// 全双工传输, 超过单次传输的最大字节数时拆分成多次传输
+    /// tx/rx 为空指针时不发送/不接收
+    fn full_duplex(&mut self, tx: *const u8, rx: *mut u8, len: usize) -> Result<(), EspError> {
+        for offset in (0..len).step_by(self.max_transfer_size) {
+            let chunk = (len - offset).min(self.max_transfer_size);
+            let mut transaction = spi_transaction_t {
+                length: chunk * 8,
+                rxlength: if rx.is_null() { 0 } else { chunk * 8 },
+                __bindgen_anon_1: spi_transaction_t__bindgen_ty_1 {
+                    tx_buffer: if tx.is_null() {
+                        ptr::null()
+                    } else {
+                        unsafe { tx.add(offset) as *const _ }
+                    },
+                },
+                __bindgen_anon_2: spi_transaction_t__bindgen_ty_2 {
+                    rx_buffer: if rx.is_null() {
+                        ptr::null_mut()
+                    } else {
+                        unsafe { rx.add(offset) as *mut _ }
+                    },
+                },
+                ..Default::default()
+            };
+            esp!(unsafe { spi_device_polling_transmit(self.device, &mut transaction) })?;
+        }
+        Ok(())
+    }
+
+    /// 执行一个 SPI 操作
+    fn operation(&mut self, operation: &mut Operation<'_, u8>) -> Result<(), EspError> {
+        match operation {
+            Operation::Read(words) => {
+                self.full_duplex(ptr::null(), words.as_mut_ptr(), words.len())
+            }
+            Operation::Write(words) => {
+                self.full_duplex(words.as_ptr(), ptr::null_mut(), words.len())
+            }
+            Operation::Transfer(read, write) => {
+                // 公共部分同时收发, 剩余部分只发送或只接收
+                let common = read.len().min(write.len());
+                self.full_duplex(write.as_ptr(), read.as_mut_ptr(), common)?;
+                self.full_duplex(
+                    write[common..].as_ptr(),
+                    ptr::null_mut(),
+                    write.len() - common,
+                )?;
+                self.full_duplex(
+                    ptr::null(),
+                    read[common..].as_mut_ptr(),
+                    read.len() - common,
+                )
+            }
+            Operation::TransferInPlace(words) => {
+                self.full_duplex(words.as_ptr(), words.as_mut_ptr(), words.len())
+            }
+            Operation::DelayUs(us) => {
+                Ets::delay_us(*us);
+                Ok(())
+            }
+        }
+    }
+
+    /// 在一次片选内执行一次半双工多线读取
+    fn read_chunk(
+        &mut self,
+        mode: ReadMode,
+        address: u32,
+        data: &mut [u8],
+    ) -> Result<(), EspError> {
+        let data_flags = match mode.data_lines() {
+            2 => SPI_TRANS_MODE_DIO,
+            4 => SPI_TRANS_MODE_QIO,
+            _ => 0,
+        };
+        let address_flags = if mode.address_lines() > 1 {
+            SPI_TRANS_MULTILINE_ADDR
+        } else {
+            0
+        };
+        // 模式位跟在地址之后, 使用与地址相同的线数发送
+        let (addr, address_bits) = match mode.mode_bits() {
+            Some(bits) => (((address as u64) << 8) | bits as u64, 32),
+            None => (address as u64, 24),
+        };
+
+        let mut transaction = spi_transaction_ext_t {
+            base: spi_transaction_t {
+                flags: data_flags
+                    | address_flags
+                    | SPI_TRANS_VARIABLE_CMD
+                    | SPI_TRANS_VARIABLE_ADDR
+                    | SPI_TRANS_VARIABLE_DUMMY,
+                cmd: mode.command() as u16,
+                addr,
+                length: 0,
+                rxlength: data.len() * 8,
+                __bindgen_anon_2: spi_transaction_t__bindgen_ty_2 {
+                    rx_buffer: data.as_mut_ptr() as *mut _,
+                },
+                ..Default::default()
+            },
+            command_bits: 8,
+            address_bits,
+            dummy_bits: mode.dummy_cycles(),
+        };
+
+        self.cs.set_low()?;
+        let result = esp!(unsafe {
+            spi_device_polling_transmit(self.multi_io_device, &mut transaction.base)
+        });
+        self.cs.set_high()?;
+        result
+    }
+}
+
+impl<'d> Drop for QspiDevice<'d> {
+    fn drop(&mut self) {
+        unsafe {
+            spi_bus_remove_device(self.multi_io_device);
+            spi_bus_remove_device(self.device);
+            spi_bus_free(self.host);
+        }
+    }
+}
+
+impl<'d> ErrorType for QspiDevice<'d> {
+    type Error = QspiError;
+}
+
+impl<'d> SpiDevice for QspiDevice<'d> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.cs.set_low()?;
+        let result = operations
+            .iter_mut()
+            .try_for_each(|operation| self.operation(operation));
+        self.cs.set_high()?;
+        Ok(result?)
+    }
+}
+
+impl<'d> MultiIoSpi for QspiDevice<'d> {
+    fn data_lines(&self) -> u8 {
+        self.data_lines
+    }
+
+    fn read_multi_io(
+        &mut self,
+        mode: ReadMode,
+        address: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        // 超过单次传输的最大字节数时, 拆分成多次读取, 每次重新发送指令和地址
+        let mut address = address;
+        for chunk in data.chunks_mut(self.max_transfer_size) {
+            self.read_chunk(mode, address, chunk)?;
+            address += chunk.len() as u32;
+        }
+        Ok(())
+    }
+}
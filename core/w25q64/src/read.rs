@@ -0,0 +1,96 @@
+//! 读取模式
+//!
+//! 标准读取和快速读取只使用 MOSI/MISO 两根数据线, 任何 `SpiDevice` 都可以执行;
+//! 双线和四线读取需要支持多线传输的 SPI 控制器, 由 `MultiIoSpi` 描述。
+use embedded_hal::spi::SpiDevice;
+
+use super::conf::*;
+
+/// 读取数据的模式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    /// 标准读取, 时钟最高 50MHz
+    #[default]
+    Standard,
+    /// 快速读取, 地址之后有 8 个虚拟时钟, 时钟最高 133MHz
+    Fast,
+    /// 双线输出, 数据使用 IO0~IO1
+    DualOutput,
+    /// 四线输出, 数据使用 IO0~IO3, 需要置位 QE
+    QuadOutput,
+    /// 四线输入输出, 地址和数据都使用 IO0~IO3, 需要置位 QE
+    QuadIO,
+}
+
+impl ReadMode {
+    /// 读取指令
+    pub fn command(&self) -> u8 {
+        match self {
+            ReadMode::Standard => W25Q64_READ_DATA,
+            ReadMode::Fast => W25Q64_FAST_READ,
+            ReadMode::DualOutput => W25Q64_FAST_READ_DUAL_OUTPUT,
+            ReadMode::QuadOutput => W25Q64_FAST_READ_QUAD_OUTPUT,
+            ReadMode::QuadIO => W25Q64_FAST_READ_QUAD_IO,
+        }
+    }
+
+    /// 发送地址使用的数据线数量
+    pub fn address_lines(&self) -> u8 {
+        match self {
+            ReadMode::QuadIO => 4,
+            _ => 1,
+        }
+    }
+
+    /// 读取数据使用的数据线数量
+    pub fn data_lines(&self) -> u8 {
+        match self {
+            ReadMode::Standard | ReadMode::Fast => 1,
+            ReadMode::DualOutput => 2,
+            ReadMode::QuadOutput | ReadMode::QuadIO => 4,
+        }
+    }
+
+    /// 地址之后的模式位 M7~M0, 只有四线输入输出模式需要
+    /// M5~M4 不为 10 时不进入连续读取模式, 下一次读取仍然需要发送指令
+    pub fn mode_bits(&self) -> Option<u8> {
+        match self {
+            ReadMode::QuadIO => Some(W25Q64_DUMMY_BYTE),
+            _ => None,
+        }
+    }
+
+    /// 模式位之后的虚拟时钟数量
+    pub fn dummy_cycles(&self) -> u8 {
+        match self {
+            ReadMode::Standard => 0,
+            ReadMode::QuadIO => 4,
+            _ => 8,
+        }
+    }
+
+    /// 是否需要多线传输
+    pub fn is_multi_io(&self) -> bool {
+        self.data_lines() > 1
+    }
+
+    /// 是否需要置位状态寄存器2的 QE 位
+    pub fn requires_quad_enable(&self) -> bool {
+        self.data_lines() == 4
+    }
+}
+
+/// 支持双线/四线读取的 SPI 设备
+pub trait MultiIoSpi: SpiDevice {
+    /// 连接的数据线数量, 2 表示只连接了 IO0~IO1, 4 表示连接了 IO0~IO3
+    fn data_lines(&self) -> u8;
+
+    /// 在一次片选内完成一次多线读取
+    /// 使用单线发送指令, 按 `mode` 的线数发送地址和模式位, 等待虚拟时钟后读取数据
+    fn read_multi_io(
+        &mut self,
+        mode: ReadMode,
+        address: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error>;
+}
@@ -1,247 +1,245 @@
-use super::conf::*;
-
+//! 寄存器版本实现
+//!
+//! 使用 GPIO 软件模拟 SPI 时序, 实现 embedded-hal 的 `SpiDevice`。
+//! 命令层与 HAL 库版本共用 `hal::W25Q64`, 这里只负责按位收发数据,
+//! 支持 SPI 模式 0~3 和可配置的时钟延时。
+use core::fmt::Debug;
+use core::time::Duration;
+use std::time::Instant;
+
+use embedded_hal::{
+    digital::{InputPin, OutputPin},
+    spi::{self, ErrorKind, ErrorType, Mode, Operation, Phase, Polarity, SpiDevice, MODE_0},
+};
+#[cfg(target_os = "espidf")]
 use esp_idf_hal::{
     gpio::{AnyIOPin, Input, Output, PinDriver},
     sys::EspError,
 };
 
-pub struct W25Q64<'d> {
-    ss: PinDriver<'d, AnyIOPin, Output>,
-    sck: PinDriver<'d, AnyIOPin, Output>,
-    mosi: PinDriver<'d, AnyIOPin, Output>,
-    miso: PinDriver<'d, AnyIOPin, Input>,
+#[cfg(target_os = "espidf")]
+use super::hal;
+
+/// 软件 SPI 的错误, 包含引脚操作的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoftSpiError<E>(pub E);
+
+impl<E: Debug> spi::Error for SoftSpiError<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
 }
 
-impl<'d> W25Q64<'d> {
-    pub fn spi_w_ss(&mut self, bit_value: u8) -> Result<(), EspError> {
-        if bit_value == 0 {
-            self.ss.set_low()?;
-        } else {
-            self.ss.set_high()?;
+/// 使用 GPIO 模拟的 SPI 设备, 片选低电平有效
+pub struct SoftSpi<CS, SCK, MOSI, MISO> {
+    cs: CS,
+    sck: SCK,
+    mosi: MOSI,
+    miso: MISO,
+    mode: Mode,
+    // 半个时钟周期的延时
+    clock_delay: Duration,
+}
+
+impl<CS, SCK, MOSI, MISO, E> SoftSpi<CS, SCK, MOSI, MISO>
+where
+    CS: OutputPin<Error = E>,
+    SCK: OutputPin<Error = E>,
+    MOSI: OutputPin<Error = E>,
+    MISO: InputPin<Error = E>,
+    E: Debug,
+{
+    /// 创建对象, 默认使用 SPI 模式 0, 不加时钟延时
+    /// 片选引脚需要预先设为高电平
+    pub fn new(cs: CS, sck: SCK, mosi: MOSI, miso: MISO) -> Self {
+        SoftSpi {
+            cs,
+            sck,
+            mosi,
+            miso,
+            mode: MODE_0,
+            clock_delay: Duration::ZERO,
         }
-        Ok(())
     }
 
-    pub fn spi_w_sck(&mut self, bit_value: u8) -> Result<(), EspError> {
-        if bit_value == 0 {
-            self.sck.set_low()?;
-        } else {
-            self.sck.set_high()?;
-        }
-        Ok(())
+    /// 设置 SPI 模式 0~3, 时钟的空闲电平在下一次传输开始时生效
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
     }
 
-    pub fn spi_w_mosi(&mut self, bit_value: u8) -> Result<(), EspError> {
-        if bit_value == 0 {
-            self.mosi.set_low()?;
-        } else {
-            self.mosi.set_high()?;
-        }
-        Ok(())
+    /// 设置半个时钟周期的延时, 用于降低时钟频率
+    pub fn with_clock_delay(mut self, clock_delay: Duration) -> Self {
+        self.clock_delay = clock_delay;
+        self
+    }
+
+    /// 释放引脚
+    pub fn release(self) -> (CS, SCK, MOSI, MISO) {
+        (self.cs, self.sck, self.mosi, self.miso)
     }
 
-    pub fn spi_r_miso(&mut self) -> u8 {
-        if self.miso.is_high() {
-            1
+    /// 设置时钟, active 为 true 时输出有效电平(与空闲电平相反)
+    fn write_sck(&mut self, active: bool) -> Result<(), E> {
+        let idle_high = self.mode.polarity == Polarity::IdleHigh;
+        if active != idle_high {
+            self.sck.set_high()
         } else {
-            0
+            self.sck.set_low()
         }
     }
 
-    /// PSI 开始
-    pub fn spi_start(&mut self) -> Result<(), EspError> {
-        self.spi_w_ss(0)
+    fn write_mosi(&mut self, bit: bool) -> Result<(), E> {
+        if bit {
+            self.mosi.set_high()
+        } else {
+            self.mosi.set_low()
+        }
     }
 
-    /// PSI 结束
-    pub fn spi_stop(&mut self) -> Result<(), EspError> {
-        self.spi_w_ss(1)
+    /// 等待半个时钟周期
+    fn delay(&self) {
+        if self.clock_delay.is_zero() {
+            return;
+        }
+        let start = Instant::now();
+        while start.elapsed() < self.clock_delay {}
     }
 
-    /// SPI 交换传输一个字节，使用 SPI 模式 0
-    /// ByteSend 要发送的一个字节
+    /// SPI 交换传输一个字节, 高位先行
+    /// byte_send: 要发送的一个字节
     /// 返回接收的一个字节
-    pub fn spi_swap_byte(&mut self, byte_send: u8) -> Result<u8, EspError> {
-        // 定义接收的数据，并赋初值 0x00，此处必须赋初值 0x00，后面会用到
+    fn swap_byte(&mut self, byte_send: u8) -> Result<u8, E> {
         let mut byte_receive = 0x00;
 
         // 循环 8 次，依次交换每一位数据
         for i in 0..8 {
-            // 使用掩码的方式取出 ByteSend 的指定一位数据并写入到 MOSI 线
-            self.spi_w_mosi(byte_send & (0x80 >> i))?;
-            // 拉高 SCK，上升沿移出数据
-            self.spi_w_sck(1)?;
-
-            // 读取 MISO 数据，并存储到 Byte 变量
-            // 当 MISO 为 1 时，置变量指定位为 1，当 MISO 为 0 时，不做处理，指定位为默认的初值 0
-            if self.spi_r_miso() == 1 {
+            let bit = byte_send & (0x80 >> i) != 0;
+            let sample = match self.mode.phase {
+                // 第一个边沿采样: 先输出数据, 第一个边沿读取, 第二个边沿从机移出下一位
+                Phase::CaptureOnFirstTransition => {
+                    self.write_mosi(bit)?;
+                    self.delay();
+                    self.write_sck(true)?;
+                    let sample = self.miso.is_high()?;
+                    self.delay();
+                    self.write_sck(false)?;
+                    sample
+                }
+                // 第二个边沿采样: 第一个边沿移出数据, 第二个边沿读取
+                Phase::CaptureOnSecondTransition => {
+                    self.write_sck(true)?;
+                    self.write_mosi(bit)?;
+                    self.delay();
+                    self.write_sck(false)?;
+                    let sample = self.miso.is_high()?;
+                    self.delay();
+                    sample
+                }
+            };
+            if sample {
                 byte_receive |= 0x80 >> i;
             }
-
-            // 拉低 SCK，下降沿移入数据
-            self.spi_w_sck(0)?;
         }
 
-        // 返回接收到的一个字节数据
         Ok(byte_receive)
     }
 
-    /// 写入并返回数据
-    pub fn spi_swap_bytes(&mut self, bytes: &mut [u8]) -> Result<(), EspError> {
-        self.spi_start()?;
-        for byte in bytes {
-            *byte = self.spi_swap_byte(*byte)?;
+    /// 在片选有效期间执行一个操作
+    fn operation(&mut self, operation: &mut Operation<'_, u8>) -> Result<(), E> {
+        match operation {
+            Operation::Read(words) => {
+                for word in words.iter_mut() {
+                    *word = self.swap_byte(0x00)?;
+                }
+            }
+            Operation::Write(words) => {
+                for word in words.iter() {
+                    self.swap_byte(*word)?;
+                }
+            }
+            Operation::Transfer(read, write) => {
+                for i in 0..read.len().max(write.len()) {
+                    let byte = self.swap_byte(write.get(i).copied().unwrap_or(0x00))?;
+                    if let Some(word) = read.get_mut(i) {
+                        *word = byte;
+                    }
+                }
+            }
+            Operation::TransferInPlace(words) => {
+                for word in words.iter_mut() {
+                    *word = self.swap_byte(*word)?;
+                }
+            }
+            // W25Q64 的命令不使用片选期间的延时
+            _ => {}
         }
-        self.spi_stop()?;
-
         Ok(())
     }
 }
 
-impl<'d> W25Q64<'d> {
-    pub fn new(
+impl<CS, SCK, MOSI, MISO, E> ErrorType for SoftSpi<CS, SCK, MOSI, MISO>
+where
+    CS: OutputPin<Error = E>,
+    SCK: OutputPin<Error = E>,
+    MOSI: OutputPin<Error = E>,
+    MISO: InputPin<Error = E>,
+    E: Debug,
+{
+    type Error = SoftSpiError<E>;
+}
+
+impl<CS, SCK, MOSI, MISO, E> SpiDevice for SoftSpi<CS, SCK, MOSI, MISO>
+where
+    CS: OutputPin<Error = E>,
+    SCK: OutputPin<Error = E>,
+    MOSI: OutputPin<Error = E>,
+    MISO: InputPin<Error = E>,
+    E: Debug,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        // 时钟回到空闲电平后片选拉低, 开始一次传输
+        self.write_sck(false).map_err(SoftSpiError)?;
+        self.cs.set_low().map_err(SoftSpiError)?;
+        let result = operations
+            .iter_mut()
+            .try_for_each(|operation| self.operation(operation));
+        // 片选拉高, 结束传输
+        self.cs.set_high().map_err(SoftSpiError)?;
+        result.map_err(SoftSpiError)
+    }
+}
+
+/// ESP 的 GPIO 模拟的 SPI 设备
+#[cfg(target_os = "espidf")]
+pub type EspSoftSpi<'d> = SoftSpi<
+    PinDriver<'d, AnyIOPin, Output>,
+    PinDriver<'d, AnyIOPin, Output>,
+    PinDriver<'d, AnyIOPin, Output>,
+    PinDriver<'d, AnyIOPin, Input>,
+>;
+
+/// 使用 GPIO 模拟 SPI 的 W25Q64
+#[cfg(target_os = "espidf")]
+pub type W25Q64<'d> = hal::W25Q64<EspSoftSpi<'d>>;
+
+#[cfg(target_os = "espidf")]
+impl<'d> hal::W25Q64<EspSoftSpi<'d>> {
+    /// 使用 GPIO 创建对象, SPI 模式 0
+    pub fn from_soft_pins(
         ss: AnyIOPin,
         sck: AnyIOPin,
         mosi: AnyIOPin,
         miso: AnyIOPin,
     ) -> Result<Self, EspError> {
-        let ss_driver = PinDriver::output(ss)?;
-        let sck_driver = PinDriver::output(sck)?;
-        let mosi_driver = PinDriver::output(mosi)?;
-        let miso_driver = PinDriver::input(miso)?;
-
-        let mut w25q = W25Q64 {
-            ss: ss_driver,
-            sck: sck_driver,
-            mosi: mosi_driver,
-            miso: miso_driver,
-        };
+        let mut ss = PinDriver::output(ss)?;
         // SS 默认高电平
-        w25q.spi_w_ss(1)?;
+        ss.set_high()?;
+        let mut sck = PinDriver::output(sck)?;
         // SCK 默认低电平
-        w25q.spi_w_sck(0)?;
-
-        Ok(w25q)
-    }
-
-    /// 读取 W25Q64 ID
-    pub fn read_id(&mut self) -> Result<(u8, u16), EspError> {
-        self.spi_start()?;
-        self.spi_swap_byte(W25Q64_JEDEC_DEVICE_ID)?;
-
-        let mid = self.spi_swap_byte(W25Q64_DUMMY_BYTE)?;
-        let mut did = self.spi_swap_byte(W25Q64_DUMMY_BYTE)? as u16; // 64
-        did <<= 8;
-        did |= self.spi_swap_byte(W25Q64_DUMMY_BYTE)? as u16; //  23
-        self.spi_stop()?;
+        sck.set_low()?;
 
-        Ok((mid, did))
-    }
-
-    /// 读取芯片的JEDEC设备ID
-    /// 使用Spi实例和片选引脚来发送和接收命令和数据
-    pub fn read_jedec_device_id(&mut self) -> Result<(u8, u8, u8), EspError> {
-        let mut buffer = [0; 4];
-        buffer[0] = W25Q64_JEDEC_DEVICE_ID;
-        self.spi_swap_bytes(&mut buffer)?;
-
-        let manufacturer_id = buffer[1];
-        let memory_type = buffer[2];
-        let capacity = buffer[3];
-        Ok((manufacturer_id, memory_type, capacity))
-    }
-
-    /// 读取芯片的制造商和设备ID
-    ///
-    /// 使用Spi实例和片选引脚来发送和接收命令和数据
-    /// 0xEF16: 代表W25Q64芯片
-    pub fn read_manufacturer_device_id(&mut self) -> Result<(u16, u16), EspError> {
-        let mut buffer = [0; 7];
-        buffer[0] = W25Q64_MANUFACTURER_DEVICE_ID;
-        // 发送读取制造商和设备ID的命令
-        self.spi_swap_bytes(&mut buffer)?;
-
-        let manufacturer_id = buffer[4] as u16;
-        let device_id = (buffer[5] as u16) << 8 | buffer[6] as u16;
-        Ok((manufacturer_id, device_id))
-    }
-
-    /// 启用写入功能
-    pub fn write_enable(&mut self) -> Result<(), EspError> {
-        self.spi_start()?;
-        self.spi_swap_byte(W25Q64_WRITE_ENABLE)?;
-        self.spi_stop()?;
-
-        Ok(())
-    }
-
-    /// 等待W25Q64芯片空闲
-    pub fn wait_busy(&mut self) -> Result<(), EspError> {
-        let mut timeout: u32;
-        self.spi_start()?;
-        self.spi_swap_byte(W25Q64_READ_STATUS_REGISTER_1)?;
-
-        timeout = 100000;
-        while self.spi_swap_byte(W25Q64_DUMMY_BYTE)? & 0x01 == 0x01 {
-            timeout -= 1;
-            if timeout == 0 {
-                break;
-            }
-        }
-        self.spi_stop()?;
-
-        Ok(())
-    }
-
-    /// 分页写入数据
-    pub fn page_program(&mut self, address: u32, data_array: &[u8]) -> Result<(), EspError> {
-        self.write_enable()?;
-
-        self.spi_start()?;
-        self.spi_swap_byte(W25Q64_PAGE_PROGRAM)?;
-        self.spi_swap_byte((address >> 16) as u8)?;
-        self.spi_swap_byte((address >> 8) as u8)?;
-        self.spi_swap_byte(address as u8)?;
-
-        for data in data_array {
-            self.spi_swap_byte(*data)?;
-        }
-        self.spi_stop()?;
-
-        self.wait_busy()?;
-
-        Ok(())
-    }
-
-    /// 擦除地址所在的扇区
-    pub fn sector_erase(&mut self, address: u32) -> Result<(), EspError> {
-        self.write_enable()?;
-
-        self.spi_start()?;
-        self.spi_swap_byte(W25Q64_SECTOR_ERASE_4KB)?;
-        self.spi_swap_byte((address >> 16) as u8)?;
-        self.spi_swap_byte((address >> 8) as u8)?;
-        self.spi_swap_byte(address as u8)?;
-        self.spi_stop()?;
-
-        self.wait_busy()?;
-
-        Ok(())
-    }
-
-    /// 读取数据
-    pub fn read_data(&mut self, address: u32, data_array: &mut [u8]) -> Result<(), EspError> {
-        self.spi_start()?;
-        self.spi_swap_byte(W25Q64_READ_DATA)?;
-        self.spi_swap_byte((address >> 16) as u8)?;
-        self.spi_swap_byte((address >> 8) as u8)?;
-        self.spi_swap_byte(address as u8)?;
-
-        for item in data_array {
-            *item = self.spi_swap_byte(W25Q64_DUMMY_BYTE)?;
-        }
-        self.spi_stop()?;
-
-        Ok(())
+        let spi = SoftSpi::new(ss, sck, PinDriver::output(mosi)?, PinDriver::input(miso)?);
+        Ok(hal::W25Q64::new(spi))
     }
 }
@@ -0,0 +1,140 @@
+//! 状态寄存器
+//!
+//! 状态寄存器1和2的各个标志位, 以及由 BP/TB/SEC/CMP 决定的块保护区域。
+use core::ops::Range;
+
+/// 块保护使用的小扇区大小 4KB
+const SMALL_SECTOR_SIZE: u32 = 4096;
+
+/// 状态寄存器1和2
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StatusRegister {
+    /// 忙标志位, 正在编程、擦除或写状态寄存器
+    pub busy: bool,
+    /// 写使能锁存位
+    pub wel: bool,
+    /// 块保护位 BP2~BP0
+    pub bp: u8,
+    /// 保护区域位于顶部(false)或底部(true)
+    pub tb: bool,
+    /// 保护的单位为 64KB 块(false)或 4KB 扇区(true)
+    pub sec: bool,
+    /// 状态寄存器保护位0
+    pub srp0: bool,
+    /// 状态寄存器保护位1
+    pub srp1: bool,
+    /// 四线使能位
+    pub qe: bool,
+    /// 安全寄存器锁定位 LB3~LB1
+    pub lb: u8,
+    /// 保护区域取反
+    pub cmp: bool,
+    /// 擦除/编程已暂停
+    pub sus: bool,
+}
+
+impl StatusRegister {
+    /// 从状态寄存器1和2的值解码
+    pub fn from_bits(sr1: u8, sr2: u8) -> Self {
+        StatusRegister {
+            busy: sr1 & 0x01 != 0,
+            wel: sr1 & 0x02 != 0,
+            bp: (sr1 >> 2) & 0x07,
+            tb: sr1 & 0x20 != 0,
+            sec: sr1 & 0x40 != 0,
+            srp0: sr1 & 0x80 != 0,
+            srp1: sr2 & 0x01 != 0,
+            qe: sr2 & 0x02 != 0,
+            lb: (sr2 >> 3) & 0x07,
+            cmp: sr2 & 0x40 != 0,
+            sus: sr2 & 0x80 != 0,
+        }
+    }
+
+    /// 状态寄存器1的值
+    pub fn sr1(&self) -> u8 {
+        (self.busy as u8)
+            | (self.wel as u8) << 1
+            | (self.bp & 0x07) << 2
+            | (self.tb as u8) << 5
+            | (self.sec as u8) << 6
+            | (self.srp0 as u8) << 7
+    }
+
+    /// 状态寄存器2的值
+    pub fn sr2(&self) -> u8 {
+        (self.srp1 as u8)
+            | (self.qe as u8) << 1
+            | (self.lb & 0x07) << 3
+            | (self.cmp as u8) << 6
+            | (self.sus as u8) << 7
+    }
+
+    /// 当前配置下受保护的地址范围, 没有保护时返回空范围
+    /// capacity: 芯片容量
+    pub fn protected_range(&self, capacity: u32) -> Range<u32> {
+        protected_range(self.bp, self.tb, self.sec, self.cmp, capacity)
+    }
+
+    /// 设置块保护位, 使受保护的地址范围恰好为 range
+    /// 没有对应的配置时返回 false, 状态保持不变
+    pub fn set_protected_range(&mut self, range: Range<u32>, capacity: u32) -> bool {
+        // 优先使用不取反的配置
+        for cmp in [false, true] {
+            for sec in [false, true] {
+                for tb in [false, true] {
+                    for bp in 0..8 {
+                        let protected = protected_range(bp, tb, sec, cmp, capacity);
+                        if same_range(&protected, &range) {
+                            self.bp = bp;
+                            self.tb = tb;
+                            self.sec = sec;
+                            self.cmp = cmp;
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// 两个范围是否相同, 空范围都视为相同
+fn same_range(a: &Range<u32>, b: &Range<u32>) -> bool {
+    (a.is_empty() && b.is_empty()) || a == b
+}
+
+/// 根据 BP/TB/SEC/CMP 计算受保护的地址范围
+fn protected_range(bp: u8, tb: bool, sec: bool, cmp: bool, capacity: u32) -> Range<u32> {
+    let size = match (bp & 0x07, sec) {
+        (0, _) => 0,
+        (7, _) => capacity,
+        // 以 4KB 扇区为单位: 4KB, 8KB, 16KB, 32KB
+        (bp, true) => SMALL_SECTOR_SIZE << (bp.min(4) - 1),
+        // 以容量的比例为单位: 1/64, 1/32, ... 1/2
+        (bp, false) => capacity >> (7 - bp),
+    };
+    let size = size.min(capacity);
+
+    // 顶部或底部的区域
+    let range = if tb {
+        0..size
+    } else {
+        capacity - size..capacity
+    };
+    if !cmp {
+        return range;
+    }
+
+    // 取反后为剩余的区域
+    if size == 0 {
+        0..capacity
+    } else if size == capacity {
+        0..0
+    } else if tb {
+        size..capacity
+    } else {
+        0..capacity - size
+    }
+}
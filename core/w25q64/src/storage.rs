@@ -0,0 +1,101 @@
+//! embedded-storage 存储接口
+//!
+//! 为驱动实现 `ReadNorFlash`、`NorFlash` 和 `MultiwriteNorFlash`,
+//! 使外部闪存可以接入键值存储、文件系统、引导程序等生态库。
+//! 读写的最小单位为 1 字节, 擦除的最小单位为 1 个扇区(4KB)。
+use core::fmt::Debug;
+
+use embedded_hal::spi::SpiDevice;
+use embedded_storage::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+use super::conf::*;
+use super::error::W25q64Error;
+use super::hal;
+
+impl<E: Debug> NorFlashError for W25q64Error<E> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            W25q64Error::AddressOutOfRange { .. } => NorFlashErrorKind::OutOfBounds,
+            W25q64Error::NotAligned { .. } => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// 检查擦除范围 [from, to) 是否按扇区对齐且不超出芯片容量
+pub(crate) fn check_erase<E>(from: u32, to: u32, capacity: u32) -> Result<(), W25q64Error<E>> {
+    if from > to || to > capacity {
+        return Err(W25q64Error::AddressOutOfRange {
+            address: from,
+            len: to.saturating_sub(from) as usize,
+            capacity,
+        });
+    }
+    for address in [from, to] {
+        if address as usize % W25Q64_SECTOR_SIZE != 0 {
+            return Err(W25q64Error::NotAligned { address });
+        }
+    }
+    Ok(())
+}
+
+/// 检查读写范围是否超出芯片容量
+pub(crate) fn check_slice<E>(
+    address: u32,
+    len: usize,
+    capacity: u32,
+) -> Result<(), W25q64Error<E>> {
+    if address as u64 + len as u64 > capacity as u64 {
+        return Err(W25q64Error::AddressOutOfRange {
+            address,
+            len,
+            capacity,
+        });
+    }
+    Ok(())
+}
+
+impl<SPI> ErrorType for hal::W25Q64<SPI>
+where
+    SPI: SpiDevice,
+{
+    type Error = W25q64Error<SPI::Error>;
+}
+
+impl<SPI> ReadNorFlash for hal::W25Q64<SPI>
+where
+    SPI: SpiDevice,
+{
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.check_range(offset, bytes.len())?;
+        self.read_data(offset, bytes)?;
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.cached_capacity().unwrap_or(W25Q64_CAPACITY as u32) as usize
+    }
+}
+
+impl<SPI> NorFlash for hal::W25Q64<SPI>
+where
+    SPI: SpiDevice,
+{
+    const WRITE_SIZE: usize = 1;
+
+    const ERASE_SIZE: usize = W25Q64_SECTOR_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.erase_range(from..to)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        hal::W25Q64::write(self, offset, bytes)
+    }
+}
+
+impl<SPI> MultiwriteNorFlash for hal::W25Q64<SPI> where SPI: SpiDevice {}
@@ -0,0 +1,298 @@
+//! 在主机上使用闪存模拟器测试异步版本驱动
+#![cfg(feature = "async")]
+use std::convert::Infallible;
+use std::time::Duration;
+
+use embedded_hal::spi::{ErrorType, Operation};
+use embedded_hal_async::delay::DelayUs;
+use embedded_hal_async::spi::SpiDevice;
+use futures::executor::block_on;
+use w25q64::{asynch::W25Q64, ReadMode, StatusRegister, W25q64Error};
+use w25q64_sim::W25Q64Sim;
+
+/// 把同步的模拟器包装为异步 SPI 设备
+struct AsyncSim(W25Q64Sim);
+
+impl ErrorType for AsyncSim {
+    type Error = Infallible;
+}
+
+impl SpiDevice for AsyncSim {
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Self::Error> {
+        embedded_hal::spi::SpiDevice::transaction(&mut self.0, operations)
+    }
+}
+
+/// 记录等待时间的定时器
+#[derive(Default)]
+struct MockDelay {
+    waited_us: u64,
+}
+
+impl DelayUs for MockDelay {
+    async fn delay_us(&mut self, us: u32) {
+        self.waited_us += us as u64;
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.waited_us += ms as u64 * 1000;
+    }
+}
+
+fn new_w25q(sim: W25Q64Sim) -> W25Q64<AsyncSim, MockDelay> {
+    W25Q64::new(AsyncSim(sim), MockDelay::default())
+}
+
+#[test]
+fn it_read_ids() {
+    let mut w25q = new_w25q(W25Q64Sim::new());
+
+    block_on(async {
+        assert_eq!(
+            w25q.read_jedec_device_id().await.unwrap(),
+            (0xEF, 0x40, 0x17)
+        );
+        let (manufacturer_id, device_id) = w25q.read_manufacturer_device_id().await.unwrap();
+        assert_eq!(manufacturer_id, 0xEF);
+        assert_eq!(device_id, 0x16EF);
+        assert_eq!(w25q.capacity().await.unwrap(), 8 * 1024 * 1024);
+    });
+}
+
+#[test]
+fn it_page_program_waits_with_timer() {
+    let mut w25q = new_w25q(W25Q64Sim::new().with_busy_polls(3))
+        .with_poll_interval(Duration::from_micros(100));
+
+    block_on(async {
+        w25q.page_program(0x000100, &[0x01, 0x02, 0x03])
+            .await
+            .unwrap();
+
+        let mut rx_buf = [0; 3];
+        w25q.read_data(0x000100, &mut rx_buf).await.unwrap();
+        assert_eq!(rx_buf, [0x01, 0x02, 0x03]);
+    });
+
+    assert_eq!(
+        block_on(w25q.page_program(0x000200, &[0x00; 257])),
+        Err(W25q64Error::PageOverflow {
+            address: 0x000200,
+            len: 257
+        })
+    );
+
+    let (sim, delay) = w25q.release();
+    assert!(!sim.0.is_busy());
+    // 忙碌期间每次轮询之间都等待了一个轮询间隔
+    assert_eq!(delay.waited_us, 300);
+}
+
+#[test]
+fn it_write_across_pages_and_verify() {
+    let mut w25q = new_w25q(W25Q64Sim::new().with_busy_polls(2));
+    let data: Vec<u8> = (0..600).map(|i| i as u8).collect();
+
+    block_on(async {
+        w25q.write(0x0000F0, &data).await.unwrap();
+        w25q.verify(0x0000F0, &data).await.unwrap();
+        assert_eq!(
+            w25q.verify(0x0000EF, &[0x00]).await,
+            Err(W25q64Error::VerifyMismatch {
+                address: 0x0000EF,
+                expected: 0x00,
+                actual: 0xFF
+            })
+        );
+    });
+
+    let (sim, _) = w25q.release();
+    assert_eq!(sim.0.memory()[0x0000F0 + 600], 0xFF);
+}
+
+#[test]
+fn it_erase_range() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x000000..0x020000].fill(0x00);
+    let mut w25q = new_w25q(sim);
+
+    block_on(async {
+        w25q.erase_range(0x001000..0x011000).await.unwrap();
+        assert_eq!(
+            w25q.erase_range(0x000100..0x001000).await,
+            Err(W25q64Error::NotAligned { address: 0x000100 })
+        );
+    });
+
+    let (sim, _) = w25q.release();
+    assert_eq!(sim.0.memory()[0x000FFF], 0x00);
+    assert!(sim.0.memory()[0x001000..0x011000]
+        .iter()
+        .all(|&byte| byte == 0xFF));
+    assert_eq!(sim.0.memory()[0x011000], 0x00);
+}
+
+#[test]
+fn it_protect() {
+    let mut w25q = new_w25q(W25Q64Sim::new());
+
+    block_on(async {
+        w25q.protect(0x7E0000..0x800000).await.unwrap();
+        assert_eq!(w25q.protected_range().await.unwrap(), 0x7E0000..0x800000);
+        assert_eq!(
+            w25q.page_program(0x7E0000, &[0x00]).await,
+            Err(W25q64Error::WriteProtected { address: 0x7E0000 })
+        );
+
+        w25q.protect(0..0).await.unwrap();
+        assert_eq!(
+            w25q.read_status_register().await.unwrap(),
+            StatusRegister::default()
+        );
+        w25q.page_program(0x7E0000, &[0x00]).await.unwrap();
+    });
+}
+
+#[test]
+fn it_status_registers() {
+    let mut w25q = new_w25q(W25Q64Sim::new());
+
+    block_on(async {
+        assert!(!w25q.quad_enable().await.unwrap());
+        w25q.set_quad_enable(true).await.unwrap();
+        assert!(w25q.quad_enable().await.unwrap());
+
+        let mut status = w25q.read_status_register().await.unwrap();
+        status.srp0 = true;
+        w25q.write_status_register(&status).await.unwrap();
+        assert!(w25q.check_write_protect().await.unwrap());
+
+        w25q.write_status_register_3(0x20).await.unwrap();
+        assert_eq!(w25q.read_status_register_3().await.unwrap(), 0x20);
+        assert_eq!(w25q.read_unique_id().await.unwrap(), 0xD265_381C_4713_2A2F);
+    });
+}
+
+#[test]
+fn it_erase_suspend_resume() {
+    let mut sim = W25Q64Sim::new().with_busy_polls(1000);
+    // 发送擦除指令但不等待完成
+    embedded_hal::spi::SpiDevice::write(&mut sim, &[0x06]).unwrap();
+    embedded_hal::spi::SpiDevice::write(&mut sim, &[0x20, 0x00, 0x00, 0x00]).unwrap();
+    assert!(sim.is_busy());
+    let mut w25q = new_w25q(sim);
+
+    block_on(async {
+        w25q.erase_suspend().await.unwrap();
+        let status = w25q.read_status_register().await.unwrap();
+        assert!(!status.busy);
+        assert!(status.sus);
+
+        w25q.erase_resume().await.unwrap();
+        let status = w25q.read_status_register().await.unwrap();
+        assert!(status.busy);
+        assert!(!status.sus);
+    });
+}
+
+#[test]
+fn it_program_and_erase_with_verify() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x010000..0x011000].fill(0x00);
+    let mut w25q = new_w25q(sim);
+    let data: Vec<u8> = (0..600).map(|i| (i * 7) as u8).collect();
+
+    block_on(async {
+        w25q.program_and_verify(0x000080, &data).await.unwrap();
+        // 未擦除时无法把 0 写成 1, 返回第一个不一致的地址
+        assert_eq!(
+            w25q.program_and_verify(0x000080, &[0x00, 0xFF]).await,
+            Err(W25q64Error::VerifyMismatch {
+                address: 0x000081,
+                expected: 0xFF,
+                actual: 0x07,
+            })
+        );
+
+        assert_eq!(
+            w25q.blank_check(0x010000..0x011000).await,
+            Err(W25q64Error::VerifyMismatch {
+                address: 0x010000,
+                expected: 0xFF,
+                actual: 0x00,
+            })
+        );
+        w25q.erase_and_verify(0x010000..0x011000).await.unwrap();
+    });
+}
+
+#[test]
+fn it_write_preserving_and_crc32() {
+    let mut sim = W25Q64Sim::new();
+    for (i, byte) in sim.memory_mut()[0x000000..0x002000].iter_mut().enumerate() {
+        *byte = (i % 251) as u8;
+    }
+    let mut w25q = new_w25q(sim);
+
+    block_on(async {
+        w25q.write_preserving(0x000FD0, &[0xA5; 100]).await.unwrap();
+
+        let mut expected: Vec<u8> = (0..0x2000).map(|i| (i % 251) as u8).collect();
+        expected[0x000FD0..0x000FD0 + 100].fill(0xA5);
+        assert_eq!(
+            w25q.crc32(0x000000..0x002000).await.unwrap(),
+            w25q64::crc::crc32(&expected)
+        );
+        assert!(matches!(
+            w25q.crc32(0x7FFF00..0x800100).await,
+            Err(W25q64Error::AddressOutOfRange { .. })
+        ));
+    });
+}
+
+#[test]
+fn it_fast_read() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x000200..0x000204].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+    let mut w25q = new_w25q(sim);
+
+    assert_eq!(
+        w25q.set_read_mode(ReadMode::DualOutput),
+        Err(W25q64Error::UnsupportedReadMode {
+            mode: ReadMode::DualOutput
+        })
+    );
+    w25q.set_read_mode(ReadMode::Fast).unwrap();
+    assert_eq!(w25q.read_mode(), ReadMode::Fast);
+
+    let mut rx_buf = [0; 4];
+    block_on(w25q.read_data(0x000200, &mut rx_buf)).unwrap();
+    assert_eq!(rx_buf, [0xDE, 0xAD, 0xBE, 0xEF]);
+}
+
+#[test]
+fn it_busy_timeout() {
+    let mut w25q = new_w25q(W25Q64Sim::new().with_busy_polls(u32::MAX));
+
+    block_on(async {
+        assert_eq!(
+            w25q.page_program(0x000000, &[0x00]).await,
+            Err(W25q64Error::BusyTimeout {
+                timeout: Duration::from_millis(3)
+            })
+        );
+        assert_eq!(
+            w25q.wait_for_idle(Duration::from_millis(20)).await,
+            Err(W25q64Error::BusyTimeout {
+                timeout: Duration::from_millis(20)
+            })
+        );
+    });
+
+    // 超时按照已等待的时间计算, 不依赖系统时钟
+    let (_, delay) = w25q.release();
+    assert_eq!(delay.waited_us, 23_000);
+}
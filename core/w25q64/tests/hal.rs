@@ -0,0 +1,470 @@
+//! 在主机上使用闪存模拟器测试 HAL 库版本驱动
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use w25q64::{hal::W25Q64, ReadMode, StatusRegister, W25q64Error};
+use w25q64_sim::W25Q64Sim;
+
+#[test]
+fn it_read_ids() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    assert_eq!(w25q.read_jedec_device_id().unwrap(), (0xEF, 0x40, 0x17));
+    let (manufacturer_id, device_id) = w25q.read_manufacturer_device_id().unwrap();
+    assert_eq!(manufacturer_id, 0xEF);
+    assert_eq!(device_id, 0x16EF);
+}
+
+#[test]
+fn it_page_program_and_read() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new().with_busy_polls(3));
+
+    w25q.sector_erase(0x000000).unwrap();
+    w25q.page_program(0x000000, &[0x01, 0x02, 0x03, 0x04])
+        .unwrap();
+
+    let mut rx_buf = [0; 6];
+    w25q.read_data(0x000000, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, [0x01, 0x02, 0x03, 0x04, 0xFF, 0xFF]);
+
+    let sim = w25q.release();
+    assert!(!sim.is_busy());
+    assert!(!sim.is_write_enabled());
+}
+
+#[test]
+fn it_page_program_at_most_a_page() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    assert_eq!(
+        w25q.page_program(0x000000, &[0x00; 257]),
+        Err(W25q64Error::PageOverflow {
+            address: 0x000000,
+            len: 257
+        })
+    );
+    w25q.page_program(0x000000, &[0x00; 256]).unwrap();
+
+    let sim = w25q.release();
+    assert!(sim.memory()[0x000000..0x000100].iter().all(|&b| b == 0x00));
+    assert_eq!(sim.memory()[0x000100], 0xFF);
+}
+
+#[test]
+fn it_page_program_only_clears_bits() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    w25q.page_program(0x000100, &[0b1100_1100]).unwrap();
+    w25q.page_program(0x000100, &[0b1010_1010]).unwrap();
+
+    let mut rx_buf = [0; 1];
+    w25q.read_data(0x000100, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, [0b1000_1000]);
+
+    // 擦除之后恢复为 0xFF
+    w25q.sector_erase(0x000100).unwrap();
+    w25q.read_data(0x000100, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, [0xFF]);
+}
+
+#[test]
+fn it_page_program_wraps_inside_page() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    w25q.page_program(0x0000FE, &[0x01, 0x02, 0x03]).unwrap();
+
+    let sim = w25q.release();
+    assert_eq!(&sim.memory()[0x0000FE..0x000100], &[0x01, 0x02]);
+    assert_eq!(sim.memory()[0x000000], 0x03);
+    assert_eq!(sim.memory()[0x000100], 0xFF);
+}
+
+#[test]
+fn it_erase_chip() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x001000] = 0x00;
+    sim.memory_mut()[0x7FFFFF] = 0x00;
+    let mut w25q = W25Q64::new(sim);
+
+    w25q.erase_chip().unwrap();
+
+    let sim = w25q.release();
+    assert!(sim.memory().iter().all(|&byte| byte == 0xFF));
+}
+
+#[test]
+fn it_write_disable() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    w25q.write_enable().unwrap();
+    assert!(w25q.read_status_register().unwrap().wel);
+    w25q.write_disable().unwrap();
+    assert_eq!(
+        w25q.read_status_register().unwrap(),
+        StatusRegister::default()
+    );
+    assert!(!w25q.check_write_protect().unwrap());
+}
+
+#[test]
+fn it_write_across_pages() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+    let data: Vec<u8> = (0..600).map(|i| i as u8).collect();
+
+    w25q.write(0x0000F0, &data).unwrap();
+
+    let mut rx_buf = vec![0; data.len()];
+    w25q.read_data(0x0000F0, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, data);
+
+    let sim = w25q.release();
+    assert_eq!(sim.memory()[0x0000EF], 0xFF);
+    assert_eq!(sim.memory()[0x0000F0 + 600], 0xFF);
+}
+
+#[test]
+fn it_write_preserving_across_sectors() {
+    let mut sim = W25Q64Sim::new();
+    // 预先写入旧数据, 覆盖两个扇区
+    for (i, byte) in sim.memory_mut()[0x000000..0x002000].iter_mut().enumerate() {
+        *byte = (i % 251) as u8;
+    }
+    let mut w25q = W25Q64::new(sim);
+    let data = vec![0xA5; 100];
+
+    w25q.write_preserving(0x000FD0, &data).unwrap();
+
+    let sim = w25q.release();
+    for (i, byte) in sim.memory()[0x000000..0x002000].iter().enumerate() {
+        if (0x000FD0..0x000FD0 + 100).contains(&i) {
+            assert_eq!(*byte, 0xA5, "address {:#06X}", i);
+        } else {
+            assert_eq!(*byte, (i % 251) as u8, "address {:#06X}", i);
+        }
+    }
+}
+
+#[test]
+fn it_write_out_of_range() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    assert_eq!(w25q.capacity().unwrap(), 8 * 1024 * 1024);
+    assert_eq!(
+        w25q.write(0x7FFFFF, &[0x00, 0x00]),
+        Err(W25q64Error::AddressOutOfRange {
+            address: 0x7FFFFF,
+            len: 2,
+            capacity: 0x800000
+        })
+    );
+    assert!(w25q.write_preserving(0x800000, &[0x00]).is_err());
+    assert!(w25q.write(0x7FFFFF, &[0x00]).is_ok());
+}
+
+#[test]
+fn it_block_erase() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x000000..0x020000].fill(0x00);
+    let mut w25q = W25Q64::new(sim);
+
+    w25q.block_erase_32kb(0x001234).unwrap();
+    w25q.block_erase_64kb(0x010000).unwrap();
+
+    let sim = w25q.release();
+    assert!(sim.memory()[0x000000..0x008000]
+        .iter()
+        .all(|&byte| byte == 0xFF));
+    assert!(sim.memory()[0x008000..0x010000]
+        .iter()
+        .all(|&byte| byte == 0x00));
+    assert!(sim.memory()[0x010000..0x020000]
+        .iter()
+        .all(|&byte| byte == 0xFF));
+}
+
+#[test]
+fn it_status_register_and_protect() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    let status = w25q.read_status_register().unwrap();
+    assert_eq!(status, StatusRegister::default());
+
+    // 保护顶部 1/64, 写入返回错误
+    w25q.protect(0x7E0000..0x800000).unwrap();
+    assert_eq!(w25q.protected_range().unwrap(), 0x7E0000..0x800000);
+    assert_eq!(w25q.read_status_register().unwrap().bp, 1);
+    assert_eq!(
+        w25q.page_program(0x7E0000, &[0x00]),
+        Err(W25q64Error::WriteProtected { address: 0x7E0000 })
+    );
+    assert_eq!(
+        w25q.write(0x7DFFFF, &[0x00, 0x00]),
+        Err(W25q64Error::WriteProtected { address: 0x7E0000 })
+    );
+    assert_eq!(
+        w25q.block_erase_64kb(0x7F0000),
+        Err(W25q64Error::WriteProtected { address: 0x7F0000 })
+    );
+    assert_eq!(
+        w25q.erase_chip(),
+        Err(W25q64Error::WriteProtected { address: 0x7E0000 })
+    );
+    w25q.page_program(0x7D0000, &[0x00]).unwrap();
+    let mut rx_buf = [0; 1];
+    w25q.read_data(0x7E0000, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, [0xFF]);
+    w25q.read_data(0x7D0000, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, [0x00]);
+
+    assert_eq!(
+        w25q.protect(0x001000..0x003000),
+        Err(W25q64Error::UnsupportedProtectRange {
+            start: 0x001000,
+            end: 0x003000
+        })
+    );
+
+    // 取消保护
+    w25q.protect(0..0).unwrap();
+    assert!(w25q.protected_range().unwrap().is_empty());
+    assert!(!w25q.check_write_protect().unwrap());
+
+    let mut status = w25q.read_status_register().unwrap();
+    status.srp0 = true;
+    w25q.write_status_register(&status).unwrap();
+    assert!(w25q.check_write_protect().unwrap());
+
+    w25q.write_status_register_3(0x20).unwrap();
+    assert_eq!(w25q.read_status_register_3().unwrap(), 0x20);
+}
+
+#[test]
+fn it_erase_suspend_resume() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new().with_busy_polls(1000));
+
+    // 发送擦除指令但不等待完成
+    w25q.write_enable().unwrap();
+    let mut sim = w25q.release();
+    embedded_hal::spi::SpiDevice::write(&mut sim, &[0x20, 0x00, 0x00, 0x00]).unwrap();
+    assert!(sim.is_busy());
+    let mut w25q = W25Q64::new(sim);
+
+    w25q.erase_suspend().unwrap();
+    let status = w25q.read_status_register().unwrap();
+    assert!(!status.busy);
+    assert!(status.sus);
+
+    w25q.erase_resume().unwrap();
+    let status = w25q.read_status_register().unwrap();
+    assert!(status.busy);
+    assert!(!status.sus);
+}
+
+#[test]
+fn it_power_down_and_ids() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    assert_eq!(w25q.read_unique_id().unwrap(), 0xD265_381C_4713_2A2F);
+
+    w25q.power_down().unwrap();
+    // 掉电期间忽略其他命令
+    assert_eq!(w25q.read_jedec_device_id().unwrap(), (0xFF, 0xFF, 0xFF));
+    assert_eq!(w25q.release_power_down().unwrap(), 0x16);
+    assert_eq!(w25q.read_jedec_device_id().unwrap(), (0xEF, 0x40, 0x17));
+}
+
+#[test]
+fn it_busy_timeout() {
+    // 芯片一直处于忙碌状态
+    let mut w25q = W25Q64::new(W25Q64Sim::new().with_busy_polls(u32::MAX));
+
+    let start = Instant::now();
+    assert_eq!(
+        w25q.page_program(0x000000, &[0x00]),
+        Err(W25q64Error::BusyTimeout {
+            timeout: Duration::from_millis(3)
+        })
+    );
+    assert!(start.elapsed() >= Duration::from_millis(3));
+
+    let start = Instant::now();
+    assert_eq!(
+        w25q.wait_for_idle(Duration::from_millis(20)),
+        Err(W25q64Error::BusyTimeout {
+            timeout: Duration::from_millis(20)
+        })
+    );
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn it_wait_for_idle_with_sleep() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new().with_busy_polls(20))
+        .with_poll_interval(Duration::from_micros(200));
+
+    // 忙等待结束后每次轮询之间休眠
+    w25q.sector_erase(0x000000).unwrap();
+    w25q.write(0x000000, &[0x12, 0x34]).unwrap();
+    let mut rx_buf = [0; 2];
+    w25q.read_data(0x000000, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, [0x12, 0x34]);
+}
+
+#[test]
+fn it_wait_for_idle_with_custom_sleep() {
+    static SLEEPS: AtomicU32 = AtomicU32::new(0);
+    fn sleep(duration: Duration) {
+        SLEEPS.fetch_add(1, Ordering::Relaxed);
+        thread::sleep(duration);
+    }
+
+    let mut w25q = W25Q64::new(W25Q64Sim::new().with_busy_polls(u32::MAX))
+        .with_poll_interval(Duration::from_millis(2))
+        .with_sleep(sleep);
+    // 芯片在编程后一直处于忙碌状态
+    assert!(w25q.page_program(0x000000, &[0x00]).is_err());
+    SLEEPS.store(0, Ordering::Relaxed);
+    assert!(w25q.wait_for_idle(Duration::from_millis(20)).is_err());
+    // 第一个间隔忙等待, 之后每次轮询调用一次休眠函数
+    let sleeps = SLEEPS.load(Ordering::Relaxed);
+    assert!((1..=10).contains(&sleeps), "{sleeps}");
+}
+
+#[test]
+fn it_read_modes() {
+    let mut sim = W25Q64Sim::new();
+    for (i, byte) in sim.memory_mut()[0x001000..0x001400].iter_mut().enumerate() {
+        *byte = i as u8 ^ 0x5A;
+    }
+    let expected = sim.memory()[0x001010..0x001310].to_vec();
+
+    // 普通 SPI 设备只支持单线读取
+    let mut w25q = W25Q64::new(sim);
+    w25q.set_read_mode(ReadMode::Fast).unwrap();
+    let mut rx_buf = vec![0; expected.len()];
+    w25q.read_data(0x001010, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, expected);
+    assert_eq!(
+        w25q.set_read_mode(ReadMode::QuadIO),
+        Err(W25q64Error::UnsupportedReadMode {
+            mode: ReadMode::QuadIO
+        })
+    );
+    assert_eq!(w25q.read_mode(), ReadMode::Fast);
+
+    // 多线读取, 四线模式会自动置位 QE
+    let mut w25q = W25Q64::new_multi_io(w25q.release());
+    for mode in [
+        ReadMode::Standard,
+        ReadMode::Fast,
+        ReadMode::DualOutput,
+        ReadMode::QuadOutput,
+        ReadMode::QuadIO,
+    ] {
+        w25q.set_read_mode(mode).unwrap();
+        let mut rx_buf = vec![0; expected.len()];
+        w25q.read_data(0x001010, &mut rx_buf).unwrap();
+        assert_eq!(rx_buf, expected, "{:?}", mode);
+    }
+    assert!(w25q.quad_enable().unwrap());
+
+    // 清除 QE 后四线读取无效
+    w25q.set_quad_enable(false).unwrap();
+    w25q.read_data(0x001010, &mut rx_buf).unwrap();
+    assert!(rx_buf.iter().all(|&byte| byte == 0xFF));
+}
+
+#[test]
+fn it_program_and_verify() {
+    let mut w25q = W25Q64::new(W25Q64Sim::new());
+
+    let data: Vec<u8> = (0..600).map(|i| (i * 7) as u8).collect();
+    w25q.program_and_verify(0x000080, &data).unwrap();
+    w25q.verify(0x000080, &data).unwrap();
+
+    // 未擦除时无法把 0 写成 1, 返回第一个不一致的地址
+    assert_eq!(
+        w25q.program_and_verify(0x000080, &[0x00, 0xFF, 0xFF]),
+        Err(W25q64Error::VerifyMismatch {
+            address: 0x000081,
+            expected: 0xFF,
+            actual: 0x07,
+        })
+    );
+}
+
+#[test]
+fn it_erase_and_verify() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x000000..0x030000].fill(0x00);
+    let mut w25q = W25Q64::new(sim);
+
+    assert_eq!(
+        w25q.blank_check(0x000000..0x001000),
+        Err(W25q64Error::VerifyMismatch {
+            address: 0x000000,
+            expected: 0xFF,
+            actual: 0x00,
+        })
+    );
+    w25q.erase_and_verify(0x001000..0x020000).unwrap();
+
+    let sim = w25q.release();
+    assert!(sim.memory()[..0x001000].iter().all(|&byte| byte == 0x00));
+    assert!(sim.memory()[0x020000..0x030000]
+        .iter()
+        .all(|&byte| byte == 0x00));
+
+    let mut w25q = W25Q64::new(sim);
+    w25q.blank_check(0x001000..0x020000).unwrap();
+    assert_eq!(
+        w25q.erase_and_verify(0x001000..0x001800),
+        Err(W25q64Error::NotAligned { address: 0x001800 })
+    );
+}
+
+#[test]
+fn it_crc32() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x010000..0x010009].copy_from_slice(b"123456789");
+    let mut w25q = W25Q64::new(sim);
+
+    // CRC32 (IEEE) 的标准校验值
+    assert_eq!(w25q.crc32(0x010000..0x010009).unwrap(), 0xCBF4_3926);
+    assert_eq!(w25q.crc32(0x010000..0x010000).unwrap(), 0);
+
+    // 跨越多个读取块
+    let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    w25q.write(0x020010, &data).unwrap();
+    assert_eq!(
+        w25q.crc32(0x020010..0x0203F8).unwrap(),
+        w25q64::crc::crc32(&data)
+    );
+    assert!(matches!(
+        w25q.crc32(0x7FFF00..0x800100),
+        Err(W25q64Error::AddressOutOfRange { .. })
+    ));
+}
+
+#[test]
+fn it_map_spi_error() {
+    let error: W25q64Error<u8> = W25q64Error::Spi(7);
+    assert_eq!(error.map_spi(u32::from), W25q64Error::Spi(7u32));
+
+    // 其他错误保持不变
+    let error: W25q64Error<u8> = W25q64Error::UnsupportedReadMode {
+        mode: ReadMode::QuadIO,
+    };
+    assert_eq!(
+        error.map_spi(u32::from),
+        W25q64Error::UnsupportedReadMode {
+            mode: ReadMode::QuadIO
+        }
+    );
+    let error: W25q64Error<u8> = W25q64Error::WriteProtected { address: 0x7E0000 };
+    assert_eq!(
+        error.map_spi(u32::from),
+        W25q64Error::WriteProtected { address: 0x7E0000 }
+    );
+}
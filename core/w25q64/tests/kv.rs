@@ -0,0 +1,343 @@
+//! 在主机上测试键值存储, 包括随机操作和掉电测试
+use std::collections::BTreeMap;
+
+use embedded_storage::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use w25q64::hal::W25Q64;
+use w25q64::kv::{KvError, KvStore};
+use w25q64_sim::W25Q64Sim;
+
+const SECTOR_SIZE: usize = 4096;
+
+/// 掉电错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PowerCut;
+
+impl NorFlashError for PowerCut {
+    fn kind(&self) -> NorFlashErrorKind {
+        NorFlashErrorKind::Other
+    }
+}
+
+/// 可以模拟掉电的内存存储器
+/// 每写入一个字节或擦除一个扇区消耗一个单位的电量, 电量耗尽时操作只完成一部分
+struct PowerCutFlash {
+    memory: Vec<u8>,
+    budget: Option<usize>,
+    erase_counts: Vec<usize>,
+}
+
+impl PowerCutFlash {
+    fn new(sectors: usize) -> Self {
+        PowerCutFlash {
+            memory: vec![0xFF; sectors * SECTOR_SIZE],
+            budget: None,
+            erase_counts: vec![0; sectors],
+        }
+    }
+
+    /// 消耗电量, 返回是否还有电
+    fn consume(&mut self) -> bool {
+        match &mut self.budget {
+            Some(0) => false,
+            Some(budget) => {
+                *budget -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+impl ErrorType for PowerCutFlash {
+    type Error = PowerCut;
+}
+
+impl ReadNorFlash for PowerCutFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.memory[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.memory.len()
+    }
+}
+
+impl NorFlash for PowerCutFlash {
+    const WRITE_SIZE: usize = 1;
+
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        for sector in (from as usize..to as usize).step_by(SECTOR_SIZE) {
+            if !self.consume() {
+                // 擦除到一半时掉电
+                self.memory[sector..sector + SECTOR_SIZE / 2].fill(0xFF);
+                return Err(PowerCut);
+            }
+            self.memory[sector..sector + SECTOR_SIZE].fill(0xFF);
+            self.erase_counts[sector / SECTOR_SIZE] += 1;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        for (i, byte) in bytes.iter().enumerate() {
+            if !self.consume() {
+                return Err(PowerCut);
+            }
+            self.memory[offset as usize + i] &= byte;
+        }
+        Ok(())
+    }
+}
+
+impl MultiwriteNorFlash for PowerCutFlash {}
+
+/// 伪随机数生成器, 保证测试可以复现
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// 随机操作
+#[derive(Debug, Clone)]
+enum Op {
+    Set(Vec<u8>, Vec<u8>),
+    Remove(Vec<u8>),
+}
+
+fn random_ops(seed: u64, count: usize) -> Vec<Op> {
+    let mut rng = XorShift(seed);
+    (0..count)
+        .map(|_| {
+            let key = format!("key{}", rng.below(12)).into_bytes();
+            if rng.below(5) == 0 {
+                Op::Remove(key)
+            } else {
+                let len = rng.below(300);
+                let value = (0..len).map(|_| rng.next() as u8).collect();
+                Op::Set(key, value)
+            }
+        })
+        .collect()
+}
+
+fn apply<F>(store: &mut KvStore<F>, op: &Op) -> Result<(), KvError<F::Error>>
+where
+    F: NorFlash + MultiwriteNorFlash,
+{
+    match op {
+        Op::Set(key, value) => store.set(key, value),
+        Op::Remove(key) => store.remove(key),
+    }
+}
+
+fn apply_model(model: &mut BTreeMap<Vec<u8>, Vec<u8>>, op: &Op) {
+    match op {
+        Op::Set(key, value) => {
+            model.insert(key.clone(), value.clone());
+        }
+        Op::Remove(key) => {
+            model.remove(key);
+        }
+    }
+}
+
+fn contents<F>(store: &mut KvStore<F>) -> BTreeMap<Vec<u8>, Vec<u8>>
+where
+    F: NorFlash + MultiwriteNorFlash,
+{
+    store.iter().map(|item| item.unwrap()).collect()
+}
+
+#[test]
+fn it_set_get_remove() {
+    let mut store = KvStore::mount(PowerCutFlash::new(4), 0..4).unwrap();
+    assert!(store.is_empty());
+
+    store.set(b"ssid", b"esp32").unwrap();
+    store.set(b"offset", &[1, 2, 3]).unwrap();
+    store.set(b"ssid", b"esp32-s3").unwrap();
+    assert_eq!(store.get(b"ssid").unwrap(), Some(b"esp32-s3".to_vec()));
+    assert_eq!(store.get(b"offset").unwrap(), Some(vec![1, 2, 3]));
+    assert_eq!(store.get(b"none").unwrap(), None);
+
+    store.remove(b"offset").unwrap();
+    assert_eq!(store.get(b"offset").unwrap(), None);
+    assert_eq!(store.len(), 1);
+
+    // 重新挂载后数据保持不变
+    let flash = store.release();
+    let mut store = KvStore::mount(flash, 0..4).unwrap();
+    assert_eq!(store.get(b"ssid").unwrap(), Some(b"esp32-s3".to_vec()));
+    assert!(!store.contains_key(b"offset"));
+}
+
+#[test]
+fn it_invalid_args() {
+    assert!(matches!(
+        KvStore::mount(PowerCutFlash::new(4), 0..1),
+        Err(KvError::InvalidConfig)
+    ));
+    assert!(matches!(
+        KvStore::mount(PowerCutFlash::new(4), 2..5),
+        Err(KvError::InvalidConfig)
+    ));
+
+    let mut store = KvStore::mount(PowerCutFlash::new(4), 0..4).unwrap();
+    assert_eq!(store.set(b"", b"value"), Err(KvError::InvalidKey));
+    assert_eq!(store.set(&[b'k'; 255], b"value"), Err(KvError::InvalidKey));
+    assert_eq!(
+        store.set(b"key", &vec![0; SECTOR_SIZE]),
+        Err(KvError::ValueTooLarge)
+    );
+}
+
+#[test]
+fn it_store_full() {
+    let mut store = KvStore::mount(PowerCutFlash::new(2), 0..2).unwrap();
+
+    // 只有一个扇区可以存放有效数据
+    store.set(b"a", &[0; 2000]).unwrap();
+    assert_eq!(store.set(b"b", &[0; 2100]), Err(KvError::StoreFull));
+    assert_eq!(store.get(b"a").unwrap(), Some(vec![0; 2000]));
+
+    // 覆盖同一个键时可以回收旧数据
+    for i in 0..10 {
+        store.set(b"a", &[i; 2000]).unwrap();
+    }
+    assert_eq!(store.get(b"a").unwrap(), Some(vec![9; 2000]));
+}
+
+#[test]
+fn it_random_ops_with_remount() {
+    for seed in 1..=4 {
+        let mut store = KvStore::mount(PowerCutFlash::new(6), 1..5).unwrap();
+        let mut model = BTreeMap::new();
+
+        for (i, op) in random_ops(seed, 600).iter().enumerate() {
+            apply(&mut store, op).unwrap();
+            apply_model(&mut model, op);
+
+            if i % 97 == 0 {
+                let flash = store.release();
+                store = KvStore::mount(flash, 1..5).unwrap();
+            }
+            if i % 10 == 0 {
+                assert_eq!(contents(&mut store), model, "seed {} op {}", seed, i);
+            }
+        }
+        assert_eq!(contents(&mut store), model);
+
+        // 存储区域之外的扇区没有被修改
+        let flash = store.release();
+        assert!(flash.memory[..SECTOR_SIZE].iter().all(|&byte| byte == 0xFF));
+        assert!(flash.memory[5 * SECTOR_SIZE..].iter().all(|&byte| byte == 0xFF));
+    }
+}
+
+#[test]
+fn it_wear_leveling() {
+    let mut store = KvStore::mount(PowerCutFlash::new(4), 0..4).unwrap();
+    for op in random_ops(7, 2000) {
+        apply(&mut store, &op).unwrap();
+    }
+
+    let flash = store.release();
+    let max = *flash.erase_counts.iter().max().unwrap();
+    let min = *flash.erase_counts.iter().min().unwrap();
+    assert!(min > 0);
+    assert!(max - min <= 1, "erase counts {:?}", flash.erase_counts);
+}
+
+#[test]
+fn it_power_cut() {
+    let ops = random_ops(42, 120);
+
+    // 先不掉电执行一遍, 统计总共消耗的电量
+    let mut flash = PowerCutFlash::new(3);
+    flash.budget = Some(usize::MAX);
+    let mut store = KvStore::mount(flash, 0..3).unwrap();
+    for op in ops.iter() {
+        apply(&mut store, op).unwrap();
+    }
+    let total = usize::MAX - store.release().budget.unwrap();
+
+    for cut in (0..total).step_by(37) {
+        let mut flash = PowerCutFlash::new(3);
+        flash.budget = Some(cut);
+        let mut store = KvStore::mount(flash, 0..3).unwrap();
+
+        // 执行到掉电为止, 记录已经完成的操作
+        let mut model = BTreeMap::new();
+        let mut interrupted = None;
+        for op in ops.iter() {
+            if apply(&mut store, op).is_err() {
+                interrupted = Some(op.clone());
+                break;
+            }
+            apply_model(&mut model, op);
+        }
+
+        // 重新上电挂载
+        let mut flash = store.release();
+        flash.budget = None;
+        let mut store = KvStore::mount(flash, 0..3).unwrap();
+        let recovered = contents(&mut store);
+
+        // 被打断的操作要么完全生效, 要么完全没有生效
+        let mut expected_new = model.clone();
+        if let Some(op) = &interrupted {
+            apply_model(&mut expected_new, op);
+        }
+        assert!(
+            recovered == model || recovered == expected_new,
+            "cut {} interrupted {:?}",
+            cut,
+            interrupted
+        );
+
+        // 恢复后可以继续正常使用
+        let mut model = recovered;
+        for op in random_ops(cut as u64 + 1, 40).iter() {
+            apply(&mut store, op).unwrap();
+            apply_model(&mut model, op);
+        }
+        let flash = store.release();
+        let mut store = KvStore::mount(flash, 0..3).unwrap();
+        assert_eq!(contents(&mut store), model, "cut {}", cut);
+    }
+}
+
+#[test]
+fn it_on_w25q64() {
+    let flash = W25Q64::new(W25Q64Sim::new().with_busy_polls(2));
+    let mut store = KvStore::mount(flash, 16..20).unwrap();
+
+    store.set(b"calibration", &[0x12, 0x34, 0x56]).unwrap();
+    store.set(b"name", b"esp32-s3").unwrap();
+
+    let flash = store.release();
+    let mut store = KvStore::mount(flash, 16..20).unwrap();
+    assert_eq!(
+        store.get(b"calibration").unwrap(),
+        Some(vec![0x12, 0x34, 0x56])
+    );
+    assert_eq!(store.keys().count(), 2);
+}
@@ -0,0 +1,192 @@
+//! 在主机上测试软件 SPI 在 4 种模式下的时序
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal::spi::{Mode, Phase, Polarity, SpiDevice, MODE_0, MODE_1, MODE_2, MODE_3};
+use w25q64::reg::SoftSpi;
+
+/// 引脚级的 SPI 从机, 按照指定的模式在时钟边沿采样和移出数据
+struct Slave {
+    mode: Mode,
+    selected: bool,
+    sck: bool,
+    mosi: bool,
+    miso: bool,
+    // 当前字节已经传输的位数
+    bits: u32,
+    shift_in: u8,
+    shift_out: u8,
+    // 待发送的数据
+    tx: VecDeque<u8>,
+    // 已接收的数据
+    rx: Vec<u8>,
+}
+
+impl Slave {
+    fn new(mode: Mode, tx: &[u8]) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Slave {
+            mode,
+            selected: false,
+            sck: false,
+            mosi: false,
+            miso: false,
+            bits: 0,
+            shift_in: 0,
+            shift_out: 0,
+            tx: tx.iter().copied().collect(),
+            rx: Vec::new(),
+        }))
+    }
+
+    fn select(&mut self, selected: bool) {
+        self.selected = selected;
+        if selected {
+            self.bits = 0;
+            self.load();
+        }
+    }
+
+    /// 装载下一个要发送的字节, 模式 0/2 在片选有效或上一个字节的最后一个边沿输出第一位
+    fn load(&mut self) {
+        self.shift_out = self.tx.pop_front().unwrap_or(0xFF);
+        if self.mode.phase == Phase::CaptureOnFirstTransition {
+            self.shift_out_bit();
+        }
+    }
+
+    fn shift_out_bit(&mut self) {
+        self.miso = self.shift_out & (0x80 >> self.bits) != 0;
+    }
+
+    fn sample(&mut self) {
+        self.shift_in = self.shift_in << 1 | self.mosi as u8;
+        self.bits += 1;
+        if self.bits == 8 {
+            self.rx.push(self.shift_in);
+            self.bits = 0;
+            // 模式 1/3 在下一个字节的第一个边沿才输出数据
+            if self.mode.phase == Phase::CaptureOnSecondTransition {
+                self.load();
+            }
+        }
+    }
+
+    fn clock(&mut self, level: bool) {
+        if level == self.sck {
+            return;
+        }
+        self.sck = level;
+        if !self.selected {
+            return;
+        }
+
+        let idle = self.mode.polarity == Polarity::IdleHigh;
+        let leading = level != idle;
+        match (self.mode.phase, leading) {
+            (Phase::CaptureOnFirstTransition, true) => self.sample(),
+            (Phase::CaptureOnFirstTransition, false) => {
+                if self.bits == 0 {
+                    self.load();
+                } else {
+                    self.shift_out_bit();
+                }
+            }
+            (Phase::CaptureOnSecondTransition, true) => self.shift_out_bit(),
+            (Phase::CaptureOnSecondTransition, false) => self.sample(),
+        }
+    }
+}
+
+enum Line {
+    Cs,
+    Sck,
+    Mosi,
+}
+
+struct Pin(Rc<RefCell<Slave>>, Line);
+
+impl ErrorType for Pin {
+    type Error = Infallible;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set(true);
+        Ok(())
+    }
+}
+
+impl Pin {
+    fn set(&mut self, level: bool) {
+        let mut slave = self.0.borrow_mut();
+        match self.1 {
+            Line::Cs => slave.select(!level),
+            Line::Sck => slave.clock(level),
+            Line::Mosi => slave.mosi = level,
+        }
+    }
+}
+
+struct MisoPin(Rc<RefCell<Slave>>);
+
+impl ErrorType for MisoPin {
+    type Error = Infallible;
+}
+
+impl InputPin for MisoPin {
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.0.borrow().miso)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.0.borrow().miso)
+    }
+}
+
+type TestSpi = SoftSpi<Pin, Pin, Pin, MisoPin>;
+
+fn soft_spi(slave: &Rc<RefCell<Slave>>, mode: Mode) -> TestSpi {
+    SoftSpi::new(
+        Pin(slave.clone(), Line::Cs),
+        Pin(slave.clone(), Line::Sck),
+        Pin(slave.clone(), Line::Mosi),
+        MisoPin(slave.clone()),
+    )
+    .with_mode(mode)
+}
+
+#[test]
+fn it_transfer_in_all_modes() {
+    for mode in [MODE_0, MODE_1, MODE_2, MODE_3] {
+        let slave = Slave::new(mode, &[0xEF, 0x40, 0x17, 0x81]);
+        let mut spi = soft_spi(&slave, mode);
+
+        let mut buf = [0x9F, 0x00, 0x5A, 0xC3];
+        spi.transfer_in_place(&mut buf).unwrap();
+        assert_eq!(buf, [0xEF, 0x40, 0x17, 0x81], "{:?}", mode);
+        assert_eq!(slave.borrow().rx, [0x9F, 0x00, 0x5A, 0xC3], "{:?}", mode);
+        assert!(!slave.borrow().selected);
+    }
+}
+
+#[test]
+fn it_clock_delay() {
+    let slave = Slave::new(MODE_0, &[0x12, 0x34]);
+    let mut spi = soft_spi(&slave, MODE_0).with_clock_delay(Duration::from_micros(5));
+
+    let start = Instant::now();
+    let mut buf = [0; 2];
+    spi.read(&mut buf).unwrap();
+    assert_eq!(buf, [0x12, 0x34]);
+    // 每一位有两个半周期
+    assert!(start.elapsed() >= Duration::from_micros(2 * 16 * 5));
+}
@@ -0,0 +1,63 @@
+//! 测试状态寄存器的解码和块保护区域
+use w25q64::StatusRegister;
+
+const CAPACITY: u32 = 8 * 1024 * 1024;
+
+#[test]
+fn it_from_bits() {
+    let status = StatusRegister::from_bits(0b1011_1011, 0b1100_0011);
+    assert!(status.busy);
+    assert!(status.wel);
+    assert_eq!(status.bp, 0b110);
+    assert!(status.tb);
+    assert!(!status.sec);
+    assert!(status.srp0);
+    assert!(status.srp1);
+    assert!(status.qe);
+    assert_eq!(status.lb, 0);
+    assert!(status.cmp);
+    assert!(status.sus);
+    assert_eq!(status.sr1(), 0b1011_1011);
+    assert_eq!(status.sr2(), 0b1100_0011);
+}
+
+#[test]
+fn it_protected_range() {
+    let range = |sr1: u8, sr2: u8| StatusRegister::from_bits(sr1, sr2).protected_range(CAPACITY);
+
+    // 没有保护
+    assert!(range(0x00, 0x00).is_empty());
+    // 顶部 1/64 (128KB)
+    assert_eq!(range(0b0000_0100, 0x00), 0x7E0000..0x800000);
+    // 顶部 1/2 (4MB)
+    assert_eq!(range(0b0001_1000, 0x00), 0x400000..0x800000);
+    // 底部 1/4 (2MB)
+    assert_eq!(range(0b0011_0100, 0x00), 0x000000..0x200000);
+    // 全部
+    assert_eq!(range(0b0001_1100, 0x00), 0x000000..0x800000);
+    // 顶部 4KB 扇区
+    assert_eq!(range(0b0100_0100, 0x00), 0x7FF000..0x800000);
+    // 底部 32KB
+    assert_eq!(range(0b0111_0000, 0x00), 0x000000..0x008000);
+    // 取反: 除顶部 128KB 之外
+    assert_eq!(range(0b0000_0100, 0x40), 0x000000..0x7E0000);
+    // 取反: 全部不保护
+    assert!(range(0b0001_1100, 0x40).is_empty());
+}
+
+#[test]
+fn it_set_protected_range() {
+    let mut status = StatusRegister::default();
+
+    assert!(status.set_protected_range(0x000000..0x100000, CAPACITY));
+    assert_eq!((status.bp, status.tb, status.sec, status.cmp), (4, true, false, false));
+
+    assert!(status.set_protected_range(0x000000..0x7FF000, CAPACITY));
+    assert_eq!(status.protected_range(CAPACITY), 0x000000..0x7FF000);
+    assert!(status.cmp);
+
+    assert!(status.set_protected_range(0..0, CAPACITY));
+    assert!(status.protected_range(CAPACITY).is_empty());
+
+    assert!(!status.set_protected_range(0x001000..0x002000, CAPACITY));
+}
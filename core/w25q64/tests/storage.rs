@@ -0,0 +1,77 @@
+//! 在主机上使用闪存模拟器测试 embedded-storage 存储接口
+use embedded_storage::nor_flash::{NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+use w25q64::hal::W25Q64;
+use w25q64_sim::W25Q64Sim;
+
+#[test]
+fn it_read_write_erase() {
+    let mut flash = W25Q64::new(W25Q64Sim::new());
+    assert_eq!(ReadNorFlash::capacity(&flash), 8 * 1024 * 1024);
+
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    NorFlash::write(&mut flash, 0x001F80, &data).unwrap();
+
+    let mut rx_buf = vec![0; data.len()];
+    ReadNorFlash::read(&mut flash, 0x001F80, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, data);
+
+    // 擦除第一个扇区, 第二个扇区的数据保持不变
+    NorFlash::erase(&mut flash, 0x001000, 0x002000).unwrap();
+    ReadNorFlash::read(&mut flash, 0x001F80, &mut rx_buf).unwrap();
+    assert!(rx_buf[..0x80].iter().all(|&byte| byte == 0xFF));
+    assert_eq!(rx_buf[0x80..], data[0x80..]);
+}
+
+#[test]
+fn it_multiwrite() {
+    let mut flash = W25Q64::new(W25Q64Sim::new());
+
+    NorFlash::write(&mut flash, 0x000000, &[0b1111_0000]).unwrap();
+    NorFlash::write(&mut flash, 0x000000, &[0b1100_1100]).unwrap();
+
+    let mut rx_buf = [0; 1];
+    ReadNorFlash::read(&mut flash, 0x000000, &mut rx_buf).unwrap();
+    assert_eq!(rx_buf, [0b1100_0000]);
+}
+
+#[test]
+fn it_erase_range_validation() {
+    let mut flash = W25Q64::new(W25Q64Sim::new());
+
+    let err = NorFlash::erase(&mut flash, 0x000100, 0x001000).unwrap_err();
+    assert_eq!(err.kind(), NorFlashErrorKind::NotAligned);
+    let err = NorFlash::erase(&mut flash, 0x000000, 0x000800).unwrap_err();
+    assert_eq!(err.kind(), NorFlashErrorKind::NotAligned);
+    let err = NorFlash::erase(&mut flash, 0x002000, 0x001000).unwrap_err();
+    assert_eq!(err.kind(), NorFlashErrorKind::OutOfBounds);
+    let err = NorFlash::erase(&mut flash, 0x7FF000, 0x801000).unwrap_err();
+    assert_eq!(err.kind(), NorFlashErrorKind::OutOfBounds);
+
+    assert!(NorFlash::erase(&mut flash, 0x7FF000, 0x800000).is_ok());
+    assert!(NorFlash::erase(&mut flash, 0x001000, 0x001000).is_ok());
+}
+
+#[test]
+fn it_read_write_out_of_bounds() {
+    let mut flash = W25Q64::new(W25Q64Sim::new());
+
+    let mut rx_buf = [0; 2];
+    let err = ReadNorFlash::read(&mut flash, 0x7FFFFF, &mut rx_buf).unwrap_err();
+    assert_eq!(err.kind(), NorFlashErrorKind::OutOfBounds);
+    let err = NorFlash::write(&mut flash, 0x800000, &[0x00]).unwrap_err();
+    assert_eq!(err.kind(), NorFlashErrorKind::OutOfBounds);
+}
+
+#[test]
+fn it_erase_with_blocks() {
+    let mut sim = W25Q64Sim::new();
+    sim.memory_mut()[0x000000..0x020000].fill(0x00);
+    let mut flash = W25Q64::new(sim);
+
+    NorFlash::erase(&mut flash, 0x007000, 0x01F000).unwrap();
+
+    let sim = flash.release();
+    assert!(sim.memory()[..0x007000].iter().all(|&byte| byte == 0x00));
+    assert!(sim.memory()[0x007000..0x01F000].iter().all(|&byte| byte == 0xFF));
+    assert!(sim.memory()[0x01F000..0x020000].iter().all(|&byte| byte == 0x00));
+}
@@ -0,0 +1,373 @@
+//! W25Q64 闪存模拟器
+//!
+//! 在主机上以内存模拟 W25Q64 芯片, 实现 embedded-hal 的 `SpiDevice`,
+//! 使 `w25q64` 驱动的页编程、擦除等逻辑可以在 `cargo test` 中运行。
+//!
+//! 一次 `transaction` 对应一次片选(CS)拉低到拉高的过程:
+//! 读类命令在时钟移位时返回数据, 写类命令在片选拉高时执行。
+//! 模拟器同时实现了 `MultiIoSpi`, 可以测试双线/四线读取。
+use core::convert::Infallible;
+
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+
+use w25q64::{conf::*, MultiIoSpi, ReadMode, StatusRegister};
+
+/// JEDEC ID: 制造商 Winbond, 存储类型, 容量 2^23
+pub const JEDEC_ID: [u8; 3] = [0xEF, 0x40, 0x17];
+/// 制造商和设备ID
+pub const MANUFACTURER_DEVICE_ID: [u8; 2] = [0xEF, 0x16];
+/// 64位唯一ID
+pub const UNIQUE_ID: [u8; 8] = [0xD2, 0x65, 0x38, 0x1C, 0x47, 0x13, 0x2A, 0x2F];
+
+// 状态寄存器1的忙标志位
+const STATUS_BUSY: u8 = 0x01;
+// 状态寄存器1的写使能锁存位
+const STATUS_WEL: u8 = 0x02;
+// 状态寄存器1中可写入的位: BP0~2, TB, SEC, SRP0
+const STATUS_1_WRITABLE: u8 = 0xFC;
+// 状态寄存器2中可写入的位: SRP1, QE, CMP, LB1~3 只能置位
+const STATUS_2_WRITABLE: u8 = 0x43;
+const STATUS_2_LOCK_BITS: u8 = 0x38;
+// 状态寄存器2的擦除暂停位
+const STATUS_SUS: u8 = 0x80;
+
+/// 内存模拟的 W25Q64 芯片
+pub struct W25Q64Sim {
+    memory: Vec<u8>,
+    status: u8,
+    status_2: u8,
+    status_3: u8,
+    // 是否处于掉电模式
+    power_down: bool,
+    // 擦除暂停时剩余的忙碌读取次数
+    suspended: Option<u32>,
+    // 编程/擦除之后, 忙标志位保持的状态寄存器读取次数
+    busy_polls: u32,
+    // 剩余的忙碌读取次数
+    busy_remaining: u32,
+    // 当前片选周期内移入的字节
+    command: Vec<u8>,
+}
+
+impl Default for W25Q64Sim {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl W25Q64Sim {
+    /// 创建一个已擦除(全部为 0xFF)的芯片
+    pub fn new() -> Self {
+        W25Q64Sim {
+            memory: vec![0xFF; W25Q64_CAPACITY],
+            status: 0,
+            status_2: 0,
+            status_3: 0x60,
+            power_down: false,
+            suspended: None,
+            busy_polls: 0,
+            busy_remaining: 0,
+            command: Vec::new(),
+        }
+    }
+
+    /// 设置编程/擦除之后忙标志位保持的状态寄存器读取次数
+    pub fn with_busy_polls(mut self, busy_polls: u32) -> Self {
+        self.busy_polls = busy_polls;
+        self
+    }
+
+    /// 芯片存储的内容
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// 直接修改芯片存储的内容, 不经过 SPI 命令
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// 是否忙碌
+    pub fn is_busy(&self) -> bool {
+        self.status & STATUS_BUSY != 0
+    }
+
+    /// 写使能锁存位是否置位
+    pub fn is_write_enabled(&self) -> bool {
+        self.status & STATUS_WEL != 0
+    }
+
+    /// 是否处于掉电模式
+    pub fn is_powered_down(&self) -> bool {
+        self.power_down
+    }
+
+    /// 擦除是否被暂停
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.is_some()
+    }
+
+    /// 状态寄存器1和2
+    pub fn status_register(&self) -> StatusRegister {
+        StatusRegister::from_bits(self.status, self.status_2)
+    }
+
+    /// 地址范围是否与块保护区域重叠
+    fn is_protected(&self, range: core::ops::Range<usize>) -> bool {
+        let protected = self
+            .status_register()
+            .protected_range(W25Q64_CAPACITY as u32);
+        (protected.start as usize) < range.end && range.start < protected.end as usize
+    }
+
+    /// 片选拉低, 开始一次命令
+    fn select(&mut self) {
+        self.command.clear();
+    }
+
+    /// 交换一个字节, 返回 MISO 上的数据
+    fn swap_byte(&mut self, byte: u8) -> u8 {
+        let index = self.command.len();
+        self.command.push(byte);
+        if index == 0 {
+            return 0xFF;
+        }
+
+        let cmd = self.command[0];
+        // 掉电期间只响应退出掉电命令
+        if self.power_down && cmd != W25Q64_RELEASE_POWER_DOWN_HPM_DEVICE_ID {
+            return 0xFF;
+        }
+        // 忙碌期间只响应读状态寄存器命令, 暂停擦除命令在片选拉高时处理
+        if self.is_busy()
+            && cmd != W25Q64_READ_STATUS_REGISTER_1
+            && cmd != W25Q64_READ_STATUS_REGISTER_2
+        {
+            return 0xFF;
+        }
+
+        match cmd {
+            W25Q64_READ_STATUS_REGISTER_1 => {
+                let status = self.status;
+                self.tick_busy();
+                status
+            }
+            W25Q64_READ_STATUS_REGISTER_2 => self.status_2,
+            W25Q64_READ_STATUS_REGISTER_3 => self.status_3,
+            W25Q64_JEDEC_DEVICE_ID => JEDEC_ID.get(index - 1).copied().unwrap_or(0xFF),
+            W25Q64_MANUFACTURER_DEVICE_ID if index >= 4 => {
+                // 地址最低位决定先输出制造商ID还是设备ID
+                let first = (self.command[3] & 0x01) as usize;
+                MANUFACTURER_DEVICE_ID[(first + index - 4) % 2]
+            }
+            W25Q64_RELEASE_POWER_DOWN_HPM_DEVICE_ID if index >= 4 => MANUFACTURER_DEVICE_ID[1],
+            W25Q64_READ_UNIQUE_ID if index >= 5 => {
+                UNIQUE_ID.get(index - 5).copied().unwrap_or(0xFF)
+            }
+            W25Q64_READ_DATA if index >= 4 => {
+                let address = address_of(&self.command) + (index - 4);
+                self.memory[address % W25Q64_CAPACITY]
+            }
+            // 快速读取在地址之后有一个虚拟字节
+            W25Q64_FAST_READ if index >= 5 => {
+                let address = address_of(&self.command) + (index - 5);
+                self.memory[address % W25Q64_CAPACITY]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// 片选拉高, 执行写类命令
+    fn deselect(&mut self) {
+        let command = core::mem::take(&mut self.command);
+        if command.is_empty() {
+            return;
+        }
+        if self.power_down {
+            if command[0] == W25Q64_RELEASE_POWER_DOWN_HPM_DEVICE_ID {
+                self.power_down = false;
+            }
+            return;
+        }
+        if self.is_busy() {
+            if command[0] == W25Q64_ERASE_SUSPEND && command.len() == 1 {
+                self.suspend();
+            }
+            return;
+        }
+
+        let write_enabled = self.is_write_enabled();
+        match command[0] {
+            W25Q64_WRITE_ENABLE => self.status |= STATUS_WEL,
+            W25Q64_WRITE_DISABLE => self.status &= !STATUS_WEL,
+            W25Q64_POWER_DOWN if command.len() == 1 => self.power_down = true,
+            W25Q64_ERASE_RESUME if command.len() == 1 => self.resume(),
+            W25Q64_WRITE_STATUS_REGISTER if command.len() >= 2 && write_enabled => {
+                self.status = (self.status & !STATUS_1_WRITABLE) | (command[1] & STATUS_1_WRITABLE);
+                if let Some(&status_2) = command.get(2) {
+                    // 安全寄存器锁定位是一次性可编程的, 只能置位
+                    let lock_bits = (self.status_2 | status_2) & STATUS_2_LOCK_BITS;
+                    self.status_2 = (self.status_2 & !(STATUS_2_WRITABLE | STATUS_2_LOCK_BITS))
+                        | (status_2 & STATUS_2_WRITABLE)
+                        | lock_bits;
+                }
+                self.start_busy();
+            }
+            W25Q64_WRITE_STATUS_REGISTER_3 if command.len() == 2 && write_enabled => {
+                self.status_3 = command[1];
+                self.start_busy();
+            }
+            W25Q64_PAGE_PROGRAM if command.len() > 4 && write_enabled => {
+                let address = address_of(&command);
+                let page = address & !(W25Q64_PAGE_SIZE - 1);
+                if !self.is_protected(page..page + W25Q64_PAGE_SIZE) {
+                    // 超过一页时只保留最后 256 个字节
+                    let data = &command[4..];
+                    let data = &data[data.len().saturating_sub(W25Q64_PAGE_SIZE)..];
+                    for (i, byte) in data.iter().enumerate() {
+                        // 在页内回绕, 编程只能把 1 写成 0
+                        let offset = (address + i) % W25Q64_PAGE_SIZE;
+                        self.memory[page + offset] &= byte;
+                    }
+                }
+                self.start_busy();
+            }
+            W25Q64_SECTOR_ERASE_4KB if command.len() == 4 && write_enabled => {
+                self.erase(address_of(&command), W25Q64_SECTOR_SIZE);
+            }
+            W25Q64_BLOCK_ERASE_32KB if command.len() == 4 && write_enabled => {
+                self.erase(address_of(&command), W25Q64_BLOCK_32KB_SIZE);
+            }
+            W25Q64_BLOCK_ERASE_64KB if command.len() == 4 && write_enabled => {
+                self.erase(address_of(&command), W25Q64_BLOCK_64KB_SIZE);
+            }
+            W25Q64_CHIP_ERASE if command.len() == 1 && write_enabled => {
+                self.erase(0, W25Q64_CAPACITY);
+            }
+            _ => {}
+        }
+    }
+
+    /// 擦除地址所在的区域, 与保护区域重叠或者擦除暂停期间忽略
+    fn erase(&mut self, address: usize, size: usize) {
+        let start = address & !(size - 1);
+        if self.suspended.is_none() && !self.is_protected(start..start + size) {
+            self.memory[start..start + size].fill(0xFF);
+        }
+        self.start_busy();
+    }
+
+    /// 暂停擦除, 清除忙标志位
+    fn suspend(&mut self) {
+        self.suspended = Some(self.busy_remaining);
+        self.status &= !STATUS_BUSY;
+        self.status_2 |= STATUS_SUS;
+    }
+
+    /// 恢复擦除
+    fn resume(&mut self) {
+        if let Some(busy_remaining) = self.suspended.take() {
+            self.status_2 &= !STATUS_SUS;
+            self.status |= STATUS_BUSY;
+            self.busy_remaining = busy_remaining;
+        }
+    }
+
+    /// 开始编程/擦除, 置位忙标志位
+    fn start_busy(&mut self) {
+        self.busy_remaining = self.busy_polls;
+        if self.busy_remaining == 0 {
+            self.finish_busy();
+        } else {
+            self.status |= STATUS_BUSY;
+        }
+    }
+
+    /// 读取一次状态寄存器, 忙碌计数减一
+    fn tick_busy(&mut self) {
+        if !self.is_busy() {
+            return;
+        }
+        self.busy_remaining = self.busy_remaining.saturating_sub(1);
+        if self.busy_remaining == 0 {
+            self.finish_busy();
+        }
+    }
+
+    /// 编程/擦除完成, 清除忙标志位和写使能锁存位
+    fn finish_busy(&mut self) {
+        self.status &= !(STATUS_BUSY | STATUS_WEL);
+    }
+}
+
+/// 命令中的24位地址
+fn address_of(command: &[u8]) -> usize {
+    let address = (command[1] as usize) << 16 | (command[2] as usize) << 8 | command[3] as usize;
+    address % W25Q64_CAPACITY
+}
+
+impl ErrorType for W25Q64Sim {
+    type Error = Infallible;
+}
+
+impl MultiIoSpi for W25Q64Sim {
+    fn data_lines(&self) -> u8 {
+        4
+    }
+
+    fn read_multi_io(
+        &mut self,
+        mode: ReadMode,
+        address: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        // 四线读取需要置位 QE, 否则 IO2/IO3 仍然是 WP/HOLD 引脚, 读到的数据无效
+        let quad_enabled = self.status_register().qe;
+        if self.power_down || self.is_busy() || (mode.requires_quad_enable() && !quad_enabled) {
+            data.fill(0xFF);
+            return Ok(());
+        }
+
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = self.memory[(address as usize + i) % W25Q64_CAPACITY];
+        }
+        Ok(())
+    }
+}
+
+impl SpiDevice for W25Q64Sim {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.select();
+        for operation in operations {
+            match operation {
+                Operation::Read(words) => {
+                    for word in words.iter_mut() {
+                        *word = self.swap_byte(0x00);
+                    }
+                }
+                Operation::Write(words) => {
+                    for word in words.iter() {
+                        self.swap_byte(*word);
+                    }
+                }
+                Operation::Transfer(read, write) => {
+                    for i in 0..read.len().max(write.len()) {
+                        let byte = self.swap_byte(write.get(i).copied().unwrap_or(0x00));
+                        if let Some(word) = read.get_mut(i) {
+                            *word = byte;
+                        }
+                    }
+                }
+                Operation::TransferInPlace(words) => {
+                    for word in words.iter_mut() {
+                        *word = self.swap_byte(*word);
+                    }
+                }
+                // 延时对模拟器没有影响
+                _ => {}
+            }
+        }
+        self.deselect();
+        Ok(())
+    }
+}